@@ -0,0 +1,269 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{github::GithubApiWrapper, PlaidFunctionError};
+
+/// The current status of a check run
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckRunStatus {
+    Queued,
+    InProgress,
+    Completed,
+}
+
+/// The final result of a completed check run. Branch protection treats
+/// `success`, `neutral` and `skipped` as passing.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckRunConclusion {
+    Success,
+    Failure,
+    Neutral,
+    Cancelled,
+    Skipped,
+    TimedOut,
+    ActionRequired,
+}
+
+/// How severe an annotation is
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnotationLevel {
+    Notice,
+    Warning,
+    Failure,
+}
+
+/// A comment on specific lines of a file, shown in the pull request's diff
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CheckRunAnnotation {
+    /// The path of the file, relative to the root of the repository
+    pub path: String,
+    pub start_line: u64,
+    pub end_line: u64,
+    pub annotation_level: AnnotationLevel,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_details: Option<String>,
+}
+
+/// What a check run reports, shown on the pull request's Checks tab
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CheckRunOutput {
+    pub title: String,
+    /// Summary of the result, in Markdown
+    pub summary: String,
+    /// Details of the result, in Markdown
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// At most 50 annotations can be sent per request
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<CheckRunAnnotation>,
+}
+
+/// Request to create a check run on a commit
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CreateCheckRunRequest {
+    pub owner: String,
+    pub repo: String,
+    /// The name of the check. This is what branch protection rules require.
+    pub name: String,
+    /// The SHA of the commit to check
+    pub head_sha: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<CheckRunStatus>,
+    /// Required if `status` is `completed`. Setting it also sets `status` to `completed`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conclusion: Option<CheckRunConclusion>,
+    /// A link to more details about the check
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details_url: Option<String>,
+    /// An identifier of the check in the rule's own records
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<CheckRunOutput>,
+}
+
+/// Request to update a check run, e.g., to complete it. Fields that are not set are left unchanged.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct UpdateCheckRunRequest {
+    pub owner: String,
+    pub repo: String,
+    /// The ID returned when the check run was created
+    pub check_run_id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<CheckRunStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conclusion: Option<CheckRunConclusion>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details_url: Option<String>,
+    /// Replaces the check run's title, summary and text. Annotations are added to the existing ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<CheckRunOutput>,
+}
+
+/// A check run, as returned by GitHub
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CheckRun {
+    pub id: u64,
+    pub name: String,
+    pub head_sha: String,
+    pub status: CheckRunStatus,
+    pub conclusion: Option<CheckRunConclusion>,
+    pub html_url: Option<String>,
+}
+
+/// The state of a commit status
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CommitState {
+    Error,
+    Failure,
+    Pending,
+    Success,
+}
+
+/// Request to set a status on a commit
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CreateCommitStatusRequest {
+    pub owner: String,
+    pub repo: String,
+    /// The SHA of the commit
+    pub sha: String,
+    pub state: CommitState,
+    /// A link to more details about the status
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_url: Option<String>,
+    /// A short description of the status
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Identifies the status among the commit's statuses. This is what branch protection
+    /// rules require. GitHub uses `default` if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+}
+
+/// Create a check run on a commit. Check runs can only be created by GitHub Apps, so
+/// `client_id` must select a client configured with App authentication.
+///
+/// For more details, see https://docs.github.com/en/rest/checks/runs?apiVersion=2022-11-28#create-a-check-run
+///
+/// ## Arguments
+/// * `client_id` - Selects which configured GitHub client to use (supports multiple clients).
+/// * `request` - The check run to create.
+///
+/// ## Returns
+/// * `Ok(CheckRun)` with the created check run, whose `id` is needed to update it, or
+/// * `Err(PlaidFunctionError)` if the request fails.
+pub fn create_check_run(
+    client_id: impl Display,
+    request: CreateCheckRunRequest,
+) -> Result<CheckRun, PlaidFunctionError> {
+    extern "C" {
+        new_host_function_with_error_buffer!(github, create_check_run);
+    }
+
+    let wrapped = GithubApiWrapper {
+        client_id: client_id.to_string(),
+        params: request,
+    };
+
+    let request = serde_json::to_string(&wrapped).unwrap();
+
+    const RETURN_BUFFER_SIZE: usize = 64 * 1024; // 64 KiB
+    let mut return_buffer = vec![0; RETURN_BUFFER_SIZE];
+
+    let res = unsafe {
+        github_create_check_run(
+            request.as_ptr(),
+            request.len(),
+            return_buffer.as_mut_ptr(),
+            RETURN_BUFFER_SIZE,
+        )
+    };
+
+    // There was an error with the Plaid system. Maybe the API is not
+    // configured.
+    if res < 0 {
+        return Err(res.into());
+    }
+
+    return_buffer.truncate(res as usize);
+    // This should be safe because unless the Plaid runtime is expressly trying
+    // to mess with us, this came from a String in the API module.
+    let res = String::from_utf8(return_buffer).unwrap();
+
+    serde_json::from_str(&res).map_err(|_| PlaidFunctionError::InternalApiError)
+}
+
+/// Update a check run, e.g., to report its conclusion once the rule has finished checking.
+///
+/// For more details, see https://docs.github.com/en/rest/checks/runs?apiVersion=2022-11-28#update-a-check-run
+///
+/// ## Arguments
+/// * `client_id` - Selects which configured GitHub client to use (supports multiple clients).
+///   It must be the client that created the check run.
+/// * `request` - The check run to update and the changes to make.
+pub fn update_check_run(
+    client_id: impl Display,
+    request: UpdateCheckRunRequest,
+) -> Result<(), PlaidFunctionError> {
+    extern "C" {
+        new_host_function!(github, update_check_run);
+    }
+
+    let wrapped = GithubApiWrapper {
+        client_id: client_id.to_string(),
+        params: request,
+    };
+
+    let request = serde_json::to_string(&wrapped).unwrap();
+
+    let res = unsafe { github_update_check_run(request.as_ptr(), request.len()) };
+
+    // There was an error with the Plaid system. Maybe the API is not
+    // configured.
+    if res < 0 {
+        return Err(res.into());
+    }
+
+    Ok(())
+}
+
+/// Set a status on a commit.
+///
+/// For more details, see https://docs.github.com/en/rest/commits/statuses?apiVersion=2022-11-28#create-a-commit-status
+///
+/// ## Arguments
+/// * `client_id` - Selects which configured GitHub client to use (supports multiple clients).
+/// * `request` - The commit and the status to set on it.
+pub fn create_commit_status(
+    client_id: impl Display,
+    request: CreateCommitStatusRequest,
+) -> Result<(), PlaidFunctionError> {
+    extern "C" {
+        new_host_function!(github, create_commit_status);
+    }
+
+    let wrapped = GithubApiWrapper {
+        client_id: client_id.to_string(),
+        params: request,
+    };
+
+    let request = serde_json::to_string(&wrapped).unwrap();
+
+    let res = unsafe { github_create_commit_status(request.as_ptr(), request.len()) };
+
+    // There was an error with the Plaid system. Maybe the API is not
+    // configured.
+    if res < 0 {
+        return Err(res.into());
+    }
+
+    Ok(())
+}
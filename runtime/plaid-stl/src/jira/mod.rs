@@ -66,23 +66,87 @@ pub struct PostCommentRequest {
 }
 
 /// Request sent to the runtime to search for Jira issues
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Default)]
 pub struct SearchIssueRequest {
     pub jql: String,
     pub max_results: Option<u32>,
+    /// Fields to return for each issue, in addition to the id and key
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+    /// Continue a previous search from the `next_page_token` it returned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_page_token: Option<String>,
 }
 
-/// Represents a Jira issue with only the most basic fields (id and key)
+/// Represents a Jira issue with its id, key and any fields requested in the search
 #[derive(Serialize, Deserialize)]
 pub struct JiraIssue {
     pub id: String,
     pub key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fields: Option<Value>,
 }
 
 /// Response received from the runtime when searching for Jira issues
 #[derive(Serialize, Deserialize)]
 pub struct SearchIssueResponse {
     pub issues: Vec<JiraIssue>,
+    /// Set if the search stopped at `max_results` and there are more issues to fetch.
+    /// Pass it in the next request to continue the search.
+    #[serde(default)]
+    pub next_page_token: Option<String>,
+}
+
+/// A transition that can move a Jira issue to another status
+#[derive(Serialize, Deserialize)]
+pub struct JiraTransition {
+    pub id: String,
+    pub name: String,
+    /// The name of the status the issue moves to
+    pub to_status: Option<String>,
+}
+
+/// Response received from the runtime when fetching the transitions available for a Jira issue
+#[derive(Serialize, Deserialize)]
+pub struct GetTransitionsResponse {
+    pub transitions: Vec<JiraTransition>,
+}
+
+/// Request sent to the runtime to move a Jira issue through its workflow
+#[derive(Serialize, Deserialize)]
+pub struct TransitionIssueRequest {
+    pub issue_id: String,
+    pub transition_id: String,
+    /// Fields to set as part of the transition (e.g., a resolution), in the format expected by Jira
+    #[serde(default)]
+    pub fields: Option<Value>,
+    /// A comment to add to the issue as part of the transition
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+/// Request sent to the runtime to attach a file to a Jira issue
+#[derive(Serialize, Deserialize)]
+pub struct AddAttachmentRequest {
+    pub issue_id: String,
+    pub filename: String,
+    pub content: Vec<u8>,
+}
+
+/// Response received from the runtime when attaching a file to a Jira issue
+#[derive(Serialize, Deserialize)]
+pub struct AddAttachmentResponse {
+    pub id: String,
+    pub filename: String,
+}
+
+/// Request sent to the runtime to link two Jira issues
+#[derive(Serialize, Deserialize)]
+pub struct CreateIssueLinkRequest {
+    /// The name of the link type, e.g., "Blocks" or "Relates"
+    pub link_type: String,
+    pub inward_issue_id: String,
+    pub outward_issue_id: String,
 }
 
 // ==============================================================================================================
@@ -211,16 +275,25 @@ pub fn post_comment(payload: PostCommentRequest) -> Result<(), PlaidFunctionErro
 pub fn search_issues(
     jql: impl Display,
     max_results: Option<u32>,
+) -> Result<SearchIssueResponse, PlaidFunctionError> {
+    search_issues_with_options(SearchIssueRequest {
+        jql: jql.to_string(),
+        max_results,
+        ..Default::default()
+    })
+}
+
+/// Search for Jira issues, selecting the fields to return and continuing from a previous page.
+///
+/// If the search stops at `max_results` and there are more issues, the response's
+/// `next_page_token` can be set on the next request to fetch them.
+pub fn search_issues_with_options(
+    payload: SearchIssueRequest,
 ) -> Result<SearchIssueResponse, PlaidFunctionError> {
     extern "C" {
         new_host_function_with_error_buffer!(jira, search_issues);
     }
 
-    let payload = SearchIssueRequest {
-        jql: jql.to_string(),
-        max_results,
-    };
-
     let request = serde_json::to_string(&payload).unwrap();
 
     const RETURN_BUFFER_SIZE: usize = 5 * 1024 * 1024; // 5 MiB
@@ -242,3 +315,106 @@ pub fn search_issues(
     return_buffer.truncate(res as usize);
     Ok(serde_json::from_str(&String::from_utf8(return_buffer).unwrap()).unwrap())
 }
+
+/// Get the transitions that can currently be performed on a Jira issue
+pub fn get_transitions(
+    issue_id: impl Display,
+) -> Result<GetTransitionsResponse, PlaidFunctionError> {
+    extern "C" {
+        new_host_function_with_error_buffer!(jira, get_transitions);
+    }
+
+    let request = issue_id.to_string();
+
+    const RETURN_BUFFER_SIZE: usize = 64 * 1024; // 64 KiB
+    let mut return_buffer = vec![0; RETURN_BUFFER_SIZE];
+
+    let res = unsafe {
+        jira_get_transitions(
+            request.as_ptr(),
+            request.len(),
+            return_buffer.as_mut_ptr(),
+            RETURN_BUFFER_SIZE,
+        )
+    };
+
+    if res < 0 {
+        return Err(res.into());
+    }
+
+    return_buffer.truncate(res as usize);
+    Ok(serde_json::from_str(&String::from_utf8(return_buffer).unwrap()).unwrap())
+}
+
+/// Move a Jira issue through its workflow with one of the transitions from `get_transitions`
+pub fn transition_issue(payload: TransitionIssueRequest) -> Result<(), PlaidFunctionError> {
+    extern "C" {
+        new_host_function!(jira, transition_issue);
+    }
+
+    let request = serde_json::to_string(&payload).unwrap();
+    let res = unsafe { jira_transition_issue(request.as_ptr(), request.len()) };
+
+    match res {
+        0 => Ok(()),
+        x => Err(x.into()),
+    }
+}
+
+/// Attach a file to a Jira issue
+///
+/// Args:
+/// - `issue_id`: The issue to attach the file to
+/// - `filename`: The name the attachment will have in Jira
+/// - `content`: The raw file contents
+pub fn add_attachment(
+    issue_id: impl Display,
+    filename: impl Display,
+    content: impl Into<Vec<u8>>,
+) -> Result<AddAttachmentResponse, PlaidFunctionError> {
+    extern "C" {
+        new_host_function_with_error_buffer!(jira, add_attachment);
+    }
+
+    let payload = AddAttachmentRequest {
+        issue_id: issue_id.to_string(),
+        filename: filename.to_string(),
+        content: content.into(),
+    };
+
+    let request = serde_json::to_string(&payload).unwrap();
+
+    const RETURN_BUFFER_SIZE: usize = 4 * 1024; // 4 KiB
+    let mut return_buffer = vec![0; RETURN_BUFFER_SIZE];
+
+    let res = unsafe {
+        jira_add_attachment(
+            request.as_ptr(),
+            request.len(),
+            return_buffer.as_mut_ptr(),
+            RETURN_BUFFER_SIZE,
+        )
+    };
+
+    if res < 0 {
+        return Err(res.into());
+    }
+
+    return_buffer.truncate(res as usize);
+    Ok(serde_json::from_str(&String::from_utf8(return_buffer).unwrap()).unwrap())
+}
+
+/// Link two Jira issues
+pub fn create_issue_link(payload: CreateIssueLinkRequest) -> Result<(), PlaidFunctionError> {
+    extern "C" {
+        new_host_function!(jira, create_issue_link);
+    }
+
+    let request = serde_json::to_string(&payload).unwrap();
+    let res = unsafe { jira_create_issue_link(request.as_ptr(), request.len()) };
+
+    match res {
+        0 => Ok(()),
+        x => Err(x.into()),
+    }
+}
@@ -21,24 +21,57 @@ impl super::CreateIssueRequest {
     }
 }
 
+/// Wrap plain text in the Atlassian Document Format Jira expects for comments
+fn text_to_document(text: &str) -> Value {
+    json!({
+      "type": "doc",
+      "version": 1,
+      "content": [
+        {
+          "type": "paragraph",
+          "content": [
+            {
+              "text": text,
+              "type": "text"
+            }
+          ]
+        }
+      ]
+    })
+}
+
 impl super::PostCommentRequest {
+    pub fn to_payload(&self) -> Value {
+        json!({ "body": text_to_document(&self.comment) })
+    }
+}
+
+impl super::TransitionIssueRequest {
+    pub fn to_payload(&self) -> Value {
+        let mut payload = json!({
+            "transition": { "id": self.transition_id },
+        });
+
+        if let Some(fields) = &self.fields {
+            payload["fields"] = fields.clone();
+        }
+
+        if let Some(comment) = &self.comment {
+            payload["update"] = json!({
+                "comment": [{ "add": { "body": text_to_document(comment) } }]
+            });
+        }
+
+        payload
+    }
+}
+
+impl super::CreateIssueLinkRequest {
     pub fn to_payload(&self) -> Value {
         json!({
-          "body": {
-            "type": "doc",
-            "version": 1,
-            "content": [
-              {
-                "type": "paragraph",
-                "content": [
-                  {
-                    "text": self.comment,
-                    "type": "text"
-                  }
-                ]
-              }
-            ]
-          }
+            "type": { "name": self.link_type },
+            "inwardIssue": { "key": self.inward_issue_id },
+            "outwardIssue": { "key": self.outward_issue_id },
         })
     }
 }
@@ -35,6 +35,8 @@ pub enum PlaidFunctionError {
     Unknown,
     FailedToLogBack,
     LogbackBudgetExhausted,
+    InvalidCounter,
+    LogBudgetExhausted,
 }
 
 impl Error for PlaidFunctionError {}
@@ -59,6 +61,8 @@ impl core::fmt::Display for PlaidFunctionError {
             PlaidFunctionError::Unknown => write!(f, "An unknown error occurred. This can happen if the Plaid runtime is newer than the STL this rule was compiled against."),
             PlaidFunctionError::FailedToLogBack => write!(f, "Failed to dispatch log message: the receiver is disconnected or at capacity"),
             PlaidFunctionError::LogbackBudgetExhausted => write!(f, "Logback budget exhausted"),
+            PlaidFunctionError::InvalidCounter => write!(f, "The stored value is not an integer, or adding to it would overflow"),
+            PlaidFunctionError::LogBudgetExhausted => write!(f, "The module has used up its log volume budget"),
         }
     }
 }
@@ -82,6 +86,8 @@ impl From<i32> for PlaidFunctionError {
             -14 => Self::TimeoutElapsed,
             -15 => Self::FailedToLogBack,
             -16 => Self::LogbackBudgetExhausted,
+            -17 => Self::InvalidCounter,
+            -18 => Self::LogBudgetExhausted,
             _ => Self::Unknown,
         }
     }
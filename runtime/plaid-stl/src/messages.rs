@@ -6,6 +6,11 @@ pub enum Generator {
     Okta,
     Interval(String),
     SQS(String),
+    Kafka(String),
+    PubSub(String),
+    Syslog(String),
+    FileTail(String),
+    HttpPoll(String),
     WebSocketExternal(String),
 }
 
@@ -16,6 +21,11 @@ impl std::fmt::Display for Generator {
             Generator::Okta => write!(f, "okta"),
             Generator::Interval(job) => write!(f, "interval/{job}"),
             Generator::SQS(name) => write!(f, "sqs/{name}"),
+            Generator::Kafka(name) => write!(f, "kafka/{name}"),
+            Generator::PubSub(name) => write!(f, "pubsub/{name}"),
+            Generator::Syslog(name) => write!(f, "syslog/{name}"),
+            Generator::FileTail(name) => write!(f, "file_tail/{name}"),
+            Generator::HttpPoll(name) => write!(f, "http_poll/{name}"),
             Generator::WebSocketExternal(ws) => write!(f, "websocket/{ws}"),
         }
     }
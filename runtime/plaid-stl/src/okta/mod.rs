@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
 
 use crate::PlaidFunctionError;
 
@@ -38,13 +38,85 @@ pub fn remove_user_from_group(user_id: &str, group_id: &str) -> Result<(), Plaid
         new_host_function!(okta, remove_user_from_group);
     }
 
-    let mut params: HashMap<&'static str, &str> = HashMap::new();
-    params.insert("user_id", user_id);
-    params.insert("group_id", group_id);
+    let request = GroupMembershipRequest {
+        user_id: user_id.to_owned(),
+        group_id: group_id.to_owned(),
+    };
+    lifecycle_operation(&request, okta_remove_user_from_group)
+}
+
+/// Request to add a user to, or remove a user from, an Okta group
+#[derive(Serialize, Deserialize)]
+pub struct GroupMembershipRequest {
+    pub user_id: String,
+    pub group_id: String,
+}
+
+/// Request for a lifecycle operation on a single Okta user
+#[derive(Serialize, Deserialize)]
+pub struct UserRequest {
+    pub user_id: String,
+}
+
+/// Request to clear all of a user's Okta sessions
+#[derive(Serialize, Deserialize)]
+pub struct ClearUserSessionsRequest {
+    pub user_id: String,
+    /// Also revoke the OAuth and OpenID Connect tokens issued to the user
+    #[serde(default)]
+    pub revoke_oauth_tokens: bool,
+}
+
+/// Request for one page of an Okta group's members
+#[derive(Serialize, Deserialize)]
+pub struct ListGroupMembersRequest {
+    pub group_id: String,
+    /// The maximum number of members to return. If not set, Okta's default is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    /// The cursor returned with the previous page, if this is not the first page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+}
 
-    let params = serde_json::to_string(&params).unwrap();
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupMemberProfile {
+    pub login: String,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub first_name: Option<String>,
+    #[serde(default)]
+    pub last_name: Option<String>,
+}
 
-    let res = unsafe { okta_remove_user_from_group(params.as_ptr(), params.len()) };
+/// A member of an Okta group
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GroupMember {
+    pub id: String,
+    /// The user's lifecycle status, e.g., `ACTIVE` or `SUSPENDED`
+    pub status: String,
+    pub profile: GroupMemberProfile,
+}
+
+/// One page of an Okta group's members
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ListGroupMembersResponse {
+    pub members: Vec<GroupMember>,
+    /// Pass this as `after` to get the next page. `None` if this is the last page.
+    pub next: Option<String>,
+}
+
+/// Send a request for a lifecycle operation that returns nothing on success
+fn lifecycle_operation(
+    request: &impl Serialize,
+    host_function: unsafe extern "C" fn(*const u8, usize) -> i32,
+) -> Result<(), PlaidFunctionError> {
+    let request =
+        serde_json::to_string(request).map_err(|_| PlaidFunctionError::ErrorCouldNotSerialize)?;
+
+    let res = unsafe { host_function(request.as_ptr(), request.len()) };
 
     // There was an error with the Plaid system. Maybe the API is not
     // configured.
@@ -57,3 +129,113 @@ pub fn remove_user_from_group(user_id: &str, group_id: &str) -> Result<(), Plaid
         _ => Err(PlaidFunctionError::InternalApiError),
     }
 }
+
+/// Add a user to an Okta group
+pub fn add_user_to_group(user_id: &str, group_id: &str) -> Result<(), PlaidFunctionError> {
+    extern "C" {
+        new_host_function!(okta, add_user_to_group);
+    }
+
+    let request = GroupMembershipRequest {
+        user_id: user_id.to_owned(),
+        group_id: group_id.to_owned(),
+    };
+    lifecycle_operation(&request, okta_add_user_to_group)
+}
+
+/// Suspend an Okta user. A suspended user can't sign in until they are unsuspended.
+pub fn suspend_user(user_id: &str) -> Result<(), PlaidFunctionError> {
+    extern "C" {
+        new_host_function!(okta, suspend_user);
+    }
+
+    let request = UserRequest {
+        user_id: user_id.to_owned(),
+    };
+    lifecycle_operation(&request, okta_suspend_user)
+}
+
+/// Unsuspend a suspended Okta user
+pub fn unsuspend_user(user_id: &str) -> Result<(), PlaidFunctionError> {
+    extern "C" {
+        new_host_function!(okta, unsuspend_user);
+    }
+
+    let request = UserRequest {
+        user_id: user_id.to_owned(),
+    };
+    lifecycle_operation(&request, okta_unsuspend_user)
+}
+
+/// Deactivate an Okta user. This removes the user from all their apps and can't be undone
+/// without reactivating the user.
+pub fn deactivate_user(user_id: &str) -> Result<(), PlaidFunctionError> {
+    extern "C" {
+        new_host_function!(okta, deactivate_user);
+    }
+
+    let request = UserRequest {
+        user_id: user_id.to_owned(),
+    };
+    lifecycle_operation(&request, okta_deactivate_user)
+}
+
+/// Clear all of an Okta user's sessions, optionally revoking their OAuth tokens too
+pub fn clear_user_sessions(
+    user_id: &str,
+    revoke_oauth_tokens: bool,
+) -> Result<(), PlaidFunctionError> {
+    extern "C" {
+        new_host_function!(okta, clear_user_sessions);
+    }
+
+    let request = ClearUserSessionsRequest {
+        user_id: user_id.to_owned(),
+        revoke_oauth_tokens,
+    };
+    lifecycle_operation(&request, okta_clear_user_sessions)
+}
+
+/// Reset all of an Okta user's enrolled MFA factors
+pub fn reset_user_factors(user_id: &str) -> Result<(), PlaidFunctionError> {
+    extern "C" {
+        new_host_function!(okta, reset_user_factors);
+    }
+
+    let request = UserRequest {
+        user_id: user_id.to_owned(),
+    };
+    lifecycle_operation(&request, okta_reset_user_factors)
+}
+
+/// Get one page of an Okta group's members. Pass the returned `next` cursor as `after`
+/// to get the following page.
+pub fn list_group_members(
+    request: &ListGroupMembersRequest,
+) -> Result<ListGroupMembersResponse, PlaidFunctionError> {
+    extern "C" {
+        new_host_function_with_error_buffer!(okta, list_group_members);
+    }
+
+    let request =
+        serde_json::to_string(request).map_err(|_| PlaidFunctionError::ErrorCouldNotSerialize)?;
+    let mut return_buffer = vec![0; RETURN_BUFFER_SIZE];
+
+    let res = unsafe {
+        okta_list_group_members(
+            request.as_ptr(),
+            request.len(),
+            return_buffer.as_mut_ptr(),
+            RETURN_BUFFER_SIZE,
+        )
+    };
+
+    // There was an error with the Plaid system. Maybe the API is not
+    // configured.
+    if res < 0 {
+        return Err(res.into());
+    }
+
+    return_buffer.truncate(res as usize);
+    serde_json::from_slice(&return_buffer).map_err(|_| PlaidFunctionError::InternalApiError)
+}
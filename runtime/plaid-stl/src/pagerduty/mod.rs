@@ -2,10 +2,53 @@ use serde::{Deserialize, Serialize};
 
 use crate::PlaidFunctionError;
 
-#[derive(Serialize)]
-struct TriggerRequest {
-    service: String,
-    description: String,
+/// How severe an incident is. PagerDuty uses it to decide how urgently responders are notified.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Critical,
+    #[default]
+    Error,
+    Warning,
+    Info,
+}
+
+/// A link shown on the incident in PagerDuty
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Link {
+    pub href: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+/// Request to trigger a PagerDuty incident
+#[derive(Serialize, Deserialize, Default)]
+pub struct TriggerIncidentRequest {
+    /// The service to trigger an incident for, as named in Plaid's configuration
+    pub service: String,
+    /// The summary of the incident
+    pub description: String,
+    #[serde(default)]
+    pub severity: Severity,
+    /// Identifies the incident. Triggering again with the same key adds an alert to the open
+    /// incident instead of creating a new one. The same key is needed to acknowledge or resolve
+    /// the incident, so set it if the rule will do that. If not set, PagerDuty generates one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dedup_key: Option<String>,
+    /// Any additional details about the incident
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom_details: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<Link>,
+}
+
+/// Request to acknowledge or resolve a PagerDuty incident
+#[derive(Serialize, Deserialize)]
+pub struct IncidentEventRequest {
+    /// The service the incident was triggered for
+    pub service: String,
+    /// The key the incident was triggered with
+    pub dedup_key: String,
 }
 
 pub enum TriggerIncidentResult {
@@ -45,20 +88,25 @@ pub fn trigger_incident(service: &str, description: &str) -> Result<(), i32> {
 pub fn trigger_incident_detailed(
     service: &str,
     description: &str,
+) -> Result<TriggerIncidentResult, PlaidFunctionError> {
+    trigger_incident_with_options(&TriggerIncidentRequest {
+        service: service.to_owned(),
+        description: description.to_owned(),
+        ..Default::default()
+    })
+}
+
+/// Trigger a PagerDuty incident with a severity, dedup key, custom details and links
+pub fn trigger_incident_with_options(
+    request: &TriggerIncidentRequest,
 ) -> Result<TriggerIncidentResult, PlaidFunctionError> {
     extern "C" {
         // Trigger a PagerDuty incident for a given service
         new_host_function!(pagerduty, trigger_incident);
     }
 
-    let request = TriggerRequest {
-        service: service.to_owned(),
-        description: description.to_owned(),
-    };
-
-    // There shouldn't be anyway for this to fail because none of the
-    // unserializable types are possible here
-    let request = serde_json::to_string(&request).unwrap();
+    let request =
+        serde_json::to_string(request).map_err(|_| PlaidFunctionError::ErrorCouldNotSerialize)?;
     let res = unsafe { pagerduty_trigger_incident(request.as_ptr(), request.len()) };
 
     // There was an error with the Plaid system. Maybe the API is not
@@ -69,7 +117,7 @@ pub fn trigger_incident_detailed(
 
     match res {
         0 => Ok(TriggerIncidentResult::Success),
-        // This should never happen because the STL handles creating the request
+        // The dedup key was empty or too long
         1 => Ok(TriggerIncidentResult::BadRequest),
         2 => Ok(TriggerIncidentResult::UnknownService),
         3 => Ok(TriggerIncidentResult::TriggerFailed),
@@ -77,6 +125,90 @@ pub fn trigger_incident_detailed(
     }
 }
 
+pub enum IncidentEventResult {
+    Success,
+    BadRequest,
+    UnknownService,
+    EventFailed,
+    Unknown(u32),
+}
+
+impl core::fmt::Display for IncidentEventResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IncidentEventResult::Success => write!(f, "Event was accepted"),
+            IncidentEventResult::BadRequest => write!(f, "Request was not encoded correctly"),
+            IncidentEventResult::UnknownService => write!(f, "Requested service is unknown"),
+            IncidentEventResult::EventFailed => {
+                write!(f, "PagerDuty returned a failure when the event was sent")
+            }
+            IncidentEventResult::Unknown(x) => {
+                write!(f, "Plaid gave an unknown status code from PagerDuty: {x}")
+            }
+        }
+    }
+}
+
+impl From<i32> for IncidentEventResult {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => IncidentEventResult::Success,
+            1 => IncidentEventResult::BadRequest,
+            2 => IncidentEventResult::UnknownService,
+            3 => IncidentEventResult::EventFailed,
+            n => IncidentEventResult::Unknown(n as u32),
+        }
+    }
+}
+
+/// Acknowledge the incident that was triggered for `service` with `dedup_key`.
+/// This stops responders from being notified while they work on it.
+pub fn acknowledge_incident(
+    service: &str,
+    dedup_key: &str,
+) -> Result<IncidentEventResult, PlaidFunctionError> {
+    extern "C" {
+        new_host_function!(pagerduty, acknowledge_incident);
+    }
+
+    let request = IncidentEventRequest {
+        service: service.to_owned(),
+        dedup_key: dedup_key.to_owned(),
+    };
+    let request =
+        serde_json::to_string(&request).map_err(|_| PlaidFunctionError::ErrorCouldNotSerialize)?;
+    let res = unsafe { pagerduty_acknowledge_incident(request.as_ptr(), request.len()) };
+
+    if res < 0 {
+        return Err(res.into());
+    }
+    Ok(res.into())
+}
+
+/// Resolve the incident that was triggered for `service` with `dedup_key`,
+/// e.g., once the condition that triggered it has cleared
+pub fn resolve_incident(
+    service: &str,
+    dedup_key: &str,
+) -> Result<IncidentEventResult, PlaidFunctionError> {
+    extern "C" {
+        new_host_function!(pagerduty, resolve_incident);
+    }
+
+    let request = IncidentEventRequest {
+        service: service.to_owned(),
+        dedup_key: dedup_key.to_owned(),
+    };
+    let request =
+        serde_json::to_string(&request).map_err(|_| PlaidFunctionError::ErrorCouldNotSerialize)?;
+    let res = unsafe { pagerduty_resolve_incident(request.as_ptr(), request.len()) };
+
+    if res < 0 {
+        return Err(res.into());
+    }
+    Ok(res.into())
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct GetIncidentAlertsRequest {
     pub incident_id: String,
@@ -0,0 +1,81 @@
+//! Leveled, structured logs from a module. Unlike `print_debug_string`, these are sent
+//! to every configured logging backend, tagged with the module, message and log type
+//! that produced them.
+//!
+//! ```ignore
+//! plaid::log::warn("Repository made public", &[("repo", &repo), ("actor", &actor)])?;
+//! ```
+//!
+//! Each module can only log so many bytes per minute. Logs over the budget are dropped
+//! and the call returns `PlaidFunctionError::LogBudgetExhausted`.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::PlaidFunctionError;
+
+/// How important a log is
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+/// A log written by a module
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ModuleLog {
+    pub level: Level,
+    pub message: String,
+    /// Key-value pairs giving context to the message
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+}
+
+/// Write a log at the given level, with key-value fields giving it context
+pub fn log(level: Level, message: &str, fields: &[(&str, &str)]) -> Result<(), PlaidFunctionError> {
+    extern "C" {
+        /// Send a structured log to the logging system
+        fn log_module_message(log: *const u8, log_len: usize) -> i32;
+    }
+
+    let log = ModuleLog {
+        level,
+        message: message.to_string(),
+        fields: fields
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+    };
+    let log = serde_json::to_string(&log).map_err(|_| PlaidFunctionError::InternalApiError)?;
+
+    let code = unsafe { log_module_message(log.as_ptr(), log.len()) };
+    if code == 0 {
+        Ok(())
+    } else {
+        Err(code.into())
+    }
+}
+
+/// Write a debug log
+pub fn debug(message: &str, fields: &[(&str, &str)]) -> Result<(), PlaidFunctionError> {
+    log(Level::Debug, message, fields)
+}
+
+/// Write an info log
+pub fn info(message: &str, fields: &[(&str, &str)]) -> Result<(), PlaidFunctionError> {
+    log(Level::Info, message, fields)
+}
+
+/// Write a warning log
+pub fn warn(message: &str, fields: &[(&str, &str)]) -> Result<(), PlaidFunctionError> {
+    log(Level::Warn, message, fields)
+}
+
+/// Write an error log
+pub fn error(message: &str, fields: &[(&str, &str)]) -> Result<(), PlaidFunctionError> {
+    log(Level::Error, message, fields)
+}
@@ -1,6 +1,7 @@
 use crate::PlaidFunctionError;
 
 pub mod cache;
+pub mod log;
 pub mod random;
 pub mod storage;
 
@@ -53,6 +53,58 @@ pub fn insert(key: &str, value: &[u8]) -> Result<Vec<u8>, PlaidFunctionError> {
     }
 }
 
+/// Store `value` at `key` in this rule's namespace and have it deleted after `ttl` seconds.
+///
+/// The key is absent as soon as it expires. Inserting the key again with [`insert`] keeps it
+/// until it is deleted.
+///
+/// Returns the **previous** value at `key`, not the value that was written. If the key is new,
+/// returns an empty vector.
+pub fn insert_with_ttl(key: &str, value: &[u8], ttl: u32) -> Result<Vec<u8>, PlaidFunctionError> {
+    extern "C" {
+        fn storage_insert_with_ttl(
+            key: *const u8,
+            key_len: usize,
+            value: *const u8,
+            value_len: usize,
+            ttl: u32,
+            data: *const u8,
+            data_len: usize,
+        ) -> i32;
+
+        fn storage_get(key: *const u8, key_len: usize, data: *const u8, data_len: usize) -> i32;
+    }
+
+    let key_bytes = key.as_bytes().to_vec();
+
+    let buffer_size =
+        unsafe { storage_get(key_bytes.as_ptr(), key_bytes.len(), vec![].as_mut_ptr(), 0) };
+
+    if buffer_size < 0 {
+        return Err(buffer_size.into());
+    }
+
+    let mut data_buffer = vec![0; buffer_size as usize];
+    let copied_size = unsafe {
+        storage_insert_with_ttl(
+            key_bytes.as_ptr(),
+            key_bytes.len(),
+            value.as_ptr(),
+            value.len(),
+            ttl,
+            data_buffer.as_mut_ptr(),
+            buffer_size as usize,
+        )
+    };
+
+    if copied_size == buffer_size {
+        data_buffer.truncate(copied_size as usize);
+        Ok(data_buffer)
+    } else {
+        Err(PlaidFunctionError::ReturnBufferTooSmall)
+    }
+}
+
 /// Store `value` at `key` in a shared namespace.
 ///
 /// Returns the **previous** value at `key`, not the value that was written. If the key is new,
@@ -410,3 +462,231 @@ pub fn delete_shared(namespace: &str, key: &str) -> Result<Vec<u8>, PlaidFunctio
         Err(PlaidFunctionError::ReturnBufferTooSmall)
     }
 }
+
+/// Set `key` in this rule's namespace to `value`, but only if it currently holds `expected`.
+///
+/// Returns whether the value was set. Use this instead of [`get`] followed by [`insert`] when
+/// other executions of the rule may write the same key at the same time.
+pub fn compare_and_swap(
+    key: &str,
+    expected: &[u8],
+    value: &[u8],
+) -> Result<bool, PlaidFunctionError> {
+    extern "C" {
+        fn storage_compare_and_swap(
+            key: *const u8,
+            key_len: usize,
+            expected: *const u8,
+            expected_len: usize,
+            value: *const u8,
+            value_len: usize,
+        ) -> i32;
+    }
+
+    let key_bytes = key.as_bytes().to_vec();
+
+    let code = unsafe {
+        storage_compare_and_swap(
+            key_bytes.as_ptr(),
+            key_bytes.len(),
+            expected.as_ptr(),
+            expected.len(),
+            value.as_ptr(),
+            value.len(),
+        )
+    };
+
+    if code < 0 {
+        return Err(code.into());
+    }
+    Ok(code == 1)
+}
+
+/// Set `key` in a shared namespace to `value`, but only if it currently holds `expected`.
+///
+/// Returns whether the value was set. The rule must have read-write access to `namespace` in
+/// Plaid's configuration.
+pub fn compare_and_swap_shared(
+    namespace: &str,
+    key: &str,
+    expected: &[u8],
+    value: &[u8],
+) -> Result<bool, PlaidFunctionError> {
+    extern "C" {
+        fn storage_compare_and_swap_shared(
+            namespace: *const u8,
+            namespace_len: usize,
+            key: *const u8,
+            key_len: usize,
+            expected: *const u8,
+            expected_len: usize,
+            value: *const u8,
+            value_len: usize,
+        ) -> i32;
+    }
+
+    let namespace_bytes = namespace.as_bytes().to_vec();
+    let key_bytes = key.as_bytes().to_vec();
+
+    let code = unsafe {
+        storage_compare_and_swap_shared(
+            namespace_bytes.as_ptr(),
+            namespace_bytes.len(),
+            key_bytes.as_ptr(),
+            key_bytes.len(),
+            expected.as_ptr(),
+            expected.len(),
+            value.as_ptr(),
+            value.len(),
+        )
+    };
+
+    if code < 0 {
+        return Err(code.into());
+    }
+    Ok(code == 1)
+}
+
+/// Store `value` at `key` in this rule's namespace, but only if the key is not set.
+///
+/// Returns whether the value was stored. This is useful for deduplication: only the first
+/// execution to insert a key gets `true`.
+pub fn insert_if_absent(key: &str, value: &[u8]) -> Result<bool, PlaidFunctionError> {
+    extern "C" {
+        fn storage_insert_if_absent(
+            key: *const u8,
+            key_len: usize,
+            value: *const u8,
+            value_len: usize,
+        ) -> i32;
+    }
+
+    let key_bytes = key.as_bytes().to_vec();
+
+    let code = unsafe {
+        storage_insert_if_absent(
+            key_bytes.as_ptr(),
+            key_bytes.len(),
+            value.as_ptr(),
+            value.len(),
+        )
+    };
+
+    if code < 0 {
+        return Err(code.into());
+    }
+    Ok(code == 1)
+}
+
+/// Store `value` at `key` in a shared namespace, but only if the key is not set.
+///
+/// Returns whether the value was stored. The rule must have read-write access to `namespace`
+/// in Plaid's configuration.
+pub fn insert_if_absent_shared(
+    namespace: &str,
+    key: &str,
+    value: &[u8],
+) -> Result<bool, PlaidFunctionError> {
+    extern "C" {
+        fn storage_insert_if_absent_shared(
+            namespace: *const u8,
+            namespace_len: usize,
+            key: *const u8,
+            key_len: usize,
+            value: *const u8,
+            value_len: usize,
+        ) -> i32;
+    }
+
+    let namespace_bytes = namespace.as_bytes().to_vec();
+    let key_bytes = key.as_bytes().to_vec();
+
+    let code = unsafe {
+        storage_insert_if_absent_shared(
+            namespace_bytes.as_ptr(),
+            namespace_bytes.len(),
+            key_bytes.as_ptr(),
+            key_bytes.len(),
+            value.as_ptr(),
+            value.len(),
+        )
+    };
+
+    if code < 0 {
+        return Err(code.into());
+    }
+    Ok(code == 1)
+}
+
+/// Atomically add `delta` to the counter at `key` in this rule's namespace.
+///
+/// A counter that is not set starts at 0. Counters are stored as integers in decimal, so they
+/// can also be read with [`get`]. Returns the new value of the counter.
+pub fn increment(key: &str, delta: i64) -> Result<i64, PlaidFunctionError> {
+    extern "C" {
+        fn storage_increment(
+            key: *const u8,
+            key_len: usize,
+            delta: i64,
+            data: *const u8,
+            data_len: usize,
+        ) -> i32;
+    }
+
+    let key_bytes = key.as_bytes().to_vec();
+
+    let mut data_buffer = [0u8; 8];
+    let copied_size = unsafe {
+        storage_increment(
+            key_bytes.as_ptr(),
+            key_bytes.len(),
+            delta,
+            data_buffer.as_mut_ptr(),
+            data_buffer.len(),
+        )
+    };
+
+    if copied_size < 0 {
+        return Err(copied_size.into());
+    }
+    Ok(i64::from_le_bytes(data_buffer))
+}
+
+/// Atomically add `delta` to the counter at `key` in a shared namespace.
+///
+/// A counter that is not set starts at 0. Returns the new value of the counter. The rule must
+/// have read-write access to `namespace` in Plaid's configuration.
+pub fn increment_shared(namespace: &str, key: &str, delta: i64) -> Result<i64, PlaidFunctionError> {
+    extern "C" {
+        fn storage_increment_shared(
+            namespace: *const u8,
+            namespace_len: usize,
+            key: *const u8,
+            key_len: usize,
+            delta: i64,
+            data: *const u8,
+            data_len: usize,
+        ) -> i32;
+    }
+
+    let namespace_bytes = namespace.as_bytes().to_vec();
+    let key_bytes = key.as_bytes().to_vec();
+
+    let mut data_buffer = [0u8; 8];
+    let copied_size = unsafe {
+        storage_increment_shared(
+            namespace_bytes.as_ptr(),
+            namespace_bytes.len(),
+            key_bytes.as_ptr(),
+            key_bytes.len(),
+            delta,
+            data_buffer.as_mut_ptr(),
+            data_buffer.len(),
+        )
+    };
+
+    if copied_size < 0 {
+        return Err(copied_size.into());
+    }
+    Ok(i64::from_le_bytes(data_buffer))
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A payload Slack sends to an app's interactivity URL when a user interacts with it,
+/// e.g., by clicking a button or submitting a modal.
+///
+/// Slack signs these requests: configure the webhook receiving them with
+/// `verification.scheme = "slack"` so that unsigned requests never reach the rule.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InteractivePayload {
+    /// A user interacted with a block element, e.g., a button or a select menu
+    BlockActions(BlockActionsPayload),
+    /// A user submitted a modal
+    ViewSubmission(ViewPayload),
+    /// A user closed a modal that was opened with `notify_on_close`
+    ViewClosed(ViewPayload),
+    /// A user triggered a global shortcut
+    Shortcut(ShortcutPayload),
+    /// A user triggered a message shortcut
+    MessageAction(ShortcutPayload),
+    /// Any interaction this type does not model yet
+    #[serde(other)]
+    Other,
+}
+
+/// The user who interacted with the app
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InteractiveUser {
+    pub id: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub team_id: Option<String>,
+}
+
+/// The workspace the interaction happened in
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InteractiveTeam {
+    pub id: String,
+    #[serde(default)]
+    pub domain: Option<String>,
+}
+
+/// The channel the interaction happened in
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InteractiveChannel {
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// An option picked in a select menu, radio group or checkboxes
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SelectedOption {
+    pub value: String,
+    #[serde(default)]
+    pub text: Option<Value>,
+}
+
+/// A single interaction with a block element
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockAction {
+    pub action_id: String,
+    pub block_id: String,
+    /// The type of element, e.g., `button` or `static_select`
+    #[serde(rename = "type")]
+    pub action_type: String,
+    /// The value of a button
+    #[serde(default)]
+    pub value: Option<String>,
+    #[serde(default)]
+    pub selected_option: Option<SelectedOption>,
+    #[serde(default)]
+    pub selected_options: Vec<SelectedOption>,
+    #[serde(default)]
+    pub selected_user: Option<String>,
+    #[serde(default)]
+    pub selected_channel: Option<String>,
+    #[serde(default)]
+    pub selected_conversation: Option<String>,
+    #[serde(default)]
+    pub selected_date: Option<String>,
+    #[serde(default)]
+    pub action_ts: Option<String>,
+}
+
+/// Payload for interactions with block elements in messages, modals or the home tab
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BlockActionsPayload {
+    pub user: InteractiveUser,
+    #[serde(default)]
+    pub team: Option<InteractiveTeam>,
+    /// Not set for interactions in modals or the home tab
+    #[serde(default)]
+    pub channel: Option<InteractiveChannel>,
+    /// The message containing the element, if it was in a message
+    #[serde(default)]
+    pub message: Option<Value>,
+    /// The view containing the element, if it was in a modal or the home tab
+    #[serde(default)]
+    pub view: Option<SlackView>,
+    /// Can be passed to `views_open` within 3 seconds of the interaction
+    #[serde(default)]
+    pub trigger_id: Option<String>,
+    /// Can be used to reply to the interaction for up to 30 minutes
+    #[serde(default)]
+    pub response_url: Option<String>,
+    pub actions: Vec<BlockAction>,
+}
+
+impl BlockActionsPayload {
+    /// Get the action taken on the element with `action_id`, if any
+    pub fn action(&self, action_id: &str) -> Option<&BlockAction> {
+        self.actions.iter().find(|a| a.action_id == action_id)
+    }
+
+    /// The timestamp of the message containing the element, if it was in a message
+    pub fn message_ts(&self) -> Option<&str> {
+        self.message.as_ref()?.get("ts")?.as_str()
+    }
+}
+
+/// The state of the inputs in a view, keyed by block ID and then by action ID
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ViewState {
+    #[serde(default)]
+    pub values: HashMap<String, HashMap<String, Value>>,
+}
+
+/// A modal or home tab
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SlackView {
+    pub id: String,
+    #[serde(default)]
+    pub callback_id: Option<String>,
+    /// Data the rule stored in the view when it opened it
+    #[serde(default)]
+    pub private_metadata: Option<String>,
+    #[serde(default)]
+    pub state: ViewState,
+}
+
+impl SlackView {
+    /// Get the state of the input with `action_id` in the block with `block_id`
+    pub fn input(&self, block_id: &str, action_id: &str) -> Option<&Value> {
+        self.state.values.get(block_id)?.get(action_id)
+    }
+
+    /// Get the value typed into the plain text input with `action_id` in the block with `block_id`
+    pub fn text_input(&self, block_id: &str, action_id: &str) -> Option<&str> {
+        self.input(block_id, action_id)?.get("value")?.as_str()
+    }
+}
+
+/// Payload for a submitted or closed modal
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ViewPayload {
+    pub user: InteractiveUser,
+    #[serde(default)]
+    pub team: Option<InteractiveTeam>,
+    pub view: SlackView,
+    #[serde(default)]
+    pub trigger_id: Option<String>,
+}
+
+/// Payload for a global or message shortcut
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ShortcutPayload {
+    pub callback_id: String,
+    pub user: InteractiveUser,
+    #[serde(default)]
+    pub team: Option<InteractiveTeam>,
+    pub trigger_id: String,
+    /// Only set for message shortcuts
+    #[serde(default)]
+    pub channel: Option<InteractiveChannel>,
+    /// The message the shortcut was triggered on. Only set for message shortcuts.
+    #[serde(default)]
+    pub message: Option<Value>,
+    #[serde(default)]
+    pub response_url: Option<String>,
+}
+
+/// Reasons a webhook body could not be parsed as an interactive payload
+#[derive(Debug)]
+pub enum InteractivePayloadError {
+    /// The body is not UTF-8 or not correctly percent-encoded
+    BadEncoding,
+    /// The body has no `payload` field
+    MissingPayload,
+    /// The `payload` field is not a valid interactive payload
+    BadPayload(String),
+}
+
+impl std::fmt::Display for InteractivePayloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadEncoding => write!(f, "The body is not a correctly encoded form"),
+            Self::MissingPayload => write!(f, "The body has no payload field"),
+            Self::BadPayload(e) => write!(f, "The payload could not be parsed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for InteractivePayloadError {}
+
+/// Parse the body of a request Slack sent to the interactivity URL. Slack sends the payload
+/// as JSON in the `payload` field of a `application/x-www-form-urlencoded` body.
+pub fn parse_interactive_payload(
+    body: &[u8],
+) -> Result<InteractivePayload, InteractivePayloadError> {
+    let body = std::str::from_utf8(body).map_err(|_| InteractivePayloadError::BadEncoding)?;
+
+    let payload = body
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == "payload")
+        .ok_or(InteractivePayloadError::MissingPayload)?
+        .1;
+    let payload = form_decode(payload).ok_or(InteractivePayloadError::BadEncoding)?;
+
+    serde_json::from_str(&payload).map_err(|e| InteractivePayloadError::BadPayload(e.to_string()))
+}
+
+/// Decode a value from an `application/x-www-form-urlencoded` body
+fn form_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 2;
+            }
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+
+    String::from_utf8(decoded).ok()
+}
@@ -1,9 +1,13 @@
+mod interactive;
+
 use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
 use crate::PlaidFunctionError;
 
+pub use interactive::*;
+
 #[derive(Serialize)]
 pub struct SlackMessage {
     channel: String,
@@ -928,3 +932,308 @@ pub fn remove_from_channel(bot: &str, channel: &str, user: &str) -> Result<(), P
 
     Ok(())
 }
+
+/// Data to be sent to the runtime for uploading a file
+#[derive(Serialize, Deserialize)]
+pub struct UploadFile {
+    pub bot: String,
+    /// ID of the channel to share the file in
+    pub channel: String,
+    pub filename: String,
+    pub content: Vec<u8>,
+    #[serde(default)]
+    pub title: Option<String>,
+    /// A message to post with the file
+    #[serde(default)]
+    pub initial_comment: Option<String>,
+    /// Share the file as a reply in this thread
+    #[serde(default)]
+    pub thread_ts: Option<String>,
+}
+
+/// A file uploaded to Slack
+#[derive(Serialize, Deserialize)]
+pub struct SlackFile {
+    pub id: String,
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+/// Response from Slack when uploading a file
+#[derive(Serialize, Deserialize)]
+pub struct UploadFileResponse {
+    pub ok: bool,
+    pub files: Vec<SlackFile>,
+}
+
+/// Upload a file and share it in a channel
+/// - `bot`: bot to use for uploading the file
+/// - `channel`: ID of the channel to share the file in
+/// - `filename`: name of the file
+/// - `content`: raw file contents
+/// - `initial_comment`: optional message to post with the file
+/// - `thread_ts`: optional timestamp of a message to share the file as a reply to
+pub fn upload_file(
+    bot: &str,
+    channel: &str,
+    filename: &str,
+    content: impl Into<Vec<u8>>,
+    initial_comment: Option<&str>,
+    thread_ts: Option<&str>,
+) -> Result<UploadFileResponse, PlaidFunctionError> {
+    extern "C" {
+        new_host_function_with_error_buffer!(slack, upload_file);
+    }
+    const RETURN_BUFFER_SIZE: usize = 32 * 1024; // 32 KiB
+    let mut return_buffer = vec![0; RETURN_BUFFER_SIZE];
+
+    let params = serde_json::to_string(&UploadFile {
+        bot: bot.to_string(),
+        channel: channel.to_string(),
+        filename: filename.to_string(),
+        content: content.into(),
+        title: None,
+        initial_comment: initial_comment.map(|c| c.to_string()),
+        thread_ts: thread_ts.map(|t| t.to_string()),
+    })
+    .unwrap();
+
+    let res = unsafe {
+        slack_upload_file(
+            params.as_ptr(),
+            params.len(),
+            return_buffer.as_mut_ptr(),
+            RETURN_BUFFER_SIZE,
+        )
+    };
+
+    if res < 0 {
+        return Err(res.into());
+    }
+
+    return_buffer.truncate(res as usize);
+    // This should be safe because unless the Plaid runtime is expressly trying
+    // to mess with us, this came from a String in the API module.
+    let res = String::from_utf8(return_buffer).unwrap();
+
+    serde_json::from_str(&res).map_err(|_| PlaidFunctionError::Unknown)
+}
+
+/// Data to be sent to the runtime for adding a reaction to a message
+#[derive(Serialize, Deserialize)]
+pub struct AddReaction {
+    pub bot: String,
+    /// ID of the channel containing the message
+    pub channel: String,
+    /// Timestamp of the message to react to
+    pub timestamp: String,
+    /// Name of the emoji, without colons
+    pub name: String,
+}
+
+impl AddReaction {
+    /// Serialize the body for the Slack API request
+    pub fn body(&self) -> Result<String, String> {
+        #[derive(Serialize)]
+        struct AddReactionBody<'a> {
+            channel: &'a str,
+            timestamp: &'a str,
+            name: &'a str,
+        }
+
+        let body = AddReactionBody {
+            channel: &self.channel,
+            timestamp: &self.timestamp,
+            name: &self.name,
+        };
+
+        serde_json::to_string(&body).map_err(|e| format!("Failed to serialize body: {}", e))
+    }
+}
+
+/// Add a reaction to a message
+/// - `bot`: bot to react with
+/// - `channel`: ID of the channel containing the message
+/// - `timestamp`: timestamp of the message to react to
+/// - `name`: name of the emoji, without colons (e.g., `white_check_mark`)
+pub fn add_reaction(
+    bot: &str,
+    channel: &str,
+    timestamp: &str,
+    name: &str,
+) -> Result<(), PlaidFunctionError> {
+    extern "C" {
+        new_host_function!(slack, add_reaction);
+    }
+
+    let params = serde_json::to_string(&AddReaction {
+        bot: bot.to_string(),
+        channel: channel.to_string(),
+        timestamp: timestamp.to_string(),
+        name: name.trim_matches(':').to_string(),
+    })
+    .unwrap();
+
+    let res = unsafe { slack_add_reaction(params.as_ptr(), params.len()) };
+
+    if res < 0 {
+        return Err(res.into());
+    }
+
+    Ok(())
+}
+
+/// Data to be sent to the runtime for getting the replies in a thread
+#[derive(Serialize, Deserialize)]
+pub struct GetThreadReplies {
+    pub bot: String,
+    pub channel: String,
+    /// Timestamp of the thread's parent message
+    pub ts: String,
+    /// Maximum number of messages to return. If not set, Slack's default is used.
+    #[serde(default)]
+    pub limit: Option<u32>,
+    /// The `next_cursor` from the previous page, if this is not the first page
+    #[serde(default)]
+    pub cursor: Option<String>,
+}
+
+/// Data to be sent to the runtime for getting the messages in a channel
+#[derive(Serialize, Deserialize)]
+pub struct GetChannelHistory {
+    pub bot: String,
+    pub channel: String,
+    /// Maximum number of messages to return. If not set, Slack's default is used.
+    #[serde(default)]
+    pub limit: Option<u32>,
+    /// The `next_cursor` from the previous page, if this is not the first page
+    #[serde(default)]
+    pub cursor: Option<String>,
+    /// Only return messages after this timestamp
+    #[serde(default)]
+    pub oldest: Option<String>,
+    /// Only return messages before this timestamp
+    #[serde(default)]
+    pub latest: Option<String>,
+}
+
+/// A message in a channel or thread
+#[derive(Serialize, Deserialize)]
+pub struct SlackHistoryMessage {
+    pub ts: String,
+    /// ID of the user who posted the message. Not set for some bot messages.
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub bot_id: Option<String>,
+    #[serde(default)]
+    pub text: String,
+    /// Set if the message is in a thread or is the parent of one
+    #[serde(default)]
+    pub thread_ts: Option<String>,
+    /// The number of replies, if the message is the parent of a thread
+    #[serde(default)]
+    pub reply_count: Option<u32>,
+    #[serde(default)]
+    pub subtype: Option<String>,
+}
+
+/// Pagination information returned by Slack
+#[derive(Serialize, Deserialize, Default)]
+pub struct ResponseMetadata {
+    #[serde(default)]
+    pub next_cursor: String,
+}
+
+/// Response from Slack with one page of the messages in a channel or thread
+#[derive(Serialize, Deserialize)]
+pub struct ConversationMessagesResponse {
+    pub ok: bool,
+    pub messages: Vec<SlackHistoryMessage>,
+    #[serde(default)]
+    pub has_more: bool,
+    #[serde(default)]
+    pub response_metadata: Option<ResponseMetadata>,
+}
+
+impl ConversationMessagesResponse {
+    /// The cursor to fetch the next page with, if there is one
+    pub fn next_cursor(&self) -> Option<&str> {
+        self.response_metadata
+            .as_ref()
+            .map(|m| m.next_cursor.as_str())
+            .filter(|c| !c.is_empty())
+    }
+}
+
+/// Get one page of the messages in a thread, starting with the parent message
+/// - `bot`: bot to use for reading the thread
+/// - `channel`: ID of the channel containing the thread
+/// - `ts`: timestamp of the thread's parent message
+/// - `limit`: maximum number of messages to return
+/// - `cursor`: cursor returned by the previous page
+pub fn get_thread_replies(
+    bot: &str,
+    channel: &str,
+    ts: &str,
+    limit: Option<u32>,
+    cursor: Option<&str>,
+) -> Result<ConversationMessagesResponse, PlaidFunctionError> {
+    extern "C" {
+        new_host_function_with_error_buffer!(slack, get_thread_replies);
+    }
+
+    let params = serde_json::to_string(&GetThreadReplies {
+        bot: bot.to_string(),
+        channel: channel.to_string(),
+        ts: ts.to_string(),
+        limit,
+        cursor: cursor.map(|c| c.to_string()),
+    })
+    .unwrap();
+
+    conversation_messages(&params, slack_get_thread_replies)
+}
+
+/// Get one page of the messages in a channel, newest first
+/// - `request`: the channel to read and which page of its history to return
+pub fn get_channel_history(
+    request: &GetChannelHistory,
+) -> Result<ConversationMessagesResponse, PlaidFunctionError> {
+    extern "C" {
+        new_host_function_with_error_buffer!(slack, get_channel_history);
+    }
+
+    let params = serde_json::to_string(request).unwrap();
+
+    conversation_messages(&params, slack_get_channel_history)
+}
+
+/// Call a host function that returns a page of messages
+fn conversation_messages(
+    params: &str,
+    host_function: unsafe extern "C" fn(*const u8, usize, *mut u8, usize) -> i32,
+) -> Result<ConversationMessagesResponse, PlaidFunctionError> {
+    const RETURN_BUFFER_SIZE: usize = 1024 * 1024; // 1 MiB
+    let mut return_buffer = vec![0; RETURN_BUFFER_SIZE];
+
+    let res = unsafe {
+        host_function(
+            params.as_ptr(),
+            params.len(),
+            return_buffer.as_mut_ptr(),
+            RETURN_BUFFER_SIZE,
+        )
+    };
+
+    if res < 0 {
+        return Err(res.into());
+    }
+
+    return_buffer.truncate(res as usize);
+    // This should be safe because unless the Plaid runtime is expressly trying
+    // to mess with us, this came from a String in the API module.
+    let res = String::from_utf8(return_buffer).unwrap();
+
+    serde_json::from_str(&res).map_err(|_| PlaidFunctionError::Unknown)
+}
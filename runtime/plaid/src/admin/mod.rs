@@ -0,0 +1,351 @@
+//! An authenticated HTTP API for inspecting and controlling a running Plaid instance.
+//!
+//! Every request must carry the configured token in an `Authorization: Bearer <token>` header.
+//!
+//! | Method | Path | Description |
+//! |--------|------|-------------|
+//! | GET | `/modules` | Loaded modules and their limits |
+//! | POST | `/modules/reload` | Reload changed modules from `module_dir` |
+//! | GET | `/queues` | Depth and capacity of every execution queue |
+//! | POST | `/queues/{name}/drain` | Discard every message waiting in a queue |
+//! | GET | `/log_types/paused` | Paused log types and how many messages are held for each |
+//! | POST | `/log_types/{log_type}/pause` | Hold messages for a log type instead of executing them |
+//! | POST | `/log_types/{log_type}/resume` | Queue the held messages and execute new ones again |
+//! | GET | `/logbacks` | Delayed logbacks waiting to be executed |
+//! | DELETE | `/logbacks/{id}` | Cancel a delayed logback |
+//! | GET | `/dead_letters` | Messages that ran out of retries |
+//! | POST | `/dead_letters/{id}/replay` | Run the failed module on a dead letter again |
+//! | DELETE | `/dead_letters/{id}` | Delete a dead letter |
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use ring::hmac;
+use serde::{Deserialize, Deserializer, Serialize};
+use warp::http::StatusCode;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+use crate::cache::Cache;
+use crate::data::DelayedLogPersister;
+use crate::executor::retry::DeadLetterQueue;
+use crate::executor::Executor;
+use crate::loader::{self, Configuration as LoaderConfiguration, LimitValue, PlaidModules};
+use crate::storage::Storage;
+
+/// Configuration for the admin API
+#[derive(Deserialize)]
+pub struct AdminConfiguration {
+    /// The address and port to listen on for admin requests
+    pub listen_address: SocketAddr,
+    /// The bearer token requests must present. This should be a `{plaid-secret{...}}`
+    /// reference so that the value lives in the secrets file.
+    #[serde(deserialize_with = "deserialize_token")]
+    pub token: String,
+}
+
+fn deserialize_token<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let token = String::deserialize(deserializer)?;
+    if token.trim().is_empty() {
+        return Err(serde::de::Error::custom("Admin token must not be empty"));
+    }
+    Ok(token)
+}
+
+/// Everything the admin API can inspect or act on
+pub struct AdminState {
+    pub modules: Arc<PlaidModules>,
+    pub executor: Arc<Executor>,
+    pub delayed_logs: DelayedLogPersister,
+    pub dead_letters: DeadLetterQueue,
+    pub loading_config: Arc<LoaderConfiguration>,
+    pub storage: Option<Arc<Storage>>,
+    pub cache: Option<Arc<Cache>>,
+}
+
+/// What the admin API reports about a loaded module
+#[derive(Serialize)]
+struct ModuleInfo {
+    name: String,
+    log_type: String,
+    computation_limit: u64,
+    page_limit: u32,
+    storage_used: u64,
+    storage_limit: LimitValue,
+    test_mode: bool,
+}
+
+/// The request did not present the admin token
+#[derive(Debug)]
+struct Unauthorized;
+
+impl warp::reject::Reject for Unauthorized {}
+
+/// Build the admin API's routes
+pub fn routes(
+    config: &AdminConfiguration,
+    state: Arc<AdminState>,
+) -> impl Filter<Extract = (impl Reply,), Error = Infallible> + Clone {
+    let token = Arc::new(config.token.clone());
+    let authorized = warp::header::optional::<String>("authorization")
+        .and_then(move |header: Option<String>| {
+            let token = token.clone();
+            async move {
+                if is_authorized(&token, header.as_deref()) {
+                    Ok(())
+                } else {
+                    Err(warp::reject::custom(Unauthorized))
+                }
+            }
+        })
+        .untuple_one();
+    let state = warp::any().map(move || state.clone());
+
+    let list_modules = warp::path!("modules")
+        .and(warp::get())
+        .and(state.clone())
+        .map(|state: Arc<AdminState>| json(&module_info(&state.modules), StatusCode::OK));
+
+    let reload_modules = warp::path!("modules" / "reload")
+        .and(warp::post())
+        .and(state.clone())
+        .then(|state: Arc<AdminState>| async move {
+            info!("Admin API: reloading modules");
+            loader::reload(
+                state.loading_config.clone(),
+                &state.modules,
+                state.storage.clone(),
+                state.cache.clone(),
+            )
+            .await;
+            json(&module_info(&state.modules), StatusCode::OK)
+        });
+
+    let list_queues = warp::path!("queues")
+        .and(warp::get())
+        .and(state.clone())
+        .map(|state: Arc<AdminState>| json(&state.executor.queue_status(), StatusCode::OK));
+
+    let drain_queue = warp::path!("queues" / String / "drain")
+        .and(warp::post())
+        .and(state.clone())
+        .map(
+            |name: String, state: Arc<AdminState>| match state.executor.drain_queue(&name) {
+                Some(drained) => {
+                    warn!("Admin API: drained {drained} messages from queue [{name}]");
+                    json(&HashMap::from([("drained", drained)]), StatusCode::OK)
+                }
+                None => StatusCode::NOT_FOUND.into_response(),
+            },
+        );
+
+    let list_paused = warp::path!("log_types" / "paused")
+        .and(warp::get())
+        .and(state.clone())
+        .map(|state: Arc<AdminState>| {
+            json(&state.executor.paused_log_types().list(), StatusCode::OK)
+        });
+
+    let pause = warp::path!("log_types" / String / "pause")
+        .and(warp::post())
+        .and(state.clone())
+        .map(|log_type: String, state: Arc<AdminState>| {
+            if state.executor.paused_log_types().pause(&log_type) {
+                warn!("Admin API: paused log type [{log_type}]");
+            }
+            StatusCode::NO_CONTENT.into_response()
+        });
+
+    let resume = warp::path!("log_types" / String / "resume")
+        .and(warp::post())
+        .and(state.clone())
+        .map(|log_type: String, state: Arc<AdminState>| {
+            match state.executor.resume_log_type(&log_type) {
+                Some((queued, dropped)) => {
+                    info!("Admin API: resumed log type [{log_type}] and queued {queued} held messages");
+                    json(
+                        &HashMap::from([("queued", queued), ("dropped", dropped)]),
+                        StatusCode::OK,
+                    )
+                }
+                None => StatusCode::NOT_FOUND.into_response(),
+            }
+        });
+
+    let list_logbacks = warp::path!("logbacks")
+        .and(warp::get())
+        .and(state.clone())
+        .then(|state: Arc<AdminState>| async move {
+            match state.delayed_logs.list_pending().await {
+                Ok(pending) => json(&pending, StatusCode::OK),
+                Err(e) => {
+                    error!("Admin API: failed to list delayed logbacks: {e}");
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                }
+            }
+        });
+
+    let cancel_logback = warp::path!("logbacks" / String)
+        .and(warp::delete())
+        .and(state.clone())
+        .then(|id: String, state: Arc<AdminState>| async move {
+            match state.delayed_logs.cancel(&id).await {
+                Ok(true) => {
+                    warn!("Admin API: cancelled delayed logback [{id}]");
+                    StatusCode::NO_CONTENT.into_response()
+                }
+                Ok(false) => StatusCode::NOT_FOUND.into_response(),
+                Err(e) => {
+                    error!("Admin API: failed to cancel delayed logback [{id}]: {e}");
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                }
+            }
+        });
+
+    let list_dead_letters = warp::path!("dead_letters")
+        .and(warp::get())
+        .and(state.clone())
+        .then(|state: Arc<AdminState>| async move {
+            match state.dead_letters.list().await {
+                Ok(letters) => json(&letters, StatusCode::OK),
+                Err(e) => {
+                    error!("Admin API: failed to list dead letters: {e}");
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                }
+            }
+        });
+
+    let replay_dead_letter = warp::path!("dead_letters" / String / "replay")
+        .and(warp::post())
+        .and(state.clone())
+        .then(|id: String, state: Arc<AdminState>| async move {
+            let mut letter = match state.dead_letters.take(&id).await {
+                Ok(Some(letter)) => letter,
+                Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+                Err(e) => {
+                    error!("Admin API: failed to take dead letter [{id}]: {e}");
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+            };
+
+            // The replay gets the full number of attempts again
+            letter.message.attempt = 0;
+            match state.executor.execute_webhook_message(letter.message) {
+                Ok(()) => {
+                    info!(
+                        "Admin API: replaying dead letter [{id}] on module [{}]",
+                        letter.module
+                    );
+                    StatusCode::NO_CONTENT.into_response()
+                }
+                Err(e) => {
+                    // Put the message back so it isn't lost
+                    letter.message = e.into_inner();
+                    if let Err(e) = state.dead_letters.park(letter).await {
+                        error!("Admin API: failed to return dead letter [{id}] to the queue: {e}");
+                    }
+                    StatusCode::TOO_MANY_REQUESTS.into_response()
+                }
+            }
+        });
+
+    let delete_dead_letter = warp::path!("dead_letters" / String)
+        .and(warp::delete())
+        .and(state)
+        .then(|id: String, state: Arc<AdminState>| async move {
+            match state.dead_letters.take(&id).await {
+                Ok(Some(_)) => {
+                    warn!("Admin API: deleted dead letter [{id}]");
+                    StatusCode::NO_CONTENT.into_response()
+                }
+                Ok(None) => StatusCode::NOT_FOUND.into_response(),
+                Err(e) => {
+                    error!("Admin API: failed to delete dead letter [{id}]: {e}");
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                }
+            }
+        });
+
+    authorized
+        .and(
+            list_modules
+                .or(reload_modules)
+                .unify()
+                .or(list_queues)
+                .unify()
+                .or(drain_queue)
+                .unify()
+                .or(list_paused)
+                .unify()
+                .or(pause)
+                .unify()
+                .or(resume)
+                .unify()
+                .or(list_logbacks)
+                .unify()
+                .or(cancel_logback)
+                .unify()
+                .or(list_dead_letters)
+                .unify()
+                .or(replay_dead_letter)
+                .unify()
+                .or(delete_dead_letter)
+                .unify(),
+        )
+        .recover(handle_rejection)
+        .unify()
+}
+
+/// Turn rejections into empty responses with a matching status code
+async fn handle_rejection(rejection: Rejection) -> Result<Response, Infallible> {
+    let status = if rejection.find::<Unauthorized>().is_some() {
+        StatusCode::UNAUTHORIZED
+    } else if rejection.is_not_found() {
+        StatusCode::NOT_FOUND
+    } else if rejection.find::<warp::reject::MethodNotAllowed>().is_some() {
+        StatusCode::METHOD_NOT_ALLOWED
+    } else {
+        StatusCode::BAD_REQUEST
+    };
+    Ok(status.into_response())
+}
+
+/// Check a request's `Authorization` header against the admin token in constant time
+fn is_authorized(token: &str, header: Option<&str>) -> bool {
+    let Some(presented) = header.and_then(|header| header.strip_prefix("Bearer ")) else {
+        return false;
+    };
+
+    // Comparing MACs of the two tokens keeps the comparison constant time even when
+    // their lengths differ
+    let key = hmac::Key::new(hmac::HMAC_SHA256, token.as_bytes());
+    let expected = hmac::sign(&key, token.as_bytes());
+    hmac::verify(&key, presented.as_bytes(), expected.as_ref()).is_ok()
+}
+
+fn module_info(modules: &PlaidModules) -> Vec<ModuleInfo> {
+    let mut info: Vec<ModuleInfo> = modules
+        .get_modules()
+        .into_values()
+        .map(|module| ModuleInfo {
+            name: module.name.clone(),
+            log_type: module.logtype.clone(),
+            computation_limit: module.computation_limit,
+            page_limit: module.page_limit,
+            storage_used: *module.storage_current.read().unwrap(),
+            storage_limit: module.storage_limit.clone(),
+            test_mode: module.test_mode,
+        })
+        .collect();
+    info.sort_by(|a, b| a.name.cmp(&b.name));
+    info
+}
+
+fn json<T: Serialize>(value: &T, status: StatusCode) -> Response {
+    warp::reply::with_status(warp::reply::json(value), status).into_response()
+}
@@ -353,6 +353,8 @@ pub mod tests {
             secrets: Default::default(),
             persistent_response: Default::default(),
             test_mode,
+            rate_limiter: None,
+            log_budget: None,
         })
     }
 
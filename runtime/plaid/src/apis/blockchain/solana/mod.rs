@@ -619,6 +619,8 @@ mod tests {
             secrets: Default::default(),
             persistent_response: Default::default(),
             test_mode: false,
+            rate_limiter: None,
+            log_budget: None,
         })
     }
 
@@ -501,6 +501,8 @@ mod tests {
             secrets: Default::default(),
             persistent_response: Default::default(),
             test_mode,
+            rate_limiter: None,
+            log_budget: None,
         })
     }
 
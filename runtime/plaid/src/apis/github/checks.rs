@@ -0,0 +1,203 @@
+use std::sync::Arc;
+
+use plaid_stl::github::{
+    CheckRun, CreateCheckRunRequest, CreateCommitStatusRequest, GithubApiWrapper,
+    UpdateCheckRunRequest,
+};
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{Authentication, Github};
+use crate::{
+    apis::{github::GitHubError, ApiError},
+    loader::PlaidModule,
+};
+
+/// GitHub rejects requests with more annotations than this
+const MAX_ANNOTATIONS_PER_REQUEST: usize = 50;
+
+/// GitHub rejects summaries and texts longer than this
+const MAX_OUTPUT_LENGTH: usize = 65535;
+
+/// Serialize a request to the body GitHub expects, leaving out the fields that are
+/// already in the address
+fn request_body<T: Serialize>(
+    params: &T,
+    address_fields: &[&str],
+) -> Result<Value, Box<ApiError>> {
+    let mut body = serde_json::to_value(params).map_err(|_| ApiError::ImpossibleError)?;
+    if let Some(body) = body.as_object_mut() {
+        for field in address_fields {
+            body.remove(*field);
+        }
+    }
+    Ok(body)
+}
+
+impl Github {
+    /// Check that a client is authenticated as a GitHub App, which is the only
+    /// kind of client that can create or update check runs
+    fn validate_app_client(&self, client_id: &str) -> Result<(), Box<ApiError>> {
+        let error = match self.config.authentication.get(client_id) {
+            Some(Authentication::App { .. }) => return Ok(()),
+            Some(_) => format!(
+                "Client [{client_id}] is not a GitHub App and cannot manage check runs"
+            ),
+            None => format!("Client ID not found: {client_id}"),
+        };
+        Err(Box::new(ApiError::GitHubError(GitHubError::InvalidInput(error))))
+    }
+
+    /// Check that a check run's output is within GitHub's limits
+    fn validate_check_run_output(
+        &self,
+        output: Option<&plaid_stl::github::CheckRunOutput>,
+    ) -> Result<(), Box<ApiError>> {
+        let Some(output) = output else {
+            return Ok(());
+        };
+
+        if output.annotations.len() > MAX_ANNOTATIONS_PER_REQUEST
+            || output.summary.len() > MAX_OUTPUT_LENGTH
+            || output
+                .text
+                .as_ref()
+                .is_some_and(|t| t.len() > MAX_OUTPUT_LENGTH)
+        {
+            return Err(Box::new(ApiError::BadRequest));
+        }
+
+        for annotation in &output.annotations {
+            self.validate_path(&annotation.path)?;
+            if self
+                .validate_contains_parent_directory_component(&annotation.path)
+                .is_ok()
+            {
+                return Err(Box::new(ApiError::BadRequest));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Create a check run on a commit
+    pub async fn create_check_run(
+        &self,
+        params: &str,
+        module: Arc<PlaidModule>,
+    ) -> Result<String, ApiError> {
+        let request: GithubApiWrapper<CreateCheckRunRequest> =
+            serde_json::from_str(params).map_err(|_| ApiError::BadRequest)?;
+
+        self.validate_app_client(&request.client_id)?;
+        let owner = self.validate_org(&request.params.owner)?;
+        let repo = self.validate_repository_name(&request.params.repo)?;
+        let head_sha = self.validate_commit_hash(&request.params.head_sha)?;
+        self.validate_check_run_output(request.params.output.as_ref())?;
+
+        info!(
+            "Creating check run [{}] on [{owner}/{repo}@{head_sha}] on behalf of {module}",
+            request.params.name
+        );
+
+        let address = format!("/repos/{owner}/{repo}/check-runs");
+        let body = request_body(&request.params, &["owner", "repo"])?;
+
+        match self
+            .make_generic_post_request(&request.client_id, address, body, module)
+            .await
+        {
+            Ok((status, Ok(body))) => {
+                if status == 201 {
+                    // Only return the fields the rule needs to follow up on the check run
+                    let check_run: CheckRun = serde_json::from_str(&body)
+                        .map_err(|_| ApiError::GitHubError(GitHubError::BadResponse))?;
+                    serde_json::to_string(&check_run).map_err(|_| ApiError::ImpossibleError)
+                } else {
+                    Err(ApiError::GitHubError(GitHubError::UnexpectedStatusCode(
+                        status,
+                    )))
+                }
+            }
+            Ok((_, Err(e))) => Err(e),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Update a check run
+    pub async fn update_check_run(
+        &self,
+        params: &str,
+        module: Arc<PlaidModule>,
+    ) -> Result<u32, ApiError> {
+        let request: GithubApiWrapper<UpdateCheckRunRequest> =
+            serde_json::from_str(params).map_err(|_| ApiError::BadRequest)?;
+
+        self.validate_app_client(&request.client_id)?;
+        let owner = self.validate_org(&request.params.owner)?;
+        let repo = self.validate_repository_name(&request.params.repo)?;
+        let check_run_id = request.params.check_run_id;
+        self.validate_check_run_output(request.params.output.as_ref())?;
+
+        info!("Updating check run [{check_run_id}] on [{owner}/{repo}] on behalf of {module}");
+
+        let address = format!("/repos/{owner}/{repo}/check-runs/{check_run_id}");
+        let body = request_body(&request.params, &["owner", "repo", "check_run_id"])?;
+
+        match self
+            .make_generic_patch_request(&request.client_id, address, Some(&body), module)
+            .await
+        {
+            Ok((status, Ok(_))) => {
+                if status == 200 {
+                    Ok(0)
+                } else {
+                    Err(ApiError::GitHubError(GitHubError::UnexpectedStatusCode(
+                        status,
+                    )))
+                }
+            }
+            Ok((_, Err(e))) => Err(e),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Set a status on a commit
+    pub async fn create_commit_status(
+        &self,
+        params: &str,
+        module: Arc<PlaidModule>,
+    ) -> Result<u32, ApiError> {
+        let request: GithubApiWrapper<CreateCommitStatusRequest> =
+            serde_json::from_str(params).map_err(|_| ApiError::BadRequest)?;
+
+        let owner = self.validate_org(&request.params.owner)?;
+        let repo = self.validate_repository_name(&request.params.repo)?;
+        let sha = self.validate_commit_hash(&request.params.sha)?;
+
+        info!(
+            "Setting commit status [{}] on [{owner}/{repo}@{sha}] on behalf of {module}",
+            request.params.context.as_deref().unwrap_or("default")
+        );
+
+        let address = format!("/repos/{owner}/{repo}/statuses/{sha}");
+        let body = request_body(&request.params, &["owner", "repo", "sha"])?;
+
+        match self
+            .make_generic_post_request(&request.client_id, address, body, module)
+            .await
+        {
+            Ok((status, Ok(_))) => {
+                if status == 201 {
+                    Ok(0)
+                } else {
+                    Err(ApiError::GitHubError(GitHubError::UnexpectedStatusCode(
+                        status,
+                    )))
+                }
+            }
+            Ok((_, Err(e))) => Err(e),
+            Err(e) => Err(e),
+        }
+    }
+}
@@ -1,4 +1,5 @@
 mod actions;
+mod checks;
 mod code;
 mod copilot;
 mod deploy_keys;
@@ -5,10 +5,12 @@ use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use http::{HeaderMap, HeaderValue};
 use plaid_stl::jira::{
-    CreateIssueRequest, CreateIssueResponse, GetIssueResponse, GetUserResponse, JiraIssue,
-    PostCommentRequest, SearchIssueRequest, SearchIssueResponse, UpdateIssueRequest,
+    AddAttachmentRequest, AddAttachmentResponse, CreateIssueLinkRequest, CreateIssueRequest,
+    CreateIssueResponse, GetIssueResponse, GetTransitionsResponse, GetUserResponse, JiraIssue,
+    JiraTransition, PostCommentRequest, SearchIssueRequest, SearchIssueResponse,
+    TransitionIssueRequest, UpdateIssueRequest,
 };
-use reqwest::Client;
+use reqwest::{multipart, Client};
 use serde::Deserialize;
 
 use crate::{apis::ApiError, loader::PlaidModule};
@@ -356,12 +358,21 @@ impl Jira {
         // Do we want to put guardrails on the JQL query, for example by forcing the rule to pass
         // a struct with a prescribed set of fields to filter on instead of a free-form JQL query?
 
+        for field in &request.fields {
+            self.validate_field_name(field)?;
+        }
+
+        let mut fields = vec!["id".to_string(), "key".to_string()];
+        fields.extend(request.fields.iter().cloned());
+
         // Percent-encode the JQL query to be passed as a query parameter in the URL
         let percent_encoded_jql = urlencoding::encode(&request.jql);
 
         let request_url = format!(
-            "{}/search/jql?fields=id,key&jql={}",
-            self.base_url, percent_encoded_jql
+            "{}/search/jql?fields={}&jql={}",
+            self.base_url,
+            fields.join(","),
+            percent_encoded_jql
         );
 
         info!(
@@ -399,16 +410,58 @@ impl Jira {
             }
         };
 
+        // Build the URL for a page, asking Jira for no more issues than we still need so a
+        // search that stops at `max_results` can be continued from its page token
+        let page_url = |next_page_token: Option<&str>, collected: usize| {
+            let mut url = request_url.clone();
+            if let Some(max_results) = request.max_results {
+                url.push_str(&format!(
+                    "&maxResults={}",
+                    (max_results as usize).saturating_sub(collected)
+                ));
+            }
+            if let Some(next_page_token) = next_page_token {
+                url.push_str(&format!(
+                    "&nextPageToken={}",
+                    urlencoding::encode(next_page_token)
+                ));
+            }
+            url
+        };
+
         let mut result = vec![];
+        let mut next_page_token = request.next_page_token.clone();
+        let mut skipped = 0;
+
         // First page
-        let mut current_page = fetch_page(request_url.clone()).await?;
+        let mut current_page = fetch_page(page_url(next_page_token.as_deref(), 0)).await?;
 
         loop {
-            result.extend(current_page.issues);
+            next_page_token = if current_page.is_last {
+                None
+            } else {
+                current_page.next_page_token.take()
+            };
+
+            for mut issue in current_page.issues {
+                // Only return issues in projects the module has access to
+                let project = issue.key.split("-").next().unwrap_or_default();
+                if self
+                    .validate_module_permission(&module.name, project)
+                    .is_err()
+                {
+                    skipped += 1;
+                    continue;
+                }
+
+                if request.fields.is_empty() {
+                    issue.fields = None;
+                }
+                result.push(issue);
+            }
 
             if let Some(max_results) = request.max_results {
                 if result.len() as u32 >= max_results {
-                    result.truncate(max_results as usize);
                     break;
                 }
             }
@@ -417,13 +470,11 @@ impl Jira {
                 break;
             }
 
-            match current_page.next_page_token {
-                Some(next_page_token) => {
-                    let url = format!("{request_url}&nextPageToken={next_page_token}");
+            match &next_page_token {
+                Some(token) => {
+                    info!("Fetching the next page of Jira search results with token [{token}]");
 
-                    info!("Fetching the next page of Jira search results with token [{next_page_token}]");
-
-                    current_page = fetch_page(url).await?;
+                    current_page = fetch_page(page_url(Some(token), result.len())).await?;
                 }
                 None => {
                     warn!("Jira search response indicated there are more pages, but no next page token was provided. Stopping pagination.");
@@ -432,9 +483,240 @@ impl Jira {
             }
         }
 
-        let output = SearchIssueResponse { issues: result };
+        if skipped > 0 {
+            warn!("Left {skipped} Jira issues out of the search results for [{module}] because it doesn't have access to their projects");
+        }
+
+        let output = SearchIssueResponse {
+            issues: result,
+            next_page_token,
+        };
 
         Ok(serde_json::to_string(&output)
             .map_err(|_| ApiError::JiraError(JiraError::InvalidResponse))?)
     }
+
+    /// Validate an issue ID and that the module is allowed to access the issue's project
+    fn validate_issue_access<'a>(
+        &self,
+        module: &str,
+        issue_id: &'a str,
+    ) -> Result<&'a str, Box<ApiError>> {
+        // Validate the request: verify the issue ID is in the form ABC...-1234..., get the project key and ensure the module can access it
+        let issue_id = self.validate_issue_id(issue_id)?;
+
+        // We are sure we can extract a project key because the string has passed validation
+        let project = issue_id.split("-").collect::<Vec<&str>>()[0];
+
+        self.validate_module_permission(module, project)?;
+
+        Ok(issue_id)
+    }
+
+    /// Get the transitions that can currently be performed on a Jira issue
+    pub async fn get_transitions(
+        &self,
+        params: &str,
+        module: Arc<PlaidModule>,
+    ) -> Result<String, ApiError> {
+        let issue_id = self.validate_issue_access(&module.name, params)?;
+
+        let url = format!("{}/issue/{issue_id}/transitions", self.base_url);
+
+        info!("Getting the transitions of Jira issue [{issue_id}] on behalf of [{module}]");
+
+        match self.client.get(url).send().await {
+            Ok(resp) => {
+                if resp.status() != 200 {
+                    let status = resp.status();
+                    let text = resp.text().await.unwrap_or_else(|_| "N/A".to_string());
+                    warn!("Jira returned {status}: {text}");
+                    return Err(ApiError::JiraError(JiraError::UnexpectedStatusCode(
+                        status.as_u16(),
+                    )));
+                }
+
+                // Internal structs used to deserialize the response from the REST API
+                #[derive(Deserialize)]
+                struct Status {
+                    name: String,
+                }
+
+                #[derive(Deserialize)]
+                struct Transition {
+                    id: String,
+                    name: String,
+                    to: Option<Status>,
+                }
+
+                #[derive(Deserialize)]
+                struct Transitions {
+                    transitions: Vec<Transition>,
+                }
+
+                let body: Transitions = resp
+                    .json()
+                    .await
+                    .map_err(|_| ApiError::JiraError(JiraError::InvalidResponse))?;
+
+                let res = GetTransitionsResponse {
+                    transitions: body
+                        .transitions
+                        .into_iter()
+                        .map(|t| JiraTransition {
+                            id: t.id,
+                            name: t.name,
+                            to_status: t.to.map(|s| s.name),
+                        })
+                        .collect(),
+                };
+
+                serde_json::to_string(&res)
+                    .map_err(|_| ApiError::JiraError(JiraError::InvalidResponse))
+            }
+            Err(e) => Err(ApiError::JiraError(JiraError::NetworkError(e))),
+        }
+    }
+
+    /// Move a Jira issue through its workflow
+    pub async fn transition_issue(
+        &self,
+        params: &str,
+        module: Arc<PlaidModule>,
+    ) -> Result<u32, ApiError> {
+        let request = serde_json::from_str::<TransitionIssueRequest>(params)
+            .map_err(|_| ApiError::BadRequest)?;
+
+        let issue_id = self.validate_issue_access(&module.name, &request.issue_id)?;
+        let transition_id = self.validate_transition_id(&request.transition_id)?;
+
+        let url = format!("{}/issue/{issue_id}/transitions", self.base_url);
+
+        let payload = request.to_payload();
+
+        info!("Performing transition [{transition_id}] on Jira issue [{issue_id}] on behalf of [{module}]");
+
+        match self.client.post(url).json(&payload).send().await {
+            Ok(resp) => {
+                if resp.status() != 204 {
+                    let status = resp.status();
+                    let text = resp.text().await.unwrap_or_else(|_| "N/A".to_string());
+                    warn!("Jira returned {status}: {text}");
+                    return Err(ApiError::JiraError(JiraError::UnexpectedStatusCode(
+                        status.as_u16(),
+                    )));
+                }
+
+                Ok(0)
+            }
+            Err(e) => Err(ApiError::JiraError(JiraError::NetworkError(e))),
+        }
+    }
+
+    /// Attach a file to a Jira issue
+    pub async fn add_attachment(
+        &self,
+        params: &str,
+        module: Arc<PlaidModule>,
+    ) -> Result<String, ApiError> {
+        let request = serde_json::from_str::<AddAttachmentRequest>(params)
+            .map_err(|_| ApiError::BadRequest)?;
+
+        let issue_id = self.validate_issue_access(&module.name, &request.issue_id)?;
+
+        if request.filename.is_empty() || request.filename.contains(['/', '\\']) {
+            warn!("Module [{module}] tried to attach a file with an invalid name to Jira issue [{issue_id}]");
+            return Err(ApiError::BadRequest);
+        }
+
+        let url = format!("{}/issue/{issue_id}/attachments", self.base_url);
+
+        info!(
+            "Attaching [{}] ({} bytes) to Jira issue [{issue_id}] on behalf of [{module}]",
+            request.filename,
+            request.content.len()
+        );
+
+        let part = multipart::Part::bytes(request.content)
+            .file_name(request.filename)
+            .mime_str("application/octet-stream")
+            .map_err(|_| ApiError::ImpossibleError)?;
+        let form = multipart::Form::new().part("file", part);
+
+        // Jira rejects attachment uploads without this header as a CSRF protection
+        match self
+            .client
+            .post(url)
+            .header("X-Atlassian-Token", "no-check")
+            .multipart(form)
+            .send()
+            .await
+        {
+            Ok(resp) => {
+                if resp.status() != 200 {
+                    let status = resp.status();
+                    let text = resp.text().await.unwrap_or_else(|_| "N/A".to_string());
+                    warn!("Jira returned {status}: {text}");
+                    return Err(ApiError::JiraError(JiraError::UnexpectedStatusCode(
+                        status.as_u16(),
+                    )));
+                }
+
+                // Jira returns a list with the single attachment we uploaded
+                let attachments: Vec<AddAttachmentResponse> = resp
+                    .json()
+                    .await
+                    .map_err(|_| ApiError::JiraError(JiraError::InvalidResponse))?;
+
+                let attachment = attachments
+                    .into_iter()
+                    .next()
+                    .ok_or(ApiError::JiraError(JiraError::InvalidResponse))?;
+
+                serde_json::to_string(&attachment)
+                    .map_err(|_| ApiError::JiraError(JiraError::InvalidResponse))
+            }
+            Err(e) => Err(ApiError::JiraError(JiraError::NetworkError(e))),
+        }
+    }
+
+    /// Link two Jira issues
+    pub async fn create_issue_link(
+        &self,
+        params: &str,
+        module: Arc<PlaidModule>,
+    ) -> Result<u32, ApiError> {
+        let request = serde_json::from_str::<CreateIssueLinkRequest>(params)
+            .map_err(|_| ApiError::BadRequest)?;
+
+        // The module must have access to the projects of both issues
+        let inward_issue_id = self.validate_issue_access(&module.name, &request.inward_issue_id)?;
+        let outward_issue_id =
+            self.validate_issue_access(&module.name, &request.outward_issue_id)?;
+
+        let url = format!("{}/issueLink", self.base_url);
+
+        let payload = request.to_payload();
+
+        info!(
+            "Linking Jira issues [{inward_issue_id}] and [{outward_issue_id}] with type [{}] on behalf of [{module}]",
+            request.link_type
+        );
+
+        match self.client.post(url).json(&payload).send().await {
+            Ok(resp) => {
+                if resp.status() != 201 {
+                    let status = resp.status();
+                    let text = resp.text().await.unwrap_or_else(|_| "N/A".to_string());
+                    warn!("Jira returned {status}: {text}");
+                    return Err(ApiError::JiraError(JiraError::UnexpectedStatusCode(
+                        status.as_u16(),
+                    )));
+                }
+
+                Ok(0)
+            }
+            Err(e) => Err(ApiError::JiraError(JiraError::NetworkError(e))),
+        }
+    }
 }
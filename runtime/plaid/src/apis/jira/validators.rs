@@ -51,8 +51,16 @@ pub fn create_validators() -> HashMap<&'static str, regex::Regex> {
     // It will accept MYPROJ-123, MY-PROJ-123, MY_PROJ-123.
     define_regex_validator!(validators, "issue_id", r"^[A-Za-z_-]{1,10}-\d{1,10}$");
 
+    // This regex checks that a string is a valid Jira field name, like summary or customfield_10010.
+    define_regex_validator!(validators, "field_name", r"^[A-Za-z0-9_.-]{1,64}$");
+
+    // This regex checks that a string is a valid Jira transition ID, which is always numeric.
+    define_regex_validator!(validators, "transition_id", r"^\d{1,10}$");
+
     validators
 }
 
 create_regex_validator_func!(email);
 create_regex_validator_func!(issue_id);
+create_regex_validator_func!(field_name);
+create_regex_validator_func!(transition_id);
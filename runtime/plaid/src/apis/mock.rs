@@ -0,0 +1,87 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::ApiError;
+
+/// A mocked response to an API host function call
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum MockResponse {
+    /// The call fails as if the API returned an error
+    Error { error: String },
+    /// The call succeeds. A string is returned to the module as is, any other
+    /// value is returned serialized as JSON.
+    Value(Value),
+}
+
+/// A host function call made by a module
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RecordedCall {
+    /// The name of the host function, e.g., `slack_post_message`
+    pub function: String,
+    /// The parameters the module passed
+    pub params: String,
+}
+
+/// Mocked responses to API host functions, used instead of the real APIs when testing modules.
+///
+/// Responses are keyed by host function name and are returned in order. The last response for
+/// a function is repeated once the others are used up. Calls to functions without a response
+/// fail as if the API was not configured.
+///
+/// Every call is recorded, whether or not it has a response.
+#[derive(Default)]
+pub struct ApiMocks {
+    responses: Mutex<HashMap<String, VecDeque<MockResponse>>>,
+    calls: Mutex<Vec<RecordedCall>>,
+}
+
+impl ApiMocks {
+    pub fn new(responses: HashMap<String, Vec<MockResponse>>) -> Self {
+        Self {
+            responses: Mutex::new(
+                responses
+                    .into_iter()
+                    .map(|(function, responses)| (function, responses.into()))
+                    .collect(),
+            ),
+            calls: Mutex::new(vec![]),
+        }
+    }
+
+    /// Record a call to `function` and get its mocked response, if there is one
+    pub fn respond(&self, function: &str, params: &str) -> Option<Result<String, ApiError>> {
+        self.calls.lock().unwrap().push(RecordedCall {
+            function: function.to_string(),
+            params: params.to_string(),
+        });
+
+        let mut responses = self.responses.lock().unwrap();
+        let queue = responses.get_mut(function)?;
+        let response = if queue.len() > 1 {
+            queue.pop_front()?
+        } else {
+            queue.front()?.clone()
+        };
+
+        Some(match response {
+            MockResponse::Error { error } => Err(ApiError::MockError(error)),
+            MockResponse::Value(Value::String(value)) => Ok(value),
+            MockResponse::Value(value) => Ok(value.to_string()),
+        })
+    }
+
+    /// Replace every mocked response and forget the recorded calls
+    pub fn reset(&self, responses: HashMap<String, Vec<MockResponse>>) {
+        *self.responses.lock().unwrap() = Self::new(responses).responses.into_inner().unwrap();
+        self.calls.lock().unwrap().clear();
+    }
+
+    /// Take the calls recorded since the last time this was called
+    pub fn take_calls(&self) -> Vec<RecordedCall> {
+        std::mem::take(&mut *self.calls.lock().unwrap())
+    }
+}
@@ -8,6 +8,7 @@ pub mod gcp;
 pub mod general;
 pub mod github;
 pub mod jira;
+pub mod mock;
 pub mod npm;
 pub mod okta;
 pub mod pagerduty;
@@ -43,6 +44,7 @@ use aws_sdk_kms::{error::SdkError, operation::sign::SignError};
 use bloom_filter::BloomFilterConfig;
 use general::{General, GeneralConfig};
 use github::{Github, GithubConfig};
+use mock::ApiMocks;
 use npm::{Npm, NpmConfig};
 use okta::{Okta, OktaConfig};
 use pagerduty::{PagerDuty, PagerDutyConfig};
@@ -78,6 +80,9 @@ pub struct Api {
     pub web: Option<Web>,
     pub blockchain: Option<Blockchain>,
     pub bloom_filter: Option<BloomFilter>,
+    /// If set, API host functions are answered by these mocks instead of the APIs above.
+    /// This is only used when testing modules.
+    pub mocks: Option<ApiMocks>,
 }
 
 /// Configurations for all the APIs Plaid can use
@@ -147,6 +152,15 @@ pub enum ApiError {
     TlsError(String),
     BloomFilterError(String),
     CouldNotInstatiateRuntime(String),
+    MockError(String),
+}
+
+/// Helpers that only run before a request is made return boxed errors to keep their
+/// results small. They are unboxed when returned from the API call.
+impl From<Box<ApiError>> for ApiError {
+    fn from(e: Box<ApiError>) -> Self {
+        *e
+    }
 }
 
 impl From<BlockchainError> for ApiError {
@@ -294,6 +308,33 @@ impl Api {
             splunk,
             yubikey,
             web,
+            mocks: None,
+        })
+    }
+
+    /// Create an API where every API host function is answered by `mocks`
+    pub fn with_mocks(mocks: ApiMocks) -> std::io::Result<Self> {
+        Ok(Self {
+            runtime: Runtime::new()?,
+            #[cfg(feature = "aws")]
+            aws: None,
+            #[cfg(feature = "gcp")]
+            gcp: None,
+            blockchain: None,
+            cryptography: None,
+            bloom_filter: None,
+            general: None,
+            github: None,
+            jira: None,
+            npm: None,
+            okta: None,
+            pagerduty: None,
+            rustica: None,
+            slack: None,
+            splunk: None,
+            yubikey: None,
+            web: None,
+            mocks: Some(mocks),
         })
     }
 }
@@ -1,48 +1,186 @@
-use std::{collections::HashMap, sync::Arc};
+use std::sync::Arc;
 
-use http::StatusCode;
+use http::{header::LINK, HeaderMap, StatusCode};
+use plaid_stl::okta::{
+    GroupMember, GroupMembershipRequest, ListGroupMembersRequest, ListGroupMembersResponse,
+};
+use reqwest::{Method, Url};
 
 use crate::{apis::ApiError, loader::PlaidModule};
 
-use super::{Okta, OktaError};
+use super::{valid_okta_id, Okta, OktaError, OktaOperation};
 
 impl Okta {
     /// Remove a user from an Okta group
     pub async fn remove_user_from_group(
         &self,
         params: &str,
-        _: Arc<PlaidModule>,
+        module: Arc<PlaidModule>,
     ) -> Result<u32, ApiError> {
-        let request: HashMap<&str, &str> =
+        let op = OktaOperation::RemoveUserFromGroup;
+        let request = self.parse_group_membership_request(params, &op, &module)?;
+
+        self.send_management_request(
+            Method::DELETE,
+            &format!(
+                "/api/v1/groups/{}/users/{}",
+                request.group_id, request.user_id
+            ),
+            &op,
+            StatusCode::NO_CONTENT,
+        )
+        .await
+    }
+
+    /// Add a user to an Okta group
+    pub async fn add_user_to_group(
+        &self,
+        params: &str,
+        module: Arc<PlaidModule>,
+    ) -> Result<u32, ApiError> {
+        let op = OktaOperation::AddUserToGroup;
+        let request = self.parse_group_membership_request(params, &op, &module)?;
+
+        info!(
+            "{module} is adding {} to Okta group {}",
+            request.user_id, request.group_id
+        );
+        self.send_management_request(
+            Method::PUT,
+            &format!(
+                "/api/v1/groups/{}/users/{}",
+                request.group_id, request.user_id
+            ),
+            &op,
+            StatusCode::NO_CONTENT,
+        )
+        .await
+    }
+
+    /// Get one page of an Okta group's members
+    pub async fn list_group_members(
+        &self,
+        params: &str,
+        module: Arc<PlaidModule>,
+    ) -> Result<String, ApiError> {
+        let op = OktaOperation::ListGroupMembers;
+        self.check_module_permissions(&op, &module)?;
+
+        let request: ListGroupMembersRequest =
             serde_json::from_str(params).map_err(|_| ApiError::BadRequest)?;
+        if !valid_okta_id(&request.group_id) {
+            warn!("{module} tried to list the members of an Okta group with an invalid id");
+            return Err(ApiError::BadRequest);
+        }
 
-        let user_id = request.get("user_id").ok_or(ApiError::BadRequest)?;
-        let group_id = request.get("group_id").ok_or(ApiError::BadRequest)?;
+        let mut query = vec![];
+        if let Some(limit) = request.limit {
+            query.push(("limit", limit.to_string()));
+        }
+        if let Some(after) = request.after {
+            query.push(("after", after));
+        }
 
         let res = self
             .client
-            .delete(format!(
-                "https://{}/api/v1/groups/{group_id}/users/{user_id}",
-                &self.config.domain
+            .get(format!(
+                "https://{}/api/v1/groups/{}/users",
+                &self.config.domain, request.group_id
             ))
+            .query(&query)
             .header(
                 "Authorization",
-                self.get_authorization_header(&super::OktaOperation::RemoveUserFromGroup)
+                self.get_authorization_header(&op)
                     .await
                     .map_err(ApiError::OktaError)?,
             )
-            .header("Content-Type", "application/json")
             .header("Accept", "application/json");
 
         let response = res.send().await.map_err(ApiError::NetworkError)?;
-
         let status = response.status();
-        if status == StatusCode::NO_CONTENT {
-            Ok(0)
-        } else {
-            Err(ApiError::OktaError(OktaError::UnexpectedStatusCode(
+        let next = next_page_cursor(response.headers());
+        let text = response.text().await.map_err(ApiError::NetworkError)?;
+
+        if status != StatusCode::OK {
+            error!("Okta API Error listing group members: {text}");
+            return Err(ApiError::OktaError(OktaError::UnexpectedStatusCode(
                 status.as_u16(),
-            )))
+            )));
         }
+
+        let members: Vec<GroupMember> = serde_json::from_str(&text)
+            .map_err(|_| ApiError::OktaError(OktaError::BadJsonResponse))?;
+
+        serde_json::to_string(&ListGroupMembersResponse { members, next })
+            .map_err(|_| ApiError::ImpossibleError)
+    }
+
+    /// Parse a request to change a group's membership and check that `module` can make it
+    fn parse_group_membership_request(
+        &self,
+        params: &str,
+        op: &OktaOperation,
+        module: &PlaidModule,
+    ) -> Result<GroupMembershipRequest, Box<ApiError>> {
+        self.check_module_permissions(op, module)?;
+
+        let request: GroupMembershipRequest =
+            serde_json::from_str(params).map_err(|_| ApiError::BadRequest)?;
+        if !valid_okta_id(&request.user_id) || !valid_okta_id(&request.group_id) {
+            warn!(
+                "{module} tried to call Okta {} with an invalid id",
+                op.name()
+            );
+            return Err(Box::new(ApiError::BadRequest));
+        }
+
+        Ok(request)
+    }
+}
+
+/// Get the `after` cursor from the `rel="next"` link Okta returns when there are more results
+fn next_page_cursor(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(LINK)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find(|link| link.contains("rel=\"next\""))
+        .and_then(|link| {
+            let url = link.split(';').next()?.trim();
+            let url = url.strip_prefix('<')?.strip_suffix('>')?;
+            Url::parse(url).ok()
+        })
+        .and_then(|url| {
+            url.query_pairs()
+                .find(|(key, _)| key == "after")
+                .map(|(_, value)| value.into_owned())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn next_page_cursor_from_link_header() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            LINK,
+            HeaderValue::from_static(
+                "<https://example.okta.com/api/v1/groups/00g1/users?limit=2>; rel=\"self\"",
+            ),
+        );
+        assert_eq!(next_page_cursor(&headers), None);
+
+        headers.append(
+            LINK,
+            HeaderValue::from_static(
+                "<https://example.okta.com/api/v1/groups/00g1/users?after=00u2&limit=2>; rel=\"next\"",
+            ),
+        );
+        assert_eq!(next_page_cursor(&headers), Some("00u2".to_string()));
     }
 }
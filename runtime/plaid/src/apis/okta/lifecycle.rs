@@ -0,0 +1,157 @@
+use std::sync::Arc;
+
+use http::StatusCode;
+use plaid_stl::okta::{ClearUserSessionsRequest, UserRequest};
+use reqwest::Method;
+
+use crate::{apis::ApiError, loader::PlaidModule};
+
+use super::{valid_okta_id, Okta, OktaError, OktaOperation};
+
+impl Okta {
+    /// Suspend an Okta user
+    pub async fn suspend_user(
+        &self,
+        params: &str,
+        module: Arc<PlaidModule>,
+    ) -> Result<u32, ApiError> {
+        self.user_lifecycle_operation(params, "suspend", OktaOperation::SuspendUser, &module)
+            .await
+    }
+
+    /// Unsuspend a suspended Okta user
+    pub async fn unsuspend_user(
+        &self,
+        params: &str,
+        module: Arc<PlaidModule>,
+    ) -> Result<u32, ApiError> {
+        self.user_lifecycle_operation(params, "unsuspend", OktaOperation::UnsuspendUser, &module)
+            .await
+    }
+
+    /// Deactivate an Okta user
+    pub async fn deactivate_user(
+        &self,
+        params: &str,
+        module: Arc<PlaidModule>,
+    ) -> Result<u32, ApiError> {
+        self.user_lifecycle_operation(params, "deactivate", OktaOperation::DeactivateUser, &module)
+            .await
+    }
+
+    /// Reset all of an Okta user's enrolled factors
+    pub async fn reset_user_factors(
+        &self,
+        params: &str,
+        module: Arc<PlaidModule>,
+    ) -> Result<u32, ApiError> {
+        self.user_lifecycle_operation(
+            params,
+            "reset_factors",
+            OktaOperation::ResetUserFactors,
+            &module,
+        )
+        .await
+    }
+
+    /// Clear all of an Okta user's sessions
+    pub async fn clear_user_sessions(
+        &self,
+        params: &str,
+        module: Arc<PlaidModule>,
+    ) -> Result<u32, ApiError> {
+        let op = OktaOperation::ClearUserSessions;
+        self.check_module_permissions(&op, &module)?;
+
+        let request: ClearUserSessionsRequest =
+            serde_json::from_str(params).map_err(|_| ApiError::BadRequest)?;
+        if !valid_okta_id(&request.user_id) {
+            warn!("{module} tried to clear Okta sessions with an invalid user id");
+            return Err(ApiError::BadRequest);
+        }
+
+        info!(
+            "{module} is clearing the Okta sessions of {}",
+            request.user_id
+        );
+        self.send_management_request(
+            Method::DELETE,
+            &format!(
+                "/api/v1/users/{}/sessions?oauthTokens={}",
+                request.user_id, request.revoke_oauth_tokens
+            ),
+            &op,
+            StatusCode::NO_CONTENT,
+        )
+        .await
+    }
+
+    /// Send a request to `/api/v1/users/{user_id}/lifecycle/{action}`
+    async fn user_lifecycle_operation(
+        &self,
+        params: &str,
+        action: &str,
+        op: OktaOperation,
+        module: &PlaidModule,
+    ) -> Result<u32, ApiError> {
+        self.check_module_permissions(&op, module)?;
+
+        let request: UserRequest =
+            serde_json::from_str(params).map_err(|_| ApiError::BadRequest)?;
+        if !valid_okta_id(&request.user_id) {
+            warn!(
+                "{module} tried to call Okta {} with an invalid user id",
+                op.name()
+            );
+            return Err(ApiError::BadRequest);
+        }
+
+        info!(
+            "{module} is calling Okta {} for {}",
+            op.name(),
+            request.user_id
+        );
+        self.send_management_request(
+            Method::POST,
+            &format!("/api/v1/users/{}/lifecycle/{action}", request.user_id),
+            &op,
+            StatusCode::OK,
+        )
+        .await
+    }
+
+    /// Send a request with no body to the Okta management API and check that it
+    /// returned the `expected` status
+    pub(super) async fn send_management_request(
+        &self,
+        method: Method,
+        path: &str,
+        op: &OktaOperation,
+        expected: StatusCode,
+    ) -> Result<u32, ApiError> {
+        let res = self
+            .client
+            .request(method, format!("https://{}{path}", &self.config.domain))
+            .header(
+                "Authorization",
+                self.get_authorization_header(op)
+                    .await
+                    .map_err(ApiError::OktaError)?,
+            )
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/json");
+
+        let response = res.send().await.map_err(ApiError::NetworkError)?;
+
+        let status = response.status();
+        if status == expected {
+            Ok(0)
+        } else {
+            let text = response.text().await.unwrap_or_default();
+            error!("Okta API Error calling {}: {text}", op.name());
+            Err(ApiError::OktaError(OktaError::UnexpectedStatusCode(
+                status.as_u16(),
+            )))
+        }
+    }
+}
@@ -1,4 +1,5 @@
 mod groups;
+mod lifecycle;
 mod users;
 
 use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
@@ -11,7 +12,8 @@ use std::{
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use super::default_timeout_seconds;
+use super::{default_timeout_seconds, ApiError};
+use crate::loader::PlaidModule;
 
 /// Determine how to authenticate to the Okta API
 #[derive(Deserialize)]
@@ -52,12 +54,51 @@ pub struct OktaConfig {
     pub domain: String,
     /// How the authentication to Okta is made
     authentication: Authentication,
+    /// Which rules can use each of the lifecycle management host functions
+    #[serde(default)]
+    allowed_rules: OktaAllowedRules,
     /// The number of seconds until an external API request times out.
     /// If no value is provided, the result of `default_timeout_seconds()` will be used.
     #[serde(default = "default_timeout_seconds")]
     api_timeout_seconds: u64,
 }
 
+/// Rules allowed to call each of the lifecycle management host functions. A rule that is not
+/// listed for a function cannot call it.
+#[derive(Deserialize, Default)]
+struct OktaAllowedRules {
+    #[serde(default)]
+    add_user_to_group: Vec<String>,
+    #[serde(default)]
+    suspend_user: Vec<String>,
+    #[serde(default)]
+    unsuspend_user: Vec<String>,
+    #[serde(default)]
+    deactivate_user: Vec<String>,
+    #[serde(default)]
+    clear_user_sessions: Vec<String>,
+    #[serde(default)]
+    reset_user_factors: Vec<String>,
+    #[serde(default)]
+    list_group_members: Vec<String>,
+}
+
+impl OktaAllowedRules {
+    /// The rules allowed to perform `op`, or `None` if any rule can
+    fn for_operation(&self, op: &OktaOperation) -> Option<&Vec<String>> {
+        match op {
+            OktaOperation::GetUserInfo | OktaOperation::RemoveUserFromGroup => None,
+            OktaOperation::AddUserToGroup => Some(&self.add_user_to_group),
+            OktaOperation::SuspendUser => Some(&self.suspend_user),
+            OktaOperation::UnsuspendUser => Some(&self.unsuspend_user),
+            OktaOperation::DeactivateUser => Some(&self.deactivate_user),
+            OktaOperation::ClearUserSessions => Some(&self.clear_user_sessions),
+            OktaOperation::ResetUserFactors => Some(&self.reset_user_factors),
+            OktaOperation::ListGroupMembers => Some(&self.list_group_members),
+        }
+    }
+}
+
 /// The Okta API Plaid interacts with
 pub struct Okta {
     /// Config for the Okta API
@@ -81,6 +122,13 @@ pub enum OktaError {
 pub enum OktaOperation {
     GetUserInfo,
     RemoveUserFromGroup,
+    AddUserToGroup,
+    SuspendUser,
+    UnsuspendUser,
+    DeactivateUser,
+    ClearUserSessions,
+    ResetUserFactors,
+    ListGroupMembers,
 }
 
 impl OktaOperation {
@@ -91,6 +139,32 @@ impl OktaOperation {
             Self::GetUserInfo => "okta.users.read",
             // https://developer.okta.com/docs/api/openapi/okta-management/management/tag/Group/#tag/Group/operation/unassignUserFromGroup
             Self::RemoveUserFromGroup => "okta.groups.manage",
+            // https://developer.okta.com/docs/api/openapi/okta-management/management/tag/Group/#tag/Group/operation/assignUserToGroup
+            Self::AddUserToGroup => "okta.groups.manage",
+            // https://developer.okta.com/docs/api/openapi/okta-management/management/tag/UserLifecycle/
+            Self::SuspendUser
+            | Self::UnsuspendUser
+            | Self::DeactivateUser
+            | Self::ResetUserFactors => "okta.users.manage",
+            // https://developer.okta.com/docs/api/openapi/okta-management/management/tag/UserSessions/#tag/UserSessions/operation/revokeUserSessions
+            Self::ClearUserSessions => "okta.users.manage",
+            // https://developer.okta.com/docs/api/openapi/okta-management/management/tag/Group/#tag/Group/operation/listGroupUsers
+            Self::ListGroupMembers => "okta.groups.read",
+        }
+    }
+
+    /// The name of the host function that performs the operation, for logging
+    fn name(&self) -> &str {
+        match self {
+            Self::GetUserInfo => "get_user_data",
+            Self::RemoveUserFromGroup => "remove_user_from_group",
+            Self::AddUserToGroup => "add_user_to_group",
+            Self::SuspendUser => "suspend_user",
+            Self::UnsuspendUser => "unsuspend_user",
+            Self::DeactivateUser => "deactivate_user",
+            Self::ClearUserSessions => "clear_user_sessions",
+            Self::ResetUserFactors => "reset_user_factors",
+            Self::ListGroupMembers => "list_group_members",
         }
     }
 }
@@ -105,6 +179,21 @@ impl Okta {
         Self { config, client }
     }
 
+    /// Check that `module` is allowed to perform `op`
+    fn check_module_permissions(
+        &self,
+        op: &OktaOperation,
+        module: &PlaidModule,
+    ) -> Result<(), Box<ApiError>> {
+        match self.config.allowed_rules.for_operation(op) {
+            Some(allowed_rules) if !allowed_rules.contains(&module.to_string()) => {
+                warn!("{module} tried to call Okta {} without permission", op.name());
+                Err(Box::new(ApiError::BadRequest))
+            }
+            _ => Ok(()),
+        }
+    }
+
     /// Return an appropriate authorization header, to be used when making a REST call.
     pub async fn get_authorization_header(&self, op: &OktaOperation) -> Result<String, OktaError> {
         match &self.config.authentication {
@@ -208,3 +297,8 @@ impl Okta {
         Ok(access_token)
     }
 }
+
+/// Whether `id` looks like an Okta object ID and is safe to put in a request path
+fn valid_okta_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 64 && id.bytes().all(|b| b.is_ascii_alphanumeric())
+}
@@ -0,0 +1,185 @@
+use std::sync::Arc;
+
+use super::{PagerDuty, PagerDutyError};
+use crate::{apis::ApiError, loader::PlaidModule};
+
+use plaid_stl::pagerduty::{IncidentEventRequest, Link, Severity, TriggerIncidentRequest};
+use serde::Serialize;
+
+const PAGERDUTY_ENQUEUE_ADDRESS: &str = "https://events.pagerduty.com/v2/enqueue";
+
+/// The longest dedup key PagerDuty accepts
+const MAX_DEDUP_KEY_LENGTH: usize = 255;
+
+/// Payload sent to PagerDuty to trigger an incident
+#[derive(Serialize)]
+struct PagerDutyTriggerPayload {
+    summary: String,
+    source: String,
+    severity: Severity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    custom_details: Option<serde_json::Value>,
+}
+
+/// Event sent to PagerDuty to trigger, acknowledge or resolve an incident
+#[derive(Serialize)]
+struct PagerDutyEvent {
+    routing_key: String,
+    event_action: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dedup_key: Option<String>,
+    /// Only sent when triggering an incident
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload: Option<PagerDutyTriggerPayload>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    links: Vec<Link>,
+}
+
+enum IncidentEventResult {
+    Success = 0,
+    BadRequest = 1,
+    UnknownService = 2,
+    EventFailed = 3,
+}
+
+fn valid_dedup_key(dedup_key: &str) -> bool {
+    !dedup_key.is_empty() && dedup_key.len() <= MAX_DEDUP_KEY_LENGTH
+}
+
+impl PagerDuty {
+    /// Trigger a PagerDuty incident
+    pub async fn trigger_incident(
+        &self,
+        request: &str,
+        module_name: Arc<PlaidModule>,
+    ) -> Result<u32, ApiError> {
+        let request: TriggerIncidentRequest = match serde_json::from_str(request) {
+            Ok(r) => r,
+            Err(_) => return Ok(IncidentEventResult::BadRequest as u32),
+        };
+
+        if request
+            .dedup_key
+            .as_deref()
+            .is_some_and(|k| !valid_dedup_key(k))
+        {
+            return Ok(IncidentEventResult::BadRequest as u32);
+        }
+
+        let payload = PagerDutyTriggerPayload {
+            summary: request.description,
+            source: module_name.to_string(),
+            severity: request.severity,
+            custom_details: request.custom_details,
+        };
+
+        self.send_event(
+            &request.service,
+            "trigger",
+            request.dedup_key,
+            Some(payload),
+            request.links,
+            &module_name,
+        )
+        .await
+    }
+
+    /// Acknowledge a PagerDuty incident
+    pub async fn acknowledge_incident(
+        &self,
+        request: &str,
+        module_name: Arc<PlaidModule>,
+    ) -> Result<u32, ApiError> {
+        self.update_incident(request, "acknowledge", &module_name)
+            .await
+    }
+
+    /// Resolve a PagerDuty incident
+    pub async fn resolve_incident(
+        &self,
+        request: &str,
+        module_name: Arc<PlaidModule>,
+    ) -> Result<u32, ApiError> {
+        self.update_incident(request, "resolve", &module_name).await
+    }
+
+    /// Send an event that acknowledges or resolves the incident with a dedup key
+    async fn update_incident(
+        &self,
+        request: &str,
+        event_action: &'static str,
+        module: &PlaidModule,
+    ) -> Result<u32, ApiError> {
+        let request: IncidentEventRequest = match serde_json::from_str(request) {
+            Ok(r) => r,
+            Err(_) => return Ok(IncidentEventResult::BadRequest as u32),
+        };
+
+        if !valid_dedup_key(&request.dedup_key) {
+            return Ok(IncidentEventResult::BadRequest as u32);
+        }
+
+        self.send_event(
+            &request.service,
+            event_action,
+            Some(request.dedup_key),
+            None,
+            vec![],
+            module,
+        )
+        .await
+    }
+
+    /// Send an event to the integration of `service`, if `module` is allowed to use it
+    async fn send_event(
+        &self,
+        service: &str,
+        event_action: &'static str,
+        dedup_key: Option<String>,
+        payload: Option<PagerDutyTriggerPayload>,
+        links: Vec<Link>,
+        module: &PlaidModule,
+    ) -> Result<u32, ApiError> {
+        let routing_key = match self.config.services.get(service) {
+            Some(s) if s.allows(&module.to_string()) => s.integration_key().to_owned(),
+            Some(_) => {
+                warn!("{module} tried to use PagerDuty service {service} without permission");
+                return Err(ApiError::BadRequest);
+            }
+            None => {
+                warn!("{module} tried to use a PagerDuty service that doesn't exist: {service}");
+                return Ok(IncidentEventResult::UnknownService as u32);
+            }
+        };
+
+        let event = PagerDutyEvent {
+            routing_key,
+            event_action,
+            dedup_key,
+            payload,
+            links,
+        };
+
+        info!("Sending a {event_action} event for {service}");
+        match self
+            .client
+            .post(PAGERDUTY_ENQUEUE_ADDRESS)
+            .json(&event)
+            .send()
+            .await
+        {
+            Ok(r) => {
+                debug!("{:?}", r);
+                if r.status().is_success() {
+                    Ok(IncidentEventResult::Success as u32)
+                } else {
+                    Ok(IncidentEventResult::EventFailed as u32)
+                }
+            }
+            Err(e) => {
+                debug!("{:?}", e);
+                Err(ApiError::PagerDutyError(PagerDutyError::NetworkError(e)))
+            }
+        }
+    }
+}
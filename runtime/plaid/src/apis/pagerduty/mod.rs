@@ -5,8 +5,8 @@ use std::{collections::HashMap, time::Duration};
 
 use super::default_timeout_seconds;
 
+mod events;
 mod incident_alerts;
-mod trigger;
 
 #[derive(Deserialize)]
 pub struct PagerDutyConfig {
@@ -14,7 +14,7 @@ pub struct PagerDutyConfig {
     /// the integration key relevant to creating an incident in PagerDuty under that
     /// same service
     #[serde(default)]
-    services: HashMap<String, String>,
+    services: HashMap<String, PagerDutyService>,
     /// Configuration for the `pagerduty_get_incident_alerts` host call.
     #[serde(default)]
     get_incident_alerts: Option<PagerDutyGetIncidentAlertsConfig>,
@@ -24,6 +24,38 @@ pub struct PagerDutyConfig {
     api_timeout_seconds: u64,
 }
 
+/// A PagerDuty service that rules can trigger, acknowledge and resolve incidents for
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PagerDutyService {
+    /// Only the integration key is given: all rules can use the service
+    IntegrationKey(String),
+    /// Only the listed rules can use the service
+    Restricted {
+        integration_key: String,
+        allowed_rules: Vec<String>,
+    },
+}
+
+impl PagerDutyService {
+    fn integration_key(&self) -> &str {
+        match self {
+            Self::IntegrationKey(key) => key,
+            Self::Restricted {
+                integration_key, ..
+            } => integration_key,
+        }
+    }
+
+    /// Whether `module` can use the service
+    fn allows(&self, module: &str) -> bool {
+        match self {
+            Self::IntegrationKey(_) => true,
+            Self::Restricted { allowed_rules, .. } => allowed_rules.iter().any(|r| r == module),
+        }
+    }
+}
+
 #[derive(Deserialize)]
 struct PagerDutyGetIncidentAlertsConfig {
     token: String,
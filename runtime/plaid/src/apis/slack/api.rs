@@ -1,11 +1,13 @@
 use std::sync::Arc;
 
 use plaid_stl::slack::{
-    CreateChannel, CreateChannelResponse, GetDndInfo, GetDndInfoResponse, GetIdFromEmail,
-    GetPresence, GetPresenceResponse, InviteToChannel, PostMessage, RemoveFromChannel,
-    UpdateMessage, UserInfo, UserInfoResponse, ViewOpen,
+    AddReaction, ConversationMessagesResponse, CreateChannel, CreateChannelResponse,
+    GetChannelHistory, GetDndInfo, GetDndInfoResponse, GetIdFromEmail, GetPresence,
+    GetPresenceResponse, GetThreadReplies, InviteToChannel, PostMessage, RemoveFromChannel,
+    UpdateMessage, UploadFile, UploadFileResponse, UserInfo, UserInfoResponse, ViewOpen,
 };
 use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
 
 use crate::{
     apis::{slack::SlackError, ApiError},
@@ -25,6 +27,11 @@ enum Apis {
     CreateChannel(plaid_stl::slack::CreateChannel),
     InviteToChannel(plaid_stl::slack::InviteToChannel),
     RemoveFromChannel(plaid_stl::slack::RemoveFromChannel),
+    AddReaction(plaid_stl::slack::AddReaction),
+    GetThreadReplies(plaid_stl::slack::GetThreadReplies),
+    GetChannelHistory(plaid_stl::slack::GetChannelHistory),
+    GetUploadUrlExternal { filename: String, length: usize },
+    CompleteUploadExternal(String),
 }
 
 const SLACK_API_URL: &str = "https://slack.com/api/";
@@ -37,6 +44,32 @@ struct GenericSlackResponse {
     ok: bool,
 }
 
+/// Response from Slack when asking where to upload a file
+#[derive(Deserialize)]
+struct GetUploadUrlResponse {
+    ok: bool,
+    upload_url: String,
+    file_id: String,
+}
+
+/// Slack only hands out upload URLs on this host. Anything else is not sent the file.
+const SLACK_UPLOAD_URL_PREFIX: &str = "https://files.slack.com/";
+
+/// Add the optional query parameters shared by the conversation history APIs
+fn paginated_query(
+    mut query: Vec<(&'static str, String)>,
+    limit: Option<u32>,
+    cursor: &Option<String>,
+) -> Vec<(&'static str, String)> {
+    if let Some(limit) = limit {
+        query.push(("limit", limit.to_string()));
+    }
+    if let Some(cursor) = cursor {
+        query.push(("cursor", cursor.clone()));
+    }
+    query
+}
+
 impl Apis {
     fn build_request(&self, client: &Client) -> RequestBuilder {
         match self {
@@ -82,6 +115,52 @@ impl Apis {
                 .post(format!("{SLACK_API_URL}{api}", api = "conversations.kick"))
                 .body(p.body().unwrap_or_default()) // TODO this is not great: maybe this method should be fallible
                 .header("Content-Type", "application/json; charset=utf-8"),
+            Self::AddReaction(p) => client
+                .post(format!("{SLACK_API_URL}{api}", api = "reactions.add"))
+                .body(p.body().unwrap_or_default()) // TODO this is not great: maybe this method should be fallible
+                .header("Content-Type", "application/json; charset=utf-8"),
+            Self::GetThreadReplies(p) => client
+                .get(format!(
+                    "{SLACK_API_URL}{api}",
+                    api = "conversations.replies"
+                ))
+                .query(&paginated_query(
+                    vec![("channel", p.channel.clone()), ("ts", p.ts.clone())],
+                    p.limit,
+                    &p.cursor,
+                )),
+            Self::GetChannelHistory(p) => {
+                let mut query =
+                    paginated_query(vec![("channel", p.channel.clone())], p.limit, &p.cursor);
+                if let Some(oldest) = &p.oldest {
+                    query.push(("oldest", oldest.clone()));
+                }
+                if let Some(latest) = &p.latest {
+                    query.push(("latest", latest.clone()));
+                }
+                client
+                    .get(format!(
+                        "{SLACK_API_URL}{api}",
+                        api = "conversations.history"
+                    ))
+                    .query(&query)
+            }
+            Self::GetUploadUrlExternal { filename, length } => client
+                .get(format!(
+                    "{SLACK_API_URL}{api}",
+                    api = "files.getUploadURLExternal"
+                ))
+                .query(&[
+                    ("filename", filename.clone()),
+                    ("length", length.to_string()),
+                ]),
+            Self::CompleteUploadExternal(body) => client
+                .post(format!(
+                    "{SLACK_API_URL}{api}",
+                    api = "files.completeUploadExternal"
+                ))
+                .body(body.clone())
+                .header("Content-Type", "application/json; charset=utf-8"),
         }
     }
 }
@@ -99,6 +178,11 @@ impl std::fmt::Display for Apis {
             Self::CreateChannel(_) => write!(f, "CreateChannel"),
             Self::InviteToChannel(_) => write!(f, "InviteToChannel"),
             Self::RemoveFromChannel(_) => write!(f, "RemoveFromChannel"),
+            Self::AddReaction(_) => write!(f, "AddReaction"),
+            Self::GetThreadReplies(_) => write!(f, "GetThreadReplies"),
+            Self::GetChannelHistory(_) => write!(f, "GetChannelHistory"),
+            Self::GetUploadUrlExternal { .. } => write!(f, "GetUploadUrlExternal"),
+            Self::CompleteUploadExternal(_) => write!(f, "CompleteUploadExternal"),
         }
     }
 }
@@ -400,4 +484,185 @@ impl Slack {
             Err(e) => Err(e),
         }
     }
+
+    /// Add a reaction to a message
+    pub async fn add_reaction(&self, params: &str, module: Arc<PlaidModule>) -> Result<u32> {
+        let p: AddReaction = serde_json::from_str(params).map_err(|_| ApiError::BadRequest)?;
+        match self
+            .call_slack(p.bot.clone(), Apis::AddReaction(p), module)
+            .await
+        {
+            Ok((200, response)) => {
+                let reaction_response: GenericSlackResponse = serde_json::from_str(&response)
+                    .map_err(|e| {
+                        ApiError::SlackError(SlackError::UnexpectedPayload(e.to_string()))
+                    })?;
+                if !reaction_response.ok {
+                    return Err(ApiError::SlackError(SlackError::UnexpectedPayload(
+                        response,
+                    )));
+                }
+                Ok(0)
+            }
+            Ok((status, _)) => Err(ApiError::SlackError(SlackError::UnexpectedStatusCode(
+                status,
+            ))),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get one page of the messages in a thread
+    pub async fn get_thread_replies(
+        &self,
+        params: &str,
+        module: Arc<PlaidModule>,
+    ) -> Result<String> {
+        let p: GetThreadReplies = serde_json::from_str(params).map_err(|_| ApiError::BadRequest)?;
+        self.conversation_messages(p.bot.clone(), Apis::GetThreadReplies(p), module)
+            .await
+    }
+
+    /// Get one page of the messages in a channel
+    pub async fn get_channel_history(
+        &self,
+        params: &str,
+        module: Arc<PlaidModule>,
+    ) -> Result<String> {
+        let p: GetChannelHistory =
+            serde_json::from_str(params).map_err(|_| ApiError::BadRequest)?;
+        self.conversation_messages(p.bot.clone(), Apis::GetChannelHistory(p), module)
+            .await
+    }
+
+    /// Call an API that returns a page of messages. Only the fields modules use are
+    /// returned, which keeps the response small enough for the module's buffer.
+    async fn conversation_messages(
+        &self,
+        bot: String,
+        api: Apis,
+        module: Arc<PlaidModule>,
+    ) -> Result<String> {
+        match self.call_slack(bot, api, module).await {
+            Ok((200, response)) => {
+                let messages_response: ConversationMessagesResponse =
+                    serde_json::from_str(&response).map_err(|_| {
+                        ApiError::SlackError(SlackError::UnexpectedPayload(response.clone()))
+                    })?;
+                if !messages_response.ok {
+                    return Err(ApiError::SlackError(SlackError::UnexpectedPayload(
+                        response,
+                    )));
+                }
+                serde_json::to_string(&messages_response).map_err(|_| ApiError::ImpossibleError)
+            }
+            Ok((status, _)) => Err(ApiError::SlackError(SlackError::UnexpectedStatusCode(
+                status,
+            ))),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Upload a file and share it in a channel. Slack needs three calls for this: one to get
+    /// a URL to upload the file to, the upload itself, and one to share the uploaded file.
+    pub async fn upload_file(&self, params: &str, module: Arc<PlaidModule>) -> Result<String> {
+        let p: UploadFile = serde_json::from_str(params).map_err(|_| ApiError::BadRequest)?;
+        if p.filename.is_empty() || p.content.is_empty() {
+            return Err(ApiError::BadRequest);
+        }
+
+        let upload_url = match self
+            .call_slack(
+                p.bot.clone(),
+                Apis::GetUploadUrlExternal {
+                    filename: p.filename.clone(),
+                    length: p.content.len(),
+                },
+                module.clone(),
+            )
+            .await
+        {
+            Ok((200, response)) => {
+                let url_response: GetUploadUrlResponse =
+                    serde_json::from_str(&response).map_err(|_| {
+                        ApiError::SlackError(SlackError::UnexpectedPayload(response.clone()))
+                    })?;
+                if !url_response.ok || !url_response.upload_url.starts_with(SLACK_UPLOAD_URL_PREFIX)
+                {
+                    return Err(ApiError::SlackError(SlackError::UnexpectedPayload(
+                        response,
+                    )));
+                }
+                url_response
+            }
+            Ok((status, _)) => {
+                return Err(ApiError::SlackError(SlackError::UnexpectedStatusCode(
+                    status,
+                )))
+            }
+            Err(e) => return Err(e),
+        };
+
+        let status = self
+            .client
+            .post(&upload_url.upload_url)
+            .body(p.content)
+            .send()
+            .await
+            .map_err(ApiError::NetworkError)?
+            .status();
+        if !status.is_success() {
+            return Err(ApiError::SlackError(SlackError::UnexpectedStatusCode(
+                status.as_u16(),
+            )));
+        }
+
+        #[derive(serde::Serialize)]
+        struct UploadedFile {
+            id: String,
+            title: String,
+        }
+
+        #[derive(serde::Serialize)]
+        struct CompleteUploadBody {
+            files: Vec<UploadedFile>,
+            channel_id: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            initial_comment: Option<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            thread_ts: Option<String>,
+        }
+
+        let body = serde_json::to_string(&CompleteUploadBody {
+            files: vec![UploadedFile {
+                id: upload_url.file_id,
+                title: p.title.unwrap_or(p.filename),
+            }],
+            channel_id: p.channel,
+            initial_comment: p.initial_comment,
+            thread_ts: p.thread_ts,
+        })
+        .map_err(|_| ApiError::ImpossibleError)?;
+
+        match self
+            .call_slack(p.bot, Apis::CompleteUploadExternal(body), module)
+            .await
+        {
+            Ok((200, response)) => {
+                let upload_response: UploadFileResponse =
+                    serde_json::from_str(&response).map_err(|_| {
+                        ApiError::SlackError(SlackError::UnexpectedPayload(response.clone()))
+                    })?;
+                if !upload_response.ok {
+                    return Err(ApiError::SlackError(SlackError::UnexpectedPayload(
+                        response,
+                    )));
+                }
+                serde_json::to_string(&upload_response).map_err(|_| ApiError::ImpossibleError)
+            }
+            Ok((status, _)) => Err(ApiError::SlackError(SlackError::UnexpectedStatusCode(
+                status,
+            ))),
+            Err(e) => Err(e),
+        }
+    }
 }
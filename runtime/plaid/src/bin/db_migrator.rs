@@ -1,6 +1,11 @@
-use clap::{Arg, Command};
-use plaid::{data::DelayedMessage, executor::Message, storage::StorageProvider};
+use clap::{Arg, ArgAction, Command};
+use plaid::{
+    data::DelayedMessage,
+    executor::Message,
+    storage::{StorageError, StorageProvider},
+};
 use serde_json::Value;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 // These functions take additional parameters and produce the function that defines the data migration.
 // We use this pattern to be able to take arbitrary parameters that would not fit in the migration
@@ -105,14 +110,21 @@ and call appropriately from the code below.
 
 #[tokio::main]
 async fn main() -> Result<(), ()> {
+    #[allow(unused_mut)]
+    let mut db_types = vec!["sled", "dynamodb"];
+    #[cfg(feature = "sql")]
+    db_types.push("sql");
+    #[cfg(feature = "redis")]
+    db_types.push("redis");
+
     let matches = Command::new("plaid_db_migrator")
         .version("0.22.2")
-        .about("Tool to apply a data migration (written as a Rust function) to a Plaid DB")
+        .about("Tool to apply a data migration (written as a Rust function) to a Plaid DB, or to copy data between Plaid DBs")
         .arg(
             Arg::new("db_type")
                 .long("db")
-                .help("The type of DB to apply the migration on")
-                .value_parser(["sled", "dynamodb"])
+                .help("The type of DB to apply the migration on, or to copy data from")
+                .value_parser(db_types.clone())
                 .value_name("TYPE")
                 .required(true)
                 .num_args(1),
@@ -128,15 +140,23 @@ async fn main() -> Result<(), ()> {
             Arg::new("dynamo_table_name")
                 .long("table-name")
                 .value_name("TABLE")
-                .help("DynamoDB table name")
+                .help("DynamoDB or SQL table name")
                 .required_if_eq("db_type", "dynamodb")
                 .conflicts_with("sled_db_path"),
         )
+        .arg(
+            Arg::new("sql_url")
+                .long("url")
+                .value_name("URL")
+                .help("URL of the SQL or Redis database")
+                .required_if_eq_any([("db_type", "sql"), ("db_type", "redis")]),
+        )
         .arg(
             Arg::new("namespace")
                 .long("namespace")
-                .help("DB namespace to apply the migration on")
+                .help("DB namespace to apply the migration on or to copy. Can be repeated")
                 .value_name("NS")
+                .action(ArgAction::Append)
                 .required(true),
         )
         .arg(
@@ -145,67 +165,159 @@ async fn main() -> Result<(), ()> {
                 .help("Log source to use if missing in the DB")
                 .value_name("SOURCE"),
         )
+        .arg(
+            Arg::new("copy_to")
+                .long("copy-to")
+                .help("Instead of applying the migration, copy the namespaces to a DB of this type")
+                .value_parser(db_types)
+                .value_name("TYPE"),
+        )
+        .arg(
+            Arg::new("copy_to_path")
+                .long("copy-to-path")
+                .value_name("PATH")
+                .help("Path to the sled database to copy to")
+                .required_if_eq("copy_to", "sled"),
+        )
+        .arg(
+            Arg::new("copy_to_table_name")
+                .long("copy-to-table-name")
+                .value_name("TABLE")
+                .help("DynamoDB or SQL table name to copy to")
+                .required_if_eq("copy_to", "dynamodb"),
+        )
+        .arg(
+            Arg::new("copy_to_url")
+                .long("copy-to-url")
+                .value_name("URL")
+                .help("URL of the SQL or Redis database to copy to")
+                .required_if_eq_any([("copy_to", "sql"), ("copy_to", "redis")]),
+        )
         .get_matches();
 
     let db_type = matches.get_one::<String>("db_type").unwrap();
-    let namespace = matches.get_one::<String>("namespace").unwrap();
+    let namespaces: Vec<&String> = matches.get_many::<String>("namespace").unwrap().collect();
     let missing_logsource = "missing_logsource".to_string();
     let logsource = matches
         .get_one::<String>("logsource")
         .unwrap_or(&missing_logsource);
 
-    match db_type.as_str() {
-        "sled" => {
-            let sled_path = matches.get_one::<String>("sled_db_path").unwrap();
-            apply_sled_migration(sled_path, namespace, logsource)
-                .await
-                .unwrap();
+    let db = open_db(
+        db_type,
+        matches.get_one::<String>("sled_db_path"),
+        matches.get_one::<String>("dynamo_table_name"),
+        matches.get_one::<String>("sql_url"),
+    )
+    .await;
+
+    match matches.get_one::<String>("copy_to") {
+        Some(copy_to) => {
+            let destination = open_db(
+                copy_to,
+                matches.get_one::<String>("copy_to_path"),
+                matches.get_one::<String>("copy_to_table_name"),
+                matches.get_one::<String>("copy_to_url"),
+            )
+            .await;
+            for namespace in namespaces {
+                copy_namespace(db.as_ref(), destination.as_ref(), namespace)
+                    .await
+                    .unwrap();
+            }
+            println!("Copy complete.");
         }
-        "dynamodb" => {
-            let table_name = matches.get_one::<String>("dynamo_table_name").unwrap();
-            apply_dynamodb_migration(table_name, namespace, logsource)
+        None => {
+            for namespace in namespaces {
+                db.apply_migration(
+                    namespace,
+                    create_migration_function_harmonize_logbacks_0_22_2(logsource.to_string()),
+                )
                 .await
                 .unwrap();
+            }
+            println!("Migration complete.");
         }
-        _ => unreachable!("Unknown DB type {db_type}"),
     }
 
-    println!("Migration complete.");
     Ok(())
 }
 
-async fn apply_sled_migration(path: &str, namespace: &str, logsource: &str) -> Result<(), ()> {
-    let config = plaid::storage::sled::Config {
-        sled_path: path.to_string(),
-    };
-    let sled = plaid::storage::sled::Sled::new(config).unwrap();
-    sled.apply_migration(
-        namespace,
-        create_migration_function_harmonize_logbacks_0_22_2(logsource.to_string()),
-    )
-    .await
-    .unwrap();
-    Ok(())
+/// Open a DB of the given type, with the options that were passed for it
+#[cfg_attr(not(any(feature = "sql", feature = "redis")), allow(unused_variables))]
+async fn open_db(
+    db_type: &str,
+    sled_path: Option<&String>,
+    table_name: Option<&String>,
+    url: Option<&String>,
+) -> Box<dyn StorageProvider + Send + Sync> {
+    match db_type {
+        "sled" => {
+            let config = plaid::storage::sled::Config {
+                sled_path: sled_path.unwrap().to_string(),
+            };
+            Box::new(plaid::storage::sled::Sled::new(config).unwrap())
+        }
+        "dynamodb" => {
+            let config = plaid::storage::dynamodb::Config {
+                authentication: plaid::AwsAuthentication::Iam {},
+                table_name: table_name.unwrap().to_string(),
+            };
+            Box::new(
+                plaid::storage::dynamodb::DynamoDb::new(config)
+                    .await
+                    .unwrap(),
+            )
+        }
+        #[cfg(feature = "sql")]
+        "sql" => {
+            let mut config = plaid::storage::sql::Config::new(url.unwrap().to_string());
+            if let Some(table_name) = table_name {
+                config.table_name = table_name.to_string();
+            }
+            Box::new(plaid::storage::sql::Sql::new(config).await.unwrap())
+        }
+        #[cfg(feature = "redis")]
+        "redis" => {
+            let config = plaid::storage::redis::Config::new(url.unwrap().to_string());
+            Box::new(plaid::storage::redis::Redis::new(config).await.unwrap())
+        }
+        _ => unreachable!("Unknown DB type {db_type}"),
+    }
 }
 
-async fn apply_dynamodb_migration(
-    table_name: &str,
+/// Copy every key in `namespace` from one DB to another. Keys inserted with a TTL keep their
+/// expiry, and keys that have expired are not copied.
+async fn copy_namespace(
+    source: &(dyn StorageProvider + Send + Sync),
+    destination: &(dyn StorageProvider + Send + Sync),
     namespace: &str,
-    logsource: &str,
-) -> Result<(), ()> {
-    let config = plaid::storage::dynamodb::Config {
-        authentication: plaid::AwsAuthentication::Iam {},
-        table_name: table_name.to_string(),
-    };
-    let dynamodb = plaid::storage::dynamodb::DynamoDb::new(config)
-        .await
-        .unwrap();
-    dynamodb
-        .apply_migration(
-            namespace,
-            create_migration_function_harmonize_logbacks_0_22_2(logsource.to_string()),
-        )
-        .await
-        .unwrap();
+) -> Result<(), StorageError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let data = source.fetch_all(namespace, None).await?;
+    let mut count = 0;
+    for (key, value) in data {
+        let Some(value) = value else {
+            continue;
+        };
+        match source.get_expiry(namespace, &key).await? {
+            // The key expired after it was listed
+            Some(expires_at) if expires_at <= now => continue,
+            Some(expires_at) => {
+                destination
+                    .insert_with_ttl(namespace.to_string(), key, value, expires_at)
+                    .await?
+            }
+            None => {
+                destination
+                    .insert(namespace.to_string(), key, value)
+                    .await?
+            }
+        };
+        count += 1;
+    }
+    println!("Copied {count} keys in namespace [{namespace}]");
     Ok(())
 }
@@ -11,13 +11,14 @@ use plaid::{
         CachingMode, ConfigurationWithRoles, GetMode, ResponseMode, WebhookConfig,
         WebhookServerConfiguration,
     },
-    loader::PlaidModule,
+    loader::PlaidModules,
     logging::Logger,
+    webhooks::metrics::WebhookMetrics,
     *,
 };
 
 use apis::Api;
-use data::Data;
+use data::{Data, DelayedMessage};
 use executor::metrics::{ModuleExecutionMetrics, QueueMetrics};
 use executor::*;
 use plaid::metrics::MetricsHandle;
@@ -73,6 +74,7 @@ async fn post_handler(
     headers: HeaderMap,
     webhooks: HashMap<String, WebhookConfig>,
     exec: Arc<Executor>,
+    webhook_metrics: Option<Arc<WebhookMetrics>>,
 ) -> impl warp::Reply {
     // The status code we'll return. Defaults to 200, but is bumped to 429 if the
     // execution system's bounded queue is full so the sender can back off and retry.
@@ -98,6 +100,26 @@ async fn post_handler(
             }
         };
 
+        // Reject requests that aren't signed by the sender before anything else sees them
+        if let Some(verification) = &webhook_configuration.verification {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            if let Err(e) = verification.verify(&headers, &full_body, now) {
+                error!("Rejecting request to webhook [{webhook}]. Verification failed: {e}");
+                if let Some(webhook_metrics) = &webhook_metrics {
+                    webhook_metrics.record_verification_failure(
+                        webhook_configuration.label.as_deref().unwrap_or(&webhook),
+                    );
+                }
+                return Box::new(warp::reply::with_status(
+                    warp::reply(),
+                    StatusCode::UNAUTHORIZED,
+                ));
+            }
+        }
+
         // Create the message we're going to send into the execution system.
         let mut message = Message::new(
             webhook_configuration.log_type.to_owned(),
@@ -117,10 +139,15 @@ async fn post_handler(
             }
         }
 
+        // Continue the sender's trace, if they sent one. The span ends once the log is queued.
+        let traceparent = headers.get("traceparent").and_then(|v| v.to_str().ok());
+        let trace_context = telemetry::start_ingestion(&webhook, traceparent, &mut message);
+
         // Webhook exists, buffer log
         if let Err(e) = exec.execute_webhook_message(message) {
             match e {
                 TrySendError::Full(_) => {
+                    telemetry::record_error(&trace_context, "execution queue is full");
                     error!(
                         "Queue Full! [{}] log dropped!",
                         webhook_configuration.log_type
@@ -253,6 +280,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let ConfigurationWithRoles { config, roles } = config::configure()?;
     info!("This is what this instance is running: {roles:?}");
 
+    let tracing = match config.tracing {
+        Some(tracing_config) => {
+            info!("Exporting traces to: {}", tracing_config.endpoint);
+            Some(telemetry::Tracing::start(tracing_config)?)
+        }
+        None => None,
+    };
+
     // Create thread pools for log execution
     let exec_thread_pools = thread_pools::ExecutionThreadPools::new(&config.executor);
 
@@ -261,11 +296,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .as_ref()
         .map(|_| Arc::new(MetricsHandle::new()));
 
-    let module_execution_metrics = if let Some(handle) = &metrics {
+    let (module_execution_metrics, webhook_metrics) = if let Some(handle) = &metrics {
         QueueMetrics::register(handle, &exec_thread_pools);
-        Some(Arc::new(ModuleExecutionMetrics::register(handle)))
+        (
+            Some(Arc::new(ModuleExecutionMetrics::register(handle))),
+            Some(Arc::new(WebhookMetrics::register(handle))),
+        )
     } else {
-        None
+        (None, None)
     };
 
     // For convenience, keep a sender for the general channel, so that we can quickly clone it around
@@ -291,6 +329,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 Some(storage::DatabaseConfig::Sled(_)) => {
                     info!("Using Sled");
                 }
+                #[cfg(feature = "sql")]
+                Some(storage::DatabaseConfig::Sql(_)) => {
+                    info!("Using a SQL DB");
+                }
+                #[cfg(feature = "redis")]
+                Some(storage::DatabaseConfig::Redis(_)) => {
+                    info!("Using Redis");
+                }
                 Some(storage::DatabaseConfig::InMemory) => {
                     info!("Using an in-memory DB");
                     warn!(
@@ -401,12 +447,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("Loading all the modules");
     // Load all the modules that form our Nanoservices and Plaid rules
+    let loading_config = Arc::new(config.loading);
     let modules = Arc::new(
-        loader::load(&config.loading, storage.clone())
+        loader::load(&loading_config, storage.clone())
             .await
             .map_err(|_| Errors::FailedToLoadModules)?,
     );
-    let modules_by_name = Arc::new(modules.get_modules());
 
     let modules_and_logtypes = modules.get_module_logtypes();
 
@@ -457,22 +503,90 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Create the executor that will handle all the logs that come in and immediate
     // requests for handling some configured get requests.
+    // Failed executions that are out of retries are parked in the internal storage
+    let dead_letters = retry::DeadLetterQueue::new(internal_storage.clone());
+    let retry_handler = Arc::new(retry::RetryHandler::new(
+        config.executor.retry_policies,
+        dead_letters.clone(),
+    ));
+
+    // If configured, sampled and failed messages are recorded so they can be replayed later
+    let (recorder, recording_handle) = match config.executor.recorder {
+        Some(recorder_config) => {
+            let (recorder, writer) = recorder::Recorder::new(recorder_config);
+            (
+                Some(Arc::new(recorder)),
+                Some(tokio::task::spawn(writer.start())),
+            )
+        }
+        None => (None, None),
+    };
+
     let (executor, executor_threads) = Executor::new(
         exec_thread_pools.clone(),
-        modules.get_channels(),
+        modules.clone(),
         api,
-        storage,
-        Some(cache),
+        storage.clone(),
+        Some(cache.clone()),
         els.clone(),
         performance_sender.clone(),
         module_execution_metrics.clone(),
         Arc::downgrade(&immediate_dispatch),
         delayed_log_sender.clone(),
         cancellation_token.clone(),
+        retry_handler,
+        recorder,
     );
 
     let executor = Arc::new(executor);
 
+    let reload_task = if loading_config.hot_reload.is_some() {
+        info!("Hot reloading of modules is enabled");
+        Some(spawn(loader::watch_for_module_changes(
+            loading_config.clone(),
+            modules.clone(),
+            storage.clone(),
+            Some(cache.clone()),
+            cancellation_token.clone(),
+        )))
+    } else {
+        None
+    };
+
+    // Keys inserted with a TTL are deleted once they expire
+    let expiry_task = storage.clone().map(|storage| {
+        spawn(storage::expiry::sweep_expired_keys(
+            storage,
+            modules.clone(),
+            cancellation_token.clone(),
+        ))
+    });
+
+    if let Some(admin_config) = config.admin {
+        let state = Arc::new(admin::AdminState {
+            modules: modules.clone(),
+            executor: executor.clone(),
+            delayed_logs: delayed_log_persister.clone(),
+            dead_letters,
+            loading_config: loading_config.clone(),
+            storage: storage.clone(),
+            cache: Some(cache.clone()),
+        });
+        let routes = admin::routes(&admin_config, state);
+        let listen_address = admin_config.listen_address;
+        let token = cancellation_token.clone();
+
+        info!("Started admin server at: {listen_address}");
+        server_tasks.spawn(async move {
+            let (_, server) =
+                warp::serve(routes).bind_with_graceful_shutdown(listen_address, async move {
+                    token.cancelled().await;
+                });
+            server.await;
+            info!("Admin server shut down");
+        });
+    }
+
     if roles.webhooks {
         info!("Configured Webhook Servers");
         for (server_name, config) in config.webhooks {
@@ -489,6 +603,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .and(warp::header::headers_cloned())
                 .and(with(webhooks))
                 .and(with(exec.clone()))
+                .and(with(webhook_metrics.clone()))
                 .then(post_handler);
 
             // This is a cache for get requests that are configured to be cached
@@ -503,10 +618,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .and(warp::body::stream())
                 .and(warp::header::headers_cloned())
                 .and(with(webhook_config.clone()))
-                .and(with(modules_by_name.clone()))
+                .and(with(modules.clone()))
                 .and(with(get_cache.clone()))
                 .and(with(webhook_server_get_log_sender.clone()))
-                .and_then(|webhook: String, query: HashMap<String, String>, body, headers: HeaderMap, webhook_config: Arc<WebhookServerConfiguration>, modules: Arc<HashMap<String, Arc<PlaidModule>>>, get_cache: Arc<RwLock<HashMap<String, (u64, String)>>>, log_sender: crossbeam_channel::Sender<Message>| async move {
+                .and_then(|webhook: String, query: HashMap<String, String>, body, headers: HeaderMap, webhook_config: Arc<WebhookServerConfiguration>, modules: Arc<PlaidModules>, get_cache: Arc<RwLock<HashMap<String, (u64, String)>>>, log_sender: crossbeam_channel::Sender<Message>| async move {
                     if let Some(webhook_configuration) = webhook_config.webhooks.get(&webhook) {
                         match &webhook_configuration.get_mode {
                             // Note that CacheMode is elided here as there is no caching for static data
@@ -533,7 +648,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             // kind of reponse significantly more complex.
                             Some(GetMode{ response_mode: ResponseMode::Rule(name), caching_mode}) => {
                                 // Ensure that the rule configured to generated the GET response actually exists
-                                let rule = if let Some(rule) = modules.get(name) {
+                                let rule = if let Some(rule) = modules.get_module(name) {
                                     rule
                                 } else {
                                     warn!("Got a get request to {webhook} but the rule [{name}] configured to handle it does not exist");
@@ -614,8 +729,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                     }
                                 }
 
+                                // Continue the sender's trace, if they sent one. The span ends once the response is sent.
+                                let traceparent = headers.get("traceparent").and_then(|v| v.to_str().ok());
+                                let trace_context = telemetry::start_ingestion(&webhook, traceparent, &mut message);
+
                                 // Put the message into the standard message queue
                                 if let Err(e) = log_sender.try_send(message) {
+                                    telemetry::record_error(&trace_context, "execution queue is full");
                                     match e {
                                         TrySendError::Full(_) => error!("Queue Full! [{}] log dropped!", webhook_configuration.log_type),
                                         // TODO: Have this actually cause Plaid to exit
@@ -708,6 +828,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         log_join_result("data generator", result);
     }
 
+    if let Some(reload_task) = reload_task {
+        log_join_result("module reload", reload_task.await);
+    }
+
+    if let Some(expiry_task) = expiry_task {
+        log_join_result("storage expiry sweeper", expiry_task.await);
+    }
+
     // Webhook/probe servers stop accepting new requests once cancelled; join any in-flight work.
     info!("Waiting for server tasks to shutdown...");
     while let Some(result) = server_tasks.join_next().await {
@@ -720,14 +848,28 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     drop(log_sender);
     drop(exec_thread_pools);
     drop(immediate_dispatch);
+    let paused_log_types = executor.paused_log_types();
     drop(executor);
     executor_threads.join();
 
     // Persist any delayed logbacks still in the in-memory channel.
     info!("Flushing delayed logbacks to storage...");
     delayed_log_persister.flush_pending().await;
+
+    // Messages held for paused log types are persisted as logbacks so they run after a restart
+    for message in paused_log_types.take_all() {
+        delayed_log_persister
+            .persist(DelayedMessage::new(0, message))
+            .await;
+    }
     drop(delayed_log_sender);
 
+    // The recording writer exits once the executor threads have dropped the recorder
+    if let Some(handle) = recording_handle {
+        info!("Waiting for recordings to be written...");
+        log_join_result("recording writer", handle.await);
+    }
+
     // Performance loop exits the final sender disconnects.
     drop(performance_sender);
     if let Some(handle) = performance_handle {
@@ -744,6 +886,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         error!("Logging thread panicked during shutdown: {e:?}");
     }
 
+    // Every execution has finished, so export the last of the spans
+    if let Some(tracing) = tracing {
+        info!("Exporting remaining traces...");
+        tracing.shutdown();
+    }
+
     info!("Plaid shutdown complete.");
     Ok(())
 }
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use clap::Parser;
+use plaid::apis::mock::MockResponse;
+use plaid::harness::replay::{read_recordings, replay};
+use plaid::harness::{load_module, Harness, DEFAULT_COMPUTATION_LIMIT, DEFAULT_PAGE_LIMIT};
+use plaid::loader::{CompilerBackend, PlaidModule};
+
+#[derive(Parser)]
+#[command(
+    name = "plaid-replay",
+    version,
+    about = "Replay recorded messages on a module and diff its side effects against another version",
+    long_about = None
+)]
+struct Cli {
+    /// The module to replay the recordings on
+    #[arg(long)]
+    module: PathBuf,
+    /// Another version of the module to compare with
+    #[arg(long)]
+    baseline: Option<PathBuf>,
+    /// A JSON file with mocked API responses, keyed by host function name
+    #[arg(long)]
+    mocks: Option<PathBuf>,
+    /// Only replay recordings of this log type
+    #[arg(long)]
+    log_type: Option<String>,
+    /// The computation limit for each run
+    #[arg(long, default_value_t = DEFAULT_COMPUTATION_LIMIT)]
+    computation_limit: u64,
+    /// The number of memory pages the modules can use
+    #[arg(long, default_value_t = DEFAULT_PAGE_LIMIT)]
+    page_limit: u64,
+    /// Files with recordings, one recording per line
+    #[arg(required = true)]
+    recordings: Vec<PathBuf>,
+}
+
+fn load(cli: &Cli, path: &Path) -> PlaidModule {
+    match load_module(
+        path,
+        cli.log_type.clone(),
+        cli.computation_limit,
+        cli.page_limit,
+        &CompilerBackend::Cranelift,
+    ) {
+        Ok(module) => module,
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    }
+}
+
+fn main() {
+    env_logger::init();
+    let cli = Cli::parse();
+
+    let mocks: HashMap<String, Vec<MockResponse>> = match &cli.mocks {
+        Some(path) => {
+            let mocks = std::fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|mocks| serde_json::from_str(&mocks).map_err(|e| e.to_string()));
+            match mocks {
+                Ok(mocks) => mocks,
+                Err(e) => {
+                    eprintln!("Could not read mocks from {}: {e}", path.display());
+                    process::exit(1);
+                }
+            }
+        }
+        None => HashMap::new(),
+    };
+
+    let mut recordings = vec![];
+    for path in &cli.recordings {
+        match read_recordings(path) {
+            Ok(read) => recordings.extend(read),
+            Err(e) => {
+                eprintln!("{e}");
+                process::exit(1);
+            }
+        }
+    }
+    if let Some(log_type) = &cli.log_type {
+        recordings.retain(|recording| &recording.message.type_ == log_type);
+    }
+
+    let harnesses = Harness::new(load(&cli, &cli.module), HashMap::new()).and_then(|candidate| {
+        let baseline = cli
+            .baseline
+            .as_ref()
+            .map(|baseline| Harness::new(load(&cli, baseline), HashMap::new()))
+            .transpose()?;
+        Ok((candidate, baseline))
+    });
+    let results = harnesses.and_then(|(candidate, baseline)| {
+        replay(recordings, &mocks, &candidate, baseline.as_ref())
+    });
+    let results = match results {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    };
+
+    let mut changed = 0;
+    for result in &results {
+        let status = if !result.differences.is_empty() {
+            changed += 1;
+            "changed"
+        } else if result.outcome.errors.is_empty() {
+            "ok"
+        } else {
+            "error"
+        };
+        println!(
+            "[{status}] {} ({}, {:?})",
+            result.message_id, result.log_type, result.reason
+        );
+        for error in &result.outcome.errors {
+            println!("    error: {error}");
+        }
+        for difference in &result.differences {
+            println!("    {difference}");
+        }
+    }
+
+    println!("\n{} replayed, {changed} changed", results.len());
+    if changed > 0 {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,51 @@
+use std::env;
+use std::path::Path;
+use std::process;
+
+use plaid::harness::run_fixture;
+use plaid::loader::CompilerBackend;
+
+/// Run modules against JSON fixture files and report which cases pass.
+///
+/// Exits with a non-zero status if any case fails or a fixture cannot be run.
+fn main() {
+    env_logger::init();
+
+    let fixtures: Vec<String> = env::args().skip(1).collect();
+    if fixtures.is_empty() {
+        eprintln!("Usage: plaid-test <fixture.json> [<fixture.json>...]");
+        process::exit(1);
+    }
+
+    let mut passed = 0;
+    let mut failed = 0;
+    for fixture in &fixtures {
+        println!("{fixture}");
+        let results = match run_fixture(Path::new(fixture), &CompilerBackend::Cranelift) {
+            Ok(results) => results,
+            Err(e) => {
+                eprintln!("  ❌ {e}");
+                failed += 1;
+                continue;
+            }
+        };
+
+        for result in results {
+            if result.failures.is_empty() {
+                println!("  ✅ {}", result.name);
+                passed += 1;
+            } else {
+                println!("  ❌ {}", result.name);
+                for failure in result.failures {
+                    println!("       {failure}");
+                }
+                failed += 1;
+            }
+        }
+    }
+
+    println!("\n{passed} passed, {failed} failed");
+    if failed > 0 {
+        process::exit(1);
+    }
+}
@@ -3,6 +3,7 @@ use aws_config::{BehaviorVersion, Region};
 use aws_sdk_dynamodb::types::AttributeValue;
 use aws_sdk_dynamodb::{Client, Error};
 use clap::{Arg, Command};
+use plaid::storage::sled::{expiry_key, parse_expires_at, EXPIRIES_TREE};
 use sled::{Db, Tree};
 
 /// Migrate a sled tree by pushing all its entries to AWS DynamoDB.
@@ -11,11 +12,13 @@ use sled::{Db, Tree};
 /// * the name of the sled tree becomes the "namespace" (DDB partition key)
 /// * the sled key becomes the "key" (DDB sort key)
 /// * the sled value becomes the "value" (a DDB field called "value")
+/// * the expiry of keys inserted with a TTL, if any, becomes "expires_at" (a DDB field)
 async fn migrate_tree(
     client: &Client,
     table_name: &str,
     namespace: &str,
     tree: &Tree,
+    expiries: &Tree,
 ) -> Result<(), Error> {
     println!("Migrating namespace: {namespace}");
 
@@ -23,14 +26,21 @@ async fn migrate_tree(
         let (key, value) = item.expect("Failed to get key/value from tree");
         let key_str = String::from_utf8(key.to_vec()).expect("Failed to parse key");
 
-        client
+        let expires_at = expiries
+            .get(expiry_key(namespace, &key_str))
+            .expect("Failed to get expiry from tree")
+            .and_then(|v| parse_expires_at(&v));
+
+        let mut request = client
             .put_item()
             .table_name(table_name)
             .item("namespace", AttributeValue::S(namespace.to_string()))
             .item("key", AttributeValue::S(key_str))
-            .item("value", AttributeValue::B(value.to_vec().into()))
-            .send()
-            .await?;
+            .item("value", AttributeValue::B(value.to_vec().into()));
+        if let Some(expires_at) = expires_at {
+            request = request.item("expires_at", AttributeValue::N(expires_at.to_string()));
+        }
+        request.send().await?;
     }
 
     Ok(())
@@ -79,20 +89,24 @@ async fn main() -> Result<(), Error> {
     // See https://docs.rs/sled/latest/sled/struct.Tree.html
     // Db implements Deref<Target = Tree> such that a Db acts like the “default” Tree.
     let default_tree = &*db;
-    migrate_tree(&client, table_name, "default", default_tree).await?;
+    let expiries = db
+        .open_tree(EXPIRIES_TREE)
+        .expect("Failed to open the expiries tree");
+    migrate_tree(&client, table_name, "default", default_tree, &expiries).await?;
 
     // Migrate all named trees
     for tree_name in db.tree_names() {
         if tree_name != b"" {
             let name_str =
                 String::from_utf8(tree_name.to_vec()).expect("Failed to parse tree name");
-            if name_str == "__sled__default" {
+            // Expiries are migrated along with the keys they belong to
+            if name_str == "__sled__default" || name_str == EXPIRIES_TREE {
                 continue;
             }
             let tree = db
                 .open_tree(&tree_name)
                 .expect(&format!("Failed to open tree {name_str}"));
-            migrate_tree(&client, table_name, &name_str, &tree).await?;
+            migrate_tree(&client, table_name, &name_str, &tree, &expiries).await?;
         }
     }
 
@@ -1,6 +1,6 @@
 //! This module provides a way for Plaid to use an in-memory cache.
 
-use crate::cache::CacheError;
+use crate::{cache::CacheError, loader::LimitedAmount};
 use async_trait::async_trait;
 use lru::LruCache;
 use std::{collections::HashMap, num::NonZeroUsize};
@@ -9,7 +9,9 @@ use tokio::sync::RwLock;
 /// A wrapper for the cache
 pub struct InMemoryCache {
     /// This is mapping module names to LruCache objects: one cache per module
-    caches: HashMap<String, RwLock<LruCache<String, String>>>,
+    caches: RwLock<HashMap<String, RwLock<LruCache<String, String>>>>,
+    /// How many entries each module's cache can hold
+    cache_entries: LimitedAmount,
 }
 
 impl InMemoryCache {
@@ -18,26 +20,38 @@ impl InMemoryCache {
         let caches: HashMap<String, RwLock<LruCache<String, String>>> = modules_and_logtypes
             .iter()
             .map(|(module, logtype)| {
-                // Figure out the capacity of the cache for this module
-                let mut capacity = config.cache_entries.default;
-                if let Some(v) = config.cache_entries.log_type.get(logtype) {
-                    capacity = *v;
-                }
-                if let Some(v) = config.cache_entries.module_overrides.get(module) {
-                    capacity = *v;
-                }
-
-                let module_cache =
-                    RwLock::new(LruCache::new(NonZeroUsize::new(capacity as usize).unwrap()));
-
-                (module.to_string(), module_cache)
+                (
+                    module.to_string(),
+                    module_cache(&config.cache_entries, module, logtype),
+                )
             })
             .collect();
 
-        InMemoryCache { caches }
+        InMemoryCache {
+            caches: RwLock::new(caches),
+            cache_entries: config.cache_entries,
+        }
     }
 }
 
+/// Create an empty cache for a module, sized for the module and its log type
+fn module_cache(
+    cache_entries: &LimitedAmount,
+    module: &str,
+    logtype: &str,
+) -> RwLock<LruCache<String, String>> {
+    // Figure out the capacity of the cache for this module
+    let mut capacity = cache_entries.default;
+    if let Some(v) = cache_entries.log_type.get(logtype) {
+        capacity = *v;
+    }
+    if let Some(v) = cache_entries.module_overrides.get(module) {
+        capacity = *v;
+    }
+
+    RwLock::new(LruCache::new(NonZeroUsize::new(capacity as usize).unwrap()))
+}
+
 #[async_trait]
 impl super::CacheProvider for InMemoryCache {
     async fn put(
@@ -46,25 +60,36 @@ impl super::CacheProvider for InMemoryCache {
         key: &str,
         value: &str,
     ) -> Result<Option<String>, CacheError> {
-        let module_cache = self
-            .caches
+        let caches = self.caches.read().await;
+        let module_cache = caches
             .get(namespace)
             .ok_or(CacheError::CacheAccessError(format!(
                 "Cache not found for module {namespace}"
             )))?;
-        Ok(module_cache
+        let previous = module_cache
             .write()
             .await
-            .put(key.to_string(), value.to_string()))
+            .put(key.to_string(), value.to_string());
+        Ok(previous)
     }
 
     async fn get(&self, namespace: &str, key: &str) -> Result<Option<String>, CacheError> {
-        let module_cache = self
-            .caches
+        let caches = self.caches.read().await;
+        let module_cache = caches
             .get(namespace)
             .ok_or(CacheError::CacheAccessError(format!(
                 "Cache not found for module {namespace}"
             )))?;
-        Ok(module_cache.write().await.get(&key.to_string()).cloned())
+        let value = module_cache.write().await.get(&key.to_string()).cloned();
+        Ok(value)
+    }
+
+    async fn add_module(&self, module: &str, logtype: &str) {
+        // A module that is replaced by a new version keeps its cache
+        self.caches
+            .write()
+            .await
+            .entry(module.to_string())
+            .or_insert_with(|| module_cache(&self.cache_entries, module, logtype));
     }
 }
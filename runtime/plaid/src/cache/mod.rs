@@ -80,6 +80,9 @@ pub trait CacheProvider {
     ) -> Result<Option<String>, CacheError>;
     /// Get a value from the cache, if present.
     async fn get(&self, namespace: &str, key: &str) -> Result<Option<String>, CacheError>;
+    /// Make the cache usable by a module loaded after Plaid started. Providers without
+    /// per-module state have nothing to do.
+    async fn add_module(&self, _module: &str, _logtype: &str) {}
 }
 
 impl Cache {
@@ -127,4 +130,9 @@ impl Cache {
             .get(&namespace.to_string(), &key.to_string())
             .await
     }
+
+    /// Make the cache usable by a module loaded after Plaid started
+    pub async fn add_module(&self, module: &str, logtype: &str) {
+        self.cache.add_module(module, logtype).await
+    }
 }
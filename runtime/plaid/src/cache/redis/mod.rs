@@ -1,5 +1,7 @@
 //! This module provides a way for Plaid to use Redis as a cache layer
 
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use crate::{cache::CacheError, loader::LimitedAmount};
 use async_trait::async_trait;
 use redis::{aio::ConnectionManager, AsyncCommands};
@@ -13,6 +15,12 @@ pub enum EvictionPolicy {
     /// When the max number of entries is reached, an entry is
     /// evicted at random to make space for new insertions.
     RandomEviction,
+    /// When the max number of entries is reached, the entry that was
+    /// read or written least recently is evicted.
+    LruEviction,
+    /// When the max number of entries is reached, the entry that was
+    /// read or written the fewest times is evicted.
+    LfuEviction,
 }
 
 impl std::fmt::Display for EvictionPolicy {
@@ -20,11 +28,25 @@ impl std::fmt::Display for EvictionPolicy {
         let output = match self {
             Self::NoEviction => "no eviction".to_string(),
             Self::RandomEviction => "random eviction".to_string(),
+            Self::LruEviction => "LRU eviction".to_string(),
+            Self::LfuEviction => "LFU eviction".to_string(),
         };
         write!(f, "{output}")
     }
 }
 
+impl EvictionPolicy {
+    /// How the eviction script refers to this policy
+    fn script_name(&self) -> &'static str {
+        match self {
+            Self::NoEviction => "none",
+            Self::RandomEviction => "random",
+            Self::LruEviction => "lru",
+            Self::LfuEviction => "lfu",
+        }
+    }
+}
+
 impl Default for EvictionPolicy {
     fn default() -> Self {
         Self::NoEviction
@@ -92,6 +114,19 @@ impl Config {
     }
 }
 
+/// The sorted set tracking how the entries of a namespace are used, for LRU and LFU eviction
+fn usage_key(namespace: &str) -> String {
+    format!("{namespace}:usage")
+}
+
+/// The current Unix time, in milliseconds
+fn now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
 impl RedisCache {
     pub async fn new(config: Config, max_capacity: LimitedAmount) -> Result<Self, CacheError> {
         let conn_string = config.build_connection_string();
@@ -140,7 +175,9 @@ impl super::CacheProvider for RedisCache {
 
                 old
             }
-            EvictionPolicy::RandomEviction => {
+            EvictionPolicy::RandomEviction
+            | EvictionPolicy::LruEviction
+            | EvictionPolicy::LfuEviction => {
                 // Figure out how many entries can be stored in this cache.
                 // If we have a value for this namespace (i.e., this module), use
                 // that; otherwise fallback on the default value.
@@ -155,9 +192,12 @@ impl super::CacheProvider for RedisCache {
 
                 let (old, evicted): (Option<String>, bool) = script
                     .key(namespace)
+                    .key(usage_key(namespace))
                     .arg(key)
                     .arg(value)
                     .arg(max_entries)
+                    .arg(self.eviction_policy.script_name())
+                    .arg(now_millis().to_string())
                     .invoke_async(&mut connection)
                     .await
                     .map_err(|e| CacheError::PutError(e.to_string()))?;
@@ -175,10 +215,34 @@ impl super::CacheProvider for RedisCache {
 
     async fn get(&self, namespace: &str, key: &str) -> Result<Option<String>, CacheError> {
         let mut connection = self.connection_manager.clone();
-        let r = connection
-            .hget(namespace, key)
-            .await
-            .map_err(|e| CacheError::GetError(e.to_string()))?;
+        let r = match self.eviction_policy {
+            EvictionPolicy::NoEviction | EvictionPolicy::RandomEviction => connection
+                .hget(namespace, key)
+                .await
+                .map_err(|e| CacheError::GetError(e.to_string()))?,
+            // Reading an entry counts as using it. XX makes sure we only
+            // track entries that are already in the cache.
+            EvictionPolicy::LruEviction | EvictionPolicy::LfuEviction => {
+                let mut usage = redis::cmd("ZADD");
+                usage.arg(usage_key(namespace)).arg("XX");
+                match self.eviction_policy {
+                    EvictionPolicy::LruEviction => usage.arg(now_millis().to_string()),
+                    _ => usage.arg("INCR").arg(1),
+                };
+                usage.arg(key);
+
+                let (r,): (Option<String>,) = redis::pipe()
+                    .cmd("HGET")
+                    .arg(namespace)
+                    .arg(key)
+                    .add_command(usage)
+                    .ignore()
+                    .query_async(&mut connection)
+                    .await
+                    .map_err(|e| CacheError::GetError(e.to_string()))?;
+                r
+            }
+        };
         Ok(r)
     }
 }
@@ -9,13 +9,18 @@ use std::path::PathBuf;
 use crate::performance::PerformanceMonitoring;
 use crate::InstanceRoles;
 
+use super::admin::AdminConfiguration;
 use super::apis::ApiConfigs;
 use super::cache::Config as CacheConfig;
 use super::data::DataConfig;
+use super::executor::recorder::RecorderConfig;
+use super::executor::retry::RetryPolicy;
 use super::loader::Configuration as LoaderConfiguration;
 use super::logging::LoggingConfiguration;
 use super::metrics::MetricsConfiguration;
 use super::storage::Config as StorageConfig;
+use super::telemetry::TracingConfiguration;
+use super::webhooks::verification::{deserialize_verification, WebhookVerification};
 
 /// How should responses to GET requests be cached.
 #[derive(Default, Deserialize, Clone)]
@@ -97,6 +102,10 @@ pub struct WebhookConfig {
     /// will be able to as well). If this is not set, it will default to Limited(0).
     #[serde(default)]
     pub logbacks_allowed: LogbacksAllowed,
+    /// If set, POST requests must carry a valid signature from the sender. Requests that
+    /// fail verification are rejected with a 401 and never reach the modules.
+    #[serde(default, deserialize_with = "deserialize_verification")]
+    pub verification: Option<WebhookVerification>,
 }
 
 /// Configuration for a webhook server
@@ -132,6 +141,14 @@ pub struct ExecutorConfig {
     /// This is a mapping {log type --> num threads}.
     #[serde(default)]
     pub dedicated_threads: HashMap<String, DedicatedThreadsConfig>,
+    /// How failed module executions are retried for specific log types.
+    /// This is a mapping {log type --> retry policy}. Failures for log types
+    /// without a policy are logged and the message is not run again.
+    #[serde(default)]
+    pub retry_policies: HashMap<String, RetryPolicy>,
+    /// Records production messages so module failures can be replayed later.
+    /// Nothing is recorded if this is not set.
+    pub recorder: Option<RecorderConfig>,
 }
 
 /// The full configuration of Plaid
@@ -164,6 +181,10 @@ pub struct Configuration {
     pub cache: CacheConfig,
     /// Optional Prometheus metrics endpoint configuration.
     pub metrics: Option<MetricsConfiguration>,
+    /// Optional OpenTelemetry tracing of messages through the system.
+    pub tracing: Option<TracingConfiguration>,
+    /// Optional admin API for inspecting and controlling this instance.
+    pub admin: Option<AdminConfiguration>,
 }
 
 /// Plaid's configuration augmented with the roles that this instance is playing.
@@ -0,0 +1,657 @@
+use crate::{executor::Message, parse_duration, storage::Storage};
+use crossbeam_channel::{Sender, TrySendError};
+use plaid_stl::messages::{Generator, LogSource, LogbacksAllowed};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::Metadata,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+    task::JoinSet,
+};
+use tokio_util::sync::CancellationToken;
+
+use super::get_dg_storage_namespace;
+
+/// The number of bytes read from a file at a time
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Configuration of all file tailing data generators.
+#[derive(Deserialize)]
+pub struct FileTailConfig {
+    /// A map of tails, identified by their name. The name is also used to store
+    /// the read offsets, so renaming a tail makes it start over.
+    tails: HashMap<String, FileTailerConfig>,
+}
+
+/// Represents the configuration for a tail that follows a set of files and sends
+/// every record appended to them to a log type.
+#[derive(Deserialize)]
+pub struct FileTailerConfig {
+    /// The files to follow. Each entry is a path or a glob pattern (e.g., `/var/log/app/*.jsonl`),
+    /// which is expanded again on every poll so new files are picked up. Files are recognized after
+    /// being renamed, so patterns can also match the names files are rotated to.
+    paths: Vec<String>,
+    /// The log type that records are sent to
+    log_type: String,
+    /// The sequence of characters records are separated by.
+    /// If no value is provided here, we will use a newline.
+    #[serde(default = "default_delimiter")]
+    #[serde(deserialize_with = "parse_delimiter")]
+    delimiter: String,
+    /// Where to start reading files that are found when Plaid starts and for which no offset
+    /// is stored. Files that appear later are always read from the beginning.
+    #[serde(default)]
+    start_position: StartPosition,
+    /// Denotes if logs produced by this generator are allowed to initiate log backs
+    #[serde(default)]
+    logbacks_allowed: LogbacksAllowed,
+    /// Time to wait in between checks for new data.
+    /// If no value is provided here, we will use a default value (1 second).
+    #[serde(default = "default_poll_interval")]
+    #[serde(deserialize_with = "parse_duration")]
+    poll_interval: Duration,
+    /// The maximum size of a single record, in bytes. Longer records are discarded.
+    /// If no value is provided here, we will use a default value (1 MiB).
+    #[serde(default = "default_max_record_size")]
+    max_record_size: usize,
+    /// Time to wait before trying again to send a record to the executor, when its queue is full.
+    /// If no value is provided here, we will use a default value (100 milliseconds).
+    #[serde(default = "default_queue_full_backoff")]
+    #[serde(deserialize_with = "parse_duration")]
+    queue_full_backoff: Duration,
+}
+
+/// Where reading starts in a file without a stored offset
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StartPosition {
+    /// Send everything already in the file
+    #[default]
+    Beginning,
+    /// Only send what is appended from now on
+    End,
+}
+
+/// Custom parser for the delimiter. Returns an error if the delimiter is empty.
+fn parse_delimiter<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let delimiter = String::deserialize(deserializer)?;
+    if delimiter.is_empty() {
+        return Err(serde::de::Error::custom("The delimiter cannot be empty"));
+    }
+    Ok(delimiter)
+}
+
+fn default_delimiter() -> String {
+    "\n".to_string()
+}
+
+/// This function provides the default poll interval.
+/// It is used as the default value for deserialization of the `poll_interval` field,
+/// of `FileTailerConfig` in the event that no value is provided.
+fn default_poll_interval() -> Duration {
+    Duration::from_millis(1000)
+}
+
+/// This function provides the default maximum record size.
+fn default_max_record_size() -> usize {
+    1024 * 1024
+}
+
+/// This function provides the default wait before retrying to send a record to a full queue.
+/// It is used as the default value for deserialization of the `queue_full_backoff` field,
+/// of `FileTailerConfig` in the event that no value is provided.
+fn default_queue_full_backoff() -> Duration {
+    Duration::from_millis(100)
+}
+
+/// The read position of a file, as persisted in storage under the file's [`FileId`]
+#[derive(Serialize, Deserialize)]
+struct StoredOffset {
+    /// The offset of the first byte that has not been sent for processing
+    offset: u64,
+}
+
+/// Represents the entire file tailing data generator set up
+pub struct FileTail {
+    /// The tails, each following its own set of files
+    tailers: Vec<FileTailer>,
+}
+
+/// Follows the files matching a set of patterns and forwards their records to the executor
+struct FileTailer {
+    /// The name of this tail
+    name: String,
+    /// The configuration of this tail
+    config: FileTailerConfig,
+    /// The logger used to send logs to the execution system for processing
+    logger: Sender<Message>,
+    /// Where read offsets are persisted
+    storage: Arc<Storage>,
+    /// The namespace read offsets are stored under, keyed by file
+    storage_namespace: String,
+    /// The files currently being followed
+    files: HashMap<FileId, TrackedFile>,
+    /// Whether the files have been listed at least once
+    started: bool,
+}
+
+/// A file being followed
+struct TrackedFile {
+    /// The open file. This is kept open so that a rotated file can be read to the end.
+    file: File,
+    /// The path the file was last seen at
+    path: PathBuf,
+    /// The offset of the first byte that has not been sent for processing
+    offset: u64,
+    /// Bytes read after `offset` that do not form a complete record yet
+    pending: Vec<u8>,
+    /// Whether the rest of the current record is being discarded because it is too long
+    skipping: bool,
+}
+
+/// Identifies a file, so that it is followed from the same offset after being renamed
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+struct FileId(String);
+
+/// Identify a file by its device and inode
+#[cfg(unix)]
+fn file_id(_path: &Path, metadata: &Metadata) -> FileId {
+    use std::os::unix::fs::MetadataExt;
+    FileId(format!("{}:{}", metadata.dev(), metadata.ino()))
+}
+
+/// Identify a file by its path. Without inodes, renamed files are read again and rotation
+/// is only detected when the file at a path becomes shorter.
+#[cfg(not(unix))]
+fn file_id(path: &Path, _metadata: &Metadata) -> FileId {
+    FileId(path.to_string_lossy().to_string())
+}
+
+/// Find the position of `delimiter` in `haystack`
+fn find_delimiter(haystack: &[u8], delimiter: &[u8]) -> Option<usize> {
+    haystack
+        .windows(delimiter.len())
+        .position(|window| window == delimiter)
+}
+
+impl FileTail {
+    pub fn new(config: FileTailConfig, logger: Sender<Message>, storage: Arc<Storage>) -> Self {
+        let tailers = config
+            .tails
+            .into_iter()
+            .map(|(name, config)| FileTailer {
+                storage_namespace: get_dg_storage_namespace(
+                    &Generator::FileTail(name.clone()).to_string(),
+                ),
+                name,
+                config,
+                logger: logger.clone(),
+                storage: storage.clone(),
+                files: HashMap::new(),
+                started: false,
+            })
+            .collect();
+
+        Self { tailers }
+    }
+
+    /// Start a task for each tail. Each task returns once `cancellation_token` is cancelled.
+    pub fn start(self, join_set: &mut JoinSet<()>, cancellation_token: CancellationToken) {
+        for tailer in self.tailers {
+            join_set.spawn(tailer.follow(cancellation_token.clone()));
+        }
+    }
+}
+
+impl FileTailer {
+    async fn follow(mut self, ct: CancellationToken) {
+        loop {
+            if !self.poll(&ct).await {
+                return;
+            }
+
+            // Allow shutdown to interrupt the sleep immediately instead of
+            // waiting the full interval before exiting the task.
+            tokio::select! {
+                _ = ct.cancelled() => return,
+                _ = tokio::time::sleep(self.config.poll_interval) => {}
+            }
+        }
+    }
+
+    /// List the files that currently match the configured paths
+    fn list_files(&self) -> BTreeSet<PathBuf> {
+        let mut paths = BTreeSet::new();
+        for pattern in &self.config.paths {
+            match glob::glob(pattern) {
+                Ok(matches) => paths.extend(matches.filter_map(Result::ok).filter(|p| p.is_file())),
+                Err(e) => error!("file_tail/{} invalid pattern [{pattern}]: {e}", self.name),
+            }
+        }
+        paths
+    }
+
+    /// Send the records appended to the followed files since the last poll.
+    /// Returns false if the tail should stop.
+    async fn poll(&mut self, ct: &CancellationToken) -> bool {
+        let first_poll = !self.started;
+        self.started = true;
+
+        // Identify the files that currently match, so that renamed files are recognized
+        let mut current: Vec<(FileId, PathBuf, Metadata)> = vec![];
+        for path in self.list_files() {
+            match tokio::fs::metadata(&path).await {
+                Ok(metadata) => {
+                    let id = file_id(&path, &metadata);
+                    // A file with several matching hard links is only followed once
+                    if !current.iter().any(|(other, _, _)| *other == id) {
+                        current.push((id, path, metadata));
+                    }
+                }
+                Err(e) => error!(
+                    "file_tail/{} cannot read {}: {e}",
+                    self.name,
+                    path.display()
+                ),
+            }
+        }
+
+        // Files that no longer match were rotated away (or deleted): finish reading them
+        let gone: Vec<FileId> = self
+            .files
+            .keys()
+            .filter(|id| !current.iter().any(|(other, _, _)| other == *id))
+            .cloned()
+            .collect();
+        for id in gone {
+            let mut tracked = self.files.remove(&id).unwrap(); // we just listed the key
+            if !self.read_records(&id, &mut tracked, true, ct).await {
+                return false;
+            }
+            self.forget_offset(&id, &tracked.path).await;
+        }
+
+        for (id, path, metadata) in current {
+            let mut tracked = match self.files.remove(&id) {
+                Some(tracked) if metadata.len() < tracked.offset + tracked.pending.len() as u64 => {
+                    info!("file_tail/{} {} was truncated", self.name, path.display());
+                    match self.open(&path, 0).await {
+                        Some(tracked) => tracked,
+                        None => continue,
+                    }
+                }
+                Some(mut tracked) => {
+                    if tracked.path != path {
+                        info!(
+                            "file_tail/{} {} was renamed to {}",
+                            self.name,
+                            tracked.path.display(),
+                            path.display()
+                        );
+                        tracked.path = path;
+                    }
+                    tracked
+                }
+                None => {
+                    let start = self.initial_offset(&id, &path, &metadata, first_poll).await;
+                    match self.open(&path, start).await {
+                        Some(tracked) => tracked,
+                        None => continue,
+                    }
+                }
+            };
+
+            let keep_going = self.read_records(&id, &mut tracked, false, ct).await;
+            self.files.insert(id, tracked);
+            if !keep_going {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Decide where to start reading a file that is not being followed yet
+    async fn initial_offset(
+        &self,
+        id: &FileId,
+        path: &Path,
+        metadata: &Metadata,
+        first_poll: bool,
+    ) -> u64 {
+        match self.storage.get(&self.storage_namespace, &id.0).await {
+            Ok(Some(stored)) => match serde_json::from_slice::<StoredOffset>(&stored) {
+                Ok(stored) if stored.offset <= metadata.len() => return stored.offset,
+                Ok(_) => {
+                    info!(
+                        "file_tail/{} {} changed since its offset was stored, reading it from the beginning",
+                        self.name,
+                        path.display()
+                    );
+                    return 0;
+                }
+                Err(e) => error!(
+                    "file_tail/{} could not parse the stored offset of {}: {e}",
+                    self.name,
+                    path.display()
+                ),
+            },
+            Ok(None) => (),
+            Err(e) => error!(
+                "file_tail/{} could not read the stored offset of {}: {e}",
+                self.name,
+                path.display()
+            ),
+        }
+
+        match (first_poll, &self.config.start_position) {
+            (true, StartPosition::End) => metadata.len(),
+            _ => 0,
+        }
+    }
+
+    /// Open a file and position it at `offset`
+    async fn open(&self, path: &Path, offset: u64) -> Option<TrackedFile> {
+        let opened = async {
+            let mut file = File::open(path).await?;
+            file.seek(SeekFrom::Start(offset)).await?;
+            Ok::<_, std::io::Error>(file)
+        };
+
+        match opened.await {
+            Ok(file) => {
+                info!(
+                    "file_tail/{} following {} from offset {offset}",
+                    self.name,
+                    path.display()
+                );
+                Some(TrackedFile {
+                    file,
+                    path: path.to_path_buf(),
+                    offset,
+                    pending: vec![],
+                    skipping: false,
+                })
+            }
+            Err(e) => {
+                error!(
+                    "file_tail/{} cannot open {}: {e}",
+                    self.name,
+                    path.display()
+                );
+                None
+            }
+        }
+    }
+
+    /// Read a file to its end and send every complete record. If `last_read` is set, the file
+    /// will not be read again, so a final record without a delimiter is sent as well.
+    /// Returns false if the tail should stop.
+    async fn read_records(
+        &self,
+        id: &FileId,
+        tracked: &mut TrackedFile,
+        last_read: bool,
+        ct: &CancellationToken,
+    ) -> bool {
+        let path = tracked.path.clone();
+        let path = path.as_path();
+        let delimiter = self.config.delimiter.as_bytes();
+        let start_offset = tracked.offset;
+        let mut chunk = vec![0u8; READ_CHUNK_SIZE];
+        let mut keep_going = true;
+
+        'read: loop {
+            let read = match tracked.file.read(&mut chunk).await {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) => {
+                    error!(
+                        "file_tail/{} cannot read {}: {e}",
+                        self.name,
+                        path.display()
+                    );
+                    break;
+                }
+            };
+            tracked.pending.extend_from_slice(&chunk[..read]);
+
+            let mut consumed = 0;
+            while let Some(end) = find_delimiter(&tracked.pending[consumed..], delimiter) {
+                let record = &tracked.pending[consumed..consumed + end];
+                if tracked.skipping {
+                    tracked.skipping = false;
+                } else if !record.is_empty() && !self.send(record.to_vec(), path, ct).await {
+                    keep_going = false;
+                    tracked.pending.drain(..consumed);
+                    break 'read;
+                }
+                consumed += end + delimiter.len();
+                tracked.offset += (end + delimiter.len()) as u64;
+            }
+            tracked.pending.drain(..consumed);
+
+            if tracked.skipping || tracked.pending.len() > self.config.max_record_size {
+                if !tracked.skipping {
+                    error!(
+                        "file_tail/{} discarding a record longer than {} bytes in {}",
+                        self.name,
+                        self.config.max_record_size,
+                        path.display()
+                    );
+                    tracked.skipping = true;
+                }
+                tracked.offset += tracked.pending.len() as u64;
+                tracked.pending.clear();
+            }
+        }
+
+        if keep_going && last_read && !tracked.pending.is_empty() && !tracked.skipping {
+            let record = std::mem::take(&mut tracked.pending);
+            let length = record.len() as u64;
+            keep_going = self.send(record, path, ct).await;
+            if keep_going {
+                tracked.offset += length;
+            }
+        }
+
+        if tracked.offset != start_offset && !last_read {
+            self.store_offset(id, tracked).await;
+        }
+
+        keep_going
+    }
+
+    /// Persist the offset of a file, so that a restart resumes from it
+    async fn store_offset(&self, id: &FileId, tracked: &TrackedFile) {
+        let stored = StoredOffset {
+            offset: tracked.offset,
+        };
+        // Serializing this struct cannot fail
+        let stored = serde_json::to_vec(&stored).unwrap();
+
+        if let Err(e) = self
+            .storage
+            .insert(self.storage_namespace.clone(), id.0.clone(), stored)
+            .await
+        {
+            error!(
+                "file_tail/{} could not store the offset of {}. Continuing anyway. Error: {e}",
+                self.name,
+                tracked.path.display()
+            );
+        }
+    }
+
+    /// Remove the stored offset of a file that is no longer followed
+    async fn forget_offset(&self, id: &FileId, path: &Path) {
+        if let Err(e) = self.storage.delete(&self.storage_namespace, &id.0).await {
+            error!(
+                "file_tail/{} could not delete the offset of {}: {e}",
+                self.name,
+                path.display()
+            );
+        }
+    }
+
+    /// Send a record to the executor. While the executor's queue is full, the record is retried.
+    /// Returns false if the record was not accepted and the tail should stop.
+    async fn send(&self, record: Vec<u8>, path: &Path, ct: &CancellationToken) -> bool {
+        let mut log = Message::new(
+            self.config.log_type.clone(),
+            record,
+            LogSource::Generator(Generator::FileTail(self.name.clone())),
+            self.config.logbacks_allowed.clone(),
+        );
+        log.headers.insert(
+            "path".to_string(),
+            path.to_string_lossy().as_bytes().to_vec(),
+        );
+
+        loop {
+            match self.logger.try_send(log) {
+                Ok(()) => return true,
+                Err(TrySendError::Full(returned)) => {
+                    log = returned;
+                    tokio::select! {
+                        _ = ct.cancelled() => return false,
+                        _ = tokio::time::sleep(self.config.queue_full_backoff) => {}
+                    }
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    error!(
+                        "file_tail/{} cannot send records because the executor has stopped",
+                        self.name
+                    );
+                    return false;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tailer(
+        dir: &Path,
+        delimiter: &str,
+        storage: Arc<Storage>,
+    ) -> (FileTail, crossbeam_channel::Receiver<Message>) {
+        let config: FileTailConfig = toml::from_str(&format!(
+            r#"
+            [tails.audit]
+            paths = ["{}/*.jsonl"]
+            log_type = "audit"
+            delimiter = "{delimiter}"
+            "#,
+            dir.display()
+        ))
+        .unwrap();
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        (FileTail::new(config, sender, storage), receiver)
+    }
+
+    fn received(receiver: &crossbeam_channel::Receiver<Message>) -> Vec<String> {
+        receiver
+            .try_iter()
+            .map(|m| String::from_utf8(m.data).unwrap())
+            .collect()
+    }
+
+    fn append(path: &Path, contents: &str) {
+        use std::io::Write;
+        let mut file = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn follow_rotation_and_resume() {
+        let dir = std::env::temp_dir().join(format!("plaid-file-tail-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let path = dir.join("audit.jsonl");
+        let storage = Arc::new(Storage::new_in_memory());
+        let ct = CancellationToken::new();
+
+        std::fs::write(&path, "{\"a\":1}\n{\"a\":2}\n{\"a\"").unwrap();
+        let (mut tail, receiver) = tailer(&dir, "\\n", storage.clone());
+        let first = &mut tail.tailers[0];
+        assert!(first.poll(&ct).await);
+        assert_eq!(received(&receiver), ["{\"a\":1}", "{\"a\":2}"]);
+
+        // The partial record is completed, then the file is rotated with a partial record
+        append(&path, ":3}\n{\"a\":4}");
+        assert!(first.poll(&ct).await);
+        assert_eq!(received(&receiver), ["{\"a\":3}"]);
+
+        std::fs::rename(&path, dir.join("audit.jsonl.1")).unwrap();
+        std::fs::write(&path, "{\"a\":5}\n").unwrap();
+        assert!(first.poll(&ct).await);
+        assert_eq!(received(&receiver), ["{\"a\":4}", "{\"a\":5}"]);
+
+        // A new tail with the same storage resumes where the previous one stopped
+        append(&path, "{\"a\":6}\n");
+        let (mut tail, receiver) = tailer(&dir, "\\n", storage);
+        assert!(tail.tailers[0].poll(&ct).await);
+        assert_eq!(received(&receiver), ["{\"a\":6}"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn follow_renamed_files() {
+        let dir = std::env::temp_dir().join(format!("plaid-file-tail-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let path = dir.join("audit.jsonl");
+        let storage = Arc::new(Storage::new_in_memory());
+        let ct = CancellationToken::new();
+
+        std::fs::write(&path, "{\"a\":1}\n{\"a\"").unwrap();
+        let (mut tail, receiver) = tailer(&dir, "\\n", storage.clone());
+        assert!(tail.tailers[0].poll(&ct).await);
+        assert_eq!(received(&receiver), ["{\"a\":1}"]);
+
+        // The file is rotated to a name that still matches: it is not read again
+        let rotated = dir.join("audit-1.jsonl");
+        std::fs::rename(&path, &rotated).unwrap();
+        append(&rotated, ":2}\n");
+        std::fs::write(&path, "{\"a\":3}\n").unwrap();
+        assert!(tail.tailers[0].poll(&ct).await);
+        let mut records = received(&receiver);
+        records.sort();
+        assert_eq!(records, ["{\"a\":2}", "{\"a\":3}"]);
+
+        // A file renamed while Plaid is not running is resumed from its offset
+        append(&rotated, "{\"a\":4}\n");
+        std::fs::rename(&rotated, dir.join("audit-2.jsonl")).unwrap();
+        let (mut tail, receiver) = tailer(&dir, "\\n", storage);
+        assert!(tail.tailers[0].poll(&ct).await);
+        assert_eq!(received(&receiver), ["{\"a\":4}"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn custom_delimiter() {
+        let dir = std::env::temp_dir().join(format!("plaid-file-tail-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("events.jsonl"), "one||two||||three").unwrap();
+
+        let (mut tail, receiver) = tailer(&dir, "||", Arc::new(Storage::new_in_memory()));
+        assert!(tail.tailers[0].poll(&CancellationToken::new()).await);
+        assert_eq!(received(&receiver), ["one", "two"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
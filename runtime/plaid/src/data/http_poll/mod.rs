@@ -0,0 +1,750 @@
+use crate::{executor::Message, parse_duration, storage::Storage};
+use crossbeam_channel::Sender;
+use lru::LruCache;
+use plaid_stl::messages::{Generator, LogSource, LogbacksAllowed};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{collections::HashMap, num::NonZeroUsize, str::FromStr, sync::Arc, time::Duration};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use url::Url;
+
+use super::{
+    get_and_process_dg_logs, get_next_from_link_header, update_dg_from_storage, DataGenerator,
+    DataGeneratorLog,
+};
+
+/// Configuration of all HTTP polling data generators.
+#[derive(Deserialize)]
+pub struct HttpPollConfig {
+    /// A map of pollers, identified by their name. The name is also used to store
+    /// the poller's state, so renaming a poller makes it start over.
+    pollers: HashMap<String, HttpPollerConfig>,
+}
+
+/// Represents the configuration for a poller that periodically pulls events from an HTTP API.
+#[derive(Deserialize)]
+pub struct HttpPollerConfig {
+    /// The URL to fetch events from. The `{since}` and `{until}` placeholders are replaced with
+    /// the boundaries of the time window being fetched, formatted according to `query_time_format`.
+    url: String,
+    /// The log type that events are sent to
+    log_type: String,
+    /// Header used to authenticate to the API. Its value should be a `{plaid-secret{...}}`
+    /// reference, e.g., `Bearer {plaid-secret{workspace-token}}`. It is not sent to next links
+    /// whose scheme, host or port differ from those of `url`.
+    auth_header: Option<AuthHeader>,
+    /// Additional headers sent with every request
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    /// How the API returns the following page of events
+    #[serde(default)]
+    pagination: Pagination,
+    /// Path to the array of events in the response body, e.g., `$.items`.
+    /// A missing or `null` value is treated as an empty page.
+    #[serde(default = "default_events_path")]
+    events_path: JsonPath,
+    /// Path to the unique ID of an event, relative to the event itself
+    id_field: JsonPath,
+    /// Path to the time at which an event was produced, relative to the event itself
+    timestamp_field: JsonPath,
+    /// How the API represents event timestamps
+    #[serde(default)]
+    timestamp_format: TimeFormat,
+    /// How the `{since}` and `{until}` placeholders are formatted in the URL
+    #[serde(default)]
+    query_time_format: TimeFormat,
+    /// Denotes if logs produced by this generator are allowed to initiate log backs
+    #[serde(default)]
+    logbacks_allowed: LogbacksAllowed,
+    /// Time to wait in between calls to the API.
+    /// If no value is provided here, we will use a default value (1 second).
+    #[serde(default = "default_sleep")]
+    #[serde(deserialize_with = "parse_duration")]
+    sleep_duration: Duration,
+    /// Canonicalization time, i.e., after how many seconds we can consider logs as "stable"
+    #[serde(default = "default_canon_time")]
+    canon_time: u64,
+    /// Size of the LRU cache that we use to deduplicate logs
+    #[serde(default = "default_lru_cache_size")]
+    lru_cache_size: usize,
+    /// Max number of seconds in the since..until span for pulling logs from the source
+    #[serde(default = "default_since_until")]
+    max_since_until: u64,
+    /// Max number of seconds for the look-back window
+    #[serde(default = "default_max_catchup")]
+    max_catchup: u64,
+}
+
+/// A header carrying the credentials for an API
+#[derive(Deserialize)]
+pub struct AuthHeader {
+    /// The name of the header, e.g., `Authorization`
+    name: String,
+    /// The value of the header
+    value: String,
+}
+
+/// The ways an API can point to the following page of events
+#[derive(Default, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Pagination {
+    /// All the events in the time window are returned in a single response
+    #[default]
+    None,
+    /// The URL of the next page is in the `rel="next"` entry of the `link` header
+    LinkHeader,
+    /// The response body contains a cursor that is sent back as a query parameter
+    Cursor {
+        /// Path to the cursor in the response body. A missing, `null` or empty cursor
+        /// means there are no more pages.
+        cursor_path: JsonPath,
+        /// The query parameter the cursor is sent in, e.g., `pageToken`
+        cursor_param: String,
+    },
+}
+
+/// The representations of a point in time understood by the HTTP poller
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeFormat {
+    /// An RFC 3339 string, e.g., `2024-05-01T12:00:00Z`
+    #[default]
+    Rfc3339,
+    /// Seconds since the Unix epoch, as a number or a string
+    Unix,
+    /// Milliseconds since the Unix epoch, as a number or a string
+    UnixMillis,
+}
+
+impl TimeFormat {
+    /// Format a datetime so it can be used in a query
+    fn format(self, datetime: OffsetDateTime) -> Result<String, time::error::Format> {
+        match self {
+            Self::Rfc3339 => datetime.format(&Rfc3339),
+            Self::Unix => Ok(datetime.unix_timestamp().to_string()),
+            Self::UnixMillis => Ok((datetime.unix_timestamp_nanos() / 1_000_000).to_string()),
+        }
+    }
+
+    /// Parse a datetime out of a JSON value. Returns `None` if the value does not have this format.
+    fn parse(self, value: &Value) -> Option<OffsetDateTime> {
+        let number = || {
+            value
+                .as_i64()
+                .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+        };
+
+        match self {
+            Self::Rfc3339 => OffsetDateTime::parse(value.as_str()?, &Rfc3339).ok(),
+            Self::Unix => OffsetDateTime::from_unix_timestamp(number()?).ok(),
+            Self::UnixMillis => {
+                OffsetDateTime::from_unix_timestamp_nanos(number()? as i128 * 1_000_000).ok()
+            }
+        }
+    }
+}
+
+/// A single step of a `JsonPath`
+#[derive(Debug, PartialEq)]
+enum PathSegment {
+    /// A field of an object
+    Key(String),
+    /// An element of an array
+    Index(usize),
+}
+
+/// A path to a value inside a JSON document. This supports the subset of JSONPath
+/// that selects a single value: `$`, `.field`, `['field']` and `[index]`.
+#[derive(Debug, PartialEq)]
+pub struct JsonPath(Vec<PathSegment>);
+
+impl JsonPath {
+    /// Get the value this path points to, if there is one
+    fn resolve<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        self.0
+            .iter()
+            .try_fold(value, |value, segment| match segment {
+                PathSegment::Key(key) => value.get(key),
+                PathSegment::Index(index) => value.get(index),
+            })
+    }
+}
+
+impl FromStr for JsonPath {
+    type Err = String;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let Some(mut rest) = path.strip_prefix('$') else {
+            return Err(format!("JSONPath [{path}] must start with $"));
+        };
+
+        let mut segments = vec![];
+        while !rest.is_empty() {
+            if let Some(after_dot) = rest.strip_prefix('.') {
+                let end = after_dot.find(['.', '[']).unwrap_or(after_dot.len());
+                if end == 0 {
+                    return Err(format!("JSONPath [{path}] has an empty field name"));
+                }
+                segments.push(PathSegment::Key(after_dot[..end].to_string()));
+                rest = &after_dot[end..];
+            } else if let Some(after_bracket) = rest.strip_prefix('[') {
+                let Some(end) = after_bracket.find(']') else {
+                    return Err(format!("JSONPath [{path}] has an unclosed bracket"));
+                };
+                let inner = &after_bracket[..end];
+                let quoted = inner
+                    .strip_prefix('\'')
+                    .and_then(|s| s.strip_suffix('\''))
+                    .or_else(|| inner.strip_prefix('"').and_then(|s| s.strip_suffix('"')));
+                let segment = match quoted {
+                    Some(key) => PathSegment::Key(key.to_string()),
+                    None => PathSegment::Index(inner.parse().map_err(|_| {
+                        format!("JSONPath [{path}] has an invalid index [{inner}]")
+                    })?),
+                };
+                segments.push(segment);
+                rest = &after_bracket[end + 1..];
+            } else {
+                return Err(format!("JSONPath [{path}] is not supported at [{rest}]"));
+            }
+        }
+
+        Ok(Self(segments))
+    }
+}
+
+impl<'de> Deserialize<'de> for JsonPath {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// By default, the response body is expected to be the array of events
+fn default_events_path() -> JsonPath {
+    JsonPath(vec![])
+}
+
+/// This function provides the default sleep duration.
+/// It is used as the default value for deserialization of the `sleep_duration` field,
+/// of `HttpPollerConfig` in the event that no value is provided.
+fn default_sleep() -> Duration {
+    Duration::from_millis(1000)
+}
+
+/// This function provides the default max value for the since..until time span, in seconds.
+/// It is used as the default value for deserialization of the `max_since_until` field,
+/// of `HttpPollerConfig` in the event that no value is provided.
+fn default_since_until() -> u64 {
+    60
+}
+
+/// This function provides the default max value for the max catch-up look-back window.
+fn default_max_catchup() -> u64 {
+    3 * 3600 // 3 hours
+}
+
+/// This function provides the default size of the LRU cache.
+fn default_lru_cache_size() -> usize {
+    4096
+}
+
+fn default_canon_time() -> u64 {
+    60
+}
+
+/// Represents the entire HTTP polling data generator set up
+pub struct HttpPoll {
+    /// The pollers, each pulling events from a single API
+    pollers: Vec<HttpPoller>,
+}
+
+/// A data generator that pulls events from an HTTP API, as described by its configuration
+struct HttpPoller {
+    /// The name of this poller
+    name: String,
+    /// A `reqwest` client to send API calls with
+    client: Client,
+    /// The configuration of this poller
+    config: HttpPollerConfig,
+    /// Timestamp of the last seen log we have processed
+    last_seen: OffsetDateTime,
+    /// Sending channel used to send logs into the execution system
+    logger: Sender<Message>,
+    /// An LRU where we store the IDs of events that we have already seen and sent into the logging system.
+    /// Note: we only use the "key" part to keep track of the IDs we have seen. The "value" part is not used and always set to 0u32.
+    seen_logs_uuid: LruCache<String, u32>,
+}
+
+impl HttpPoll {
+    pub fn new(config: HttpPollConfig, logger: Sender<Message>) -> Self {
+        let pollers = config
+            .pollers
+            .into_iter()
+            .map(|(name, config)| HttpPoller::new(name, config, logger.clone()))
+            .collect();
+
+        Self { pollers }
+    }
+
+    /// Start a task for each poller. Each task returns once `cancellation_token` is cancelled.
+    pub fn start(
+        self,
+        join_set: &mut JoinSet<()>,
+        storage: Arc<Storage>,
+        cancellation_token: CancellationToken,
+    ) {
+        for mut poller in self.pollers {
+            let storage = storage.clone();
+            let ct_clone = cancellation_token.clone();
+
+            join_set.spawn(async move {
+                // Recover the last_seen and seen_logs_uuid from a previous run
+                update_dg_from_storage(&mut poller, Some(storage.clone())).await;
+
+                let sleep_duration = Duration::from_secs(10);
+                loop {
+                    if ct_clone.is_cancelled() {
+                        return;
+                    }
+
+                    if get_and_process_dg_logs(&mut poller, Some(storage.clone()))
+                        .await
+                        .is_err()
+                    {
+                        error!("{} Data Fetch Error", poller.get_name());
+                    }
+
+                    tokio::select! {
+                        _ = ct_clone.cancelled() => {
+                            return;
+                        }
+
+                        _ = tokio::time::sleep(sleep_duration) => {}
+                    }
+                }
+            });
+        }
+    }
+}
+
+impl HttpPoller {
+    fn new(name: String, config: HttpPollerConfig, logger: Sender<Message>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        let lru_cache_size = match config.lru_cache_size {
+            0 => {
+                warn!(
+                    "http_poll/{name}: The LRU cache size must be greater than 0. Using default value of {}.",
+                    default_lru_cache_size()
+                );
+                default_lru_cache_size()
+            }
+            size => size,
+        };
+
+        Self {
+            name,
+            client,
+            config,
+            last_seen: OffsetDateTime::now_utc(),
+            logger,
+            seen_logs_uuid: LruCache::new(NonZeroUsize::new(lru_cache_size).unwrap()),
+        }
+    }
+
+    /// Build the URL of the first page of events produced between `since` and `until`
+    fn build_url(&self, since: OffsetDateTime, until: OffsetDateTime) -> Result<String, ()> {
+        let format = |datetime| {
+            self.config
+                .query_time_format
+                .format(datetime)
+                .map(|s| urlencoding::encode(&s).into_owned())
+                .map_err(|e| error!("{} could not format a query time: {e}", self.get_name()))
+        };
+
+        Ok(self
+            .config
+            .url
+            .replace("{since}", &format(since)?)
+            .replace("{until}", &format(until)?))
+    }
+
+    /// Turn the events of a response body into logs. Events that lack an ID or a valid
+    /// timestamp are skipped.
+    fn parse_events(&self, body: &Value) -> Result<Vec<DataGeneratorLog>, ()> {
+        let events = match self.config.events_path.resolve(body) {
+            None | Some(Value::Null) => return Ok(vec![]),
+            Some(Value::Array(events)) => events,
+            Some(_) => {
+                error!(
+                    "{} response does not contain an array of events",
+                    self.get_name()
+                );
+                return Err(());
+            }
+        };
+
+        let mut logs = Vec::with_capacity(events.len());
+        for event in events {
+            let id = match self.config.id_field.resolve(event) {
+                Some(Value::String(id)) => id.clone(),
+                Some(id @ Value::Number(_)) => id.to_string(),
+                _ => {
+                    error!("Missing or invalid ID field in {} event", self.get_name());
+                    continue;
+                }
+            };
+
+            let timestamp = match self
+                .config
+                .timestamp_field
+                .resolve(event)
+                .and_then(|ts| self.config.timestamp_format.parse(ts))
+            {
+                Some(timestamp) => timestamp,
+                None => {
+                    error!(
+                        "Missing or invalid timestamp field in {} event",
+                        self.get_name()
+                    );
+                    continue;
+                }
+            };
+
+            let payload = match serde_json::to_vec(event) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    error!(
+                        "Failed to serialize {} event to bytes. Error: {e}",
+                        self.get_name()
+                    );
+                    continue;
+                }
+            };
+
+            logs.push(DataGeneratorLog {
+                id,
+                timestamp,
+                payload,
+            });
+        }
+
+        Ok(logs)
+    }
+
+    /// Get the URL of the page after the one the cursor was read from
+    fn next_page_from_cursor(
+        &self,
+        url: &str,
+        body: &Value,
+        cursor_path: &JsonPath,
+        cursor_param: &str,
+    ) -> Option<String> {
+        let cursor = match cursor_path.resolve(body)? {
+            Value::String(cursor) if !cursor.is_empty() => cursor.clone(),
+            Value::Number(cursor) => cursor.to_string(),
+            _ => return None,
+        };
+
+        let mut url = Url::parse(url)
+            .map_err(|e| error!("{} could not parse URL: {e}", self.get_name()))
+            .ok()?;
+        let query: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(key, _)| key != cursor_param)
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+        url.query_pairs_mut()
+            .clear()
+            .extend_pairs(query)
+            .append_pair(cursor_param, &cursor);
+
+        Some(url.into())
+    }
+}
+
+/// Whether `url` has the same scheme, host and port as `origin`. URLs that cannot be parsed
+/// never match.
+fn same_origin(url: &str, origin: &str) -> bool {
+    match (Url::parse(url), Url::parse(origin)) {
+        (Ok(url), Ok(origin)) => {
+            url.scheme() == origin.scheme()
+                && url.host_str() == origin.host_str()
+                && url.port_or_known_default() == origin.port_or_known_default()
+        }
+        _ => false,
+    }
+}
+
+impl DataGenerator for HttpPoller {
+    // For the documentation on these methods, see the trait.
+
+    async fn fetch_logs(
+        &self,
+        since: OffsetDateTime,
+        until: OffsetDateTime,
+    ) -> Result<Vec<DataGeneratorLog>, ()> {
+        let mut output_logs = vec![];
+        let first = self.build_url(since, until)?;
+        let mut next = Some(first.clone());
+
+        while let Some(address) = next {
+            let mut request = self
+                .client
+                .get(&address)
+                .header("Accept", "application/json");
+            for (name, value) in &self.config.headers {
+                request = request.header(name, value);
+            }
+            // Next links come from the API's response, so the credentials are only sent to
+            // the origin that was configured
+            if let Some(auth) = &self.config.auth_header {
+                if same_origin(&address, &first) {
+                    request = request.header(&auth.name, &auth.value);
+                } else {
+                    warn!(
+                        "{} is not sending its auth header to a next page on another origin: {address}",
+                        self.get_name()
+                    );
+                }
+            }
+
+            let response = request.send().await.map_err(|e| {
+                error!("Could not get logs from {}: {e}", self.get_name());
+            })?;
+
+            // If the status is outside of the 2XX range, we log the error and return, allowing
+            // the data generator to retry later
+            if !response.status().is_success() {
+                let status = response.status();
+                let error_body = response.text().await.ok();
+                error!(
+                    "Call to {} failed with code: {status}. Error: {}",
+                    self.get_name(),
+                    error_body.unwrap_or_default()
+                );
+                return Err(());
+            }
+
+            let link_next = match self.config.pagination {
+                Pagination::LinkHeader => response
+                    .headers()
+                    .get("link")
+                    .and_then(get_next_from_link_header),
+                _ => None,
+            };
+
+            let body: Value = response
+                .json()
+                .await
+                .map_err(|e| error!("Could not parse data from {}: {e}", self.get_name()))?;
+
+            let logs = self.parse_events(&body)?;
+            if logs.is_empty() {
+                break;
+            }
+            output_logs.extend(logs);
+
+            next = match &self.config.pagination {
+                Pagination::None => None,
+                Pagination::LinkHeader => link_next,
+                Pagination::Cursor {
+                    cursor_path,
+                    cursor_param,
+                } => self.next_page_from_cursor(&address, &body, cursor_path, cursor_param),
+            };
+        }
+
+        Ok(output_logs)
+    }
+
+    fn get_name(&self) -> String {
+        Generator::HttpPoll(self.name.clone()).to_string()
+    }
+
+    fn get_sleep_duration(&self) -> Duration {
+        self.config.sleep_duration
+    }
+
+    fn get_canon_time(&self) -> u64 {
+        self.config.canon_time
+    }
+
+    fn get_last_seen(&self) -> OffsetDateTime {
+        self.last_seen
+    }
+
+    fn set_last_seen(&mut self, v: OffsetDateTime) {
+        self.last_seen = v;
+    }
+
+    fn was_already_seen(&self, id: impl std::fmt::Display) -> bool {
+        self.seen_logs_uuid.contains(&id.to_string())
+    }
+
+    fn mark_already_seen(&mut self, id: impl std::fmt::Display) {
+        self.seen_logs_uuid.put(id.to_string(), 0u32);
+    }
+
+    fn send_for_processing(&self, payload: Vec<u8>) -> Result<(), ()> {
+        self.logger
+            .send(Message::new(
+                self.config.log_type.clone(),
+                payload,
+                LogSource::Generator(Generator::HttpPoll(self.name.clone())),
+                self.config.logbacks_allowed.clone(),
+            ))
+            .map_err(|_| ())
+    }
+
+    fn list_already_seen(&self) -> Vec<String> {
+        self.seen_logs_uuid
+            .iter()
+            .map(|(key, _val)| key.to_string())
+            .collect()
+    }
+
+    fn get_max_since_until_interval(&self) -> u64 {
+        self.config.max_since_until
+    }
+
+    fn get_max_catchup_time(&self) -> u64 {
+        self.config.max_catchup
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn poller(config: &str) -> HttpPoller {
+        let config: HttpPollConfig = toml::from_str(config).unwrap();
+        let (name, config) = config.pollers.into_iter().next().unwrap();
+        let (sender, _) = crossbeam_channel::unbounded();
+        HttpPoller::new(name, config, sender)
+    }
+
+    #[test]
+    fn parse_json_path() {
+        assert_eq!("$".parse::<JsonPath>().unwrap(), JsonPath(vec![]));
+        assert_eq!(
+            "$.id.time['unique id'][2]".parse::<JsonPath>().unwrap(),
+            JsonPath(vec![
+                PathSegment::Key("id".to_string()),
+                PathSegment::Key("time".to_string()),
+                PathSegment::Key("unique id".to_string()),
+                PathSegment::Index(2),
+            ])
+        );
+        assert!("items".parse::<JsonPath>().is_err());
+        assert!("$..items".parse::<JsonPath>().is_err());
+        assert!("$.items[*]".parse::<JsonPath>().is_err());
+        assert!("$.items[0".parse::<JsonPath>().is_err());
+    }
+
+    #[test]
+    fn parse_events() {
+        let poller = poller(
+            r#"
+            [pollers.workspace]
+            url = "https://admin.googleapis.com/activity?startTime={since}&endTime={until}"
+            log_type = "workspace_login"
+            events_path = "$.items"
+            id_field = "$.id.uniqueQualifier"
+            timestamp_field = "$.id.time"
+            "#,
+        );
+
+        let body = json!({
+            "items": [
+                { "id": { "uniqueQualifier": "1", "time": "2024-05-01T12:00:00Z" } },
+                { "id": { "uniqueQualifier": 2, "time": "2024-05-01T12:00:01.5Z" } },
+                { "id": { "uniqueQualifier": "3" } },
+            ],
+        });
+        let logs = poller.parse_events(&body).unwrap();
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[0].id, "1");
+        assert_eq!(logs[1].id, "2");
+        assert_eq!(logs[0].timestamp.unix_timestamp(), 1714564800);
+
+        // Empty pages may omit the array altogether
+        assert!(poller.parse_events(&json!({})).unwrap().is_empty());
+        assert!(poller.parse_events(&json!({ "items": {} })).is_err());
+    }
+
+    #[test]
+    fn build_urls() {
+        let poller = poller(
+            r#"
+            [pollers.cloudflare]
+            url = "https://api.example.com/logs?since={since}&before={until}&limit=10"
+            log_type = "cloudflare_audit"
+            id_field = "$.id"
+            timestamp_field = "$.when"
+            timestamp_format = "unix_millis"
+            query_time_format = "unix"
+            pagination = { type = "cursor", cursor_path = "$.cursor", cursor_param = "cursor" }
+            "#,
+        );
+
+        let since = OffsetDateTime::from_unix_timestamp(1714564800).unwrap();
+        let until = OffsetDateTime::from_unix_timestamp(1714564860).unwrap();
+        let url = poller.build_url(since, until).unwrap();
+        assert_eq!(
+            url,
+            "https://api.example.com/logs?since=1714564800&before=1714564860&limit=10"
+        );
+
+        let Pagination::Cursor {
+            cursor_path,
+            cursor_param,
+        } = &poller.config.pagination
+        else {
+            panic!("expected cursor pagination");
+        };
+        let next = poller
+            .next_page_from_cursor(&url, &json!({ "cursor": "a b" }), cursor_path, cursor_param)
+            .unwrap();
+        assert_eq!(
+            next,
+            "https://api.example.com/logs?since=1714564800&before=1714564860&limit=10&cursor=a+b"
+        );
+        // Following pages replace the cursor instead of adding another one
+        let next = poller
+            .next_page_from_cursor(&next, &json!({ "cursor": "c" }), cursor_path, cursor_param)
+            .unwrap();
+        assert!(next.ends_with("&limit=10&cursor=c"));
+        assert!(poller
+            .next_page_from_cursor(&next, &json!({ "cursor": "" }), cursor_path, cursor_param)
+            .is_none());
+
+        assert_eq!(
+            TimeFormat::UnixMillis.parse(&json!(1714564800123i64)),
+            OffsetDateTime::from_unix_timestamp_nanos(1714564800123000000).ok()
+        );
+        assert_eq!(TimeFormat::Unix.parse(&json!("1714564800")), Some(since));
+    }
+
+    #[test]
+    fn same_origins() {
+        let origin = "https://api.example.com/logs?since=1";
+        assert!(same_origin("https://api.example.com/logs?page=2", origin));
+        assert!(same_origin("https://api.example.com:443/other", origin));
+        assert!(!same_origin("http://api.example.com/logs", origin));
+        assert!(!same_origin("https://evil.example.com/logs", origin));
+        assert!(!same_origin("https://api.example.com:8443/logs", origin));
+        assert!(!same_origin("/logs?page=2", origin));
+    }
+}
@@ -1,7 +1,11 @@
-use crate::{executor::Message, storage::Storage};
+use crate::{
+    executor::Message,
+    storage::{Storage, StorageError},
+};
 
 use crossbeam_channel::{bounded, Receiver, Sender, TrySendError};
 
+use plaid_stl::messages::LogSource;
 use serde::{Deserialize, Serialize};
 
 use std::{
@@ -49,6 +53,16 @@ impl std::cmp::Ord for DelayedMessage {
     }
 }
 
+/// A delayed logback waiting in storage for its delay to elapse
+#[derive(Serialize)]
+pub struct PendingLogback {
+    pub id: String,
+    pub log_type: String,
+    pub source: LogSource,
+    /// The Unix time, in seconds, at which the logback will be executed
+    pub execute_at: u64,
+}
+
 /// Persists incoming delayed logbacks to storage. Holds no `Sender<Message>` so the
 /// perpetual listener task cannot keep executor ingress channels alive.
 #[derive(Clone)]
@@ -68,6 +82,33 @@ impl DelayedLogPersister {
     pub async fn flush_pending(&self) {
         self.listen_for_incoming_logs().await;
     }
+
+    /// Persist a delayed logback without going through the in-memory channel
+    pub async fn persist(&self, log: DelayedMessage) {
+        persist_delayed_log(&self.storage, log).await;
+    }
+
+    /// Get every delayed logback that is waiting to be executed, soonest first
+    pub async fn list_pending(&self) -> Result<Vec<PendingLogback>, DataError> {
+        let mut pending: Vec<PendingLogback> = fill_heap_from_db(self.storage.clone())
+            .await?
+            .into_iter()
+            .map(|Reverse(log)| PendingLogback {
+                id: log.message.id,
+                log_type: log.message.type_,
+                source: log.message.source,
+                execute_at: log.delay,
+            })
+            .collect();
+        pending.sort_by_key(|log| log.execute_at);
+        Ok(pending)
+    }
+
+    /// Cancel a delayed logback so it is never executed. Returns `false` if there is
+    /// no pending logback with that ID.
+    pub async fn cancel(&self, id: &str) -> Result<bool, StorageError> {
+        Ok(self.storage.delete(LOGBACK_NS, id).await?.is_some())
+    }
 }
 
 pub struct Internal {
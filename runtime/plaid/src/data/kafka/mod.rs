@@ -0,0 +1,318 @@
+use crate::{executor::Message, parse_duration};
+use crossbeam_channel::{Sender, TrySendError};
+use plaid_stl::messages::{Generator, LogSource, LogbacksAllowed};
+use rdkafka::{
+    consumer::{CommitMode, Consumer, StreamConsumer},
+    error::KafkaError,
+    message::BorrowedMessage,
+    ClientConfig, Message as KafkaMessage,
+};
+use serde::Deserialize;
+use std::{collections::HashMap, time::Duration};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+/// Configuration of all Kafka data generators.
+#[derive(Deserialize)]
+pub struct KafkaConfig {
+    /// A map of Kafka consumers, identified by their name.
+    consumers: HashMap<String, KafkaConsumerConfig>,
+}
+
+/// Represents the configuration for a Kafka consumer.
+#[derive(Deserialize)]
+pub struct KafkaConsumerConfig {
+    /// Comma-separated list of the brokers used to bootstrap the connection to the cluster
+    brokers: String,
+    /// The consumer group this consumer joins. Partitions of the topics are balanced
+    /// between all the Plaid instances in the same group.
+    group_id: String,
+    /// The topics to consume from, each with the log type its messages are sent to
+    topics: HashMap<String, KafkaTopic>,
+    /// Additional librdkafka properties for the consumer, e.g., `security.protocol`
+    /// or `sasl.mechanisms`. Plaid manages offset commits, so `enable.auto.offset.store`
+    /// cannot be overridden.
+    #[serde(default)]
+    client_config: HashMap<String, String>,
+    /// Time to wait before trying again to send a message to the executor, when its queue is full.
+    /// Consumption is paused until the message is accepted. If this takes longer than the
+    /// consumer's `max.poll.interval.ms`, its partitions are reassigned and uncommitted messages
+    /// are consumed again.
+    /// If no value is provided here, we will use a default value (100 milliseconds).
+    #[serde(default = "default_queue_full_backoff")]
+    #[serde(deserialize_with = "parse_duration")]
+    queue_full_backoff: Duration,
+    /// Time to wait before trying again to receive a message, after failing to receive one,
+    /// e.g., because the brokers cannot be reached.
+    /// If no value is provided here, we will use a default value (1 second).
+    #[serde(default = "default_receive_error_backoff")]
+    #[serde(deserialize_with = "parse_duration")]
+    receive_error_backoff: Duration,
+}
+
+/// Represents the configuration for a topic consumed by a Kafka consumer.
+#[derive(Deserialize)]
+pub struct KafkaTopic {
+    /// The log type that messages from this topic are sent to
+    log_type: String,
+    /// The number of Logbacks this generator is allowed to trigger
+    #[serde(default)]
+    logbacks_allowed: LogbacksAllowed,
+}
+
+/// This function provides the default wait before retrying to send a message to a full queue.
+/// It is used as the default value for deserialization of the `queue_full_backoff` field,
+/// of `KafkaConsumerConfig` in the event that no value is provided.
+fn default_queue_full_backoff() -> Duration {
+    Duration::from_millis(100)
+}
+
+/// This function provides the default wait before trying again to receive a message after an error.
+/// It is used as the default value for deserialization of the `receive_error_backoff` field,
+/// of `KafkaConsumerConfig` in the event that no value is provided.
+fn default_receive_error_backoff() -> Duration {
+    Duration::from_secs(1)
+}
+
+/// Represents the entire Kafka data generator set up
+pub struct Kafka {
+    /// The consumers, identified by their name
+    consumers: Vec<KafkaConsumer>,
+}
+
+/// A single Kafka consumer that forwards messages from its topics to the executor
+struct KafkaConsumer {
+    /// The name of this consumer
+    name: String,
+    /// The configuration of this consumer
+    config: KafkaConsumerConfig,
+    /// The client used to consume from the cluster
+    consumer: StreamConsumer,
+    /// The sender used to send logs to the execution system for processing
+    sender: Sender<Message>,
+}
+
+impl Kafka {
+    pub fn new(config: KafkaConfig, sender: Sender<Message>) -> Result<Self, KafkaError> {
+        let consumers = config
+            .consumers
+            .into_iter()
+            .map(|(name, config)| KafkaConsumer::new(name, config, sender.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { consumers })
+    }
+
+    /// Start a task for each consumer. Each task returns once `cancellation_token` is cancelled.
+    pub fn start(self, join_set: &mut JoinSet<()>, cancellation_token: CancellationToken) {
+        for consumer in self.consumers {
+            join_set.spawn(consumer.consume(cancellation_token.clone()));
+        }
+    }
+}
+
+impl KafkaConsumer {
+    fn new(
+        name: String,
+        config: KafkaConsumerConfig,
+        sender: Sender<Message>,
+    ) -> Result<Self, KafkaError> {
+        let mut client_config = ClientConfig::new();
+        client_config
+            .set("bootstrap.servers", &config.brokers)
+            .set("group.id", &config.group_id)
+            .set("enable.auto.commit", "true")
+            .set("auto.offset.reset", "earliest");
+        for (key, value) in &config.client_config {
+            client_config.set(key, value);
+        }
+        // Offsets are stored only once a message has been accepted by the executor,
+        // and librdkafka periodically commits the offsets that have been stored.
+        client_config.set("enable.auto.offset.store", "false");
+
+        let consumer: StreamConsumer = client_config.create()?;
+        let topics: Vec<&str> = config.topics.keys().map(String::as_str).collect();
+        consumer.subscribe(&topics)?;
+
+        info!(
+            "kafka/{name} subscribed to [{}] as part of group [{}]",
+            topics.join(", "),
+            config.group_id
+        );
+
+        Ok(Self {
+            name,
+            config,
+            consumer,
+            sender,
+        })
+    }
+
+    async fn consume(self, cancellation_token: CancellationToken) {
+        loop {
+            let message = tokio::select! {
+                _ = cancellation_token.cancelled() => break,
+                message = self.consumer.recv() => message,
+            };
+
+            match message {
+                Ok(message) => {
+                    if !self.forward(&message, &cancellation_token).await {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    error!("kafka/{} failed to receive a message: {e}", self.name);
+                    tokio::select! {
+                        _ = cancellation_token.cancelled() => break,
+                        _ = tokio::time::sleep(self.config.receive_error_backoff) => {}
+                    }
+                }
+            }
+        }
+
+        // Commit the offsets stored since the last automatic commit before leaving the group
+        if let Err(e) = self.consumer.commit_consumer_state(CommitMode::Sync) {
+            match e {
+                KafkaError::ConsumerCommit(rdkafka::types::RDKafkaErrorCode::NoOffset) => (),
+                e => error!("kafka/{} failed to commit offsets: {e}", self.name),
+            }
+        }
+    }
+
+    /// Send a message to the executor and store its offset once it has been accepted.
+    /// While the executor's queue is full, consumption is paused and the message is retried.
+    /// Returns false if the consumer should stop.
+    async fn forward(
+        &self,
+        message: &BorrowedMessage<'_>,
+        cancellation_token: &CancellationToken,
+    ) -> bool {
+        let topic = message.topic();
+        let Some(topic_config) = self.config.topics.get(topic) else {
+            error!(
+                "kafka/{} received a message from unconfigured topic [{topic}]",
+                self.name
+            );
+            self.store_offset(message);
+            return true;
+        };
+
+        // Tombstones have no payload and carry nothing for a module to process
+        let Some(payload) = message.payload() else {
+            self.store_offset(message);
+            return true;
+        };
+
+        let mut log = Message::new(
+            topic_config.log_type.clone(),
+            payload.to_vec(),
+            LogSource::Generator(Generator::Kafka(self.name.clone())),
+            topic_config.logbacks_allowed.clone(),
+        );
+
+        let mut paused = None;
+        let accepted = loop {
+            match self.sender.try_send(log) {
+                Ok(()) => break true,
+                Err(TrySendError::Full(returned)) => {
+                    log = returned;
+                    if paused.is_none() {
+                        warn!(
+                            "kafka/{} pausing consumption because the executor queue is full",
+                            self.name
+                        );
+                        paused = self.pause();
+                    }
+
+                    tokio::select! {
+                        _ = cancellation_token.cancelled() => break false,
+                        _ = tokio::time::sleep(self.config.queue_full_backoff) => {}
+                    }
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    error!(
+                        "kafka/{} cannot send messages because the executor has stopped",
+                        self.name
+                    );
+                    break false;
+                }
+            }
+        };
+
+        if let Some(partitions) = paused {
+            if let Err(e) = self.consumer.resume(&partitions) {
+                error!("kafka/{} failed to resume consumption: {e}", self.name);
+            } else if accepted {
+                info!("kafka/{} resumed consumption", self.name);
+            }
+        }
+
+        if accepted {
+            self.store_offset(message);
+        }
+        accepted
+    }
+
+    /// Pause fetching from all the partitions assigned to this consumer and return them,
+    /// so they can be resumed later.
+    fn pause(&self) -> Option<rdkafka::TopicPartitionList> {
+        let partitions = match self.consumer.assignment() {
+            Ok(partitions) => partitions,
+            Err(e) => {
+                error!("kafka/{} failed to get its assignment: {e}", self.name);
+                return None;
+            }
+        };
+
+        match self.consumer.pause(&partitions) {
+            Ok(()) => Some(partitions),
+            Err(e) => {
+                error!("kafka/{} failed to pause consumption: {e}", self.name);
+                None
+            }
+        }
+    }
+
+    /// Mark a message as processed, so that its offset is included in the next commit
+    fn store_offset(&self, message: &BorrowedMessage<'_>) {
+        if let Err(e) = self.consumer.store_offset_from_message(message) {
+            error!(
+                "kafka/{} failed to store the offset of a message from [{}]: {e}",
+                self.name,
+                message.topic()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let config: KafkaConfig = toml::from_str(
+            r#"
+            [consumers.audit]
+            brokers = "localhost:9092"
+            group_id = "plaid"
+            [consumers.audit.topics.okta]
+            log_type = "okta_audit"
+            [consumers.audit.topics.github]
+            log_type = "github_audit"
+            logbacks_allowed = { Limited = 2 }
+            [consumers.audit.client_config]
+            "security.protocol" = "ssl"
+            "#,
+        )
+        .unwrap();
+
+        let consumer = &config.consumers["audit"];
+        assert_eq!(consumer.queue_full_backoff, Duration::from_millis(100));
+        assert_eq!(consumer.topics["okta"].log_type, "okta_audit");
+        assert!(consumer.topics["okta"].logbacks_allowed == LogbacksAllowed::default());
+        assert!(consumer.topics["github"].logbacks_allowed == LogbacksAllowed::Limited(2));
+        assert_eq!(consumer.client_config["security.protocol"], "ssl");
+    }
+}
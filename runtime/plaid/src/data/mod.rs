@@ -1,9 +1,16 @@
+mod file_tail;
 pub mod github;
+mod http_poll;
 pub mod internal;
 mod interval;
+#[cfg(feature = "kafka")]
+mod kafka;
 mod okta;
+#[cfg(feature = "gcp")]
+mod pubsub;
 #[cfg(feature = "aws")]
 mod sqs;
+mod syslog;
 mod websocket;
 
 use crate::{
@@ -28,7 +35,7 @@ use time::OffsetDateTime;
 use tokio::task::JoinSet;
 use tokio_util::sync::CancellationToken;
 
-pub use self::internal::{DelayedLogPersister, DelayedMessage};
+pub use self::internal::{DelayedLogPersister, DelayedMessage, PendingLogback};
 
 const DATA_GENERATOR_STORAGE_PREFIX: &str = "__DATA_GENERATOR";
 const LAST_SEEN_KEY: &str = "last_seen";
@@ -38,24 +45,43 @@ const ALREADY_SEEN_UUIDS_KEY: &str = "already_seen_uuids";
 // send to modules
 #[derive(Deserialize)]
 pub struct DataConfig {
+    file_tail: Option<file_tail::FileTailConfig>,
     github: Option<github::GithubConfig>,
     okta: Option<okta::OktaConfig>,
+    http_poll: Option<http_poll::HttpPollConfig>,
     interval: Option<interval::IntervalConfig>,
+    #[cfg(feature = "kafka")]
+    kafka: Option<kafka::KafkaConfig>,
+    #[cfg(feature = "gcp")]
+    pubsub: Option<pubsub::PubSubConfig>,
     #[cfg(feature = "aws")]
     sqs: Option<sqs::SQSConfig>,
+    syslog: Option<syslog::SyslogConfig>,
     websocket: Option<websocket::WebSocketDataGenerator>,
 }
 
 struct DataInternal {
+    /// File tail follows files and sends the records appended to them
+    file_tail: Option<file_tail::FileTail>,
     github: Option<github::Github>,
     okta: Option<okta::Okta>,
+    /// Pulls events from HTTP APIs described in the configuration
+    http_poll: Option<http_poll::HttpPoll>,
     /// Enables rules to send logs to one another
     internal: internal::Internal,
     /// Interval manages tracking and execution of jobs that are executed on a defined interval
     interval: Option<interval::Interval>,
+    /// Kafka consumes messages from Kafka topics
+    #[cfg(feature = "kafka")]
+    kafka: Option<kafka::Kafka>,
+    /// Pub/Sub pulls messages from GCP Pub/Sub subscriptions
+    #[cfg(feature = "gcp")]
+    pubsub: Option<pubsub::PubSub>,
     /// SQS pulls messages from AWS SQS queue
     #[cfg(feature = "aws")]
     sqs: Option<sqs::SQS>,
+    /// Syslog accepts syslog messages over UDP, TCP and TLS
+    syslog: Option<syslog::Syslog>,
     /// Websocket manages the creation and maintenance of WebSockets that provide data to the executor
     websocket_external: Option<websocket::WebsocketGenerator>,
 }
@@ -66,6 +92,11 @@ pub struct Data {}
 pub enum DataError {
     StorageError(StorageError),
     ApiError(ApiError),
+    #[cfg(feature = "kafka")]
+    KafkaError(rdkafka::error::KafkaError),
+    #[cfg(feature = "gcp")]
+    PubSubError(pubsub::PubSubError),
+    SyslogError(syslog::SyslogError),
 }
 
 impl std::fmt::Display for DataError {
@@ -73,6 +104,11 @@ impl std::fmt::Display for DataError {
         match self {
             Self::StorageError(e) => write!(f, "DataError | StorageError: {}", e),
             Self::ApiError(e) => write!(f, "DataError | ApiError: {:?}", e),
+            #[cfg(feature = "kafka")]
+            Self::KafkaError(e) => write!(f, "DataError | KafkaError: {}", e),
+            #[cfg(feature = "gcp")]
+            Self::PubSubError(e) => write!(f, "DataError | PubSubError: {}", e),
+            Self::SyslogError(e) => write!(f, "DataError | SyslogError: {}", e),
         }
     }
 }
@@ -87,6 +123,10 @@ impl DataInternal {
         els: Logger,
         metrics: Option<Arc<MetricsHandle>>,
     ) -> Result<(Self, internal::DelayedLogPersister), DataError> {
+        let file_tail = config
+            .file_tail
+            .map(|ft| file_tail::FileTail::new(ft, logger.clone(), storage.clone()));
+
         let github = config
             .github
             .map(|gh| {
@@ -99,12 +139,34 @@ impl DataInternal {
             .okta
             .map(|okta| okta::Okta::new(okta, logger.clone(), metrics.clone()));
 
+        let http_poll = config
+            .http_poll
+            .map(|http_poll| http_poll::HttpPoll::new(http_poll, logger.clone()));
+
         let (internal, persister) = internal::Internal::new(logger.clone(), storage.clone())?;
 
         let interval = config
             .interval
             .map(|config| interval::Interval::new(config, logger.clone()));
 
+        #[cfg(feature = "kafka")]
+        let kafka = if let Some(cfg) = config.kafka {
+            Some(kafka::Kafka::new(cfg, logger.clone()).map_err(DataError::KafkaError)?)
+        } else {
+            None
+        };
+
+        #[cfg(feature = "gcp")]
+        let pubsub = if let Some(cfg) = config.pubsub {
+            Some(
+                pubsub::PubSub::new(cfg, logger.clone())
+                    .await
+                    .map_err(DataError::PubSubError)?,
+            )
+        } else {
+            None
+        };
+
         #[cfg(feature = "aws")]
         let sqs = if let Some(cfg) = config.sqs {
             Some(sqs::SQS::new(cfg, logger.clone()).await)
@@ -112,18 +174,35 @@ impl DataInternal {
             None
         };
 
+        let syslog = if let Some(cfg) = config.syslog {
+            Some(
+                syslog::Syslog::new(cfg, logger.clone())
+                    .await
+                    .map_err(DataError::SyslogError)?,
+            )
+        } else {
+            None
+        };
+
         let websocket_external = config
             .websocket
             .map(|ws| websocket::WebsocketGenerator::new(ws, logger.clone(), els));
 
         Ok((
             Self {
+                file_tail,
                 github,
                 okta,
+                http_poll,
                 internal,
                 interval,
+                #[cfg(feature = "kafka")]
+                kafka,
+                #[cfg(feature = "gcp")]
+                pubsub,
                 #[cfg(feature = "aws")]
                 sqs,
+                syslog,
                 websocket_external,
             },
             persister,
@@ -213,6 +292,11 @@ impl Data {
                 });
             }
 
+            // Start a task for each HTTP poller
+            if let Some(http_poll) = di.http_poll {
+                http_poll.start(&mut join_set, storage.clone(), cancellation_token.clone());
+            }
+
             // Start the SQS task if there is one
             #[cfg(feature = "aws")]
             if let Some(mut sqs) = di.sqs {
@@ -240,6 +324,28 @@ impl Data {
                 });
             }
 
+            // Start a task for each Pub/Sub subscription
+            #[cfg(feature = "gcp")]
+            if let Some(pubsub) = di.pubsub {
+                pubsub.start(&mut join_set, cancellation_token.clone());
+            }
+
+            // Start a task for each Kafka consumer
+            #[cfg(feature = "kafka")]
+            if let Some(kafka) = di.kafka {
+                kafka.start(&mut join_set, cancellation_token.clone());
+            }
+
+            // Start a task for each file tail
+            if let Some(file_tail) = di.file_tail {
+                file_tail.start(&mut join_set, cancellation_token.clone());
+            }
+
+            // Start a task for each syslog listener
+            if let Some(syslog) = di.syslog {
+                syslog.start(&mut join_set, cancellation_token.clone());
+            }
+
             if let Some(websocket) = di.websocket_external {
                 let ct_clone = cancellation_token.clone();
                 join_set.spawn(async move {
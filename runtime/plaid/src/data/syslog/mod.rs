@@ -0,0 +1,632 @@
+mod parser;
+
+use crate::{executor::Message, parse_duration};
+use crossbeam_channel::{Sender, TrySendError};
+use plaid_stl::messages::{Generator, LogSource, LogbacksAllowed};
+use regex::Regex;
+use rustls::{
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    ServerConfig,
+};
+use serde::Deserialize;
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader},
+    net::{TcpListener, UdpSocket},
+    sync::Semaphore,
+    task::JoinSet,
+    time::timeout,
+};
+use tokio_rustls::TlsAcceptor;
+use tokio_util::sync::CancellationToken;
+
+use parser::SyslogMessage;
+
+#[derive(Error, Debug)]
+pub enum SyslogError {
+    #[error("syslog/{0} could not bind to its address: {1}")]
+    Bind(String, std::io::Error),
+    #[error("syslog/{0} has an invalid TLS configuration: {1}")]
+    Tls(String, String),
+}
+
+/// Configuration of all syslog listeners.
+#[derive(Deserialize)]
+pub struct SyslogConfig {
+    /// A map of listeners, identified by their name.
+    listeners: HashMap<String, SyslogListenerConfig>,
+}
+
+/// Represents the configuration for a listener that accepts syslog messages.
+/// Each message is parsed and sent as JSON to the log type selected by `routes`.
+#[derive(Deserialize)]
+pub struct SyslogListenerConfig {
+    /// The address and port to listen on, e.g., `0.0.0.0:514`
+    address: SocketAddr,
+    /// The transport messages are received over
+    protocol: Protocol,
+    /// The certificate and key presented to clients. Required when `protocol` is `tls`.
+    tls: Option<TlsConfig>,
+    /// Rules that select the log type of a message. The first matching route is used.
+    #[serde(default)]
+    routes: Vec<Route>,
+    /// The log type that messages matching no route are sent to.
+    /// If not provided, such messages are dropped.
+    default_log_type: Option<String>,
+    /// Denotes if logs produced by this generator are allowed to initiate log backs
+    #[serde(default)]
+    logbacks_allowed: LogbacksAllowed,
+    /// The maximum size of a single message, in bytes. Longer UDP messages are truncated
+    /// and TCP connections sending longer messages are closed.
+    /// If no value is provided here, we will use a default value (64 KiB).
+    #[serde(default = "default_max_message_size")]
+    max_message_size: usize,
+    /// Time to wait before trying again to send a message to the executor, when its queue is full.
+    /// No further messages are read from the connection (or socket) in the meantime.
+    /// If no value is provided here, we will use a default value (100 milliseconds).
+    #[serde(default = "default_queue_full_backoff")]
+    #[serde(deserialize_with = "parse_duration")]
+    queue_full_backoff: Duration,
+    /// The maximum number of TCP (or TLS) connections served at once. Connections over the limit
+    /// are closed straight away.
+    /// If no value is provided here, we will use a default value (256).
+    #[serde(default = "default_max_connections")]
+    #[serde(deserialize_with = "parse_max_connections")]
+    max_connections: usize,
+    /// Time a client has to complete the TLS handshake before its connection is closed.
+    /// If no value is provided here, we will use a default value (10 seconds).
+    #[serde(default = "default_handshake_timeout")]
+    #[serde(deserialize_with = "parse_duration")]
+    handshake_timeout: Duration,
+    /// Time a TCP (or TLS) connection can go without sending a complete message before it
+    /// is closed.
+    /// If no value is provided here, we will use a default value (5 minutes).
+    #[serde(default = "default_idle_timeout")]
+    #[serde(deserialize_with = "parse_duration")]
+    idle_timeout: Duration,
+}
+
+/// The transports a syslog listener can accept messages over
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    /// One message per datagram (RFC 5426)
+    Udp,
+    /// Messages framed by octet counting or terminated by a newline (RFC 6587)
+    Tcp,
+    /// Like `Tcp`, over TLS (RFC 5425)
+    Tls,
+}
+
+/// The certificate and key a TLS listener presents to clients
+#[derive(Deserialize)]
+pub struct TlsConfig {
+    /// The PEM-encoded certificate chain
+    certificate: String,
+    /// The PEM-encoded private key. This should be a `{plaid-secret{...}}` reference.
+    private_key: String,
+}
+
+/// Selects the log type for the messages that match all of the given patterns
+#[derive(Deserialize)]
+pub struct Route {
+    /// A regex the whole hostname must match
+    #[serde(default, deserialize_with = "parse_anchored_regex")]
+    hostname: Option<Regex>,
+    /// A regex the whole app name (or RFC 3164 tag) must match
+    #[serde(default, deserialize_with = "parse_anchored_regex")]
+    app_name: Option<Regex>,
+    /// The log type that matching messages are sent to
+    log_type: String,
+}
+
+impl Route {
+    /// Check if a message matches this route. A message without the field a pattern
+    /// applies to does not match.
+    fn matches(&self, message: &SyslogMessage) -> bool {
+        let field_matches = |pattern: &Option<Regex>, field: &Option<String>| match pattern {
+            None => true,
+            Some(pattern) => field.as_deref().is_some_and(|f| pattern.is_match(f)),
+        };
+
+        field_matches(&self.hostname, &message.hostname)
+            && field_matches(&self.app_name, &message.app_name)
+    }
+}
+
+/// Custom parser for route patterns, which must match the whole field
+fn parse_anchored_regex<'de, D>(deserializer: D) -> Result<Option<Regex>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&format!("^(?:{pattern})$"))
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// This function provides the default maximum message size.
+fn default_max_message_size() -> usize {
+    64 * 1024
+}
+
+/// This function provides the default wait before retrying to send a message to a full queue.
+/// It is used as the default value for deserialization of the `queue_full_backoff` field,
+/// of `SyslogListenerConfig` in the event that no value is provided.
+fn default_queue_full_backoff() -> Duration {
+    Duration::from_millis(100)
+}
+
+/// This function provides the default maximum number of connections served at once.
+fn default_max_connections() -> usize {
+    256
+}
+
+/// Custom parser for the maximum number of connections. Returns an error if it is 0.
+fn parse_max_connections<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let max_connections = usize::deserialize(deserializer)?;
+    if max_connections == 0 {
+        return Err(serde::de::Error::custom(
+            "`max_connections` must be at least 1",
+        ));
+    }
+    Ok(max_connections)
+}
+
+/// This function provides the default time a client has to complete the TLS handshake.
+fn default_handshake_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+/// This function provides the default time a connection can go without sending a message.
+fn default_idle_timeout() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+/// Represents the entire syslog data generator set up
+pub struct Syslog {
+    /// The listeners, with the sockets they are bound to
+    listeners: Vec<(Arc<SyslogListener>, Socket)>,
+}
+
+/// A socket a listener is bound to
+enum Socket {
+    Udp(UdpSocket),
+    Tcp(TcpListener, Option<TlsAcceptor>),
+}
+
+/// Turns the messages received by a socket into logs
+struct SyslogListener {
+    /// The name of this listener
+    name: String,
+    /// The configuration of this listener
+    config: SyslogListenerConfig,
+    /// The logger used to send logs to the execution system for processing
+    logger: Sender<Message>,
+    /// Limits the number of connections served at once
+    connections: Arc<Semaphore>,
+}
+
+impl Syslog {
+    /// Bind all the listeners, so that an unavailable address is reported at startup
+    pub async fn new(config: SyslogConfig, logger: Sender<Message>) -> Result<Self, SyslogError> {
+        let mut listeners = vec![];
+        for (name, config) in config.listeners {
+            let socket = match config.protocol {
+                Protocol::Udp => Socket::Udp(
+                    UdpSocket::bind(config.address)
+                        .await
+                        .map_err(|e| SyslogError::Bind(name.clone(), e))?,
+                ),
+                Protocol::Tcp | Protocol::Tls => {
+                    let acceptor = match (&config.protocol, &config.tls) {
+                        (Protocol::Tls, Some(tls)) => Some(
+                            build_tls_acceptor(tls)
+                                .map_err(|e| SyslogError::Tls(name.clone(), e))?,
+                        ),
+                        (Protocol::Tls, None) => {
+                            return Err(SyslogError::Tls(
+                                name,
+                                "the tls section is missing".to_string(),
+                            ))
+                        }
+                        _ => None,
+                    };
+
+                    Socket::Tcp(
+                        TcpListener::bind(config.address)
+                            .await
+                            .map_err(|e| SyslogError::Bind(name.clone(), e))?,
+                        acceptor,
+                    )
+                }
+            };
+
+            info!("syslog/{name} listening on {}", config.address);
+            let listener = SyslogListener {
+                name,
+                connections: Arc::new(Semaphore::new(config.max_connections)),
+                config,
+                logger: logger.clone(),
+            };
+            listeners.push((Arc::new(listener), socket));
+        }
+
+        Ok(Self { listeners })
+    }
+
+    /// Start a task for each listener. Each task returns once `cancellation_token` is cancelled.
+    pub fn start(self, join_set: &mut JoinSet<()>, cancellation_token: CancellationToken) {
+        for (listener, socket) in self.listeners {
+            let ct_clone = cancellation_token.clone();
+            match socket {
+                Socket::Udp(socket) => {
+                    join_set.spawn(listener.receive_datagrams(socket, ct_clone));
+                }
+                Socket::Tcp(socket, acceptor) => {
+                    join_set.spawn(listener.accept_connections(socket, acceptor, ct_clone));
+                }
+            }
+        }
+    }
+}
+
+/// Build a TLS acceptor from a PEM-encoded certificate chain and private key
+fn build_tls_acceptor(tls: &TlsConfig) -> Result<TlsAcceptor, String> {
+    let certificates = CertificateDer::pem_slice_iter(tls.certificate.as_bytes())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("invalid certificate: {e}"))?;
+    let key = PrivateKeyDer::from_pem_slice(tls.private_key.as_bytes())
+        .map_err(|e| format!("invalid private key: {e}"))?;
+
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certificates, key)
+        .map_err(|e| e.to_string())?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Read the next message from a stream, framed by octet counting (`LEN SP MSG`) or terminated
+/// by a newline. Returns `None` once the stream has been closed.
+async fn read_frame<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    max_message_size: usize,
+) -> std::io::Result<Option<Vec<u8>>> {
+    let too_long = || std::io::Error::new(std::io::ErrorKind::InvalidData, "message too long");
+
+    let starts_with_digit = match reader.fill_buf().await?.first() {
+        None => return Ok(None),
+        Some(b) => b.is_ascii_digit(),
+    };
+
+    let mut frame = vec![];
+    if starts_with_digit {
+        // The length of a message fits in far fewer digits than this
+        let mut length = vec![];
+        (&mut *reader)
+            .take(12)
+            .read_until(b' ', &mut length)
+            .await?;
+        let length: usize = std::str::from_utf8(length.trim_ascii_end())
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid octet count")
+            })?;
+        if length > max_message_size {
+            return Err(too_long());
+        }
+
+        frame.resize(length, 0);
+        reader.read_exact(&mut frame).await?;
+    } else {
+        (&mut *reader)
+            .take(max_message_size as u64 + 1)
+            .read_until(b'\n', &mut frame)
+            .await?;
+        if frame.len() > max_message_size {
+            return Err(too_long());
+        }
+    }
+
+    Ok(Some(frame))
+}
+
+impl SyslogListener {
+    async fn receive_datagrams(self: Arc<Self>, socket: UdpSocket, ct: CancellationToken) {
+        let mut buffer = vec![0u8; self.config.max_message_size];
+        loop {
+            let received = tokio::select! {
+                _ = ct.cancelled() => return,
+                received = socket.recv_from(&mut buffer) => received,
+            };
+
+            match received {
+                Ok((length, peer)) => {
+                    if !self.forward(&buffer[..length], peer, &ct).await {
+                        return;
+                    }
+                }
+                Err(e) => error!("syslog/{} failed to receive a message: {e}", self.name),
+            }
+        }
+    }
+
+    async fn accept_connections(
+        self: Arc<Self>,
+        socket: TcpListener,
+        acceptor: Option<TlsAcceptor>,
+        ct: CancellationToken,
+    ) {
+        loop {
+            let accepted = tokio::select! {
+                _ = ct.cancelled() => return,
+                accepted = socket.accept() => accepted,
+            };
+
+            let (stream, peer) = match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    error!("syslog/{} failed to accept a connection: {e}", self.name);
+                    continue;
+                }
+            };
+
+            let Ok(permit) = self.connections.clone().try_acquire_owned() else {
+                warn!(
+                    "syslog/{} closing connection from {peer}: already serving {} connections",
+                    self.name, self.config.max_connections
+                );
+                continue;
+            };
+
+            // Each connection is served by its own task, which returns once the peer
+            // disconnects, goes idle or `ct` is cancelled
+            let listener = self.clone();
+            let acceptor = acceptor.clone();
+            let ct = ct.clone();
+            tokio::spawn(async move {
+                match acceptor {
+                    None => listener.read_stream(stream, peer, ct).await,
+                    Some(acceptor) => {
+                        let handshake = tokio::select! {
+                            _ = ct.cancelled() => return,
+                            handshake = timeout(
+                                listener.config.handshake_timeout,
+                                acceptor.accept(stream),
+                            ) => handshake,
+                        };
+                        match handshake {
+                            Ok(Ok(stream)) => listener.read_stream(stream, peer, ct).await,
+                            Ok(Err(e)) => warn!(
+                                "syslog/{} TLS handshake with {peer} failed: {e}",
+                                listener.name
+                            ),
+                            Err(_) => warn!(
+                                "syslog/{} TLS handshake with {peer} timed out",
+                                listener.name
+                            ),
+                        }
+                    }
+                }
+                // The connection no longer counts towards the limit
+                drop(permit);
+            });
+        }
+    }
+
+    async fn read_stream<S: AsyncRead + Unpin>(
+        &self,
+        stream: S,
+        peer: SocketAddr,
+        ct: CancellationToken,
+    ) {
+        let mut reader = BufReader::new(stream);
+        loop {
+            let frame = tokio::select! {
+                _ = ct.cancelled() => return,
+                frame = timeout(
+                    self.config.idle_timeout,
+                    read_frame(&mut reader, self.config.max_message_size),
+                ) => frame,
+            };
+
+            let Ok(frame) = frame else {
+                warn!(
+                    "syslog/{} closing connection from {peer}: no message received for {:?}",
+                    self.name, self.config.idle_timeout
+                );
+                return;
+            };
+            match frame {
+                Ok(Some(frame)) => {
+                    if !self.forward(&frame, peer, &ct).await {
+                        return;
+                    }
+                }
+                Ok(None) => return,
+                Err(e) => {
+                    warn!("syslog/{} closing connection from {peer}: {e}", self.name);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Select the log type of a message, if any
+    fn route(&self, message: &SyslogMessage) -> Option<&str> {
+        self.config
+            .routes
+            .iter()
+            .find(|route| route.matches(message))
+            .map(|route| route.log_type.as_str())
+            .or(self.config.default_log_type.as_deref())
+    }
+
+    /// Parse a message and send it to the executor. While the executor's queue is full,
+    /// the message is retried. Returns false if the listener should stop.
+    async fn forward(&self, payload: &[u8], peer: SocketAddr, ct: &CancellationToken) -> bool {
+        let line = String::from_utf8_lossy(payload);
+        if line.trim().is_empty() {
+            return true;
+        }
+
+        let mut message = parser::parse(&line);
+        message.source_address = Some(peer.to_string());
+
+        let Some(log_type) = self.route(&message) else {
+            trace!(
+                "syslog/{} dropping a message from {peer} that matches no route",
+                self.name
+            );
+            return true;
+        };
+
+        let body = match serde_json::to_vec(&message) {
+            Ok(body) => body,
+            Err(e) => {
+                error!("syslog/{} failed to serialize a message: {e}", self.name);
+                return true;
+            }
+        };
+
+        let mut log = Message::new(
+            log_type.to_string(),
+            body,
+            LogSource::Generator(Generator::Syslog(self.name.clone())),
+            self.config.logbacks_allowed.clone(),
+        );
+
+        loop {
+            match self.logger.try_send(log) {
+                Ok(()) => return true,
+                Err(TrySendError::Full(returned)) => {
+                    log = returned;
+                    tokio::select! {
+                        _ = ct.cancelled() => return false,
+                        _ = tokio::time::sleep(self.config.queue_full_backoff) => {}
+                    }
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    error!(
+                        "syslog/{} cannot send messages because the executor has stopped",
+                        self.name
+                    );
+                    return false;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn read_frames() {
+        let stream: &[u8] = b"<13>first\n10 <13>second<13>third";
+        let mut reader = BufReader::new(stream);
+
+        for expected in [&b"<13>first\n"[..], b"<13>second", b"<13>third"] {
+            let frame = read_frame(&mut reader, 1024).await.unwrap().unwrap();
+            assert_eq!(frame, expected);
+        }
+        assert!(read_frame(&mut reader, 1024).await.unwrap().is_none());
+
+        let mut reader = BufReader::new(&b"2048 <13>too long"[..]);
+        assert!(read_frame(&mut reader, 1024).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn route_messages() {
+        let config: SyslogConfig = toml::from_str(
+            r#"
+            [listeners.appliances]
+            address = "127.0.0.1:0"
+            protocol = "udp"
+            [[listeners.appliances.routes]]
+            hostname = "fw-\\d+"
+            log_type = "firewall"
+            [[listeners.appliances.routes]]
+            app_name = "sshd"
+            log_type = "ssh"
+            "#,
+        )
+        .unwrap();
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let syslog = Syslog::new(config, sender).await.unwrap();
+        let (listener, _) = &syslog.listeners[0];
+        let peer: SocketAddr = "10.0.0.1:514".parse().unwrap();
+        let ct = CancellationToken::new();
+
+        for line in [
+            "<34>Oct 11 22:14:15 fw-01 sshd[1]: accepted",
+            "<34>Oct 11 22:14:15 host sshd[1]: accepted",
+            "<34>Oct 11 22:14:15 fw-01a cron: ran",
+        ] {
+            assert!(listener.forward(line.as_bytes(), peer, &ct).await);
+        }
+
+        let received: Vec<Message> = receiver.try_iter().collect();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].type_, "firewall");
+        assert_eq!(received[1].type_, "ssh");
+
+        let body: serde_json::Value = serde_json::from_slice(&received[1].data).unwrap();
+        assert_eq!(body["hostname"], "host");
+        assert_eq!(body["source_address"], "10.0.0.1:514");
+    }
+
+    #[tokio::test]
+    async fn limit_and_time_out_connections() {
+        use tokio::io::AsyncWriteExt;
+
+        let config: SyslogConfig = toml::from_str(
+            r#"
+            [listeners.appliances]
+            address = "127.0.0.1:0"
+            protocol = "tcp"
+            default_log_type = "appliances"
+            max_connections = 1
+            idle_timeout = 200
+            "#,
+        )
+        .unwrap();
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut syslog = Syslog::new(config, sender).await.unwrap();
+        let (listener, socket) = syslog.listeners.pop().unwrap();
+        let Socket::Tcp(socket, acceptor) = socket else {
+            panic!("the listener should be bound to a TCP socket");
+        };
+        let address = socket.local_addr().unwrap();
+        let ct = CancellationToken::new();
+        tokio::spawn(listener.accept_connections(socket, acceptor, ct.clone()));
+
+        let mut first = tokio::net::TcpStream::connect(address).await.unwrap();
+        first.write_all(b"<13>first\n").await.unwrap();
+        // The second connection is over the limit and is closed straight away
+        let mut second = tokio::net::TcpStream::connect(address).await.unwrap();
+        assert_eq!(second.read(&mut [0u8; 1]).await.unwrap(), 0);
+
+        // The first connection is closed once it goes idle
+        let closed = timeout(Duration::from_secs(5), first.read(&mut [0u8; 1])).await;
+        assert_eq!(closed.unwrap().unwrap(), 0);
+        assert_eq!(receiver.try_iter().count(), 1);
+
+        // Which makes room for another connection, once its task has finished
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let mut third = tokio::net::TcpStream::connect(address).await.unwrap();
+        third.write_all(b"<13>third\n").await.unwrap();
+        third.shutdown().await.unwrap();
+        assert_eq!(third.read(&mut [0u8; 1]).await.unwrap(), 0);
+        assert_eq!(receiver.try_iter().count(), 1);
+
+        ct.cancel();
+    }
+}
@@ -0,0 +1,335 @@
+//! Parsing of syslog messages in the formats described by RFC 5424 and RFC 3164.
+//! Anything that does not look like syslog is kept as a raw message.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// RFC 3164 timestamps begin with one of these
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// SD-ID → PARAM-NAME → PARAM-VALUE
+type StructuredData = BTreeMap<String, BTreeMap<String, String>>;
+
+/// The format a message was recognized as
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyslogFormat {
+    Rfc5424,
+    Rfc3164,
+    /// The message has no syslog header: everything is in `message`
+    Raw,
+}
+
+/// A syslog message broken down into its fields. Fields that are missing from the
+/// message (or set to the NILVALUE `-` in RFC 5424) are `None`.
+#[derive(Debug, Serialize)]
+pub struct SyslogMessage {
+    pub format: SyslogFormat,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub facility: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub severity: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<u8>,
+    /// The timestamp, exactly as it appears in the message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// The APP-NAME in RFC 5424, or the TAG in RFC 3164
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proc_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub msg_id: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub structured_data: StructuredData,
+    pub message: String,
+    /// The address of the peer that sent the message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_address: Option<String>,
+}
+
+impl SyslogMessage {
+    fn raw(message: &str) -> Self {
+        Self {
+            format: SyslogFormat::Raw,
+            facility: None,
+            severity: None,
+            version: None,
+            timestamp: None,
+            hostname: None,
+            app_name: None,
+            proc_id: None,
+            msg_id: None,
+            structured_data: BTreeMap::new(),
+            message: message.to_string(),
+            source_address: None,
+        }
+    }
+}
+
+/// Parse a single syslog message. This never fails: messages that are not valid
+/// syslog are returned in the `Raw` format.
+pub fn parse(line: &str) -> SyslogMessage {
+    let line = line.trim_end_matches(['\r', '\n', '\0']);
+
+    let Some((pri, rest)) = parse_pri(line) else {
+        return SyslogMessage::raw(line);
+    };
+
+    let mut message = parse_rfc5424(rest).unwrap_or_else(|| parse_rfc3164(rest));
+    message.facility = Some(pri / 8);
+    message.severity = Some(pri % 8);
+    message
+}
+
+/// Parse the `<PRI>` at the start of a message, returning its value and the rest of the message
+fn parse_pri(line: &str) -> Option<(u8, &str)> {
+    let rest = line.strip_prefix('<')?;
+    let end = rest.find('>')?;
+    let digits = &rest[..end];
+    if digits.is_empty() || digits.len() > 3 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    match digits.parse::<u8>() {
+        Ok(pri) if pri <= 191 => Some((pri, &rest[end + 1..])),
+        _ => None,
+    }
+}
+
+/// Split the next space-delimited header field off `s`
+fn next_field(s: &str) -> Option<(&str, &str)> {
+    let (field, rest) = s.split_once(' ').unwrap_or((s, ""));
+    (!field.is_empty()).then_some((field, rest))
+}
+
+/// Map the RFC 5424 NILVALUE to `None`
+fn nil(field: &str) -> Option<String> {
+    (field != "-").then(|| field.to_string())
+}
+
+/// Parse what follows the PRI of an RFC 5424 message:
+/// `VERSION SP TIMESTAMP SP HOSTNAME SP APP-NAME SP PROCID SP MSGID SP STRUCTURED-DATA [SP MSG]`
+fn parse_rfc5424(s: &str) -> Option<SyslogMessage> {
+    let (version, s) = next_field(s)?;
+    if version.len() > 2 || version.starts_with('0') {
+        return None;
+    }
+    let version: u8 = version.parse().ok()?;
+
+    let (timestamp, s) = next_field(s)?;
+    let (hostname, s) = next_field(s)?;
+    let (app_name, s) = next_field(s)?;
+    let (proc_id, s) = next_field(s)?;
+    let (msg_id, s) = next_field(s)?;
+    let (structured_data, s) = parse_structured_data(s)?;
+
+    let message = match s {
+        "" => "",
+        s => s.strip_prefix(' ')?,
+    };
+
+    Some(SyslogMessage {
+        format: SyslogFormat::Rfc5424,
+        facility: None,
+        severity: None,
+        version: Some(version),
+        timestamp: nil(timestamp),
+        hostname: nil(hostname),
+        app_name: nil(app_name),
+        proc_id: nil(proc_id),
+        msg_id: nil(msg_id),
+        structured_data,
+        message: message.trim_start_matches('\u{feff}').to_string(),
+        source_address: None,
+    })
+}
+
+/// Parse RFC 5424 STRUCTURED-DATA, returning the elements and the rest of the message
+fn parse_structured_data(s: &str) -> Option<(StructuredData, &str)> {
+    let mut elements = BTreeMap::new();
+    if let Some(rest) = s.strip_prefix('-') {
+        return Some((elements, rest));
+    }
+
+    let mut rest = s;
+    while let Some(element) = rest.strip_prefix('[') {
+        let id_end = element.find([' ', ']'])?;
+        let id = &element[..id_end];
+        if id.is_empty() {
+            return None;
+        }
+        rest = &element[id_end..];
+
+        let mut params = BTreeMap::new();
+        while let Some(param) = rest.strip_prefix(' ') {
+            let (name, value) = param.split_once("=\"")?;
+            if name.is_empty() || name.contains([' ', ']', '"']) {
+                return None;
+            }
+
+            // PARAM-VALUE escapes '"', '\' and ']' with a backslash
+            let mut unescaped = String::new();
+            let mut chars = value.char_indices();
+            let end = loop {
+                match chars.next()? {
+                    (_, '\\') => match chars.next()? {
+                        (_, c @ ('"' | '\\' | ']')) => unescaped.push(c),
+                        (_, c) => {
+                            unescaped.push('\\');
+                            unescaped.push(c);
+                        }
+                    },
+                    (i, '"') => break i,
+                    (_, c) => unescaped.push(c),
+                }
+            };
+
+            params.insert(name.to_string(), unescaped);
+            rest = &value[end + 1..];
+        }
+
+        rest = rest.strip_prefix(']')?;
+        elements.insert(id.to_string(), params);
+    }
+
+    // There must be at least one element
+    (!elements.is_empty()).then_some((elements, rest))
+}
+
+/// Parse what follows the PRI of an RFC 3164 message: `Mmm dd hh:mm:ss HOSTNAME TAG[PID]: MSG`.
+/// The timestamp and hostname are often missing, so this is lenient.
+fn parse_rfc3164(s: &str) -> SyslogMessage {
+    let mut message = SyslogMessage::raw(s);
+    message.format = SyslogFormat::Rfc3164;
+
+    let mut content = s;
+    if let Some((timestamp, rest)) = parse_rfc3164_timestamp(s) {
+        message.timestamp = Some(timestamp.to_string());
+        content = rest;
+        if let Some((hostname, rest)) = next_field(rest) {
+            message.hostname = Some(hostname.to_string());
+            content = rest;
+        }
+    }
+
+    // The TAG is terminated by the first character that is not alphanumeric,
+    // and is usually followed by `[PID]` and `:`
+    let tag_end = content
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/')))
+        .unwrap_or(content.len());
+    let (tag, after_tag) = content.split_at(tag_end);
+
+    let (proc_id, after_pid) = match after_tag.strip_prefix('[').and_then(|s| s.split_once(']')) {
+        Some((pid, rest)) => (Some(pid), rest),
+        None => (None, after_tag),
+    };
+
+    if let (false, Some(msg)) = (tag.is_empty(), after_pid.strip_prefix(':')) {
+        message.app_name = Some(tag.to_string());
+        message.proc_id = proc_id.map(str::to_string);
+        message.message = msg.strip_prefix(' ').unwrap_or(msg).to_string();
+    } else {
+        message.message = content.to_string();
+    }
+
+    message
+}
+
+/// Parse an RFC 3164 timestamp (e.g., `Oct  9 22:14:15`) at the start of `s`,
+/// returning it and the rest of the message
+fn parse_rfc3164_timestamp(s: &str) -> Option<(&str, &str)> {
+    let timestamp = s.get(..15)?;
+    let bytes = timestamp.as_bytes();
+
+    let valid = MONTHS.iter().any(|month| month.as_bytes() == &bytes[..3])
+        && bytes[3] == b' '
+        && (bytes[4] == b' ' || bytes[4].is_ascii_digit())
+        && bytes[5].is_ascii_digit()
+        && bytes[6] == b' '
+        && bytes[9] == b':'
+        && bytes[12] == b':'
+        && [7, 8, 10, 11, 13, 14]
+            .iter()
+            .all(|&i| bytes[i].is_ascii_digit());
+    if !valid {
+        return None;
+    }
+
+    let rest = &s[15..];
+    Some((timestamp, rest.strip_prefix(' ').unwrap_or(rest)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc5424() {
+        let message = parse(
+            r#"<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 [exampleSDID@32473 iut="3" eventSource="Application" eventID="1011"][examplePriority@32473 class="high \"x\" \]"] An application event log entry..."#,
+        );
+
+        assert_eq!(message.format, SyslogFormat::Rfc5424);
+        assert_eq!(message.facility, Some(20));
+        assert_eq!(message.severity, Some(5));
+        assert_eq!(message.version, Some(1));
+        assert_eq!(
+            message.timestamp.as_deref(),
+            Some("2003-10-11T22:14:15.003Z")
+        );
+        assert_eq!(message.hostname.as_deref(), Some("mymachine.example.com"));
+        assert_eq!(message.app_name.as_deref(), Some("evntslog"));
+        assert_eq!(message.proc_id, None);
+        assert_eq!(message.msg_id.as_deref(), Some("ID47"));
+        assert_eq!(
+            message.structured_data["exampleSDID@32473"]["eventID"],
+            "1011"
+        );
+        assert_eq!(
+            message.structured_data["examplePriority@32473"]["class"],
+            r#"high "x" ]"#
+        );
+        assert_eq!(message.message, "An application event log entry...");
+
+        let message = parse("<34>1 - - su - - -\n");
+        assert_eq!(message.format, SyslogFormat::Rfc5424);
+        assert_eq!(message.app_name.as_deref(), Some("su"));
+        assert!(message.structured_data.is_empty());
+        assert_eq!(message.message, "");
+    }
+
+    #[test]
+    fn rfc3164() {
+        let message = parse("<34>Oct 11 22:14:15 mymachine su[123]: 'su root' failed for lonvick");
+        assert_eq!(message.format, SyslogFormat::Rfc3164);
+        assert_eq!(message.facility, Some(4));
+        assert_eq!(message.severity, Some(2));
+        assert_eq!(message.timestamp.as_deref(), Some("Oct 11 22:14:15"));
+        assert_eq!(message.hostname.as_deref(), Some("mymachine"));
+        assert_eq!(message.app_name.as_deref(), Some("su"));
+        assert_eq!(message.proc_id.as_deref(), Some("123"));
+        assert_eq!(message.message, "'su root' failed for lonvick");
+
+        // Relayed messages may lack the timestamp, hostname and tag
+        let message = parse("<13>Use the BFG!");
+        assert_eq!(message.format, SyslogFormat::Rfc3164);
+        assert_eq!(message.timestamp, None);
+        assert_eq!(message.app_name, None);
+        assert_eq!(message.message, "Use the BFG!");
+    }
+
+    #[test]
+    fn raw() {
+        for line in ["plain text", "<192>too high", "<>empty", ""] {
+            let message = parse(line);
+            assert_eq!(message.format, SyslogFormat::Raw);
+            assert_eq!(message.message, line);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::Message;
+
+/// The most messages held for a single paused log type. Messages that arrive once
+/// this many are held are dropped.
+const MAX_HELD_MESSAGES: usize = 10_000;
+
+/// Log types that have been paused by an operator.
+///
+/// Messages for a paused log type are held in memory instead of being executed,
+/// and are queued again when the log type is resumed.
+#[derive(Default)]
+pub struct PausedLogTypes {
+    held: Mutex<HashMap<String, Vec<Message>>>,
+}
+
+impl PausedLogTypes {
+    /// Pause a log type. Returns `false` if it was already paused.
+    pub fn pause(&self, log_type: &str) -> bool {
+        let mut held = self.held.lock().unwrap();
+        if held.contains_key(log_type) {
+            return false;
+        }
+        held.insert(log_type.to_string(), vec![]);
+        true
+    }
+
+    /// Resume a log type, returning the messages held while it was paused,
+    /// or `None` if it was not paused.
+    pub fn resume(&self, log_type: &str) -> Option<Vec<Message>> {
+        self.held.lock().unwrap().remove(log_type)
+    }
+
+    /// Get every paused log type with the number of messages held for it
+    pub fn list(&self) -> HashMap<String, usize> {
+        self.held
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(log_type, messages)| (log_type.clone(), messages.len()))
+            .collect()
+    }
+
+    /// Resume every log type, returning all held messages. Used at shutdown
+    /// so held messages can be persisted instead of lost.
+    pub fn take_all(&self) -> Vec<Message> {
+        self.held
+            .lock()
+            .unwrap()
+            .drain()
+            .flat_map(|(_, messages)| messages)
+            .collect()
+    }
+
+    /// Hold a message if its log type is paused. If it is not paused, the message
+    /// is given back so it can be executed.
+    pub(super) fn hold(&self, message: Message) -> Option<Message> {
+        let mut held = self.held.lock().unwrap();
+        let Some(messages) = held.get_mut(&message.type_) else {
+            return Some(message);
+        };
+
+        if messages.len() >= MAX_HELD_MESSAGES {
+            error!(
+                "Log type [{}] is paused and already holding {MAX_HELD_MESSAGES} messages. Message dropped!",
+                message.type_
+            );
+        } else {
+            messages.push(message);
+        }
+        None
+    }
+}
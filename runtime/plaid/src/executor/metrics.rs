@@ -3,17 +3,21 @@ use std::collections::HashMap;
 use crossbeam_channel::Sender;
 use prometheus::core::{Collector, Desc};
 use prometheus::proto::MetricFamily;
-use prometheus::{GaugeVec, HistogramOpts, HistogramVec, IntGaugeVec, Opts};
+use prometheus::{GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts};
 
 use crate::metrics::MetricsHandle;
 
 use super::thread_pools::ExecutionThreadPools;
-use super::Message;
+use super::{Message, ModuleExecutionError};
 
-/// Histograms for per-module execution stats, updated after each successful run.
+/// Histograms for per-module execution stats, updated after each successful run,
+/// and counts of failed runs, host function calls and the runs prevented by rate limits.
 pub struct ModuleExecutionMetrics {
     computation_percentage: HistogramVec,
     execution_duration_seconds: HistogramVec,
+    execution_errors: IntCounterVec,
+    host_function_calls: IntCounterVec,
+    rate_limited: IntCounterVec,
 }
 
 impl ModuleExecutionMetrics {
@@ -37,16 +41,55 @@ impl ModuleExecutionMetrics {
         )
         .expect("valid metric definition");
 
+        let execution_errors = IntCounterVec::new(
+            Opts::new(
+                "plaid_module_execution_errors_total",
+                "Module executions that failed, by kind of error",
+            ),
+            &["module", "error"],
+        )
+        .expect("valid metric definition");
+
+        let host_function_calls = IntCounterVec::new(
+            Opts::new(
+                "plaid_host_function_calls_total",
+                "Calls modules made to host functions",
+            ),
+            &["module", "api", "function"],
+        )
+        .expect("valid metric definition");
+
+        let rate_limited = IntCounterVec::new(
+            Opts::new(
+                "plaid_module_rate_limited_total",
+                "Messages a module did not run on straight away because it was over its rate limit",
+            ),
+            &["module", "action"],
+        )
+        .expect("valid metric definition");
+
         handle
             .register(Box::new(computation_percentage.clone()))
             .expect("expected unique collector");
         handle
             .register(Box::new(execution_duration_seconds.clone()))
             .expect("expected unique collector");
+        handle
+            .register(Box::new(execution_errors.clone()))
+            .expect("expected unique collector");
+        handle
+            .register(Box::new(host_function_calls.clone()))
+            .expect("expected unique collector");
+        handle
+            .register(Box::new(rate_limited.clone()))
+            .expect("expected unique collector");
 
         Self {
             computation_percentage,
             execution_duration_seconds,
+            execution_errors,
+            host_function_calls,
+            rate_limited,
         }
     }
 
@@ -63,6 +106,25 @@ impl ModuleExecutionMetrics {
             .with_label_values(&[module])
             .observe(duration.as_secs_f64());
     }
+
+    /// Record a run of `module` that failed with `error`
+    pub fn record_execution_error(&self, module: &str, error: &ModuleExecutionError) {
+        self.execution_errors
+            .with_label_values(&[module, error.kind()])
+            .inc();
+    }
+
+    /// Record a call `module` made to one of an API's host functions
+    pub fn record_host_function_call(&self, module: &str, api: &str, function: &str) {
+        self.host_function_calls
+            .with_label_values(&[module, api, function])
+            .inc();
+    }
+
+    /// Record a message that was delayed or dropped because `module` was over its rate limit
+    pub fn record_rate_limited(&self, module: &str, action: &str) {
+        self.rate_limited.with_label_values(&[module, action]).inc();
+    }
 }
 
 /// Reports depth and percentage capacity of each execution queue. Values are
@@ -1,4 +1,7 @@
+pub mod controls;
 pub mod metrics;
+pub mod recorder;
+pub mod retry;
 pub mod thread_pools;
 
 use crate::apis::Api;
@@ -8,17 +11,22 @@ use crate::data::DelayedMessage;
 use crate::functions::{
     create_bindgen_externref_xform, create_bindgen_placeholder, link_functions_to_module, LinkError,
 };
-use crate::loader::PlaidModule;
+use crate::loader::{LimitAction, PlaidModule, PlaidModules};
 use crate::logging::{Logger, LoggingError, Severity};
 use crate::performance::ModulePerformanceMetadata;
 use crate::storage::Storage;
+use crate::telemetry;
 
+use controls::PausedLogTypes;
 use crossbeam_channel::{Receiver, RecvError, Sender, TrySendError};
 use metrics::ModuleExecutionMetrics;
+use recorder::Recorder;
+use retry::RetryHandler;
 use thread_pools::ExecutionThreadPools;
 use tokio::sync::oneshot::Sender as OneShotSender;
 use tokio_util::sync::CancellationToken;
 
+use opentelemetry::Context as TraceContext;
 use plaid_stl::messages::{LogSource, LogbacksAllowed};
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_with::{serde_as, DeserializeAs, SerializeAs};
@@ -127,6 +135,17 @@ pub struct Message {
     /// be run to generate a response.
     #[serde(skip)]
     pub module: Option<Arc<PlaidModule>>,
+    /// If this is some, only the named module will be run on the message. This is
+    /// used for retries of failed executions and for runs delayed by a rate limit.
+    #[serde(default)]
+    pub retry_module: Option<String>,
+    /// How many times a module has already been run on this message and failed
+    #[serde(default)]
+    pub attempt: u32,
+    /// The W3C `traceparent` of the span that produced this message. Modules run on
+    /// the message are traced as its children.
+    #[serde(default)]
+    pub trace_parent: Option<String>,
 }
 
 impl Message {
@@ -146,6 +165,9 @@ impl Message {
             logbacks_allowed,
             response_sender: None,
             module: None,
+            retry_module: None,
+            attempt: 0,
+            trace_parent: None,
         }
     }
 
@@ -169,6 +191,9 @@ impl Message {
             logbacks_allowed,
             response_sender,
             module,
+            retry_module: None,
+            attempt: 0,
+            trace_parent: None,
         }
     }
 
@@ -185,6 +210,9 @@ impl Message {
             logbacks_allowed: self.logbacks_allowed.clone(),
             response_sender: None,
             module: None,
+            retry_module: self.retry_module.clone(),
+            attempt: self.attempt,
+            trace_parent: self.trace_parent.clone(),
         }
     }
 }
@@ -218,11 +246,28 @@ pub struct Env {
     pub delayed_log_sender: Sender<DelayedMessage>,
     /// Shared with async tasks; set when shutdown begins.
     pub cancellation_token: CancellationToken,
+    /// The trace context of this execution. Host function calls and logbacks are its children.
+    pub trace_context: TraceContext,
+    /// Where calls to host functions are counted, if metrics are enabled
+    pub module_execution_metrics: Option<Arc<ModuleExecutionMetrics>>,
 }
 
 /// The executor that processes messages
 pub struct Executor {
     thread_pools: ExecutionThreadPools,
+    paused_log_types: Arc<PausedLogTypes>,
+}
+
+/// The state of one of the executor's queues
+#[derive(Serialize)]
+pub struct QueueStatus {
+    /// `general`, or the log type of a dedicated queue
+    pub name: String,
+    /// The number of messages waiting to be executed
+    pub depth: usize,
+    /// The maximum number of messages the queue can hold
+    pub capacity: usize,
+    pub num_threads: u8,
 }
 
 /// Join handles for executor worker threads.
@@ -284,6 +329,22 @@ pub enum ModuleExecutionError {
     UnknownExecutionError(String),
 }
 
+impl ModuleExecutionError {
+    /// A short name for the kind of error, e.g., to label metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ModuleExecutionError::ComputationExhausted(_) => "computation_exhausted",
+            ModuleExecutionError::ModuleError(_) => "module_error",
+            ModuleExecutionError::PersistentResponseNotAllowed => "persistent_response_not_allowed",
+            ModuleExecutionError::PersistentResponseTooLarge { .. } => {
+                "persistent_response_too_large"
+            }
+            ModuleExecutionError::LockingError(_) => "locking_error",
+            ModuleExecutionError::UnknownExecutionError(_) => "unknown_execution_error",
+        }
+    }
+}
+
 impl Into<ExecutorError> for ModuleExecutionError {
     fn into(self) -> ExecutorError {
         ExecutorError::ModuleExecutionError(self)
@@ -332,14 +393,10 @@ impl From<LoggingError> for ExecutorError {
 fn prepare_for_execution(
     message: Message,
     plaid_module: Arc<PlaidModule>,
-    api: Arc<Api>,
-    storage: Option<Arc<Storage>>,
-    cache: Option<Arc<Cache>>,
-    els: Logger,
     response: Option<String>,
     immediate_sender: Option<Sender<Message>>,
-    delayed_log_sender: Sender<DelayedMessage>,
-    cancellation_token: CancellationToken,
+    trace_context: TraceContext,
+    context: &ExecutionContext,
 ) -> Result<(Store, Instance, TypedFunction<(), i32>, FunctionEnv<Env>), ExecutorError> {
     // Prepare the structure for functions the module will use
     // AKA: Host Functions
@@ -349,19 +406,22 @@ fn prepare_for_execution(
     // for this message only.
     let mut store = Store::new(plaid_module.engine.clone());
 
+    let els = &context.els;
     let env = Env {
         module: plaid_module.clone(),
         message: message.create_duplicate(),
-        api: api.clone(),
-        storage: storage.clone(),
-        cache: cache.clone(),
+        api: context.api.clone(),
+        storage: context.storage.clone(),
+        cache: context.cache.clone(),
         external_logging_system: els.clone(),
         memory: None,
         response,
         execution_error_context: None,
         immediate_sender,
-        delayed_log_sender,
-        cancellation_token,
+        delayed_log_sender: context.delayed_log_sender.clone(),
+        cancellation_token: context.cancellation_token.clone(),
+        trace_context,
+        module_execution_metrics: context.module_execution_metrics.clone(),
     };
 
     let env = FunctionEnv::new(&mut store, env);
@@ -496,25 +556,66 @@ fn update_persistent_response(
     }
 }
 
+/// Everything a module run needs from the runtime, shared by all the runs on an execution thread
+#[derive(Clone)]
+pub struct ExecutionContext {
+    pub api: Arc<Api>,
+    pub storage: Option<Arc<Storage>>,
+    pub cache: Option<Arc<Cache>>,
+    pub els: Logger,
+    pub performance_mode: Option<Sender<ModulePerformanceMetadata>>,
+    pub module_execution_metrics: Option<Arc<ModuleExecutionMetrics>>,
+    pub delayed_log_sender: Sender<DelayedMessage>,
+    pub cancellation_token: CancellationToken,
+    pub retry_handler: Arc<RetryHandler>,
+    pub recorder: Option<Arc<Recorder>>,
+}
+
 /// This runs a message through a module and will handle module level errors.
 ///
 /// If there is a runtime level error then this function returns an error which
 /// will stop Plaid. This means that a module should NEVER be able to cause such
 /// an error. The only time this should return an error is if the runtime itself
 /// encounters a critical, unrecoverable error.
-fn process_message_with_module(
+pub(crate) fn process_message_with_module(
     message: Message,
     module: Arc<PlaidModule>,
-    api: Arc<Api>,
-    storage: Option<Arc<Storage>>,
-    cache: Option<Arc<Cache>>,
-    els: Logger,
-    performance_mode: Option<Sender<ModulePerformanceMetadata>>,
-    module_execution_metrics: Option<Arc<ModuleExecutionMetrics>>,
     immediate_sender: Option<Sender<Message>>,
-    delayed_log_sender: Sender<DelayedMessage>,
-    cancellation_token: CancellationToken,
+    context: &ExecutionContext,
 ) -> Result<(), ExecutorError> {
+    let ExecutionContext {
+        api,
+        els,
+        performance_mode,
+        module_execution_metrics,
+        delayed_log_sender,
+        retry_handler,
+        recorder,
+        ..
+    } = context;
+
+    // Modules over their rate limit don't run now. The permit is held until the end of the run.
+    let _permit = match &module.rate_limiter {
+        Some(limiter) => match limiter.try_acquire() {
+            Ok(permit) => Some(permit),
+            Err(retry_after) => {
+                handle_rate_limited(
+                    message,
+                    &module,
+                    limiter.on_limit(),
+                    retry_after,
+                    delayed_log_sender,
+                    module_execution_metrics.as_deref(),
+                );
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+
+    // The span for this run ends once its context is dropped, after the run completes
+    let trace_context = telemetry::start_execution(&module.name, &message);
+
     // TODO @obelisk: This will quietly swallow locking errors on the persistent response
     // This will eventually be caught if something tries to update the response but I don't
     // know if that's good enough.
@@ -524,17 +625,14 @@ fn process_message_with_module(
     let (mut store, instance, entrypoint, env) = match prepare_for_execution(
         message.create_duplicate(),
         module.clone(),
-        api.clone(),
-        storage.clone(),
-        cache.clone(),
-        els.clone(),
         persistent_response,
         immediate_sender,
-        delayed_log_sender,
-        cancellation_token,
+        trace_context.clone(),
+        context,
     ) {
         Ok((store, instance, ep, env)) => (store, instance, ep, env),
         Err(e) => {
+            telemetry::record_error(&trace_context, &e);
             els.log_module_error(
                 module.name.clone(),
                 format!("Failed to prepare for execution: {e}"),
@@ -566,7 +664,7 @@ fn process_message_with_module(
                         (remaining as f32 / computation_limit as f32) * 100.0;
                     let computation_used = 100.0 - computation_remaining_percentage;
 
-                    if let Some(metrics) = &module_execution_metrics {
+                    if let Some(metrics) = module_execution_metrics {
                         metrics.record_successful_execution(
                             &module.name,
                             computation_used as f64,
@@ -575,7 +673,7 @@ fn process_message_with_module(
                     }
 
                     // If performance monitoring is enabled, log data to the monitoring system
-                    if let Some(ref sender) = performance_mode {
+                    if let Some(sender) = performance_mode {
                         if let Err(e) = sender.send(ModulePerformanceMetadata {
                             module: module.name.clone(),
                             execution_time: begin.elapsed().as_micros(),
@@ -600,12 +698,42 @@ fn process_message_with_module(
 
     // If there was an error then log that it happened to the els
     if let Some(error) = error {
+        telemetry::record_error(&trace_context, &error);
+        if let Some(metrics) = module_execution_metrics {
+            metrics.record_execution_error(&module.name, &error);
+        }
         els.log_module_error(
             module.name.clone(),
             format!("{error}"),
             message.data.clone(),
         )?;
 
+        if let Some(recorder) = recorder {
+            recorder.record_error(&message, &module.name, &error.to_string());
+        }
+
+        // GET requests are answered straight away, so they are never retried
+        if message.response_sender.is_none() {
+            if let Some(dead_letter) =
+                retry_handler.handle_failure(&message, &module.name, &error, delayed_log_sender)
+            {
+                warn!(
+                    "Module [{}] is out of attempts on message [{}]. Parking it in the dead letter queue",
+                    module.name, message.id
+                );
+                let dead_letters = retry_handler.dead_letters().clone();
+                if let Err(e) = api
+                    .runtime
+                    .block_on(async move { dead_letters.park(dead_letter).await })
+                {
+                    error!(
+                        "Failed to park message [{}] in the dead letter queue: {e}",
+                        message.id
+                    );
+                }
+            }
+        }
+
         // Stop processing this log and move on to the next one
         return Ok(());
     }
@@ -621,6 +749,11 @@ fn process_message_with_module(
 
     // Update the persistent response
     if let Err(e) = update_persistent_response(&module, &env, &mut store, message.response_sender) {
+        if let (ExecutorError::ModuleExecutionError(error), Some(metrics)) =
+            (&e, module_execution_metrics)
+        {
+            metrics.record_execution_error(&module.name, error);
+        }
         let _ = els.log_module_error(
             module.name.clone(),
             format!("Failed to update persistent response: {e}"),
@@ -631,9 +764,52 @@ fn process_message_with_module(
     Ok(())
 }
 
+/// Delay or drop a message that `module` is over its rate limit for
+fn handle_rate_limited(
+    message: Message,
+    module: &PlaidModule,
+    action: LimitAction,
+    retry_after: u64,
+    delayed_log_sender: &Sender<DelayedMessage>,
+    module_execution_metrics: Option<&ModuleExecutionMetrics>,
+) {
+    // GET requests are waited on, so they can't be delayed
+    if action == LimitAction::Delay && message.response_sender.is_none() {
+        let mut delayed = message.create_duplicate();
+        // Delayed messages are stored by ID, so every delayed run needs its own
+        delayed.id = uuid::Uuid::new_v4().to_string();
+        delayed.retry_module = Some(module.name.clone());
+
+        match delayed_log_sender.try_send(DelayedMessage::new(retry_after, delayed)) {
+            Ok(()) => {
+                debug!(
+                    "Module [{}] is over its rate limit. Running it on message [{}] in {retry_after} seconds",
+                    module.name, message.id
+                );
+                if let Some(metrics) = module_execution_metrics {
+                    metrics.record_rate_limited(&module.name, "delayed");
+                }
+                return;
+            }
+            Err(e) => error!(
+                "Could not delay message [{}] for rate limited module [{}]: {e}",
+                message.id, module.name
+            ),
+        }
+    }
+
+    warn!(
+        "Module [{}] is over its rate limit. Message [{}] dropped",
+        module.name, message.id
+    );
+    if let Some(metrics) = module_execution_metrics {
+        metrics.record_rate_limited(&module.name, "dropped");
+    }
+}
+
 fn execution_loop(
     receiver: Receiver<Message>,
-    modules: HashMap<String, Vec<Arc<PlaidModule>>>,
+    modules: Arc<PlaidModules>,
     api: Arc<Api>,
     storage: Option<Arc<Storage>>,
     cache: Option<Arc<Cache>>,
@@ -643,55 +819,81 @@ fn execution_loop(
     immediate_sender: Weak<Sender<Message>>,
     delayed_log_sender: Sender<DelayedMessage>,
     cancellation_token: CancellationToken,
+    paused_log_types: Arc<PausedLogTypes>,
+    retry_handler: Arc<RetryHandler>,
+    recorder: Option<Arc<Recorder>>,
 ) -> Result<(), ExecutorError> {
+    let context = ExecutionContext {
+        api,
+        storage,
+        cache,
+        els,
+        performance_mode: performance_monitoring_mode,
+        module_execution_metrics,
+        delayed_log_sender,
+        cancellation_token,
+        retry_handler,
+        recorder,
+    };
+
     loop {
         let message = match receiver.recv() {
             Ok(message) => message,
             Err(RecvError) => return Ok(()),
         };
 
-        let immediate_sender = if cancellation_token.is_cancelled() {
+        // Messages for a single module (GET requests) are waited on, so they are never held
+        let message = if message.module.is_none() {
+            match paused_log_types.hold(message) {
+                Some(message) => message,
+                None => continue,
+            }
+        } else {
+            message
+        };
+
+        if let Some(recorder) = &context.recorder {
+            recorder.sample(&message);
+        }
+
+        let immediate_sender = if context.cancellation_token.is_cancelled() {
             None
         } else {
             immediate_sender.upgrade().map(|sender| (*sender).clone())
         };
 
-        // Check that we know what modules to send this new log to
-        match (&message.module, modules.get(&message.type_)) {
+        // Check that we know what modules to send this new log to. The channel is looked up
+        // for every message so that reloaded modules are picked up straight away.
+        // A retry only runs on the module that failed to process the message
+        let retry_module = match &message.retry_module {
+            Some(name) => match modules.get_module(name) {
+                Some(module) => Some(module),
+                None => {
+                    warn!("Dropping retry of message [{}] because module [{name}] is no longer loaded", message.id);
+                    continue;
+                }
+            },
+            None => None,
+        };
+
+        match (
+            message.module.clone().or(retry_module),
+            modules.get_channel(&message.type_),
+        ) {
             // If this message has a response sender, we only
             // want to run it on that rule, not any defined logging
             // channel.
-            (Some(ref module), _) => {
-                let module = module.clone();
-                process_message_with_module(
-                    message,
-                    module,
-                    api.clone(),
-                    storage.clone(),
-                    cache.clone(),
-                    els.clone(),
-                    performance_monitoring_mode.clone(),
-                    module_execution_metrics.clone(),
-                    immediate_sender.clone(),
-                    delayed_log_sender.clone(),
-                    cancellation_token.clone(),
-                )?;
+            (Some(module), _) => {
+                process_message_with_module(message, module, immediate_sender.clone(), &context)?;
             }
             (None, Some(modules)) => {
                 // For every module that operates on that log type
                 for module in modules {
                     process_message_with_module(
                         message.create_duplicate(),
-                        module.clone(),
-                        api.clone(),
-                        storage.clone(),
-                        cache.clone(),
-                        els.clone(),
-                        performance_monitoring_mode.clone(),
-                        module_execution_metrics.clone(),
+                        module,
                         immediate_sender.clone(),
-                        delayed_log_sender.clone(),
-                        cancellation_token.clone(),
+                        &context,
                     )?;
                 }
             }
@@ -731,7 +933,7 @@ fn determine_error(
 impl Executor {
     pub fn new(
         thread_pools: ExecutionThreadPools,
-        modules: HashMap<String, Vec<Arc<PlaidModule>>>,
+        modules: Arc<PlaidModules>,
         api: Arc<Api>,
         storage: Option<Arc<Storage>>,
         cache: Option<Arc<Cache>>,
@@ -741,8 +943,11 @@ impl Executor {
         immediate_sender: Weak<Sender<Message>>,
         delayed_log_sender: Sender<DelayedMessage>,
         cancellation_token: CancellationToken,
+        retry_handler: Arc<RetryHandler>,
+        recorder: Option<Arc<Recorder>>,
     ) -> (Self, ExecutorThreads) {
         let mut thread_handles = Vec::new();
+        let paused_log_types = Arc::new(PausedLogTypes::default());
 
         // General processing
         for i in 0..thread_pools.general_pool.num_threads {
@@ -758,6 +963,9 @@ impl Executor {
             let immediate_sender = immediate_sender.clone();
             let delayed_log_sender = delayed_log_sender.clone();
             let cancellation_token = cancellation_token.clone();
+            let paused_log_types = paused_log_types.clone();
+            let retry_handler = retry_handler.clone();
+            let recorder = recorder.clone();
             let handle = thread::spawn(move || {
                 if let Err(e) = execution_loop(
                     receiver.clone(),
@@ -771,6 +979,9 @@ impl Executor {
                     immediate_sender.clone(),
                     delayed_log_sender.clone(),
                     cancellation_token.clone(),
+                    paused_log_types.clone(),
+                    retry_handler.clone(),
+                    recorder.clone(),
                 ) {
                     error!("General execution thread {i} exited with error: {e}");
                 }
@@ -794,6 +1005,9 @@ impl Executor {
                 let immediate_sender = immediate_sender.clone();
                 let delayed_log_sender = delayed_log_sender.clone();
                 let cancellation_token = cancellation_token.clone();
+                let paused_log_types = paused_log_types.clone();
+                let retry_handler = retry_handler.clone();
+                let recorder = recorder.clone();
                 let handle = thread::spawn(move || {
                     if let Err(e) = execution_loop(
                         receiver.clone(),
@@ -807,6 +1021,9 @@ impl Executor {
                         immediate_sender.clone(),
                         delayed_log_sender.clone(),
                         cancellation_token.clone(),
+                        paused_log_types.clone(),
+                        retry_handler.clone(),
+                        recorder.clone(),
                     ) {
                         error!("{log_type} dedicated execution thread {i} exited with error: {e}");
                     }
@@ -814,7 +1031,13 @@ impl Executor {
                 thread_handles.push(handle);
             }
         }
-        (Self { thread_pools }, ExecutorThreads { thread_handles })
+        (
+            Self {
+                thread_pools,
+                paused_log_types,
+            },
+            ExecutorThreads { thread_handles },
+        )
     }
 
     /// Execute a message coming from a webhook, by sending it to the appropriate thread pool.
@@ -836,4 +1059,56 @@ impl Executor {
         };
         sender.try_send(message)
     }
+
+    /// Get the log types that are currently paused
+    pub fn paused_log_types(&self) -> Arc<PausedLogTypes> {
+        self.paused_log_types.clone()
+    }
+
+    /// Resume a paused log type and queue the messages held while it was paused.
+    /// Returns how many held messages were queued and how many were dropped because
+    /// the queue was full, or `None` if the log type was not paused.
+    pub fn resume_log_type(&self, log_type: &str) -> Option<(usize, usize)> {
+        let held = self.paused_log_types.resume(log_type)?;
+        let total = held.len();
+        let mut queued = 0;
+        for message in held {
+            match self.execute_webhook_message(message) {
+                Ok(()) => queued += 1,
+                Err(TrySendError::Full(_)) => {}
+                Err(TrySendError::Disconnected(_)) => break,
+            }
+        }
+
+        if queued < total {
+            error!(
+                "Queue Full! [{}] held messages for [{log_type}] dropped on resume!",
+                total - queued
+            );
+        }
+        Some((queued, total - queued))
+    }
+
+    /// Get the depth and capacity of every queue
+    pub fn queue_status(&self) -> Vec<QueueStatus> {
+        self.thread_pools
+            .named_pools()
+            .map(|(name, pool)| QueueStatus {
+                name: name.to_string(),
+                depth: pool.receiver.len(),
+                capacity: pool.receiver.capacity().unwrap_or_default(),
+                num_threads: pool.num_threads,
+            })
+            .collect()
+    }
+
+    /// Discard every message waiting in a queue. Returns the number of discarded
+    /// messages, or `None` if there is no queue with that name.
+    pub fn drain_queue(&self, name: &str) -> Option<usize> {
+        let (_, pool) = self
+            .thread_pools
+            .named_pools()
+            .find(|(queue, _)| *queue == name)?;
+        Some(pool.receiver.try_iter().count())
+    }
 }
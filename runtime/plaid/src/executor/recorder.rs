@@ -0,0 +1,417 @@
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crossbeam_channel::{bounded, Receiver, Sender, TryRecvError, TrySendError};
+use serde::{Deserialize, Deserializer, Serialize};
+
+#[cfg(feature = "aws")]
+use crate::{get_aws_sdk_config, AwsAuthentication};
+
+use super::Message;
+
+/// How many recordings can wait to be written before new ones are dropped
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Records production messages so that module failures can be reproduced later
+/// with `plaid-replay`.
+///
+/// E.g.,
+/// ```toml
+/// [executor.recorder]
+/// sample_rate = 0.01
+/// log_types = ["github"]
+/// [executor.recorder.destination.file]
+/// path = "/var/lib/plaid/recordings.jsonl"
+/// ```
+/// records every message a module fails to process and 1% of all other messages
+/// of type `github`.
+#[derive(Deserialize)]
+pub struct RecorderConfig {
+    /// The fraction of messages that are recorded, between 0 and 1. Defaults to 0.
+    #[serde(default, deserialize_with = "deserialize_sample_rate")]
+    pub sample_rate: f64,
+    /// Whether every message a module fails to process is recorded. Defaults to `true`.
+    #[serde(default = "default_record_errors")]
+    pub record_errors: bool,
+    /// Only messages of these log types are recorded. If empty, every log type is.
+    #[serde(default)]
+    pub log_types: HashSet<String>,
+    /// Where recordings are written
+    pub destination: RecordingDestination,
+}
+
+fn default_record_errors() -> bool {
+    true
+}
+
+fn deserialize_sample_rate<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = f64::deserialize(deserializer)?;
+    if !(0.0..=1.0).contains(&value) {
+        return Err(serde::de::Error::custom(
+            "`sample_rate` must be between 0 and 1",
+        ));
+    }
+    Ok(value)
+}
+
+/// Where recordings are written
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordingDestination {
+    /// A local file with one recording per line. Once the file reaches `max_size` bytes it is
+    /// rotated to `<path>.1`, the previous `<path>.1` to `<path>.2` and so on, keeping at most
+    /// `max_files` rotated files.
+    File {
+        path: PathBuf,
+        /// Defaults to 100 MiB
+        #[serde(default = "default_max_size")]
+        max_size: u64,
+        /// Defaults to 5
+        #[serde(default = "default_max_files")]
+        max_files: usize,
+    },
+    /// An S3 bucket with one object per recording, named `<prefix><recorded_at>-<uuid>.json`
+    #[cfg(feature = "aws")]
+    S3 {
+        authentication: AwsAuthentication,
+        bucket: String,
+        #[serde(default)]
+        prefix: String,
+    },
+}
+
+fn default_max_size() -> u64 {
+    100 * 1024 * 1024
+}
+
+fn default_max_files() -> usize {
+    5
+}
+
+/// Why a message was recorded
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordingReason {
+    /// The message was picked by sampling
+    Sampled,
+    /// A module failed to process the message
+    Error,
+}
+
+/// A recorded message
+#[derive(Serialize, Deserialize)]
+pub struct Recording {
+    /// The Unix time, in seconds, at which the message was recorded
+    pub recorded_at: u64,
+    pub reason: RecordingReason,
+    /// The module that failed to process the message. Only set for errors.
+    pub module: Option<String>,
+    /// The error the module failed with. Only set for errors.
+    pub error: Option<String>,
+    pub message: Message,
+}
+
+/// Picks the messages that are recorded and hands them to a [`RecordingWriter`]
+pub struct Recorder {
+    sender: Sender<Recording>,
+    sample_rate: f64,
+    record_errors: bool,
+    log_types: HashSet<String>,
+}
+
+impl Recorder {
+    /// Create a recorder and the writer that writes its recordings. The writer must be run
+    /// for anything to be written.
+    pub fn new(config: RecorderConfig) -> (Self, RecordingWriter) {
+        let (sender, receiver) = bounded(CHANNEL_CAPACITY);
+        (
+            Self {
+                sender,
+                sample_rate: config.sample_rate,
+                record_errors: config.record_errors,
+                log_types: config.log_types,
+            },
+            RecordingWriter {
+                receiver,
+                destination: config.destination,
+            },
+        )
+    }
+
+    /// Record a message that has just been received, if it is picked by sampling.
+    /// Messages re-run on a single module (retries and delayed runs) are never sampled
+    /// since the original message already had its chance.
+    pub(super) fn sample(&self, message: &Message) {
+        if self.sample_rate == 0.0 || message.retry_module.is_some() || !self.records(message) {
+            return;
+        }
+        if rand::random::<f64>() < self.sample_rate {
+            self.record(message, RecordingReason::Sampled, None, None);
+        }
+    }
+
+    /// Record a message that `module` failed to process
+    pub(super) fn record_error(&self, message: &Message, module: &str, error: &str) {
+        if self.record_errors && self.records(message) {
+            self.record(
+                message,
+                RecordingReason::Error,
+                Some(module.to_string()),
+                Some(error.to_string()),
+            );
+        }
+    }
+
+    fn records(&self, message: &Message) -> bool {
+        self.log_types.is_empty() || self.log_types.contains(&message.type_)
+    }
+
+    fn record(
+        &self,
+        message: &Message,
+        reason: RecordingReason,
+        module: Option<String>,
+        error: Option<String>,
+    ) {
+        let mut message = message.create_duplicate();
+        // The recording is replayed on a module of the user's choosing
+        message.retry_module = None;
+
+        let recording = Recording {
+            recorded_at: now(),
+            reason,
+            module,
+            error,
+            message,
+        };
+        match self.sender.try_send(recording) {
+            Ok(()) => (),
+            Err(TrySendError::Full(recording)) => warn!(
+                "Too many recordings are waiting to be written. Recording of message [{}] dropped",
+                recording.message.id
+            ),
+            Err(TrySendError::Disconnected(_)) => {
+                error!("The recording writer is no longer running")
+            }
+        }
+    }
+}
+
+/// Writes recordings to their destination
+pub struct RecordingWriter {
+    receiver: Receiver<Recording>,
+    destination: RecordingDestination,
+}
+
+impl RecordingWriter {
+    /// Write recordings as they come in. Returns once every [`Recorder`] has been dropped
+    /// and all recordings have been written.
+    pub async fn start(self) {
+        let mut destination = match Destination::open(self.destination).await {
+            Ok(destination) => destination,
+            Err(e) => {
+                error!(
+                    "Could not open the recording destination, no messages will be recorded: {e}"
+                );
+                return;
+            }
+        };
+
+        loop {
+            match self.receiver.try_recv() {
+                Ok(recording) => {
+                    let id = recording.message.id.clone();
+                    if let Err(e) = destination.write(recording).await {
+                        error!("Failed to write recording of message [{id}]: {e}");
+                    }
+                }
+                Err(TryRecvError::Empty) => tokio::time::sleep(Duration::from_secs(1)).await,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+    }
+}
+
+enum Destination {
+    File(RotatingFile),
+    #[cfg(feature = "aws")]
+    S3 {
+        client: aws_sdk_s3::Client,
+        bucket: String,
+        prefix: String,
+    },
+}
+
+impl Destination {
+    async fn open(destination: RecordingDestination) -> Result<Self, String> {
+        match destination {
+            RecordingDestination::File {
+                path,
+                max_size,
+                max_files,
+            } => {
+                info!("Recording messages to [{}]", path.display());
+                Ok(Self::File(RotatingFile::open(path, max_size, max_files)?))
+            }
+            #[cfg(feature = "aws")]
+            RecordingDestination::S3 {
+                authentication,
+                bucket,
+                prefix,
+            } => {
+                info!("Recording messages to S3 bucket [{bucket}]");
+                let sdk_config = get_aws_sdk_config(&authentication).await;
+                Ok(Self::S3 {
+                    client: aws_sdk_s3::Client::new(&sdk_config),
+                    bucket,
+                    prefix,
+                })
+            }
+        }
+    }
+
+    async fn write(&mut self, recording: Recording) -> Result<(), String> {
+        // Every record ends with a newline, so files and downloaded S3 objects can be
+        // concatenated and read back as JSON lines
+        let mut serialized = serde_json::to_vec(&recording).map_err(|e| e.to_string())?;
+        serialized.push(b'\n');
+        match self {
+            Self::File(file) => file.write(&serialized),
+            #[cfg(feature = "aws")]
+            Self::S3 {
+                client,
+                bucket,
+                prefix,
+            } => {
+                let key = format!(
+                    "{prefix}{}-{}.json",
+                    recording.recorded_at,
+                    uuid::Uuid::new_v4()
+                );
+                client
+                    .put_object()
+                    .bucket(bucket.as_str())
+                    .key(key)
+                    .body(serialized.into())
+                    .send()
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+/// A file that is rotated once it reaches a maximum size
+struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_size: u64, max_files: usize) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        let size = file
+            .metadata()
+            .map_err(|e| format!("{}: {e}", path.display()))?
+            .len();
+
+        Ok(Self {
+            path,
+            max_size,
+            max_files,
+            file,
+            size,
+        })
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), String> {
+        if self.size > 0 && self.size + data.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file
+            .write_all(data)
+            .map_err(|e| format!("{}: {e}", self.path.display()))?;
+        self.size += data.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<(), String> {
+        let rotated = |n: usize| -> PathBuf {
+            let mut path = self.path.clone().into_os_string();
+            path.push(format!(".{n}"));
+            path.into()
+        };
+
+        if self.max_files == 0 {
+            fs::remove_file(&self.path).map_err(|e| format!("{}: {e}", self.path.display()))?;
+        } else {
+            for n in (1..self.max_files).rev() {
+                rename_if_exists(&rotated(n), &rotated(n + 1))?;
+            }
+            rename_if_exists(&self.path, &rotated(1))?;
+        }
+
+        *self = Self::open(self.path.clone(), self.max_size, self.max_files)?;
+        Ok(())
+    }
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> Result<(), String> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("{} -> {}: {e}", from.display(), to.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_are_rotated_and_old_ones_removed() {
+        let dir = std::env::temp_dir().join(format!("plaid-recorder-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("recordings.jsonl");
+
+        let mut file = RotatingFile::open(path.clone(), 10, 2).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write(line.as_bytes()).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("recordings.jsonl.1")).unwrap(),
+            "third\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("recordings.jsonl.2")).unwrap(),
+            "second\n"
+        );
+        assert!(!dir.join("recordings.jsonl.3").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crossbeam_channel::Sender;
+use plaid_stl::messages::LogSource;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::data::DelayedMessage;
+use crate::storage::{Storage, StorageError};
+
+use super::{Message, ModuleExecutionError};
+
+const DEAD_LETTER_NS: &str = "dead_letter_internal";
+
+/// How executions that fail for a log type are retried.
+///
+/// E.g.,
+/// ```toml
+/// [executor.retry_policies."github"]
+/// max_attempts = 4
+/// backoff = 30
+/// ```
+/// will retry a failed execution after 30, 60 and 120 seconds before giving up.
+#[derive(Deserialize, Clone)]
+pub struct RetryPolicy {
+    /// The total number of times a module is run on a message, including the first
+    /// attempt. Must be at least 1.
+    #[serde(deserialize_with = "deserialize_max_attempts")]
+    pub max_attempts: u32,
+    /// How many seconds to wait before the first retry. Defaults to 10.
+    #[serde(default = "default_backoff")]
+    pub backoff: u64,
+    /// What the wait is multiplied by after every retry. Defaults to 2.
+    #[serde(default = "default_backoff_multiplier")]
+    pub backoff_multiplier: u64,
+    /// The longest wait between two attempts, in seconds. Defaults to 3600.
+    #[serde(default = "default_max_backoff")]
+    pub max_backoff: u64,
+    /// Whether messages that are out of attempts are parked in the dead letter queue.
+    /// Otherwise they are dropped. Defaults to `true`.
+    #[serde(default = "default_dead_letter")]
+    pub dead_letter: bool,
+}
+
+fn default_backoff() -> u64 {
+    10
+}
+
+fn default_backoff_multiplier() -> u64 {
+    2
+}
+
+fn default_max_backoff() -> u64 {
+    3600
+}
+
+fn default_dead_letter() -> bool {
+    true
+}
+
+fn deserialize_max_attempts<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = u32::deserialize(deserializer)?;
+    if value == 0 {
+        return Err(serde::de::Error::custom(
+            "`max_attempts` must be at least 1",
+        ));
+    }
+    Ok(value)
+}
+
+impl RetryPolicy {
+    /// How many seconds to wait before running a message again, after `attempts` failed attempts
+    fn backoff_after(&self, attempts: u32) -> u64 {
+        let factor = self
+            .backoff_multiplier
+            .saturating_pow(attempts.saturating_sub(1));
+        self.backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+/// A message that a module failed to process and that is out of retries
+#[derive(Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// The module that failed to process the message
+    pub module: String,
+    /// The error from the last attempt
+    pub error: String,
+    /// How many times the module was run on the message
+    pub attempts: u32,
+    /// The Unix time, in seconds, of the last attempt
+    pub failed_at: u64,
+    pub message: Message,
+}
+
+/// What is reported about a dead letter when listing the queue
+#[derive(Serialize)]
+pub struct DeadLetterSummary {
+    pub id: String,
+    pub module: String,
+    pub log_type: String,
+    pub source: LogSource,
+    pub error: String,
+    pub attempts: u32,
+    pub failed_at: u64,
+}
+
+/// Errors encountered while using the dead letter queue
+#[derive(Debug)]
+pub enum DeadLetterError {
+    StorageError(StorageError),
+    SerializationError(String),
+}
+
+impl std::fmt::Display for DeadLetterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::StorageError(e) => write!(f, "Storage error: {e}"),
+            Self::SerializationError(e) => write!(f, "Serialization error: {e}"),
+        }
+    }
+}
+
+impl From<StorageError> for DeadLetterError {
+    fn from(e: StorageError) -> Self {
+        Self::StorageError(e)
+    }
+}
+
+/// Messages that ran out of retries, kept in Plaid's internal storage until they
+/// are replayed or deleted.
+#[derive(Clone)]
+pub struct DeadLetterQueue {
+    storage: Arc<Storage>,
+}
+
+impl DeadLetterQueue {
+    pub fn new(storage: Arc<Storage>) -> Self {
+        Self { storage }
+    }
+
+    /// Park a message in the queue
+    pub async fn park(&self, letter: DeadLetter) -> Result<(), DeadLetterError> {
+        let value = serde_json::to_vec(&letter)
+            .map_err(|e| DeadLetterError::SerializationError(e.to_string()))?;
+        self.storage
+            .insert(
+                DEAD_LETTER_NS.to_string(),
+                uuid::Uuid::new_v4().to_string(),
+                value,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Get every message in the queue, oldest first
+    pub async fn list(&self) -> Result<Vec<DeadLetterSummary>, DeadLetterError> {
+        let mut letters: Vec<DeadLetterSummary> = self
+            .storage
+            .fetch_all(DEAD_LETTER_NS, None)
+            .await?
+            .into_iter()
+            .filter_map(|(id, value)| {
+                let letter = match serde_json::from_slice::<DeadLetter>(&value?) {
+                    Ok(letter) => letter,
+                    Err(e) => {
+                        warn!("Skipping dead letter [{id}] which could not be deserialized [{e}]");
+                        return None;
+                    }
+                };
+                Some(DeadLetterSummary {
+                    id,
+                    module: letter.module,
+                    log_type: letter.message.type_,
+                    source: letter.message.source,
+                    error: letter.error,
+                    attempts: letter.attempts,
+                    failed_at: letter.failed_at,
+                })
+            })
+            .collect();
+        letters.sort_by_key(|letter| letter.failed_at);
+        Ok(letters)
+    }
+
+    /// Remove a message from the queue and return it, or `None` if there is no message with that ID
+    pub async fn take(&self, id: &str) -> Result<Option<DeadLetter>, DeadLetterError> {
+        match self.storage.delete(DEAD_LETTER_NS, id).await? {
+            Some(value) => serde_json::from_slice(&value)
+                .map(Some)
+                .map_err(|e| DeadLetterError::SerializationError(e.to_string())),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Decides what happens to a message after a module fails to process it: it is either scheduled
+/// to run again through the delayed logback system, or parked in the dead letter queue.
+pub struct RetryHandler {
+    /// Mapping { log_type --> retry policy }
+    policies: HashMap<String, RetryPolicy>,
+    dead_letters: DeadLetterQueue,
+}
+
+impl RetryHandler {
+    pub fn new(policies: HashMap<String, RetryPolicy>, dead_letters: DeadLetterQueue) -> Self {
+        Self {
+            policies,
+            dead_letters,
+        }
+    }
+
+    /// Handle a failed execution of `module` on `message`. Only computation exhaustion and errors
+    /// returned by the module are retried, and only for log types that have a retry policy.
+    ///
+    /// Returns a dead letter if the message is out of attempts and should be parked.
+    pub(super) fn handle_failure(
+        &self,
+        message: &Message,
+        module: &str,
+        error: &ModuleExecutionError,
+        delayed_log_sender: &Sender<DelayedMessage>,
+    ) -> Option<DeadLetter> {
+        if !matches!(
+            error,
+            ModuleExecutionError::ComputationExhausted(_) | ModuleExecutionError::ModuleError(_)
+        ) {
+            return None;
+        }
+        let policy = self.policies.get(&message.type_)?;
+
+        let attempts = message.attempt + 1;
+        if attempts < policy.max_attempts {
+            let backoff = policy.backoff_after(attempts);
+            let mut retry = message.create_duplicate();
+            // Delayed messages are stored by ID, so every retry needs its own
+            retry.id = uuid::Uuid::new_v4().to_string();
+            retry.retry_module = Some(module.to_string());
+            retry.attempt = attempts;
+
+            match delayed_log_sender.try_send(DelayedMessage::new(backoff, retry)) {
+                Ok(()) => {
+                    info!("Retrying [{module}] on message [{}] in {backoff} seconds. Attempt {} of {}", message.id, attempts + 1, policy.max_attempts);
+                    return None;
+                }
+                Err(e) => {
+                    error!(
+                        "Could not schedule a retry of [{module}] on message [{}]: {e}",
+                        message.id
+                    );
+                }
+            }
+        }
+
+        if !policy.dead_letter {
+            return None;
+        }
+
+        let mut parked = message.create_duplicate();
+        parked.retry_module = Some(module.to_string());
+        Some(DeadLetter {
+            module: module.to_string(),
+            error: error.to_string(),
+            attempts,
+            failed_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            message: parked,
+        })
+    }
+
+    /// The dead letter queue messages are parked in
+    pub fn dead_letters(&self) -> &DeadLetterQueue {
+        &self.dead_letters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            backoff: 30,
+            backoff_multiplier: 2,
+            max_backoff: 200,
+            dead_letter: true,
+        };
+        assert_eq!(policy.backoff_after(1), 30);
+        assert_eq!(policy.backoff_after(2), 60);
+        assert_eq!(policy.backoff_after(3), 120);
+        assert_eq!(policy.backoff_after(4), 200);
+        assert_eq!(policy.backoff_after(60), 200);
+    }
+}
@@ -61,4 +61,14 @@ impl ExecutionThreadPools {
             dedicated_pools,
         }
     }
+
+    /// Get every pool along with the name of its queue: `general` for the general
+    /// pool, or the log type a dedicated pool serves.
+    pub fn named_pools(&self) -> impl Iterator<Item = (&str, &ThreadPool)> {
+        std::iter::once(("general", &self.general_pool)).chain(
+            self.dedicated_pools
+                .iter()
+                .map(|(log_type, pool)| (log_type.as_str(), pool)),
+        )
+    }
 }
@@ -1,7 +1,8 @@
-use super::{safely_write_data_back, FunctionErrors};
+use super::{record_host_call, safely_write_data_back, FunctionErrors};
 use crate::apis::ApiError;
 use crate::executor::Env;
 use crate::functions::{get_memory, safely_get_string};
+use crate::telemetry;
 use wasmer::{AsStoreRef, Function, FunctionEnv, FunctionEnvMut, Store, WasmPtr};
 
 const ALLOW_IN_TEST_MODE: bool = true;
@@ -14,7 +15,7 @@ const DISALLOW_IN_TEST_MODE: bool = false;
 ///   - Accessing and validating memory from the guest
 ///   - Checking that the API is configured.
 ///   - Running the function + returning the result (as an i32)
-/// - A public wrapper function that calls the implementation function and returns the result as an integer.
+/// - A public wrapper function that calls the implementation function, traces and counts the call, and returns the result as an integer.
 ///
 /// # Parameters
 /// - `$api`: The name of the API (e.g., `github`).
@@ -53,17 +54,28 @@ macro_rules! impl_new_function {
 
                 let params = safely_get_string(&memory_view, params_buffer, params_buffer_len)?;
 
-                // Check that the request API system is even configured.
-                // This is something like Okta, Slack, or GitHub
-                let api = env_data.api.$api.as_ref().ok_or(FunctionErrors::ApiNotConfigured)?;
-
-                // Clone the APIs Arc to use in Tokio closure
-                let env_api = env_data.api.clone();
-                let module = env_data.module.clone();
-                // Run the function on the Tokio runtime and wait for the result
-                let result = env_api.runtime.block_on(async move {
-                    api.$function_name(&params, module).await
-                });
+                // Under the test harness, the call is recorded and answered by a mock
+                let result = if let Some(mocks) = &env_data.api.mocks {
+                    match mocks
+                        .respond(stringify!([< $api _ $function_name >]), &params)
+                        .ok_or(FunctionErrors::ApiNotConfigured)?
+                    {
+                        Ok(response) => response.parse().map_err(|_| ApiError::BadRequest),
+                        Err(e) => Err(e),
+                    }
+                } else {
+                    // Check that the request API system is even configured.
+                    // This is something like Okta, Slack, or GitHub
+                    let api = env_data.api.$api.as_ref().ok_or(FunctionErrors::ApiNotConfigured)?;
+
+                    // Clone the APIs Arc to use in Tokio closure
+                    let env_api = env_data.api.clone();
+                    let module = env_data.module.clone();
+                    // Run the function on the Tokio runtime and wait for the result
+                    env_api.runtime.block_on(async move {
+                        api.$function_name(&params, module).await
+                    })
+                };
 
                 let return_data = match result {
                     Ok(return_data) => return_data,
@@ -82,13 +94,17 @@ macro_rules! impl_new_function {
 
             fn [< $api _ $function_name >] (env: FunctionEnvMut<Env>, params_buffer: WasmPtr<u8>, params_buffer_len: u32) -> i32 {
                 let name = env.data().module.name.clone();
-                match [< $api _ $function_name _impl>](env, params_buffer, params_buffer_len) {
+                let span = telemetry::start_host_call(&env.data().trace_context, stringify!($api), stringify!([< $api _ $function_name >]));
+                record_host_call(env.data(), stringify!($api), stringify!([< $api _ $function_name >]));
+                let code = match [< $api _ $function_name _impl>](env, params_buffer, params_buffer_len) {
                     Ok(res) => res,
                     Err(e) => {
                         error!("{} experienced an issue calling {}: {:?}", name, stringify!([< $api _ $function_name >]), e);
                         e as i32
                     }
-                }
+                };
+                telemetry::end_host_call(span, code);
+                code
             }
         }
     }
@@ -101,7 +117,7 @@ macro_rules! impl_new_function {
 ///   - Accessing and validating memory from the guest
 ///   - Checking that the API is configured.
 ///   - Running the function + returning the result and handling errors
-/// - A public wrapper function that calls the implementation function and returns the result as an integer.
+/// - A public wrapper function that calls the implementation function, traces and counts the call, and returns the result as an integer.
 ///
 /// # Parameters
 /// - `$api`: The name of the API (e.g., `github`).
@@ -140,16 +156,23 @@ macro_rules! impl_new_function_with_error_buffer {
 
                 let params = safely_get_string(&memory_view, params_buffer, params_buffer_len)?;
 
-                // Check the requested API system is configured.
-                let api = env_data.api.$api.as_ref().ok_or(FunctionErrors::ApiNotConfigured)?;
-
-                // Clone the APIs Arc to use in Tokio closure
-                let env_api = env_data.api.clone();
-                let module = env_data.module.clone();
-                // Run the function on the Tokio runtime and wait for the result
-                let result = env_api.runtime.block_on(async move {
-                    api.$function_name(&params, module).await
-                });
+                // Under the test harness, the call is recorded and answered by a mock
+                let result = if let Some(mocks) = &env_data.api.mocks {
+                    mocks
+                        .respond(stringify!([< $api _ $function_name >]), &params)
+                        .ok_or(FunctionErrors::ApiNotConfigured)?
+                } else {
+                    // Check the requested API system is configured.
+                    let api = env_data.api.$api.as_ref().ok_or(FunctionErrors::ApiNotConfigured)?;
+
+                    // Clone the APIs Arc to use in Tokio closure
+                    let env_api = env_data.api.clone();
+                    let module = env_data.module.clone();
+                    // Run the function on the Tokio runtime and wait for the result
+                    env_api.runtime.block_on(async move {
+                        api.$function_name(&params, module).await
+                    })
+                };
 
                 let return_data = match result {
                     Ok(return_data) => return_data,
@@ -176,13 +199,17 @@ macro_rules! impl_new_function_with_error_buffer {
 
             fn [< $api _ $function_name >] (env: FunctionEnvMut<Env>, params_buffer: WasmPtr<u8>, params_buffer_len: u32, ret_buffer: WasmPtr<u8>, ret_buffer_len: u32) -> i32 {
                 let name = env.data().module.name.clone();
-                match [< $api _ $function_name _impl>](env, params_buffer, params_buffer_len, ret_buffer, ret_buffer_len) {
+                let span = telemetry::start_host_call(&env.data().trace_context, stringify!($api), stringify!([< $api _ $function_name >]));
+                record_host_call(env.data(), stringify!($api), stringify!([< $api _ $function_name >]));
+                let code = match [< $api _ $function_name _impl>](env, params_buffer, params_buffer_len, ret_buffer, ret_buffer_len) {
                     Ok(res) => res,
                     Err(e) => {
                         error!("{} experienced an issue calling {}: {:?}", name, stringify!([< $api _ $function_name >]), e);
                         e as i32
                     }
-                }
+                };
+                telemetry::end_host_call(span, code);
+                code
             }
         }
     }
@@ -195,7 +222,7 @@ macro_rules! impl_new_function_with_error_buffer {
 ///   - Accessing and validating memory from the guest
 ///   - Checking that the API is configured.
 ///   - Running the function + returning the result and handling errors
-/// - A public wrapper function that calls the implementation function and returns the result as an integer.
+/// - A public wrapper function that calls the implementation function, traces and counts the call, and returns the result as an integer.
 ///
 /// # Parameters
 /// - `$api`: The name of the API (e.g., `aws`).
@@ -238,17 +265,24 @@ macro_rules! impl_new_sub_module_function_with_error_buffer {
 
                 let params = safely_get_string(&memory_view, params_buffer, params_buffer_len)?;
 
-                // Check that AWS API is configured
-                let aws = env_data.api.$api.as_ref().ok_or(FunctionErrors::ApiNotConfigured)?;
-                let sub_module = aws.$sub_module.as_ref().ok_or(FunctionErrors::ApiNotConfigured)?;
-
-                // Clone the APIs Arc to use in Tokio closure
-                let env_api = env_data.api.clone();
-                let module = env_data.module.clone();
-                // Run the function on the Tokio runtime and wait for the result
-                let result = env_api.runtime.block_on(async move {
-                    sub_module.$function_name(&params, module).await
-                });
+                // Under the test harness, the call is recorded and answered by a mock
+                let result = if let Some(mocks) = &env_data.api.mocks {
+                    mocks
+                        .respond(stringify!([< $api _ $sub_module _ $function_name >]), &params)
+                        .ok_or(FunctionErrors::ApiNotConfigured)?
+                } else {
+                    // Check that AWS API is configured
+                    let aws = env_data.api.$api.as_ref().ok_or(FunctionErrors::ApiNotConfigured)?;
+                    let sub_module = aws.$sub_module.as_ref().ok_or(FunctionErrors::ApiNotConfigured)?;
+
+                    // Clone the APIs Arc to use in Tokio closure
+                    let env_api = env_data.api.clone();
+                    let module = env_data.module.clone();
+                    // Run the function on the Tokio runtime and wait for the result
+                    env_api.runtime.block_on(async move {
+                        sub_module.$function_name(&params, module).await
+                    })
+                };
 
                 let return_data = match result {
                     Ok(return_data) => return_data,
@@ -275,13 +309,17 @@ macro_rules! impl_new_sub_module_function_with_error_buffer {
 
             fn [< $api _ $sub_module _ $function_name >] (env: FunctionEnvMut<Env>, params_buffer: WasmPtr<u8>, params_buffer_len: u32, ret_buffer: WasmPtr<u8>, ret_buffer_len: u32) -> i32 {
                 let name = env.data().module.name.clone();
-                match [< $api _ $sub_module _ $function_name _impl>](env, params_buffer, params_buffer_len, ret_buffer, ret_buffer_len) {
+                let span = telemetry::start_host_call(&env.data().trace_context, stringify!($api), stringify!([< $api _ $sub_module _ $function_name >]));
+                record_host_call(env.data(), stringify!($api), stringify!([< $api _ $sub_module _ $function_name >]));
+                let code = match [< $api _ $sub_module _ $function_name _impl>](env, params_buffer, params_buffer_len, ret_buffer, ret_buffer_len) {
                     Ok(res) => res,
                     Err(e) => {
                         error!("{} experienced an issue calling {}: {:?}", name,  stringify!([< $api _ $sub_module _ $function_name >]), e);
                         e as i32
                     }
-                }
+                };
+                telemetry::end_host_call(span, code);
+                code
             }
         }
     }
@@ -294,7 +332,7 @@ macro_rules! impl_new_sub_module_function_with_error_buffer {
 ///   - Accessing and validating memory from the guest
 ///   - Checking that the API is configured.
 ///   - Running the function + returning the result (as an i32)
-/// - A public wrapper function that calls the implementation function and returns the result as an integer.
+/// - A public wrapper function that calls the implementation function, traces and counts the call, and returns the result as an integer.
 ///
 /// # Parameters
 /// - `$api`: The name of the API (e.g., `aws`).
@@ -334,17 +372,28 @@ macro_rules! impl_new_sub_module_function {
 
                 let params = safely_get_string(&memory_view, params_buffer, params_buffer_len)?;
 
-                // Check that API is configured
-                let api = env_data.api.$api.as_ref().ok_or(FunctionErrors::ApiNotConfigured)?;
-                let sub_module = api.$sub_module.as_ref().ok_or(FunctionErrors::ApiNotConfigured)?;
-
-                // Clone the APIs Arc to use in Tokio closure
-                let env_api = env_data.api.clone();
-                let module = env_data.module.clone();
-                // Run the function on the Tokio runtime and wait for the result
-                let result = env_api.runtime.block_on(async move {
-                    sub_module.$function_name(&params, module).await
-                });
+                // Under the test harness, the call is recorded and answered by a mock
+                let result = if let Some(mocks) = &env_data.api.mocks {
+                    match mocks
+                        .respond(stringify!([< $api _ $sub_module _ $function_name >]), &params)
+                        .ok_or(FunctionErrors::ApiNotConfigured)?
+                    {
+                        Ok(response) => response.parse().map_err(|_| ApiError::BadRequest),
+                        Err(e) => Err(e),
+                    }
+                } else {
+                    // Check that API is configured
+                    let api = env_data.api.$api.as_ref().ok_or(FunctionErrors::ApiNotConfigured)?;
+                    let sub_module = api.$sub_module.as_ref().ok_or(FunctionErrors::ApiNotConfigured)?;
+
+                    // Clone the APIs Arc to use in Tokio closure
+                    let env_api = env_data.api.clone();
+                    let module = env_data.module.clone();
+                    // Run the function on the Tokio runtime and wait for the result
+                    env_api.runtime.block_on(async move {
+                        sub_module.$function_name(&params, module).await
+                    })
+                };
 
                 let return_data = match result {
                     Ok(return_data) => return_data,
@@ -363,13 +412,17 @@ macro_rules! impl_new_sub_module_function {
 
             fn [< $api _ $sub_module _ $function_name >] (env: FunctionEnvMut<Env>, params_buffer: WasmPtr<u8>, params_buffer_len: u32) -> i32 {
                 let name = env.data().module.name.clone();
-                match [< $api _ $sub_module _ $function_name _impl>](env, params_buffer, params_buffer_len) {
+                let span = telemetry::start_host_call(&env.data().trace_context, stringify!($api), stringify!([< $api _ $sub_module _ $function_name >]));
+                record_host_call(env.data(), stringify!($api), stringify!([< $api _ $sub_module _ $function_name >]));
+                let code = match [< $api _ $sub_module _ $function_name _impl>](env, params_buffer, params_buffer_len) {
                     Ok(res) => res,
                     Err(e) => {
                         error!("{} experienced an issue calling {}: {:?}", name,  stringify!([< $api _ $sub_module _ $function_name >]), e);
                         e as i32
                     }
-                }
+                };
+                telemetry::end_host_call(span, code);
+                code
             }
         }
     }
@@ -443,6 +496,9 @@ impl_new_function_with_error_buffer!(github, get_pull_requests, ALLOW_IN_TEST_MO
 impl_new_function_with_error_buffer!(github, create_pull_request, DISALLOW_IN_TEST_MODE);
 impl_new_function_with_error_buffer!(github, create_file, DISALLOW_IN_TEST_MODE);
 impl_new_function!(github, add_labels, DISALLOW_IN_TEST_MODE);
+impl_new_function_with_error_buffer!(github, create_check_run, DISALLOW_IN_TEST_MODE);
+impl_new_function!(github, update_check_run, DISALLOW_IN_TEST_MODE);
+impl_new_function!(github, create_commit_status, DISALLOW_IN_TEST_MODE);
 impl_new_function_with_error_buffer!(github, get_user_id_from_username, ALLOW_IN_TEST_MODE);
 impl_new_function_with_error_buffer!(github, get_username_from_user_id, ALLOW_IN_TEST_MODE);
 impl_new_function_with_error_buffer!(github, get_repo_id_from_repo_name, ALLOW_IN_TEST_MODE);
@@ -478,6 +534,10 @@ impl_new_function!(jira, update_issue, DISALLOW_IN_TEST_MODE);
 impl_new_function_with_error_buffer!(jira, get_user, ALLOW_IN_TEST_MODE);
 impl_new_function!(jira, post_comment, DISALLOW_IN_TEST_MODE);
 impl_new_function_with_error_buffer!(jira, search_issues, ALLOW_IN_TEST_MODE);
+impl_new_function_with_error_buffer!(jira, get_transitions, ALLOW_IN_TEST_MODE);
+impl_new_function!(jira, transition_issue, DISALLOW_IN_TEST_MODE);
+impl_new_function_with_error_buffer!(jira, add_attachment, DISALLOW_IN_TEST_MODE);
+impl_new_function!(jira, create_issue_link, DISALLOW_IN_TEST_MODE);
 
 // AWS functions
 
@@ -574,10 +634,19 @@ impl_new_function_with_error_buffer!(npm, get_token_details, ALLOW_IN_TEST_MODE)
 
 // Okta Functions
 impl_new_function!(okta, remove_user_from_group, DISALLOW_IN_TEST_MODE);
+impl_new_function!(okta, add_user_to_group, DISALLOW_IN_TEST_MODE);
+impl_new_function!(okta, suspend_user, DISALLOW_IN_TEST_MODE);
+impl_new_function!(okta, unsuspend_user, DISALLOW_IN_TEST_MODE);
+impl_new_function!(okta, deactivate_user, DISALLOW_IN_TEST_MODE);
+impl_new_function!(okta, clear_user_sessions, DISALLOW_IN_TEST_MODE);
+impl_new_function!(okta, reset_user_factors, DISALLOW_IN_TEST_MODE);
 impl_new_function_with_error_buffer!(okta, get_user_data, ALLOW_IN_TEST_MODE);
+impl_new_function_with_error_buffer!(okta, list_group_members, ALLOW_IN_TEST_MODE);
 
 // PagerDuty Functions
 impl_new_function!(pagerduty, trigger_incident, DISALLOW_IN_TEST_MODE);
+impl_new_function!(pagerduty, acknowledge_incident, DISALLOW_IN_TEST_MODE);
+impl_new_function!(pagerduty, resolve_incident, DISALLOW_IN_TEST_MODE);
 impl_new_function_with_error_buffer!(pagerduty, get_incident_alerts, ALLOW_IN_TEST_MODE);
 
 // Rustica Functions
@@ -596,6 +665,10 @@ impl_new_function_with_error_buffer!(slack, user_info, ALLOW_IN_TEST_MODE);
 impl_new_function_with_error_buffer!(slack, create_channel, DISALLOW_IN_TEST_MODE);
 impl_new_function!(slack, invite_to_channel, DISALLOW_IN_TEST_MODE);
 impl_new_function!(slack, remove_from_channel, DISALLOW_IN_TEST_MODE);
+impl_new_function_with_error_buffer!(slack, upload_file, ALLOW_IN_TEST_MODE);
+impl_new_function!(slack, add_reaction, ALLOW_IN_TEST_MODE);
+impl_new_function_with_error_buffer!(slack, get_thread_replies, ALLOW_IN_TEST_MODE);
+impl_new_function_with_error_buffer!(slack, get_channel_history, ALLOW_IN_TEST_MODE);
 
 // Splunk Functions
 impl_new_function!(splunk, post_hec, ALLOW_IN_TEST_MODE);
@@ -819,14 +892,22 @@ define_api_functions! {
         "set_response"             => super::response::set_response,
         "set_error_context"        => super::internal::set_error_context,
         "print_debug_string"       => super::internal::print_debug_string,
+        "log_module_message"       => super::internal::log_module_message,
         "storage_insert"           => super::storage::insert,
         "storage_insert_shared"    => super::storage::insert_shared,
+        "storage_insert_with_ttl"  => super::storage::insert_with_ttl,
         "storage_get"              => super::storage::get,
         "storage_get_shared"       => super::storage::get_shared,
         "storage_delete"           => super::storage::delete,
         "storage_delete_shared"    => super::storage::delete_shared,
         "storage_list_keys"        => super::storage::list_keys,
         "storage_list_keys_shared" => super::storage::list_keys_shared,
+        "storage_compare_and_swap"        => super::storage::compare_and_swap,
+        "storage_compare_and_swap_shared" => super::storage::compare_and_swap_shared,
+        "storage_insert_if_absent"        => super::storage::insert_if_absent,
+        "storage_insert_if_absent_shared" => super::storage::insert_if_absent_shared,
+        "storage_increment"               => super::storage::increment,
+        "storage_increment_shared"        => super::storage::increment_shared,
         "cache_insert"             => super::cache::insert,
         "cache_get"                => super::cache::get,
         "log_back"                 => super::internal::log_back,
@@ -850,7 +931,14 @@ define_api_functions! {
 
         // Okta
         "okta_remove_user_from_group" => okta_remove_user_from_group,
+        "okta_add_user_to_group"      => okta_add_user_to_group,
+        "okta_suspend_user"           => okta_suspend_user,
+        "okta_unsuspend_user"         => okta_unsuspend_user,
+        "okta_deactivate_user"        => okta_deactivate_user,
+        "okta_clear_user_sessions"    => okta_clear_user_sessions,
+        "okta_reset_user_factors"     => okta_reset_user_factors,
         "okta_get_user_data"          => okta_get_user_data,
+        "okta_list_group_members"     => okta_list_group_members,
 
         // AES calls
         "cryptography_aes_128_cbc_encrypt" => cryptography_aes_128_cbc_encrypt,
@@ -899,6 +987,9 @@ define_api_functions! {
         "github_create_file"                               => github_create_file,
         "github_get_repo_sbom"                             => github_get_repo_sbom,
         "github_add_labels"                                => github_add_labels,
+        "github_create_check_run"                          => github_create_check_run,
+        "github_update_check_run"                          => github_update_check_run,
+        "github_create_commit_status"                      => github_create_commit_status,
         "github_get_user_id_from_username"                 => github_get_user_id_from_username,
         "github_get_username_from_user_id"                 => github_get_username_from_user_id,
         "github_get_repo_id_from_repo_name"                => github_get_repo_id_from_repo_name,
@@ -925,6 +1016,10 @@ define_api_functions! {
         "slack_invite_to_channel"         => slack_invite_to_channel,
         "slack_update_message"            => slack_update_message,
         "slack_remove_from_channel"       => slack_remove_from_channel,
+        "slack_upload_file"               => slack_upload_file,
+        "slack_add_reaction"              => slack_add_reaction,
+        "slack_get_thread_replies"        => slack_get_thread_replies,
+        "slack_get_channel_history"       => slack_get_channel_history,
 
         // General Calls
         "general_simple_json_post_request"          => general_simple_json_post_request,
@@ -932,12 +1027,16 @@ define_api_functions! {
         "general_retrieve_tls_certificate_with_sni" => general_retrieve_tls_certificate_with_sni,
 
         // Jira Calls
-        "jira_create_issue"  => jira_create_issue,
-        "jira_get_issue"     => jira_get_issue,
-        "jira_update_issue"  => jira_update_issue,
-        "jira_get_user"      => jira_get_user,
-        "jira_post_comment"  => jira_post_comment,
-        "jira_search_issues" => jira_search_issues,
+        "jira_create_issue"      => jira_create_issue,
+        "jira_get_issue"         => jira_get_issue,
+        "jira_update_issue"      => jira_update_issue,
+        "jira_get_user"          => jira_get_user,
+        "jira_post_comment"      => jira_post_comment,
+        "jira_search_issues"     => jira_search_issues,
+        "jira_get_transitions"   => jira_get_transitions,
+        "jira_transition_issue"  => jira_transition_issue,
+        "jira_add_attachment"    => jira_add_attachment,
+        "jira_create_issue_link" => jira_create_issue_link,
 
         // KMS calls
         #[cfg(feature = "aws")] "aws_kms_generate_mac"           => aws_kms_generate_mac,
@@ -969,6 +1068,8 @@ define_api_functions! {
 
         // PagerDuty Calls
         "pagerduty_trigger_incident" => pagerduty_trigger_incident,
+        "pagerduty_acknowledge_incident" => pagerduty_acknowledge_incident,
+        "pagerduty_resolve_incident" => pagerduty_resolve_incident,
         "pagerduty_get_incident_alerts" => pagerduty_get_incident_alerts,
 
         // Rustica Calls
@@ -5,7 +5,7 @@ use wasmer::{AsStoreRef, FunctionEnvMut, WasmPtr};
 
 use crate::{executor::Env, functions::FunctionErrors};
 
-use super::{get_memory, safely_get_string, safely_write_data_back};
+use super::{get_memory, record_host_call, safely_get_string, safely_write_data_back};
 
 /// Store data in the cache system if one is configured
 pub fn insert(
@@ -17,6 +17,7 @@ pub fn insert(
     data_buffer: WasmPtr<u8>,
     data_buffer_len: u32,
 ) -> i32 {
+    record_host_call(env.data(), "cache", "cache_insert");
     let store = env.as_store_ref();
     let env_data = env.data();
 
@@ -113,6 +114,7 @@ pub fn get(
     data_buffer: WasmPtr<u8>,
     data_buffer_len: u32,
 ) -> i32 {
+    record_host_call(env.data(), "cache", "cache_get");
     let store = env.as_store_ref();
     let env_data = env.data();
 
@@ -1,18 +1,24 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use plaid_stl::messages::{LogSource, LogbacksAllowed};
+use plaid_stl::plaid::log::ModuleLog;
 use wasmer::{AsStoreRef, FunctionEnvMut, WasmPtr};
 
 use crate::{
     data::DelayedMessage,
     executor::{Env, Message},
     functions::{get_memory, safely_get_string},
+    telemetry,
 };
 
-use super::{calculate_max_buffer_size, safely_get_memory, safely_write_data_back, FunctionErrors};
+use super::{
+    calculate_max_buffer_size, record_host_call, safely_get_memory, safely_write_data_back,
+    FunctionErrors,
+};
 
 /// Implement a way for a module to print to env_logger
 pub fn print_debug_string(env: FunctionEnvMut<Env>, log_buffer: WasmPtr<u8>, log_buffer_size: u32) {
+    record_host_call(env.data(), "internal", "print_debug_string");
     let store = env.as_store_ref();
     let memory_view = match get_memory(&env, &store) {
         Ok(memory_view) => memory_view,
@@ -41,6 +47,80 @@ pub fn print_debug_string(env: FunctionEnvMut<Env>, log_buffer: WasmPtr<u8>, log
     debug!("Message from [{}]: {message}", env.data().module.name);
 }
 
+/// Implement a way for a module to send leveled, structured logs to the logging system.
+/// Logs are dropped once the module has used up its log volume budget.
+pub fn log_module_message(
+    env: FunctionEnvMut<Env>,
+    log_buffer: WasmPtr<u8>,
+    log_buffer_size: u32,
+) -> i32 {
+    record_host_call(env.data(), "internal", "log_module_message");
+    let store = env.as_store_ref();
+    let env_data = env.data();
+    let memory_view = match get_memory(&env, &store) {
+        Ok(memory_view) => memory_view,
+        Err(e) => {
+            error!(
+                "{}: Memory error in log_module_message: {:?}",
+                env_data.module.name, e
+            );
+            return FunctionErrors::InternalApiError as i32;
+        }
+    };
+
+    let log = match safely_get_string(&memory_view, log_buffer, log_buffer_size) {
+        Ok(s) => s,
+        Err(e) => {
+            error!(
+                "{}: Error in log_module_message: {:?}",
+                env_data.module.name, e
+            );
+            return e as i32;
+        }
+    };
+
+    let log: ModuleLog = match serde_json::from_str(&log) {
+        Ok(log) => log,
+        Err(e) => {
+            error!(
+                "{}: Could not parse log in log_module_message: {e}",
+                env_data.module.name
+            );
+            return FunctionErrors::InternalApiError as i32;
+        }
+    };
+
+    if let Some(budget) = &env_data.module.log_budget {
+        let size = log.message.len()
+            + log
+                .fields
+                .iter()
+                .map(|(key, value)| key.len() + value.len())
+                .sum::<usize>();
+        if let Err(exhausted) = budget.try_spend(size as u64) {
+            if exhausted.first_dropped {
+                warn!(
+                    "{} has used up its log volume budget. Its logs are dropped until the budget is refilled",
+                    env_data.module.name
+                );
+            }
+            return FunctionErrors::LogBudgetExhausted as i32;
+        }
+    }
+
+    if let Err(e) = env_data.external_logging_system.log_module_message(
+        env_data.module.name.clone(),
+        env_data.message.id.clone(),
+        env_data.message.type_.clone(),
+        log,
+    ) {
+        error!("Logging system is not working!!: {:?}", e);
+        return FunctionErrors::InternalApiError as i32;
+    }
+
+    0
+}
+
 /// Implement a way for a module to set a descriptive context for
 /// an error encountered during execution.
 pub fn set_error_context(
@@ -48,6 +128,7 @@ pub fn set_error_context(
     context_buffer: WasmPtr<u8>,
     context_buffer_size: u32,
 ) {
+    record_host_call(env.data(), "internal", "set_error_context");
     let store = env.as_store_ref();
     let memory_view = match get_memory(&env, &store) {
         Ok(memory_view) => memory_view,
@@ -99,6 +180,7 @@ pub fn log_back(
     // How many logbacks the rule would like this new invocation to be able to trigger
     logbacks_requested: u32,
 ) -> i32 {
+    record_host_call(env.data(), "internal", "log_back");
     log_back_detailed(
         env,
         type_buf,
@@ -119,6 +201,7 @@ pub fn log_back_unlimited(
     log_buf_len: u32,
     delay: u32,
 ) -> i32 {
+    record_host_call(env.data(), "internal", "log_back_unlimited");
     log_back_detailed(
         env,
         type_buf,
@@ -213,7 +296,9 @@ pub fn log_back_detailed(
         }
     };
 
-    let msg = Message::new(type_, log, LogSource::Logback(name), assigned_budget);
+    let mut msg = Message::new(type_, log, LogSource::Logback(name), assigned_budget);
+    // The runs triggered by the logback are traced as children of this one
+    msg.trace_parent = telemetry::traceparent(&env_data.trace_context);
     match dispatch_logback(env.data(), delay, msg) {
         Ok(()) => 0,
         Err(e) => e as i32,
@@ -260,6 +345,7 @@ pub fn fetch_random_bytes(
     data_buffer: WasmPtr<u8>,
     data_buffer_len: u16,
 ) -> i32 {
+    record_host_call(env.data(), "internal", "fetch_random_bytes");
     let store = env.as_store_ref();
     let env_data = env.data();
 
@@ -1,4 +1,4 @@
-use super::{get_memory, safely_get_string, safely_write_data_back};
+use super::{get_memory, record_host_call, safely_get_string, safely_write_data_back};
 use crate::executor::Env;
 
 use wasmer::{AsStoreRef, FunctionEnvMut, WasmPtr};
@@ -9,6 +9,7 @@ pub fn fetch_data_and_source(
     data_buffer: WasmPtr<u8>,
     buffer_size: u32,
 ) -> i32 {
+    record_host_call(env.data(), "internal", "fetch_data_and_source");
     let store = env.as_store_ref();
     let memory_view = match get_memory(&env, &store) {
         Ok(memory_view) => memory_view,
@@ -67,6 +68,7 @@ pub fn fetch_data_and_source(
 
 /// Wrap the fetch_data call in a native WASM function.
 pub fn fetch_data(env: FunctionEnvMut<Env>, data_buffer: WasmPtr<u8>, buffer_size: u32) -> i32 {
+    record_host_call(env.data(), "internal", "fetch_data");
     let store = env.as_store_ref();
     let memory_view = match get_memory(&env, &store) {
         Ok(memory_view) => memory_view,
@@ -93,6 +95,7 @@ pub fn fetch_data(env: FunctionEnvMut<Env>, data_buffer: WasmPtr<u8>, buffer_siz
 
 /// Wrap the fetch_from_module call in a native WASM function.
 pub fn fetch_source(env: FunctionEnvMut<Env>, data_buffer: WasmPtr<u8>, buffer_size: u32) -> i32 {
+    record_host_call(env.data(), "internal", "fetch_source");
     let store = env.as_store_ref();
     let memory_view = match get_memory(&env, &store) {
         Ok(memory_view) => memory_view,
@@ -145,6 +148,7 @@ macro_rules! generate_string_getter {
                 name_len: u32,
                 data_buffer: WasmPtr<u8>,
                 buffer_size: u32) -> i32 {
+                record_host_call(env.data(), "internal", stringify!([<get_ $what>]));
                 let store = env.as_store_ref();
                 let memory_view = match get_memory(&env, &store) {
                     Ok(memory_view) => memory_view,
@@ -34,6 +34,8 @@ pub enum FunctionErrors {
     TimeoutElapsed = -14,
     FailedToLogBack = -15,
     LogbackBudgetExhausted = -16,
+    InvalidCounter = -17,
+    LogBudgetExhausted = -18,
 }
 
 #[derive(Debug)]
@@ -49,6 +51,14 @@ impl std::fmt::Display for LinkError {
     }
 }
 
+/// Count a call the module running in `env` made to a host function. Every host function
+/// that has an environment calls this first, so that all of them are counted.
+fn record_host_call(env: &Env, api: &str, function: &str) {
+    if let Some(metrics) = &env.module_execution_metrics {
+        metrics.record_host_function_call(&env.module.name, api, function);
+    }
+}
+
 pub fn fake_wbindgen_describe(placeholder: i32) {
     warn!("Fake __wbindgen_describe called with placeholder: {placeholder}");
 }
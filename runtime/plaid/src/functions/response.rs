@@ -2,7 +2,7 @@ use wasmer::{AsStoreRef, FunctionEnvMut, WasmPtr};
 
 use crate::{executor::Env, functions::FunctionErrors};
 
-use super::{get_memory, safely_get_string, safely_write_data_back};
+use super::{get_memory, record_host_call, safely_get_string, safely_write_data_back};
 
 /// Implement a way for a module to get the existing response. This would have been
 /// set by previous invocations of the module and allows an additional basic form of state.
@@ -11,6 +11,7 @@ pub fn get_response(
     response_buffer: WasmPtr<u8>,
     response_buffer_size: u32,
 ) -> i32 {
+    record_host_call(env.data(), "internal", "get_response");
     let store = env.as_store_ref();
     let memory_view = match get_memory(&env, &store) {
         Ok(memory_view) => memory_view,
@@ -57,6 +58,7 @@ pub fn set_response(
     response_buffer: WasmPtr<u8>,
     response_buffer_size: u32,
 ) {
+    record_host_call(env.data(), "internal", "set_response");
     let store = env.as_store_ref();
     let memory_view = match get_memory(&env, &store) {
         Ok(memory_view) => memory_view,
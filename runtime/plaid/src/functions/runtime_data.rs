@@ -1,4 +1,4 @@
-use super::{get_memory, safely_get_string, safely_write_data_back};
+use super::{get_memory, record_host_call, safely_get_string, safely_write_data_back};
 use crate::executor::Env;
 
 use wasmer::{AsStoreRef, FunctionEnvMut, WasmPtr};
@@ -12,6 +12,7 @@ macro_rules! generate_runtime_string_getter {
                 name_len: u32,
                 data_buffer: WasmPtr<u8>,
                 buffer_size: u32) -> i32 {
+                    record_host_call(env.data(), "internal", stringify!([<get_ $what>]));
                     let Some($what) = &env.data().module.$what else {
                         // This module does not have that field: we just return 0
                         return 0;
@@ -2,10 +2,15 @@ use std::sync::{Arc, RwLock};
 
 use wasmer::{AsStoreRef, FunctionEnvMut, MemoryView, WasmPtr};
 
-use crate::{executor::Env, functions::FunctionErrors, loader::LimitValue, storage::Storage};
+use crate::{
+    executor::Env,
+    functions::FunctionErrors,
+    loader::LimitValue,
+    storage::{parse_counter, Storage, StorageError},
+};
 
 use super::{
-    calculate_max_buffer_size, get_memory, safely_get_memory, safely_get_string,
+    calculate_max_buffer_size, get_memory, record_host_call, safely_get_memory, safely_get_string,
     safely_write_data_back,
 };
 
@@ -40,7 +45,7 @@ macro_rules! safely_get_guest_memory {
     };
 }
 
-/// Code which is common to `insert` and `insert_shared`
+/// Code which is common to `insert`, `insert_with_ttl` and `insert_shared`
 fn insert_common(
     env_data: &Env,
     storage: &Arc<Storage>,
@@ -52,6 +57,7 @@ fn insert_common(
     data_buffer_len: u32,
     storage_limit: LimitValue,
     storage_counter: &Arc<RwLock<u64>>,
+    ttl: Option<u32>,
 ) -> i32 {
     // ugly, but we are dealing with a couple of "async move"s
     let storage_key = key.clone();
@@ -61,11 +67,16 @@ fn insert_common(
     let insertion_result = match storage_limit {
         LimitValue::Unlimited => {
             // The storage is unlimited, so we don't check / update any counters and just proceed with the operation
-            env_data
-                .api
-                .clone()
-                .runtime
-                .block_on(async move { storage.insert(namespace, storage_key, value).await })
+            env_data.api.clone().runtime.block_on(async move {
+                match ttl {
+                    None => storage.insert(namespace, storage_key, value).await,
+                    Some(ttl) => {
+                        storage
+                            .insert_with_ttl(namespace, storage_key, value, ttl.into())
+                            .await
+                    }
+                }
+            })
         }
         LimitValue::Limited(storage_limit) => {
             // The storage is limited, so we need to check / update counters (with locks) because the operation might have to be rejected.
@@ -81,6 +92,13 @@ fn insert_common(
                 }
             };
 
+            // An expired key is absent, so release its bytes before it is overwritten
+            if let Err(e) =
+                release_if_expired(env_data, storage, &namespace, &key, &mut storage_current)
+            {
+                return e;
+            }
+
             // We check if this insert would overwrite some existing data. If so, we need to take that into account when
             // computing the storage that would be occupied at the end of the insert operation.
             // Note: if we have existing data, then we need to count the key's length as well. This is because at the end
@@ -119,10 +137,16 @@ fn insert_common(
                 return FunctionErrors::StorageLimitReached as i32;
             }
 
-            let result =
-                env_data.api.clone().runtime.block_on(async move {
-                    storage.insert(namespace_clone, storage_key, value).await
-                });
+            let result = env_data.api.clone().runtime.block_on(async move {
+                match ttl {
+                    None => storage.insert(namespace_clone, storage_key, value).await,
+                    Some(ttl) => {
+                        storage
+                            .insert_with_ttl(namespace_clone, storage_key, value, ttl.into())
+                            .await
+                    }
+                }
+            });
             // If the insertion went well, update counter for used storage.
             // If the insertion failed for some reason, we don't update the counter and release the lock: no harm done.
             if result.is_ok() {
@@ -179,6 +203,7 @@ pub fn insert(
     data_buffer: WasmPtr<u8>,
     data_buffer_len: u32,
 ) -> i32 {
+    record_host_call(env.data(), "storage", "storage_insert");
     let store = env.as_store_ref();
     let env_data = env.data();
 
@@ -213,6 +238,58 @@ pub fn insert(
         data_buffer_len,
         env_data.module.storage_limit.clone(),
         &env_data.module.storage_current,
+        None,
+    )
+}
+
+/// Store data in the storage system if one is configured. The data is absent once `ttl`
+/// seconds have passed, and deleted when expired keys are next swept.
+pub fn insert_with_ttl(
+    env: FunctionEnvMut<Env>,
+    key_buf: WasmPtr<u8>,
+    key_buf_len: u32,
+    value_buf: WasmPtr<u8>,
+    value_buf_len: u32,
+    ttl: u32,
+    data_buffer: WasmPtr<u8>,
+    data_buffer_len: u32,
+) -> i32 {
+    record_host_call(env.data(), "storage", "storage_insert_with_ttl");
+    let store = env.as_store_ref();
+    let env_data = env.data();
+
+    let storage = if let Some(storage) = &env_data.storage {
+        storage
+    } else {
+        return FunctionErrors::ApiNotConfigured as i32;
+    };
+
+    let memory_view = match get_memory(&env, &store) {
+        Ok(memory_view) => memory_view,
+        Err(e) => {
+            error!(
+                "{}: Memory error in storage_insert_with_ttl: {:?}",
+                env_data.module.name, e
+            );
+            return FunctionErrors::CouldNotGetAdequateMemory as i32;
+        }
+    };
+
+    safely_get_guest_string!(key, memory_view, key_buf, key_buf_len, env_data);
+    safely_get_guest_memory!(value, memory_view, value_buf, value_buf_len, env_data);
+
+    insert_common(
+        env_data,
+        storage,
+        env_data.module.name.clone(),
+        key,
+        value,
+        memory_view,
+        data_buffer,
+        data_buffer_len,
+        env_data.module.storage_limit.clone(),
+        &env_data.module.storage_current,
+        Some(ttl),
     )
 }
 
@@ -228,6 +305,7 @@ pub fn insert_shared(
     data_buffer: WasmPtr<u8>,
     data_buffer_len: u32,
 ) -> i32 {
+    record_host_call(env.data(), "storage", "storage_insert_shared");
     let store = env.as_store_ref();
     let env_data = env.data();
 
@@ -300,6 +378,7 @@ pub fn insert_shared(
         data_buffer_len,
         storage_limit,
         &storage_current,
+        None,
     )
 }
 
@@ -345,6 +424,7 @@ pub fn get(
     data_buffer: WasmPtr<u8>,
     data_buffer_len: u32,
 ) -> i32 {
+    record_host_call(env.data(), "storage", "storage_get");
     let store = env.as_store_ref();
     let env_data = env.data();
 
@@ -388,6 +468,7 @@ pub fn get_shared(
     data_buffer: WasmPtr<u8>,
     data_buffer_len: u32,
 ) -> i32 {
+    record_host_call(env.data(), "storage", "storage_get_shared");
     let store = env.as_store_ref();
     let env_data = env.data();
 
@@ -458,6 +539,7 @@ pub fn list_keys(
     data_buffer: WasmPtr<u8>,
     data_buffer_len: u32,
 ) -> i32 {
+    record_host_call(env.data(), "storage", "storage_list_keys");
     let store = env.as_store_ref();
     let env_data = env.data();
 
@@ -502,6 +584,7 @@ pub fn list_keys_shared(
     data_buffer: WasmPtr<u8>,
     data_buffer_len: u32,
 ) -> i32 {
+    record_host_call(env.data(), "storage", "storage_list_keys_shared");
     let store = env.as_store_ref();
     let env_data = env.data();
 
@@ -709,6 +792,7 @@ pub fn delete(
     data_buffer: WasmPtr<u8>,
     data_buffer_len: u32,
 ) -> i32 {
+    record_host_call(env.data(), "storage", "storage_delete");
     let store = env.as_store_ref();
     let env_data = env.data();
 
@@ -754,6 +838,7 @@ pub fn delete_shared(
     data_buffer: WasmPtr<u8>,
     data_buffer_len: u32,
 ) -> i32 {
+    record_host_call(env.data(), "storage", "storage_delete_shared");
     let store = env.as_store_ref();
     let env_data = env.data();
 
@@ -825,3 +910,758 @@ pub fn delete_shared(
         &storage_current,
     )
 }
+
+/// Get the storage limit and usage counter of a shared DB, if the module can write to it.
+/// Otherwise, get the error to return to the module.
+fn writable_shared_db(
+    env_data: &Env,
+    storage: &Storage,
+    namespace: &str,
+) -> Result<(LimitValue, Arc<RwLock<u64>>), i32> {
+    match storage
+        .shared_dbs
+        .as_ref()
+        .and_then(|shared_dbs| shared_dbs.get(namespace))
+    {
+        Some(db) if db.config.rw.contains(&env_data.module.name) => {
+            Ok((db.config.size_limit.clone(), db.used_storage.clone()))
+        }
+        _ => Err(FunctionErrors::OperationNotAllowed as i32),
+    }
+}
+
+/// Delete `key` if it has expired and release the bytes it used from `storage_current`, which
+/// must be the locked usage counter of `namespace`. Otherwise, get the error to return to the module.
+fn release_if_expired(
+    env_data: &Env,
+    storage: &Storage,
+    namespace: &str,
+    key: &str,
+    storage_current: &mut u64,
+) -> Result<(), i32> {
+    match env_data
+        .api
+        .runtime
+        .block_on(storage.delete_expired(namespace, key))
+    {
+        Ok(Some(data)) => {
+            *storage_current = storage_current.saturating_sub(key.len() as u64 + data.len() as u64);
+            Ok(())
+        }
+        Ok(None) => Ok(()),
+        Err(e) => {
+            error!(
+                "There was a storage system error when key [{key}] was accessed by [{}]: {e}",
+                env_data.module.name
+            );
+            Err(FunctionErrors::InternalApiError as i32)
+        }
+    }
+}
+
+/// The key a conditional write or increment applies to, with the storage limit and usage
+/// counter of its namespace
+struct WriteTarget<'a> {
+    namespace: String,
+    key: String,
+    storage_limit: LimitValue,
+    storage_counter: &'a Arc<RwLock<u64>>,
+}
+
+/// Get the value stored at `key` under the lock on the namespace's usage counter, and the
+/// number of bytes it uses. Otherwise, get the error to return to the module.
+fn stored_value(
+    env_data: &Env,
+    storage: &Storage,
+    namespace: &str,
+    key: &str,
+) -> Result<(Option<Vec<u8>>, u64), i32> {
+    match env_data.api.runtime.block_on(storage.get(namespace, key)) {
+        Ok(stored) => {
+            let size = stored
+                .as_ref()
+                .map_or(0, |v| key.len() as u64 + v.len() as u64);
+            Ok((stored, size))
+        }
+        Err(e) => {
+            error!(
+                "There was a storage system error when key [{key}] was accessed by [{}]: {e}",
+                env_data.module.name
+            );
+            Err(FunctionErrors::InternalApiError as i32)
+        }
+    }
+}
+
+/// Work out the storage used once `stored_size` bytes are replaced by `new_size` bytes.
+/// Returns `None` if that overflows or if the counter is below what is stored, which means
+/// it is out of sync with the storage.
+fn replace_usage(storage_current: u64, stored_size: u64, new_size: u64) -> Option<u64> {
+    storage_current
+        .checked_sub(stored_size)?
+        .checked_add(new_size)
+}
+
+/// Code which is common to `compare_and_swap`, `insert_if_absent` and their shared versions.
+/// Returns 1 if the value was set and 0 if it was not.
+fn compare_and_swap_common(
+    env_data: &Env,
+    storage: &Arc<Storage>,
+    target: WriteTarget,
+    expected: Option<Vec<u8>>,
+    value: Vec<u8>,
+) -> i32 {
+    let WriteTarget {
+        namespace,
+        key,
+        storage_limit,
+        storage_counter,
+    } = target;
+
+    let result = match storage_limit {
+        LimitValue::Unlimited => {
+            // The storage is unlimited, so we don't check / update any counters and just proceed with the operation
+            env_data
+                .api
+                .runtime
+                .block_on(storage.compare_and_swap(&namespace, &key, expected, value))
+        }
+        LimitValue::Limited(storage_limit) => {
+            // Get a lock on the storage counter, as for inserts
+            let mut storage_current = match storage_counter.write() {
+                Ok(g) => g,
+                Err(e) => {
+                    error!("Critical error getting a lock on used storage: {:?}", e);
+                    return FunctionErrors::InternalApiError as i32;
+                }
+            };
+
+            // An expired key counts as absent, so release its bytes before it is overwritten
+            if let Err(e) =
+                release_if_expired(env_data, storage, &namespace, &key, &mut storage_current)
+            {
+                return e;
+            }
+
+            // If the swap happens, the stored value is what gets overwritten. It is read while
+            // holding the lock, so it can only differ from what is swapped if another Plaid
+            // instance changes it, in which case the swap fails.
+            let (stored, stored_size) = match stored_value(env_data, storage, &namespace, &key) {
+                Ok(stored) => stored,
+                Err(e) => return e,
+            };
+            if stored != expected {
+                return 0;
+            }
+
+            let new_size = key.len() as u64 + value.len() as u64;
+            let Some(would_be_used_storage) =
+                replace_usage(*storage_current, stored_size, new_size)
+            else {
+                error!(
+                    "{}: Could not work out the storage used after swapping value with key [{key}]",
+                    env_data.module.name
+                );
+                return FunctionErrors::InternalApiError as i32;
+            };
+
+            if would_be_used_storage > storage_limit {
+                error!("{}: Could not swap value with key [{key}] as that would bring us above the configured storage limit.", env_data.module.name);
+                let _ = env_data.external_logging_system.log_module_error(
+                    env_data.module.name.clone(),
+                    "Could not swap value as that would bring us above the configured storage limit.".to_string(),
+                    vec![]
+                );
+                return FunctionErrors::StorageLimitReached as i32;
+            }
+
+            let result = env_data
+                .api
+                .runtime
+                .block_on(storage.compare_and_swap(&namespace, &key, expected, value));
+            if let Ok(true) = result {
+                *storage_current = would_be_used_storage;
+            }
+            result
+        }
+    };
+
+    match result {
+        Ok(swapped) => swapped as i32,
+        Err(e) => {
+            error!(
+                "There was a storage system error when key [{key}] was accessed by [{}]: {e}",
+                env_data.module.name
+            );
+            FunctionErrors::InternalApiError as i32
+        }
+    }
+}
+
+/// Set a value in the storage system, if one is configured, only if the current value is the expected one
+pub fn compare_and_swap(
+    env: FunctionEnvMut<Env>,
+    key_buf: WasmPtr<u8>,
+    key_buf_len: u32,
+    expected_buf: WasmPtr<u8>,
+    expected_buf_len: u32,
+    value_buf: WasmPtr<u8>,
+    value_buf_len: u32,
+) -> i32 {
+    record_host_call(env.data(), "storage", "storage_compare_and_swap");
+    let store = env.as_store_ref();
+    let env_data = env.data();
+
+    let storage = if let Some(storage) = &env_data.storage {
+        storage
+    } else {
+        return FunctionErrors::ApiNotConfigured as i32;
+    };
+
+    let memory_view = match get_memory(&env, &store) {
+        Ok(memory_view) => memory_view,
+        Err(e) => {
+            error!(
+                "{}: Memory error in storage_compare_and_swap: {:?}",
+                env_data.module.name, e
+            );
+            return FunctionErrors::CouldNotGetAdequateMemory as i32;
+        }
+    };
+
+    safely_get_guest_string!(key, memory_view, key_buf, key_buf_len, env_data);
+    safely_get_guest_memory!(
+        expected,
+        memory_view,
+        expected_buf,
+        expected_buf_len,
+        env_data
+    );
+    safely_get_guest_memory!(value, memory_view, value_buf, value_buf_len, env_data);
+
+    compare_and_swap_common(
+        env_data,
+        storage,
+        WriteTarget {
+            namespace: env_data.module.name.clone(),
+            key,
+            storage_limit: env_data.module.storage_limit.clone(),
+            storage_counter: &env_data.module.storage_current,
+        },
+        Some(expected),
+        value,
+    )
+}
+
+/// Set a value in a shared namespace in the storage system, if one is configured, only if the
+/// current value is the expected one
+// The arguments are the buffers the guest passes, so they cannot be grouped
+#[allow(clippy::too_many_arguments)]
+pub fn compare_and_swap_shared(
+    env: FunctionEnvMut<Env>,
+    namespace_buf: WasmPtr<u8>,
+    namespace_buf_len: u32,
+    key_buf: WasmPtr<u8>,
+    key_buf_len: u32,
+    expected_buf: WasmPtr<u8>,
+    expected_buf_len: u32,
+    value_buf: WasmPtr<u8>,
+    value_buf_len: u32,
+) -> i32 {
+    record_host_call(env.data(), "storage", "storage_compare_and_swap_shared");
+    let store = env.as_store_ref();
+    let env_data = env.data();
+
+    let storage = if let Some(storage) = &env_data.storage {
+        storage
+    } else {
+        return FunctionErrors::ApiNotConfigured as i32;
+    };
+
+    let memory_view = match get_memory(&env, &store) {
+        Ok(memory_view) => memory_view,
+        Err(e) => {
+            error!(
+                "{}: Memory error in storage_compare_and_swap_shared: {:?}",
+                env_data.module.name, e
+            );
+            return FunctionErrors::CouldNotGetAdequateMemory as i32;
+        }
+    };
+
+    safely_get_guest_string!(
+        namespace,
+        memory_view,
+        namespace_buf,
+        namespace_buf_len,
+        env_data
+    );
+
+    let (storage_limit, storage_current) = match writable_shared_db(env_data, storage, &namespace) {
+        Ok(db) => db,
+        Err(e) => return e,
+    };
+
+    safely_get_guest_string!(key, memory_view, key_buf, key_buf_len, env_data);
+    safely_get_guest_memory!(
+        expected,
+        memory_view,
+        expected_buf,
+        expected_buf_len,
+        env_data
+    );
+    safely_get_guest_memory!(value, memory_view, value_buf, value_buf_len, env_data);
+
+    compare_and_swap_common(
+        env_data,
+        storage,
+        WriteTarget {
+            namespace,
+            key,
+            storage_limit,
+            storage_counter: &storage_current,
+        },
+        Some(expected),
+        value,
+    )
+}
+
+/// Store data in the storage system, if one is configured, only if the key doesn't exist
+pub fn insert_if_absent(
+    env: FunctionEnvMut<Env>,
+    key_buf: WasmPtr<u8>,
+    key_buf_len: u32,
+    value_buf: WasmPtr<u8>,
+    value_buf_len: u32,
+) -> i32 {
+    record_host_call(env.data(), "storage", "storage_insert_if_absent");
+    let store = env.as_store_ref();
+    let env_data = env.data();
+
+    let storage = if let Some(storage) = &env_data.storage {
+        storage
+    } else {
+        return FunctionErrors::ApiNotConfigured as i32;
+    };
+
+    let memory_view = match get_memory(&env, &store) {
+        Ok(memory_view) => memory_view,
+        Err(e) => {
+            error!(
+                "{}: Memory error in storage_insert_if_absent: {:?}",
+                env_data.module.name, e
+            );
+            return FunctionErrors::CouldNotGetAdequateMemory as i32;
+        }
+    };
+
+    safely_get_guest_string!(key, memory_view, key_buf, key_buf_len, env_data);
+    safely_get_guest_memory!(value, memory_view, value_buf, value_buf_len, env_data);
+
+    compare_and_swap_common(
+        env_data,
+        storage,
+        WriteTarget {
+            namespace: env_data.module.name.clone(),
+            key,
+            storage_limit: env_data.module.storage_limit.clone(),
+            storage_counter: &env_data.module.storage_current,
+        },
+        None,
+        value,
+    )
+}
+
+/// Store data in a shared namespace in the storage system, if one is configured, only if the
+/// key doesn't exist
+pub fn insert_if_absent_shared(
+    env: FunctionEnvMut<Env>,
+    namespace_buf: WasmPtr<u8>,
+    namespace_buf_len: u32,
+    key_buf: WasmPtr<u8>,
+    key_buf_len: u32,
+    value_buf: WasmPtr<u8>,
+    value_buf_len: u32,
+) -> i32 {
+    record_host_call(env.data(), "storage", "storage_insert_if_absent_shared");
+    let store = env.as_store_ref();
+    let env_data = env.data();
+
+    let storage = if let Some(storage) = &env_data.storage {
+        storage
+    } else {
+        return FunctionErrors::ApiNotConfigured as i32;
+    };
+
+    let memory_view = match get_memory(&env, &store) {
+        Ok(memory_view) => memory_view,
+        Err(e) => {
+            error!(
+                "{}: Memory error in storage_insert_if_absent_shared: {:?}",
+                env_data.module.name, e
+            );
+            return FunctionErrors::CouldNotGetAdequateMemory as i32;
+        }
+    };
+
+    safely_get_guest_string!(
+        namespace,
+        memory_view,
+        namespace_buf,
+        namespace_buf_len,
+        env_data
+    );
+
+    let (storage_limit, storage_current) = match writable_shared_db(env_data, storage, &namespace) {
+        Ok(db) => db,
+        Err(e) => return e,
+    };
+
+    safely_get_guest_string!(key, memory_view, key_buf, key_buf_len, env_data);
+    safely_get_guest_memory!(value, memory_view, value_buf, value_buf_len, env_data);
+
+    compare_and_swap_common(
+        env_data,
+        storage,
+        WriteTarget {
+            namespace,
+            key,
+            storage_limit,
+            storage_counter: &storage_current,
+        },
+        None,
+        value,
+    )
+}
+
+/// Code which is common to `increment` and `increment_shared`. The new counter is written back
+/// as a little-endian i64.
+fn increment_common(
+    env_data: &Env,
+    storage: &Arc<Storage>,
+    target: WriteTarget,
+    delta: i64,
+    memory_view: MemoryView,
+    data_buffer: WasmPtr<u8>,
+    data_buffer_len: u32,
+) -> i32 {
+    let WriteTarget {
+        namespace,
+        key,
+        storage_limit,
+        storage_counter,
+    } = target;
+
+    let result = match storage_limit {
+        LimitValue::Unlimited => {
+            // The storage is unlimited, so we don't check / update any counters and just proceed with the operation
+            env_data
+                .api
+                .runtime
+                .block_on(storage.increment(&namespace, &key, delta))
+        }
+        LimitValue::Limited(storage_limit) => {
+            // Get a lock on the storage counter, as for inserts
+            let mut storage_current = match storage_counter.write() {
+                Ok(g) => g,
+                Err(e) => {
+                    error!("Critical error getting a lock on used storage: {:?}", e);
+                    return FunctionErrors::InternalApiError as i32;
+                }
+            };
+
+            // An expired counter starts again from 0, so release its bytes first
+            if let Err(e) =
+                release_if_expired(env_data, storage, &namespace, &key, &mut storage_current)
+            {
+                return e;
+            }
+
+            // A counter's size depends on its value, so work out the new value from the current
+            // one to check the limit. The counter is then updated with what was actually written.
+            let (stored, stored_size) = match stored_value(env_data, storage, &namespace, &key) {
+                Ok(stored) => stored,
+                Err(e) => return e,
+            };
+            let counter = match stored.as_deref().map(parse_counter).transpose() {
+                Ok(counter) => counter.unwrap_or(0).checked_add(delta),
+                Err(_) => return FunctionErrors::InvalidCounter as i32,
+            };
+            let Some(counter) = counter else {
+                error!(
+                    "{}: Could not increment counter with key [{key}]: adding {delta} overflows",
+                    env_data.module.name
+                );
+                return FunctionErrors::InvalidCounter as i32;
+            };
+
+            let key_len = key.len() as u64;
+            let new_size = |counter: i64| key_len + counter.to_string().len() as u64;
+            let Some(would_be_used_storage) =
+                replace_usage(*storage_current, stored_size, new_size(counter))
+            else {
+                error!("{}: Could not work out the storage used after incrementing counter with key [{key}]", env_data.module.name);
+                return FunctionErrors::InternalApiError as i32;
+            };
+
+            if would_be_used_storage > storage_limit {
+                error!("{}: Could not increment counter with key [{key}] as that would bring us above the configured storage limit.", env_data.module.name);
+                let _ = env_data.external_logging_system.log_module_error(
+                    env_data.module.name.clone(),
+                    "Could not increment counter as that would bring us above the configured storage limit.".to_string(),
+                    vec![]
+                );
+                return FunctionErrors::StorageLimitReached as i32;
+            }
+
+            // Another Plaid instance may have changed the counter since it was read, so the
+            // usage is worked out again from what was actually replaced
+            env_data
+                .api
+                .runtime
+                .block_on(storage.increment(&namespace, &key, delta))
+                .and_then(|(previous, counter)| {
+                    let previous_size = previous
+                        .as_ref()
+                        .map_or(0, |v| key_len + v.len() as u64);
+                    *storage_current =
+                        replace_usage(*storage_current, previous_size, new_size(counter))
+                            .ok_or_else(|| {
+                                StorageError::Access(format!(
+                                    "Could not work out the storage used after incrementing counter [{key}]"
+                                ))
+                            })?;
+                    Ok((previous, counter))
+                })
+        }
+    };
+
+    match result {
+        Ok((_, counter)) => {
+            match safely_write_data_back(
+                &memory_view,
+                &counter.to_le_bytes(),
+                data_buffer,
+                data_buffer_len,
+            ) {
+                Ok(x) => x,
+                Err(e) => {
+                    error!(
+                        "{}: Data write error in storage_increment: {:?}",
+                        env_data.module.name, e
+                    );
+                    e as i32
+                }
+            }
+        }
+        Err(StorageError::InvalidCounter(e)) => {
+            error!(
+                "{}: Could not increment counter with key [{key}]: {e}",
+                env_data.module.name
+            );
+            FunctionErrors::InvalidCounter as i32
+        }
+        Err(e) => {
+            error!(
+                "There was a storage system error when key [{key}] was accessed by [{}]: {e}",
+                env_data.module.name
+            );
+            FunctionErrors::InternalApiError as i32
+        }
+    }
+}
+
+/// Add to a counter in the storage system, if one is configured
+pub fn increment(
+    env: FunctionEnvMut<Env>,
+    key_buf: WasmPtr<u8>,
+    key_buf_len: u32,
+    delta: i64,
+    data_buffer: WasmPtr<u8>,
+    data_buffer_len: u32,
+) -> i32 {
+    record_host_call(env.data(), "storage", "storage_increment");
+    let store = env.as_store_ref();
+    let env_data = env.data();
+
+    let storage = if let Some(storage) = &env_data.storage {
+        storage
+    } else {
+        return FunctionErrors::ApiNotConfigured as i32;
+    };
+
+    let memory_view = match get_memory(&env, &store) {
+        Ok(memory_view) => memory_view,
+        Err(e) => {
+            error!(
+                "{}: Memory error in storage_increment: {:?}",
+                env_data.module.name, e
+            );
+            return FunctionErrors::CouldNotGetAdequateMemory as i32;
+        }
+    };
+
+    safely_get_guest_string!(key, memory_view, key_buf, key_buf_len, env_data);
+
+    increment_common(
+        env_data,
+        storage,
+        WriteTarget {
+            namespace: env_data.module.name.clone(),
+            key,
+            storage_limit: env_data.module.storage_limit.clone(),
+            storage_counter: &env_data.module.storage_current,
+        },
+        delta,
+        memory_view,
+        data_buffer,
+        data_buffer_len,
+    )
+}
+
+/// Add to a counter in a shared namespace in the storage system, if one is configured
+// The arguments are the buffers the guest passes, so they cannot be grouped
+#[allow(clippy::too_many_arguments)]
+pub fn increment_shared(
+    env: FunctionEnvMut<Env>,
+    namespace_buf: WasmPtr<u8>,
+    namespace_buf_len: u32,
+    key_buf: WasmPtr<u8>,
+    key_buf_len: u32,
+    delta: i64,
+    data_buffer: WasmPtr<u8>,
+    data_buffer_len: u32,
+) -> i32 {
+    record_host_call(env.data(), "storage", "storage_increment_shared");
+    let store = env.as_store_ref();
+    let env_data = env.data();
+
+    let storage = if let Some(storage) = &env_data.storage {
+        storage
+    } else {
+        return FunctionErrors::ApiNotConfigured as i32;
+    };
+
+    let memory_view = match get_memory(&env, &store) {
+        Ok(memory_view) => memory_view,
+        Err(e) => {
+            error!(
+                "{}: Memory error in storage_increment_shared: {:?}",
+                env_data.module.name, e
+            );
+            return FunctionErrors::CouldNotGetAdequateMemory as i32;
+        }
+    };
+
+    safely_get_guest_string!(
+        namespace,
+        memory_view,
+        namespace_buf,
+        namespace_buf_len,
+        env_data
+    );
+
+    let (storage_limit, storage_current) = match writable_shared_db(env_data, storage, &namespace) {
+        Ok(db) => db,
+        Err(e) => return e,
+    };
+
+    safely_get_guest_string!(key, memory_view, key_buf, key_buf_len, env_data);
+
+    increment_common(
+        env_data,
+        storage,
+        WriteTarget {
+            namespace,
+            key,
+            storage_limit,
+            storage_counter: &storage_current,
+        },
+        delta,
+        memory_view,
+        data_buffer,
+        data_buffer_len,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use plaid_stl::messages::{Generator, LogSource, LogbacksAllowed};
+    use tokio_util::sync::CancellationToken;
+    use wasmer::{
+        sys::{Cranelift, EngineBuilder},
+        FunctionEnv, Module, Store,
+    };
+
+    use crate::{
+        apis::{mock::ApiMocks, Api},
+        executor::{metrics::ModuleExecutionMetrics, Message},
+        loader::PlaidModule,
+        logging::Logger,
+        metrics::MetricsHandle,
+    };
+
+    use super::*;
+
+    #[test]
+    fn storage_calls_are_counted() {
+        let mut store = Store::default();
+        // stub wasm module, just enough to pass validation: \0ASM + version
+        let wasm = &[0, 97, 115, 109, 1, 0, 0, 0];
+        let module = Arc::new(PlaidModule {
+            name: "counted.wasm".to_string(),
+            logtype: "test".to_string(),
+            module: Module::new(&store, wasm).unwrap(),
+            engine: EngineBuilder::new(Cranelift::default()).into(),
+            computation_limit: 0,
+            page_limit: 0,
+            storage_current: Default::default(),
+            storage_limit: LimitValue::Unlimited,
+            accessory_data: Default::default(),
+            secrets: Default::default(),
+            persistent_response: Default::default(),
+            test_mode: false,
+            rate_limiter: None,
+            log_budget: None,
+        });
+
+        let handle = MetricsHandle::new();
+        let env = Env {
+            module,
+            message: Message::new(
+                "test".to_string(),
+                vec![],
+                LogSource::Generator(Generator::Interval("test".to_string())),
+                LogbacksAllowed::Unlimited,
+            ),
+            api: Arc::new(Api::with_mocks(ApiMocks::default()).unwrap()),
+            storage: Some(Arc::new(Storage::new_in_memory())),
+            cache: None,
+            external_logging_system: Logger::to_channel(false).0,
+            memory: None,
+            response: None,
+            execution_error_context: None,
+            immediate_sender: None,
+            delayed_log_sender: crossbeam_channel::unbounded().0,
+            cancellation_token: CancellationToken::new(),
+            trace_context: Default::default(),
+            module_execution_metrics: Some(Arc::new(ModuleExecutionMetrics::register(&handle))),
+        };
+        let env = FunctionEnv::new(&mut store, env);
+
+        // The module has no memory, so the call fails, but it is still counted
+        let code = get(
+            env.into_mut(&mut store),
+            WasmPtr::null(),
+            0,
+            WasmPtr::null(),
+            0,
+        );
+        assert!(code < 0);
+
+        let metrics = handle.encode().unwrap();
+        assert!(metrics.contains(
+            r#"plaid_host_function_calls_total{api="storage",function="storage_get",module="counted.wasm"} 1"#
+        ));
+    }
+}
@@ -0,0 +1,585 @@
+//! Run a single module against messages from a fixture file, with mocked APIs and in-memory
+//! storage and cache, and check what it did.
+//!
+//! A fixture is a JSON file like
+//! ```json
+//! {
+//!     "module": "../compiled_modules/my_rule.wasm",
+//!     "secrets": { "api_key": "not-a-real-key" },
+//!     "mocks": { "slack_post_to_named_webhook": [""] },
+//!     "cases": [
+//!         {
+//!             "name": "alerts on a new admin",
+//!             "message": { "data": { "action": "member_added", "role": "admin" } },
+//!             "expect": {
+//!                 "error": false,
+//!                 "calls": [{ "function": "slack_post_to_named_webhook" }],
+//!                 "storage": { "last_admin": "alice" }
+//!             }
+//!         }
+//!     ]
+//! }
+//! ```
+//!
+//! Mocked responses are keyed by host function name. A case's `mocks` replace the fixture's for
+//! that function. Storage starts from the fixture's `storage` and is kept between cases, in order.
+//! Only the expectations that are present are checked.
+
+pub mod replay;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use plaid_stl::messages::{LogSource, LogbacksAllowed};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio_util::sync::CancellationToken;
+
+use crate::apis::mock::{ApiMocks, MockResponse, RecordedCall};
+use crate::apis::Api;
+use crate::cache::{Cache, CacheBackend, Config as CacheConfig};
+use crate::data::DelayedMessage;
+use crate::executor::retry::{DeadLetterQueue, RetryHandler};
+use crate::executor::{process_message_with_module, ExecutionContext, Message};
+use crate::loader::{CompilerBackend, LimitedAmount, PlaidModule};
+use crate::logging::{Log, Logger};
+use crate::storage::Storage;
+
+/// A module and the cases to run it on
+#[derive(Deserialize)]
+pub struct Fixture {
+    /// The path to the compiled module, relative to the fixture file
+    pub module: String,
+    /// The log type the module runs on. Defaults to the module's name without its extension.
+    pub log_type: Option<String>,
+    /// The computation limit for each run. Defaults to 55,000,000.
+    #[serde(default = "default_computation_limit")]
+    pub computation_limit: u64,
+    /// The number of memory pages the module can use. Defaults to 300.
+    #[serde(default = "default_page_limit")]
+    pub page_limit: u64,
+    /// Secrets the module can read
+    #[serde(default)]
+    pub secrets: HashMap<String, String>,
+    /// Accessory data the module can read
+    #[serde(default)]
+    pub accessory_data: HashMap<String, String>,
+    /// What is in the module's storage before the first case
+    #[serde(default)]
+    pub storage: HashMap<String, Value>,
+    /// Mocked responses for every case, keyed by host function name
+    #[serde(default)]
+    pub mocks: HashMap<String, Vec<MockResponse>>,
+    pub cases: Vec<Case>,
+}
+
+/// The computation limit modules are run with unless another is given
+pub const DEFAULT_COMPUTATION_LIMIT: u64 = 55_000_000;
+/// The number of memory pages modules can use unless another number is given
+pub const DEFAULT_PAGE_LIMIT: u64 = 300;
+
+fn default_computation_limit() -> u64 {
+    DEFAULT_COMPUTATION_LIMIT
+}
+
+fn default_page_limit() -> u64 {
+    DEFAULT_PAGE_LIMIT
+}
+
+/// A single message to run the module on and what is expected to happen
+#[derive(Deserialize)]
+pub struct Case {
+    pub name: String,
+    pub message: FixtureMessage,
+    /// Mocked responses for this case only. These replace the fixture's for the same function.
+    #[serde(default)]
+    pub mocks: HashMap<String, Vec<MockResponse>>,
+    #[serde(default)]
+    pub expect: Expectations,
+}
+
+/// The message a module is run on
+#[derive(Deserialize)]
+pub struct FixtureMessage {
+    /// The message body. A string is passed as is, any other value is passed serialized as JSON.
+    pub data: Value,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub query_params: HashMap<String, String>,
+    /// How many logbacks the module may trigger. As for webhooks, this defaults to none.
+    #[serde(default)]
+    pub logbacks_allowed: LogbacksAllowed,
+}
+
+/// What a case expects the module to have done. Missing fields are not checked.
+#[derive(Deserialize, Default)]
+pub struct Expectations {
+    /// Whether the module returns an error
+    pub error: Option<bool>,
+    /// Every host function call the module makes to a mocked API, in order
+    pub calls: Option<Vec<ExpectedCall>>,
+    /// Every logback the module triggers, in order
+    pub logbacks: Option<Vec<ExpectedLogback>>,
+    /// Keys that must be in the module's storage after the run, with their values
+    pub storage: Option<HashMap<String, Value>>,
+    /// What the module sets as its response
+    pub response: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ExpectedCall {
+    pub function: String,
+    /// The parameters of the call. Not checked if missing.
+    pub params: Option<Value>,
+}
+
+#[derive(Deserialize)]
+pub struct ExpectedLogback {
+    pub log_type: String,
+    /// The logback's body. Not checked if missing.
+    pub data: Option<Value>,
+    /// The logback's delay in seconds. Not checked if missing.
+    pub delay: Option<u64>,
+}
+
+/// A logback triggered by a module
+#[derive(Debug, PartialEq)]
+pub struct RecordedLogback {
+    pub log_type: String,
+    pub data: String,
+    pub delay: u64,
+}
+
+/// Everything a module did during a run
+#[derive(Debug, Default)]
+pub struct Outcome {
+    /// Errors the module returned or ran into
+    pub errors: Vec<String>,
+    pub calls: Vec<RecordedCall>,
+    pub logbacks: Vec<RecordedLogback>,
+    /// The module's storage after the run
+    pub storage: HashMap<String, String>,
+    pub response: Option<String>,
+}
+
+/// The result of running a single case
+pub struct CaseResult {
+    pub name: String,
+    /// Every expectation that was not met. The case passed if this is empty.
+    pub failures: Vec<String>,
+}
+
+/// Errors encountered while setting up or running a fixture. Expectations that are not met are
+/// not errors, they are reported in [`CaseResult`].
+#[derive(Debug)]
+pub enum HarnessError {
+    FixtureError(String),
+    LoadError(String),
+    StorageError(String),
+    CacheError(String),
+    ExecutionError(String),
+}
+
+impl std::fmt::Display for HarnessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FixtureError(e) => write!(f, "Could not read fixture: {e}"),
+            Self::LoadError(e) => write!(f, "Could not load module: {e}"),
+            Self::StorageError(e) => write!(f, "Storage error: {e}"),
+            Self::CacheError(e) => write!(f, "Cache error: {e}"),
+            Self::ExecutionError(e) => write!(f, "Could not run module: {e}"),
+        }
+    }
+}
+
+/// A module loaded with a fake API, storage and cache, ready to be run on messages
+pub struct Harness {
+    module: Arc<PlaidModule>,
+    api: Arc<Api>,
+    storage: Arc<Storage>,
+    cache: Arc<Cache>,
+    logger: Logger,
+    logs: Receiver<Log>,
+    logbacks: (Sender<Message>, Receiver<Message>),
+    delayed_logbacks: (Sender<DelayedMessage>, Receiver<DelayedMessage>),
+    retry_handler: Arc<RetryHandler>,
+}
+
+impl Harness {
+    /// Set up a harness for `module`, with `storage` as the module's initial storage
+    pub fn new(
+        module: PlaidModule,
+        storage: HashMap<String, Vec<u8>>,
+    ) -> Result<Self, HarnessError> {
+        let api = Arc::new(
+            Api::with_mocks(ApiMocks::default())
+                .map_err(|e| HarnessError::ExecutionError(e.to_string()))?,
+        );
+
+        let module_storage = Arc::new(Storage::new_in_memory());
+        let cache = api.runtime.block_on(async {
+            for (key, value) in storage {
+                module_storage
+                    .insert(module.name.clone(), key, value)
+                    .await
+                    .map_err(|e| HarnessError::StorageError(e.to_string()))?;
+            }
+
+            Cache::new(
+                HashMap::from([(module.name.clone(), module.logtype.clone())]),
+                CacheConfig {
+                    cache_entries: LimitedAmount {
+                        default: 1000,
+                        log_type: HashMap::new(),
+                        module_overrides: HashMap::new(),
+                    },
+                    backend: Some(CacheBackend::InMemory),
+                },
+            )
+            .await
+            .map_err(|e| HarnessError::CacheError(e.to_string()))
+        })?;
+
+        // Messages that fail are not retried, and nothing is ever parked
+        let retry_handler = Arc::new(RetryHandler::new(
+            HashMap::new(),
+            DeadLetterQueue::new(Arc::new(Storage::new_in_memory())),
+        ));
+        let (logger, logs) = Logger::to_channel(true);
+
+        Ok(Self {
+            module: Arc::new(module),
+            api,
+            storage: module_storage,
+            cache: Arc::new(cache),
+            logger,
+            logs,
+            logbacks: unbounded(),
+            delayed_logbacks: unbounded(),
+            retry_handler,
+        })
+    }
+
+    /// The mocked APIs the module calls
+    pub fn mocks(&self) -> &ApiMocks {
+        self.api.mocks.as_ref().unwrap()
+    }
+
+    /// Run the module on a message and report everything it did
+    pub fn run(&self, message: Message) -> Result<Outcome, HarnessError> {
+        if let Some(response) = &self.module.persistent_response {
+            *response.data.write().unwrap() = None;
+        }
+        // Drop anything left over from a previous run
+        self.mocks().take_calls();
+        self.logs.try_iter().for_each(drop);
+
+        let context = ExecutionContext {
+            api: self.api.clone(),
+            storage: Some(self.storage.clone()),
+            cache: Some(self.cache.clone()),
+            els: self.logger.clone(),
+            performance_mode: None,
+            module_execution_metrics: None,
+            delayed_log_sender: self.delayed_logbacks.0.clone(),
+            cancellation_token: CancellationToken::new(),
+            retry_handler: self.retry_handler.clone(),
+            recorder: None,
+        };
+        process_message_with_module(
+            message,
+            self.module.clone(),
+            Some(self.logbacks.0.clone()),
+            &context,
+        )
+        .map_err(|e| HarnessError::ExecutionError(e.to_string()))?;
+
+        let errors = self
+            .logs
+            .try_iter()
+            .filter_map(|log| match log {
+                Log::ModuleExecutionError { error, .. } => Some(error),
+                _ => None,
+            })
+            .collect();
+
+        let logbacks = self
+            .logbacks
+            .1
+            .try_iter()
+            .map(|message| DelayedMessage::new(0, message))
+            .chain(self.delayed_logbacks.1.try_iter())
+            .map(|logback| RecordedLogback {
+                log_type: logback.message.type_,
+                data: String::from_utf8_lossy(&logback.message.data).to_string(),
+                delay: logback.delay,
+            })
+            .collect();
+
+        let storage = self
+            .api
+            .runtime
+            .block_on(self.storage.fetch_all(&self.module.name, None))
+            .map_err(|e| HarnessError::StorageError(e.to_string()))?
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, String::from_utf8_lossy(&value?).to_string())))
+            .collect();
+
+        Ok(Outcome {
+            errors,
+            calls: self.mocks().take_calls(),
+            logbacks,
+            storage,
+            response: self.module.get_persistent_response_data(),
+        })
+    }
+}
+
+/// Compile the module at `path`. Its log type defaults to its file name without the extension.
+pub fn load_module(
+    path: &Path,
+    log_type: Option<String>,
+    computation_limit: u64,
+    page_limit: u64,
+    compiler_backend: &CompilerBackend,
+) -> Result<PlaidModule, HarnessError> {
+    let module_bytes = std::fs::read(path)
+        .map_err(|e| HarnessError::LoadError(format!("{}: {e}", path.display())))?;
+    let filename = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let log_type = log_type.unwrap_or_else(|| {
+        filename
+            .strip_suffix(".wasm")
+            .unwrap_or(&filename)
+            .to_string()
+    });
+
+    PlaidModule::compile_standalone(
+        &filename,
+        module_bytes,
+        &log_type,
+        computation_limit,
+        page_limit,
+        compiler_backend,
+    )
+    .map_err(|e| HarnessError::LoadError(e.to_string()))
+}
+
+/// Load the fixture at `path` and run every case in it, in order
+pub fn run_fixture(
+    path: &Path,
+    compiler_backend: &CompilerBackend,
+) -> Result<Vec<CaseResult>, HarnessError> {
+    let fixture = std::fs::read_to_string(path)
+        .map_err(|e| HarnessError::FixtureError(format!("{}: {e}", path.display())))?;
+    let fixture: Fixture = serde_json::from_str(&fixture)
+        .map_err(|e| HarnessError::FixtureError(format!("{}: {e}", path.display())))?;
+
+    let module_path = path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(&fixture.module);
+    let mut module = load_module(
+        &module_path,
+        fixture.log_type,
+        fixture.computation_limit,
+        fixture.page_limit,
+        compiler_backend,
+    )?;
+    let log_type = module.logtype.clone();
+    module.secrets = Some(to_bytes(fixture.secrets));
+    module.accessory_data = Some(to_bytes(fixture.accessory_data));
+
+    let storage = fixture
+        .storage
+        .into_iter()
+        .map(|(key, value)| (key, value_to_string(value).into_bytes()))
+        .collect();
+    let harness = Harness::new(module, storage)?;
+
+    let mut results = vec![];
+    for case in fixture.cases {
+        let mut mocks = fixture.mocks.clone();
+        mocks.extend(case.mocks);
+        harness.mocks().reset(mocks);
+
+        let mut message = Message::new(
+            log_type.clone(),
+            value_to_string(case.message.data).into_bytes(),
+            LogSource::WebhookPost("plaid-test".to_string()),
+            case.message.logbacks_allowed,
+        );
+        message.headers = to_bytes(case.message.headers);
+        message.query_params = to_bytes(case.message.query_params);
+
+        let outcome = harness.run(message)?;
+        results.push(CaseResult {
+            name: case.name,
+            failures: check(&case.expect, &outcome),
+        });
+    }
+
+    Ok(results)
+}
+
+/// Compare what a module did with what was expected, returning every difference
+pub fn check(expect: &Expectations, outcome: &Outcome) -> Vec<String> {
+    let mut failures = vec![];
+
+    if let Some(error) = expect.error {
+        if error && outcome.errors.is_empty() {
+            failures.push("expected the module to fail but it succeeded".to_string());
+        } else if !error && !outcome.errors.is_empty() {
+            failures.push(format!(
+                "expected the module to succeed but it failed: {}",
+                outcome.errors.join(", ")
+            ));
+        }
+    }
+
+    if let Some(calls) = &expect.calls {
+        if calls.len() != outcome.calls.len() {
+            failures.push(format!(
+                "expected {} host function calls but there were {}: [{}]",
+                calls.len(),
+                outcome.calls.len(),
+                outcome
+                    .calls
+                    .iter()
+                    .map(|call| call.function.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        for (i, (expected, call)) in calls.iter().zip(&outcome.calls).enumerate() {
+            if expected.function != call.function {
+                failures.push(format!(
+                    "call {i}: expected [{}] but [{}] was called",
+                    expected.function, call.function
+                ));
+            } else if let Some(params) = &expected.params {
+                if !matches(params, &call.params) {
+                    failures.push(format!(
+                        "call {i} to [{}]: expected params {params} but got {}",
+                        call.function, call.params
+                    ));
+                }
+            }
+        }
+    }
+
+    if let Some(logbacks) = &expect.logbacks {
+        if logbacks.len() != outcome.logbacks.len() {
+            failures.push(format!(
+                "expected {} logbacks but there were {}",
+                logbacks.len(),
+                outcome.logbacks.len()
+            ));
+        }
+        for (i, (expected, logback)) in logbacks.iter().zip(&outcome.logbacks).enumerate() {
+            if expected.log_type != logback.log_type {
+                failures.push(format!(
+                    "logback {i}: expected log type [{}] but got [{}]",
+                    expected.log_type, logback.log_type
+                ));
+            }
+            if let Some(data) = &expected.data {
+                if !matches(data, &logback.data) {
+                    failures.push(format!(
+                        "logback {i}: expected data {data} but got {}",
+                        logback.data
+                    ));
+                }
+            }
+            if let Some(delay) = expected.delay {
+                if delay != logback.delay {
+                    failures.push(format!(
+                        "logback {i}: expected a delay of {delay} but got {}",
+                        logback.delay
+                    ));
+                }
+            }
+        }
+    }
+
+    if let Some(storage) = &expect.storage {
+        for (key, expected) in storage {
+            match outcome.storage.get(key) {
+                Some(value) if matches(expected, value) => (),
+                Some(value) => failures.push(format!(
+                    "storage [{key}]: expected {expected} but got {value}"
+                )),
+                None => failures.push(format!(
+                    "storage [{key}]: expected {expected} but it is not set"
+                )),
+            }
+        }
+    }
+
+    if let Some(response) = &expect.response {
+        if outcome.response.as_ref() != Some(response) {
+            failures.push(format!(
+                "expected response {response:?} but got {:?}",
+                outcome.response
+            ));
+        }
+    }
+
+    failures
+}
+
+/// Whether `actual` matches an expected value. Strings are compared as is, other values are
+/// compared to `actual` parsed as JSON so formatting differences don't matter.
+fn matches(expected: &Value, actual: &str) -> bool {
+    match expected {
+        Value::String(expected) => expected == actual,
+        expected => serde_json::from_str::<Value>(actual).is_ok_and(|actual| &actual == expected),
+    }
+}
+
+fn value_to_string(value: Value) -> String {
+    match value {
+        Value::String(value) => value,
+        value => value.to_string(),
+    }
+}
+
+fn to_bytes(map: HashMap<String, String>) -> HashMap<String, Vec<u8>> {
+    map.into_iter()
+        .map(|(key, value)| (key, value.into_bytes()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unmet_expectations_are_reported() {
+        let expect: Expectations = serde_json::from_value(serde_json::json!({
+            "error": false,
+            "calls": [{ "function": "slack_post_message", "params": { "channel": "alerts" } }],
+            "storage": { "count": 2 },
+        }))
+        .unwrap();
+
+        let mut outcome = Outcome {
+            calls: vec![RecordedCall {
+                function: "slack_post_message".to_string(),
+                params: r#"{ "channel":"alerts" }"#.to_string(),
+            }],
+            storage: HashMap::from([("count".to_string(), "2".to_string())]),
+            ..Default::default()
+        };
+        assert!(check(&expect, &outcome).is_empty());
+
+        outcome.errors.push("boom".to_string());
+        outcome.storage.clear();
+        assert_eq!(check(&expect, &outcome).len(), 2);
+    }
+}
@@ -0,0 +1,130 @@
+//! Replay recorded production messages on a module and compare what it does with what
+//! another version of the module does with the same messages.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+use crate::apis::mock::MockResponse;
+use crate::executor::recorder::{Recording, RecordingReason};
+
+use super::{Harness, HarnessError, Outcome};
+
+/// What a module did with a recorded message
+pub struct ReplayResult {
+    pub message_id: String,
+    pub log_type: String,
+    pub reason: RecordingReason,
+    pub outcome: Outcome,
+    /// How the outcome differs from the baseline module's. Always empty without a baseline.
+    pub differences: Vec<String>,
+}
+
+/// Read recordings from a file written by the recorder, or from S3 objects it wrote
+/// concatenated together. There is one recording per line.
+pub fn read_recordings(path: &Path) -> Result<Vec<Recording>, HarnessError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| HarnessError::FixtureError(format!("{}: {e}", path.display())))?;
+
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| {
+                HarnessError::FixtureError(format!("{} line {}: {e}", path.display(), i + 1))
+            })
+        })
+        .collect()
+}
+
+/// Run every recording, in order, on `candidate` and, if there is one, on `baseline`.
+/// Both modules get the same mocked API responses for every message.
+pub fn replay(
+    recordings: Vec<Recording>,
+    mocks: &HashMap<String, Vec<MockResponse>>,
+    candidate: &Harness,
+    baseline: Option<&Harness>,
+) -> Result<Vec<ReplayResult>, HarnessError> {
+    let mut results = vec![];
+    for recording in recordings {
+        let baseline_outcome = match baseline {
+            Some(baseline) => {
+                baseline.mocks().reset(mocks.clone());
+                Some(baseline.run(recording.message.create_duplicate())?)
+            }
+            None => None,
+        };
+
+        candidate.mocks().reset(mocks.clone());
+        let message_id = recording.message.id.clone();
+        let log_type = recording.message.type_.clone();
+        let outcome = candidate.run(recording.message)?;
+
+        results.push(ReplayResult {
+            message_id,
+            log_type,
+            reason: recording.reason,
+            differences: baseline_outcome
+                .map(|baseline| diff(&baseline, &outcome))
+                .unwrap_or_default(),
+            outcome,
+        });
+    }
+
+    Ok(results)
+}
+
+/// Describe every way in which two modules' outcomes differ
+pub fn diff(baseline: &Outcome, candidate: &Outcome) -> Vec<String> {
+    let mut differences = vec![];
+
+    if baseline.errors != candidate.errors {
+        differences.push(format!(
+            "errors: {:?} -> {:?}",
+            baseline.errors, candidate.errors
+        ));
+    }
+
+    let calls = |outcome: &Outcome| -> Vec<String> {
+        outcome
+            .calls
+            .iter()
+            .map(|call| format!("{}({})", call.function, call.params))
+            .collect()
+    };
+    if baseline.calls != candidate.calls {
+        differences.push(format!(
+            "calls: {:?} -> {:?}",
+            calls(baseline),
+            calls(candidate)
+        ));
+    }
+
+    if baseline.logbacks != candidate.logbacks {
+        differences.push(format!(
+            "logbacks: {:?} -> {:?}",
+            baseline.logbacks, candidate.logbacks
+        ));
+    }
+
+    let keys: BTreeSet<&String> = baseline
+        .storage
+        .keys()
+        .chain(candidate.storage.keys())
+        .collect();
+    for key in keys {
+        let (before, after) = (baseline.storage.get(key), candidate.storage.get(key));
+        if before != after {
+            differences.push(format!("storage [{key}]: {before:?} -> {after:?}"));
+        }
+    }
+
+    if baseline.response != candidate.response {
+        differences.push(format!(
+            "response: {:?} -> {:?}",
+            baseline.response, candidate.response
+        ));
+    }
+
+    differences
+}
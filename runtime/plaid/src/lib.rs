@@ -8,6 +8,7 @@ use std::time::Duration;
 #[macro_use]
 extern crate log;
 
+pub mod admin;
 pub mod apis;
 pub mod cache;
 pub mod config;
@@ -15,11 +16,14 @@ pub mod cryptography;
 pub mod data;
 pub mod executor;
 pub mod functions;
+pub mod harness;
 pub mod loader;
 pub mod logging;
 pub mod metrics;
 pub mod performance;
 pub mod storage;
+pub mod telemetry;
+pub mod webhooks;
 
 /// Defines methods to authenticate to AWS with
 #[cfg(feature = "aws")]
@@ -0,0 +1,84 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long a module's log volume budget lasts before it is refilled
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// The budget a module spends when it writes logs through the logging host API.
+/// A reloaded module starts with a fresh budget.
+pub struct LogBudget {
+    /// How many bytes of logs can be written per window
+    bytes_per_window: u64,
+    window: Mutex<Window>,
+}
+
+struct Window {
+    started: Instant,
+    used: u64,
+    /// How many logs have been dropped in this window
+    dropped: u64,
+}
+
+/// Returned when a log does not fit in the module's budget
+pub struct BudgetExhausted {
+    /// True for the first log dropped in a window, so the drop can be reported once
+    pub first_dropped: bool,
+}
+
+impl LogBudget {
+    pub fn new(bytes_per_minute: u64) -> Self {
+        Self {
+            bytes_per_window: bytes_per_minute,
+            window: Mutex::new(Window {
+                started: Instant::now(),
+                used: 0,
+                dropped: 0,
+            }),
+        }
+    }
+
+    /// Spend `bytes` of the budget, if there is enough left
+    pub fn try_spend(&self, bytes: u64) -> Result<(), BudgetExhausted> {
+        self.try_spend_at(bytes, Instant::now())
+    }
+
+    fn try_spend_at(&self, bytes: u64, now: Instant) -> Result<(), BudgetExhausted> {
+        let mut window = self.window.lock().unwrap();
+        if now.duration_since(window.started) >= WINDOW {
+            *window = Window {
+                started: now,
+                used: 0,
+                dropped: 0,
+            };
+        }
+
+        if window.used + bytes > self.bytes_per_window {
+            window.dropped += 1;
+            return Err(BudgetExhausted {
+                first_dropped: window.dropped == 1,
+            });
+        }
+
+        window.used += bytes;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_refills_every_window() {
+        let budget = LogBudget::new(10);
+        let start = Instant::now();
+
+        assert!(budget.try_spend_at(6, start).is_ok());
+        assert!(budget.try_spend_at(4, start).is_ok());
+        assert!(budget.try_spend_at(1, start).unwrap_err().first_dropped);
+        assert!(!budget.try_spend_at(1, start).unwrap_err().first_dropped);
+
+        assert!(budget.try_spend_at(10, start + WINDOW).is_ok());
+        assert!(budget.try_spend_at(11, start + WINDOW * 2).is_err());
+    }
+}
@@ -1,8 +1,13 @@
 mod errors;
 mod limits;
+mod log_budget;
+mod rate_limit;
+mod reload;
 mod signing;
 mod utils;
 
+pub use reload::{reload, watch_for_module_changes};
+
 use errors::Errors;
 use limits::LimitingTunables;
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
@@ -17,10 +22,12 @@ use std::sync::{Arc, RwLock};
 
 use futures_util::stream::{self, StreamExt};
 
+pub use log_budget::{BudgetExhausted, LogBudget};
+pub use rate_limit::{ExecutionPermit, LimitAction, RateLimitConfig, RateLimiter};
 pub use utils::cost_function;
 use utils::{
-    get_module_computation_limit, get_module_page_count, get_module_persistent_storage_limit,
-    read_and_configure_secrets, read_and_parse_modules,
+    get_module_computation_limit, get_module_log_volume, get_module_page_count,
+    get_module_persistent_storage_limit, read_and_configure_secrets, read_and_parse_modules,
 };
 use wasmer::sys::{NativeEngineExt, Target};
 
@@ -35,6 +42,7 @@ use wasmer::sys::Cranelift;
 use wasmer::{sys::BaseTunables, Engine, Module, Pages};
 use wasmer_middlewares::Metering;
 
+use crate::cryptography::hash::sha256_hex;
 use crate::functions::is_known_api_function;
 use crate::storage::Storage;
 
@@ -147,6 +155,13 @@ pub struct Configuration {
     /// See persistent_response_size in PlaidModule for an explanation on how to use this
     #[serde(default)]
     pub persistent_response_size: HashMap<String, usize>,
+    /// Limits on how often and how many times at once modules can run. See [`RateLimitConfig`].
+    /// The mapping is `{rule_file_name -> limits}`
+    #[serde(default)]
+    pub rate_limits: HashMap<String, RateLimitConfig>,
+    /// How many bytes of logs a module can write per minute through the logging host API.
+    /// Logs over the budget are dropped. Not limited if unset.
+    pub log_volume: Option<LimitedAmount>,
     /// Modules will be loaded in test_mode meaning they will not be able to make any API calls that
     /// cause side effects. This does not include:
     /// * Storage
@@ -173,6 +188,24 @@ pub struct Configuration {
     /// Defaults to `true` if not provided.
     #[serde(default = "default_panic_on_load_failure")]
     pub panic_on_module_load_failure: bool,
+    /// If this value is set, Plaid will reload modules whose files are added, changed or removed
+    /// in `module_dir` without restarting. See [`HotReloadConfiguration`].
+    pub hot_reload: Option<HotReloadConfiguration>,
+}
+
+/// Configuration for reloading modules while Plaid is running.
+///
+/// A reload is triggered every `poll_interval` seconds (if set) and whenever Plaid receives
+/// a `SIGHUP`. Only files whose contents changed are recompiled, and they go through the same
+/// signature checks as at boot. A module that fails to reload keeps running its previous version.
+///
+/// Modules added after boot get no in-memory cache until Plaid is restarted.
+#[derive(Deserialize)]
+pub struct HotReloadConfiguration {
+    /// How often, in seconds, `module_dir` is checked for changes. If this is not set,
+    /// modules are only reloaded on `SIGHUP`.
+    #[serde(default, deserialize_with = "reload::deserialize_poll_interval")]
+    pub poll_interval: Option<u64>,
 }
 
 fn default_panic_on_load_failure() -> bool {
@@ -332,6 +365,10 @@ pub struct PlaidModule {
     pub persistent_response: Option<PersistentResponse>,
     /// If the module is in test mode, meaning it should not be allowed to cause side effects
     pub test_mode: bool,
+    /// Limits on how often the module runs, if any are configured
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    /// Limits how much the module can log, if a log volume is configured
+    pub log_budget: Option<Arc<LogBudget>>,
 }
 
 impl std::fmt::Display for PlaidModule {
@@ -441,9 +478,48 @@ impl PlaidModule {
             secrets: None,
             persistent_response: None,
             test_mode,
+            rate_limiter: None,
+            log_budget: None,
         })
     }
 
+    /// Compile a single module outside of a Plaid configuration, e.g., to test it.
+    ///
+    /// The module gets the given limits, unlimited persistent storage and a persistent
+    /// response with no size limit, so everything it does can be inspected afterwards.
+    pub fn compile_standalone(
+        filename: &str,
+        module_bytes: Vec<u8>,
+        log_type: &str,
+        computation_limit: u64,
+        page_limit: u64,
+        compiler_backend: &CompilerBackend,
+    ) -> Result<Self, Errors> {
+        let limit = |default| LimitedAmount {
+            default,
+            log_type: HashMap::new(),
+            module_overrides: HashMap::new(),
+        };
+        let storage_amount = LimitableAmount {
+            default: LimitValue::Unlimited,
+            log_type: HashMap::new(),
+            module_overrides: HashMap::new(),
+        };
+
+        let mut module = Self::compile(
+            filename,
+            &limit(computation_limit),
+            &limit(page_limit),
+            &storage_amount,
+            module_bytes,
+            log_type,
+            false,
+            compiler_backend,
+        )?;
+        module.persistent_response = Some(PersistentResponse::new(usize::MAX));
+        Ok(module)
+    }
+
     fn log_load_info(&self) {
         let storage_current_bytes = *self.storage_current.read().unwrap();
         info!(
@@ -462,42 +538,121 @@ impl PlaidModule {
     }
 }
 
+/// The module maps that are swapped as a unit when modules are reloaded.
+#[derive(Default, Clone)]
+struct LoadedModules {
+    channels: HashMap<String, Vec<Arc<PlaidModule>>>,
+    modules: HashMap<String, Arc<PlaidModule>>,
+    /// Hex encoded SHA256 of each module file, used to detect which files changed on reload
+    hashes: HashMap<String, String>,
+}
+
+impl LoadedModules {
+    /// Add a module, replacing any previously loaded version with the same name. A replaced
+    /// module keeps its position in its channel unless its log type changed.
+    fn insert(&mut self, module: Arc<PlaidModule>, hash: String) {
+        let name = module.name.clone();
+        let type_ = module.logtype.clone();
+
+        if let Some(previous) = self.modules.get(&name) {
+            if previous.logtype != type_ {
+                self.remove(&name);
+            }
+        }
+
+        let channel = self.channels.entry(type_).or_default();
+        match channel.iter_mut().find(|m| m.name == name) {
+            Some(slot) => *slot = module.clone(),
+            None => channel.push(module.clone()),
+        }
+
+        self.modules.insert(name.clone(), module);
+        self.hashes.insert(name, hash);
+    }
+
+    /// Remove a module from every map. Channels left without modules are removed as well.
+    fn remove(&mut self, name: &str) {
+        if let Some(module) = self.modules.remove(name) {
+            if let Some(channel) = self.channels.get_mut(&module.logtype) {
+                channel.retain(|m| m.name != name);
+                if channel.is_empty() {
+                    self.channels.remove(&module.logtype);
+                }
+            }
+        }
+        self.hashes.remove(name);
+    }
+}
+
 /// We need multiple ways of referencing the modules. To prevent duplication we use `Arc`s.
 /// Since modules are static and are executed in ephemeral instances this should be fine.
+///
+/// The maps live behind a lock so that a reload can swap them atomically. Executions that
+/// already hold an `Arc<PlaidModule>` finish on the version they started with.
 #[derive(Default)]
 pub struct PlaidModules {
-    channels: HashMap<String, Vec<Arc<PlaidModule>>>,
-    modules: HashMap<String, Arc<PlaidModule>>,
+    loaded: RwLock<LoadedModules>,
+    /// Serializes reloads. See [`reload`].
+    reload_lock: tokio::sync::Mutex<()>,
 }
 
 impl PlaidModules {
-    /// All of the modules are `Arc`s so this should be relatively inexpensive. Generally
-    /// this is used for the executor to handle logs coming in and processing them through
-    /// the correct channel of modules.
+    /// All of the modules are `Arc`s so this should be relatively inexpensive. This returns
+    /// a snapshot of the channels as they are right now: it does not follow reloads.
     pub fn get_channels(&self) -> HashMap<String, Vec<Arc<PlaidModule>>> {
-        self.channels.clone()
+        self.loaded.read().unwrap().channels.clone()
     }
 
-    /// All of the modules are `Arc`s so this should be relatively inexpensive. Generally this
-    /// is used for the GET request system so that we can reference which module is to serve a
-    /// particular webhook's GET handle.
+    /// Get the modules currently operating on a log type. Generally this is used by the
+    /// executor to find the channel of modules an incoming log should be processed by.
+    pub fn get_channel(&self, log_type: &str) -> Option<Vec<Arc<PlaidModule>>> {
+        self.loaded.read().unwrap().channels.get(log_type).cloned()
+    }
+
+    /// All of the modules are `Arc`s so this should be relatively inexpensive. This returns
+    /// a snapshot of the modules as they are right now: it does not follow reloads.
     pub fn get_modules(&self) -> HashMap<String, Arc<PlaidModule>> {
-        self.modules.clone()
+        self.loaded.read().unwrap().modules.clone()
     }
 
     /// Get a particular module by name. This makes the API ergonomic enough
-    /// we don't need to expose the underlying data structures.
+    /// we don't need to expose the underlying data structures. Generally this is used for the
+    /// GET request system so that we can reference which module is to serve a particular
+    /// webhook's GET handle.
     pub fn get_module(&self, name: &str) -> Option<Arc<PlaidModule>> {
-        self.modules.get(name).cloned()
+        self.loaded.read().unwrap().modules.get(name).cloned()
     }
 
     /// Get a mapping between module names and log types.
     pub fn get_module_logtypes(&self) -> HashMap<String, String> {
-        self.modules
+        self.loaded
+            .read()
+            .unwrap()
+            .modules
             .iter()
             .map(|(name, module)| (name.to_string(), module.logtype.clone()))
             .collect()
     }
+
+    /// Get the hash of the file every module was loaded from.
+    fn get_module_hashes(&self) -> HashMap<String, String> {
+        self.loaded.read().unwrap().hashes.clone()
+    }
+
+    /// Apply a set of changes in a single swap: `updated` modules are added or replace the
+    /// version with the same name, and `removed` modules are unloaded.
+    fn apply_changes(&self, updated: Vec<(PlaidModule, String)>, removed: &[String]) {
+        // Build the new maps before taking the write lock so executors are not held up
+        let mut next = self.loaded.read().unwrap().clone();
+        for name in removed {
+            next.remove(name);
+        }
+        for (module, hash) in updated {
+            next.insert(Arc::new(module), hash);
+        }
+
+        *self.loaded.write().unwrap() = next;
+    }
 }
 
 /// Maximum number of concurrent storage namespace byte-size lookups during module load.
@@ -548,6 +703,71 @@ async fn populate_storage_sizes(
     .await
 }
 
+/// Work out a module's log type, compile it and configure the secrets, accessory data and
+/// persistent response it is given according to Plaid's configuration.
+fn build_module(
+    config: &Configuration,
+    filename: &str,
+    module_bytes: Vec<u8>,
+    byte_secrets: &HashMap<String, HashMap<String, Vec<u8>>>,
+) -> Result<PlaidModule, Errors> {
+    let type_ = if let Some(type_) = config.log_type_overrides.get(filename) {
+        type_.to_string()
+    } else {
+        let type_: Vec<&str> = filename.split('_').collect();
+        type_[0].to_string()
+    };
+
+    let filename_without_ext = filename.trim_end_matches(".wasm");
+    if !config.log_type_overrides.contains_key(filename) && filename_without_ext != type_ {
+        warn!(
+            "Module [{filename}] assigned log type [{type_}] (inferred from first segment before '_'). \
+             If you expected log type [{filename_without_ext}], add to [loading.log_type_overrides]: \
+             \"{filename}\" = \"{filename_without_ext}\""
+        );
+    }
+
+    let test_mode = config.test_mode && !config.test_mode_exemptions.iter().any(|m| m == filename);
+
+    let mut plaid_module = PlaidModule::compile(
+        filename,
+        &config.computation_amount,
+        &config.memory_page_count,
+        &config.storage_size,
+        module_bytes,
+        &type_,
+        test_mode,
+        &config.compiler_backend,
+    )?;
+
+    let persistent_response = config
+        .persistent_response_size
+        .get(filename)
+        .copied()
+        .map(PersistentResponse::new);
+
+    plaid_module.persistent_response = persistent_response;
+    plaid_module.secrets = byte_secrets.get(&type_).cloned();
+    plaid_module.accessory_data = module_accessory_data(config, &plaid_module.name, &type_);
+    plaid_module.rate_limiter = config
+        .rate_limits
+        .get(filename)
+        .map(|limits| Arc::new(RateLimiter::new(limits)));
+    plaid_module.log_budget = config.log_volume.as_ref().map(|log_volume| {
+        Arc::new(LogBudget::new(get_module_log_volume(
+            log_volume, filename, &type_,
+        )))
+    });
+
+    Ok(plaid_module)
+}
+
+/// Get the hex encoded SHA256 of a module file. This is what reloading compares to decide
+/// whether a module changed on disk.
+fn module_hash(module_bytes: &[u8]) -> String {
+    sha256_hex(module_bytes)
+}
+
 /// Load all modules, according to Plaid's configuration
 pub async fn load(
     config: &Configuration,
@@ -556,7 +776,7 @@ pub async fn load(
     let module_paths = fs::read_dir(config.module_dir.clone())
         .unwrap()
         .collect::<Vec<_>>();
-    let mut modules = PlaidModules::default();
+    let modules = PlaidModules::default();
     let byte_secrets = read_and_configure_secrets(&config.secrets);
 
     match config.compiler_backend {
@@ -570,7 +790,7 @@ pub async fn load(
         }
     };
 
-    let loaded_modules: Vec<PlaidModule> = module_paths
+    let (loaded_modules, hashes): (Vec<PlaidModule>, Vec<(String, String)>) = module_paths
         .par_iter()
         .filter_map(|path| {
             let path = path.as_ref().inspect_err(|e| {
@@ -604,34 +824,8 @@ pub async fn load(
                 }
             }
 
-            let type_ = if let Some(type_) = config.log_type_overrides.get(&filename) {
-                type_.to_string()
-            } else {
-                let type_: Vec<&str> = filename.split('_').collect();
-                type_[0].to_string()
-            };
-
-            let filename_without_ext = filename.trim_end_matches(".wasm");
-            if !config.log_type_overrides.contains_key(&filename) && filename_without_ext != type_ {
-                warn!(
-                    "Module [{filename}] assigned log type [{type_}] (inferred from first segment before '_'). \
-                     If you expected log type [{filename_without_ext}], add to [loading.log_type_overrides]: \
-                     \"{filename}\" = \"{filename_without_ext}\""
-                );
-            }
-
-            let test_mode = config.test_mode && !config.test_mode_exemptions.contains(&filename);
-            
-            let mut plaid_module = match PlaidModule::compile(
-                &filename,
-                &config.computation_amount,
-                &config.memory_page_count,
-                &config.storage_size,
-                module_bytes,
-                &type_,
-                test_mode,
-                &config.compiler_backend,
-            ) {
+            let hash = module_hash(&module_bytes);
+            let plaid_module = match build_module(config, &filename, module_bytes, &byte_secrets) {
                 Ok(pm) => pm,
                 Err(e) => {
                     if config.panic_on_module_load_failure {
@@ -643,20 +837,10 @@ pub async fn load(
                 }
             };
 
-            let persistent_response = config
-                .persistent_response_size
-                .get(&filename)
-                .copied()
-                .map(PersistentResponse::new);
-
-            plaid_module.persistent_response = persistent_response;
-            plaid_module.secrets = byte_secrets.get(&type_).map(|x| x.clone());
-            plaid_module.accessory_data = module_accessory_data(config, &plaid_module.name, &type_);
-
             info!("Finished loading module [{filename}]");
-            Some(plaid_module)
+            Some((plaid_module, (filename, hash)))
         })
-        .collect();
+        .unzip();
 
     // Counting the bytes already in storage requires a round trip to the backing store per
     // module, so these lookups are performed concurrently rather than one at a time. Without a
@@ -666,21 +850,17 @@ pub async fn load(
         None => loaded_modules,
     };
 
-    for module in loaded_modules {
-        module.log_load_info();
-
-        let type_ = module.logtype.clone();
-        let filename = module.name.clone();
-        let plaid_module = Arc::new(module);
-
-        if let Some(mods) = modules.channels.get_mut(&type_) {
-            mods.push(plaid_module.clone());
-        } else {
-            modules.channels.insert(type_, vec![plaid_module.clone()]);
-        }
-
-        modules.modules.insert(filename, plaid_module);
-    }
+    let mut hashes: HashMap<String, String> = hashes.into_iter().collect();
+    let loaded_modules = loaded_modules
+        .into_iter()
+        .map(|module| {
+            module.log_load_info();
+            // Every module that survived loading had its hash recorded above
+            let hash = hashes.remove(&module.name).unwrap_or_default();
+            (module, hash)
+        })
+        .collect();
+    modules.apply_changes(loaded_modules, &[]);
 
     Ok(modules)
 }
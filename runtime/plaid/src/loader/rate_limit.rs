@@ -0,0 +1,234 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde::{de, Deserialize, Deserializer};
+
+/// Limits on how often and how many times at once a module can run.
+///
+/// E.g.,
+/// ```toml
+/// [loading.rate_limits."github_noisy_rule.wasm"]
+/// executions_per_second = 5
+/// burst = 20
+/// max_concurrent = 2
+/// on_limit = "drop"
+/// ```
+/// lets the module run at most 5 times per second on average, with bursts of up to 20 runs,
+/// on at most 2 threads at once. Messages over the limit are dropped.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// How many times per second the module can run on average. Not limited if unset.
+    #[serde(default, deserialize_with = "deserialize_executions_per_second")]
+    pub executions_per_second: Option<f64>,
+    /// How many runs can happen back to back before `executions_per_second` kicks in.
+    /// Defaults to `executions_per_second`, rounded up.
+    pub burst: Option<u32>,
+    /// How many threads can run the module at once. Not limited if unset.
+    #[serde(default, deserialize_with = "deserialize_max_concurrent")]
+    pub max_concurrent: Option<u32>,
+    /// What happens to messages over the limit. Defaults to `delay`.
+    #[serde(default)]
+    pub on_limit: LimitAction,
+}
+
+/// What happens to a message when a module is over its rate limit
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitAction {
+    /// The module is run on the message later, through the delayed logback system.
+    /// Messages that are waited on (GET requests) cannot be delayed and are dropped.
+    #[default]
+    Delay,
+    /// The message is not processed by the module
+    Drop,
+}
+
+fn deserialize_executions_per_second<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = f64::deserialize(deserializer)?;
+    if !value.is_finite() || value <= 0.0 {
+        return Err(de::Error::custom(
+            "`executions_per_second` must be greater than 0",
+        ));
+    }
+    Ok(Some(value))
+}
+
+fn deserialize_max_concurrent<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = u32::deserialize(deserializer)?;
+    if value == 0 {
+        return Err(de::Error::custom("`max_concurrent` must be at least 1"));
+    }
+    Ok(Some(value))
+}
+
+/// A token bucket refilled at a constant rate
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    /// Take a token, or get how many seconds it will be until there is one
+    fn take(&mut self, now: Instant) -> Result<(), f64> {
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.refilled_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err((1.0 - self.tokens) / self.rate)
+        }
+    }
+}
+
+/// Enforces a module's [`RateLimitConfig`]. A reloaded module starts with a fresh limiter,
+/// but runs of its previous version still count towards its concurrency limit.
+pub struct RateLimiter {
+    bucket: Option<Mutex<TokenBucket>>,
+    max_concurrent: Option<u32>,
+    /// How many runs hold a permit, shared with the limiters of previous versions of the module
+    running: Arc<AtomicU32>,
+    on_limit: LimitAction,
+}
+
+/// Allows a single run of a module. The run counts towards the module's concurrency limit
+/// until the permit is dropped.
+pub struct ExecutionPermit {
+    limiter: Arc<RateLimiter>,
+}
+
+impl Drop for ExecutionPermit {
+    fn drop(&mut self) {
+        if self.limiter.max_concurrent.is_some() {
+            self.limiter.running.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        let bucket = config.executions_per_second.map(|rate| {
+            let capacity = config
+                .burst
+                .map(f64::from)
+                .unwrap_or_else(|| rate.ceil())
+                .max(1.0);
+            Mutex::new(TokenBucket {
+                rate,
+                capacity,
+                tokens: capacity,
+                refilled_at: Instant::now(),
+            })
+        });
+
+        Self {
+            bucket,
+            max_concurrent: config.max_concurrent,
+            running: Arc::new(AtomicU32::new(0)),
+            on_limit: config.on_limit,
+        }
+    }
+
+    /// Count runs together with `previous`, the limiter of the version of the module this one
+    /// replaces, so that its runs still in progress count towards the concurrency limit
+    pub fn share_running(&mut self, previous: &RateLimiter) {
+        self.running = previous.running.clone();
+    }
+
+    /// What happens to messages over the limit
+    pub fn on_limit(&self) -> LimitAction {
+        self.on_limit
+    }
+
+    /// Get a permit to run the module now. If the module is over its limit, this returns
+    /// how many seconds to wait before trying again instead.
+    pub fn try_acquire(self: &Arc<Self>) -> Result<ExecutionPermit, u64> {
+        if let Some(max_concurrent) = self.max_concurrent {
+            self.running
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |running| {
+                    (running < max_concurrent).then_some(running + 1)
+                })
+                .map_err(|_| 1u64)?;
+        }
+        // From here on, dropping the permit gives back the concurrency slot
+        let permit = ExecutionPermit {
+            limiter: self.clone(),
+        };
+
+        if let Some(bucket) = &self.bucket {
+            bucket
+                .lock()
+                .unwrap()
+                .take(Instant::now())
+                .map_err(|wait| (wait.ceil() as u64).max(1))?;
+        }
+
+        Ok(permit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bursts_then_limits_and_caps_concurrency() {
+        let limiter = Arc::new(RateLimiter::new(&RateLimitConfig {
+            executions_per_second: Some(0.5),
+            burst: Some(2),
+            max_concurrent: Some(3),
+            on_limit: LimitAction::Drop,
+        }));
+
+        let first = limiter.try_acquire().unwrap();
+        let second = limiter.try_acquire().unwrap();
+        // The bucket is empty and refills a token every 2 seconds
+        assert_eq!(limiter.try_acquire().err(), Some(2));
+        // A run that is limited does not hold a concurrency slot
+        assert_eq!(limiter.running.load(Ordering::SeqCst), 2);
+        drop((first, second));
+        assert_eq!(limiter.running.load(Ordering::SeqCst), 0);
+
+        let limiter = Arc::new(RateLimiter::new(&RateLimitConfig {
+            executions_per_second: None,
+            burst: None,
+            max_concurrent: Some(1),
+            on_limit: LimitAction::Delay,
+        }));
+        let permit = limiter.try_acquire().unwrap();
+        assert!(limiter.try_acquire().is_err());
+        drop(permit);
+        assert!(limiter.try_acquire().is_ok());
+    }
+
+    #[test]
+    fn reloaded_limiter_counts_previous_runs() {
+        let config = RateLimitConfig {
+            executions_per_second: None,
+            burst: None,
+            max_concurrent: Some(1),
+            on_limit: LimitAction::Delay,
+        };
+        let previous = Arc::new(RateLimiter::new(&config));
+        let permit = previous.try_acquire().unwrap();
+
+        let mut limiter = RateLimiter::new(&config);
+        limiter.share_running(&previous);
+        let limiter = Arc::new(limiter);
+        assert!(limiter.try_acquire().is_err());
+        drop(permit);
+        assert!(limiter.try_acquire().is_ok());
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use serde::{Deserialize, Deserializer};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_util::sync::CancellationToken;
+
+use super::signing::check_module_signatures;
+use super::utils::{read_and_configure_secrets, read_and_parse_modules};
+use super::{build_module, module_hash, Configuration, PlaidModule, PlaidModules};
+use crate::cache::Cache;
+use crate::storage::Storage;
+
+/// Deserializer for the hot reload poll interval, which cannot be 0.
+pub(super) fn deserialize_poll_interval<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Option::<u64>::deserialize(deserializer)?;
+    if value == Some(0) {
+        return Err(serde::de::Error::custom(
+            "Hot reload poll interval must not be 0",
+        ));
+    }
+    Ok(value)
+}
+
+/// Reload every module whose file was added, changed or removed in `module_dir` since it was
+/// last loaded.
+///
+/// Changed files are verified and compiled off the async runtime, then all changes are swapped
+/// into `modules` at once. A module that fails to verify or compile keeps running its previous
+/// version. Replaced modules keep their storage usage, persistent response and cache. New
+/// modules are given a cache.
+pub async fn reload(
+    config: Arc<Configuration>,
+    modules: &PlaidModules,
+    storage: Option<Arc<Storage>>,
+    cache: Option<Arc<Cache>>,
+) {
+    // Only one reload can be computing changes at a time, otherwise two reloads could
+    // read the same hashes and the second swap would undo parts of the first.
+    let _reload_guard = modules.reload_lock.lock().await;
+
+    let current_hashes = modules.get_module_hashes();
+    let compile_config = config.clone();
+    let (updated, removed) = match tokio::task::spawn_blocking(move || {
+        compile_changed_modules(&compile_config, &current_hashes)
+    })
+    .await
+    {
+        Ok(changes) => changes,
+        Err(e) => {
+            error!("Module reload task failed: {e}");
+            return;
+        }
+    };
+
+    if updated.is_empty() && removed.is_empty() {
+        debug!("No module changes found in [{}]", config.module_dir);
+        return;
+    }
+
+    let mut ready = Vec::with_capacity(updated.len());
+    for (mut module, hash) in updated {
+        match modules.get_module(&module.name) {
+            Some(previous) => carry_over_state(&previous, &mut module),
+            None => {
+                if let Some(storage) = &storage {
+                    match storage.get_namespace_byte_size(&module.name).await {
+                        Ok(bytes) => *module.storage_current.write().unwrap() = bytes,
+                        Err(e) => {
+                            error!(
+                                "Module [{}] failed to look up namespace byte size: {e}. Skipping module load",
+                                module.name
+                            );
+                            continue;
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(cache) = &cache {
+            cache.add_module(&module.name, &module.logtype).await;
+        }
+
+        module.log_load_info();
+        ready.push((module, hash));
+    }
+
+    for name in &removed {
+        info!("Unloading module [{name}] because its file was removed");
+    }
+
+    info!(
+        "Reloaded {} module(s) and unloaded {} module(s)",
+        ready.len(),
+        removed.len()
+    );
+    modules.apply_changes(ready, &removed);
+}
+
+/// Reload modules whenever Plaid receives a `SIGHUP` and, if a poll interval is configured,
+/// every time it elapses. Returns once `cancellation_token` is cancelled.
+pub async fn watch_for_module_changes(
+    config: Arc<Configuration>,
+    modules: Arc<PlaidModules>,
+    storage: Option<Arc<Storage>>,
+    cache: Option<Arc<Cache>>,
+    cancellation_token: CancellationToken,
+) {
+    let poll_interval = config
+        .hot_reload
+        .as_ref()
+        .and_then(|hot_reload| hot_reload.poll_interval)
+        .map(Duration::from_secs);
+    let mut sighup = signal(SignalKind::hangup()).expect("Failed to install SIGHUP handler");
+
+    loop {
+        tokio::select! {
+            _ = cancellation_token.cancelled() => {
+                return;
+            }
+
+            _ = sighup.recv() => {
+                info!("SIGHUP received, reloading modules");
+            }
+
+            _ = wait_for_poll_interval(poll_interval) => {}
+        }
+
+        reload(config.clone(), &modules, storage.clone(), cache.clone()).await;
+    }
+}
+
+/// Sleep for the poll interval, or forever if there is none.
+async fn wait_for_poll_interval(poll_interval: Option<Duration>) {
+    match poll_interval {
+        Some(poll_interval) => tokio::time::sleep(poll_interval).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Keep the state a module accumulated while running when it is replaced by a new version.
+fn carry_over_state(previous: &PlaidModule, module: &mut PlaidModule) {
+    // The storage namespace is the module name, so the bytes already stored still count
+    module.storage_current = previous.storage_current.clone();
+
+    // Runs of the previous version that are still in progress count towards the concurrency
+    // limit. The new limiter was just built, so nothing else holds it yet.
+    if let (Some(previous), Some(current)) = (&previous.rate_limiter, &mut module.rate_limiter) {
+        if let Some(current) = Arc::get_mut(current) {
+            current.share_running(previous);
+        }
+    }
+
+    if let (Some(previous), Some(current)) = (
+        &previous.persistent_response,
+        &mut module.persistent_response,
+    ) {
+        current.data = previous.data.clone();
+    }
+}
+
+/// Compare the files in `module_dir` against the hashes of the loaded modules, then verify and
+/// compile the ones that are new or changed.
+///
+/// Returns the compiled modules with their hashes, and the names of loaded modules whose files
+/// are gone.
+fn compile_changed_modules(
+    config: &Configuration,
+    current_hashes: &HashMap<String, String>,
+) -> (Vec<(PlaidModule, String)>, Vec<String>) {
+    let entries = match fs::read_dir(&config.module_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            // Nothing is unloaded if the directory can't be read
+            error!(
+                "Could not read module directory [{}] for reload: {e}",
+                config.module_dir
+            );
+            return (vec![], vec![]);
+        }
+    };
+
+    let mut on_disk = HashSet::new();
+    let mut changed = vec![];
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                error!("Bad entry in modules directory - skipping. Error: {e}");
+                continue;
+            }
+        };
+
+        // Record the file before reading it, so that a file which can't be read right now
+        // (e.g., because it is being written) does not unload the running version.
+        on_disk.insert(entry.file_name().to_string_lossy().to_string());
+
+        let (filename, module_bytes) = match read_and_parse_modules(&entry) {
+            Ok(filename_and_bytes) => filename_and_bytes,
+            Err(_) => continue,
+        };
+
+        let hash = module_hash(&module_bytes);
+        if current_hashes.get(&filename) != Some(&hash) {
+            changed.push((filename, module_bytes, hash));
+        }
+    }
+
+    let removed = current_hashes
+        .keys()
+        .filter(|name| !on_disk.contains(*name))
+        .cloned()
+        .collect();
+
+    let byte_secrets = read_and_configure_secrets(&config.secrets);
+    let updated = changed
+        .into_par_iter()
+        .filter_map(|(filename, module_bytes, hash)| {
+            info!("Reloading module [{filename}]");
+            if let Some(signing) = &config.module_signing {
+                if let Err(e) = check_module_signatures(signing, &filename, &module_bytes) {
+                    error!(
+                        "Module [{filename}] failed signature verification: {e}. Keeping the previously loaded version, if any"
+                    );
+                    return None;
+                }
+            }
+
+            match build_module(config, &filename, module_bytes, &byte_secrets) {
+                Ok(module) => {
+                    info!("Finished reloading module [{filename}]");
+                    Some((module, hash))
+                }
+                Err(e) => {
+                    error!(
+                        "Module [{filename}] failed to load: {e}. Keeping the previously loaded version, if any"
+                    );
+                    None
+                }
+            }
+        })
+        .collect();
+
+    (updated, removed)
+}
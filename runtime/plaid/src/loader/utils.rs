@@ -60,6 +60,14 @@ pub fn get_module_computation_limit(
     get_limit_with_overrides(limit_amount, filename, log_type)
 }
 
+/// Get the number of bytes the module can log per minute by checking the following in order:
+/// 1. Module Override
+/// 2. Log Type amount
+/// 3. Default amount
+pub fn get_module_log_volume(limit_amount: &LimitedAmount, filename: &str, log_type: &str) -> u64 {
+    get_limit_with_overrides(limit_amount, filename, log_type)
+}
+
 /// Get the persistent storage limit for the module by checking the following in order:
 /// 1. Module Override
 /// 2. Log Type amount
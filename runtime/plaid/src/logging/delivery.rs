@@ -0,0 +1,511 @@
+//! This module batches logs bound for an HTTP logging backend and delivers them
+//! in the background, retrying failed requests and spilling batches to disk
+//! when the backend stays unreachable.
+//!
+//! Logs are batched by one task and delivered by another, so retries never stop
+//! new logs from being accepted. Batches that arrive while too many others are
+//! waiting to be delivered are spilled straight away.
+
+use super::LoggingError;
+use crate::parse_duration;
+
+use flate2::{write::GzEncoder, Compression};
+use reqwest::{header::HeaderMap, StatusCode};
+use serde::Deserialize;
+use std::{
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    runtime::Handle,
+    sync::mpsc::{self, error::TrySendError, Receiver, Sender},
+    time::Instant,
+};
+
+/// The number of logs that can wait to be batched before new ones are dropped
+const QUEUE_CAPACITY: usize = 16384;
+
+/// The number of batches that can wait to be delivered before new ones are spilled
+const BATCH_QUEUE_CAPACITY: usize = 64;
+
+/// Configures how logs are delivered to an HTTP backend. These fields are
+/// flattened into the configuration of each backend.
+#[derive(Deserialize)]
+pub struct DeliveryConfig {
+    /// The maximum number of logs sent in a single request. Logs in a batch are
+    /// separated by newlines. Defaults to 1, so logs are only batched if this is set.
+    #[serde(default = "default_max_batch_events")]
+    pub max_batch_events: usize,
+    /// The maximum time a log waits for its batch to fill up before being sent.
+    #[serde(default = "default_max_batch_delay")]
+    #[serde(deserialize_with = "parse_duration")]
+    pub max_batch_delay: Duration,
+    /// The number of times a failed request is retried before the batch is spilled
+    /// to disk (or dropped, if no spill directory is configured).
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// The wait before the first retry. It doubles after every attempt.
+    #[serde(default = "default_retry_backoff")]
+    #[serde(deserialize_with = "parse_duration")]
+    pub retry_backoff: Duration,
+    /// Compress request bodies with gzip
+    #[serde(default)]
+    pub gzip: bool,
+    /// A directory where batches that could not be delivered are written. They are
+    /// sent again, oldest first, when Plaid starts and once the backend accepts a request.
+    pub spill_directory: Option<PathBuf>,
+    /// The maximum number of bytes kept in the spill directory. Batches that do not fit are dropped.
+    #[serde(default = "default_max_spill_size")]
+    pub max_spill_size: u64,
+}
+
+fn default_max_batch_events() -> usize {
+    1
+}
+
+fn default_max_batch_delay() -> Duration {
+    Duration::from_millis(1000)
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_retry_backoff() -> Duration {
+    Duration::from_millis(500)
+}
+
+fn default_max_spill_size() -> u64 {
+    100 * 1024 * 1024
+}
+
+/// The endpoint a `Delivery` sends batches to
+pub struct Endpoint {
+    /// A name for the backend, used in errors and to separate spill directories
+    pub name: &'static str,
+    /// A reqwest client configured for the backend
+    pub client: reqwest::Client,
+    /// The URL batches are POSTed to
+    pub url: String,
+    /// Headers sent with every request, e.g., for authentication
+    pub headers: HeaderMap,
+}
+
+/// Accepts serialized logs and hands them to a background task that delivers them
+pub struct Delivery {
+    /// The name of the backend
+    name: &'static str,
+    /// Sends logs to the background task
+    sender: Sender<String>,
+}
+
+impl Delivery {
+    /// Start delivering logs to `endpoint` on the given runtime
+    pub fn new(endpoint: Endpoint, config: DeliveryConfig, handle: &Handle) -> Self {
+        let name = endpoint.name;
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        let (batch_sender, batch_receiver) = mpsc::channel(BATCH_QUEUE_CAPACITY);
+        let spill_directory = config
+            .spill_directory
+            .as_ref()
+            .map(|directory| directory.join(name));
+
+        let worker = Arc::new(Worker {
+            endpoint,
+            config,
+            spill_directory,
+        });
+        handle.spawn(worker.clone().deliver(batch_receiver));
+        handle.spawn(worker.batch(receiver, batch_sender));
+
+        Self { name, sender }
+    }
+
+    /// Queue a serialized log for delivery. This never waits on the backend.
+    pub fn send(&self, log: String) -> Result<(), LoggingError> {
+        self.sender.try_send(log).map_err(|e| match e {
+            TrySendError::Full(_) => LoggingError::CommunicationError(format!(
+                "{} delivery queue is full, dropping log",
+                self.name
+            )),
+            TrySendError::Closed(_) => LoggingError::LoggingSystemDead,
+        })
+    }
+}
+
+/// The outcome of trying to send a batch
+enum Attempt {
+    Delivered,
+    /// The backend may accept the batch if it is sent again later
+    Retryable(String),
+    /// The backend will never accept the batch
+    Rejected(String),
+}
+
+/// Batches logs and delivers them to an endpoint
+struct Worker {
+    endpoint: Endpoint,
+    config: DeliveryConfig,
+    /// Where this backend's undeliverable batches are written
+    spill_directory: Option<PathBuf>,
+}
+
+impl Worker {
+    /// Group logs into batches and queue them for delivery
+    async fn batch(self: Arc<Self>, mut receiver: Receiver<String>, batches: Sender<Vec<String>>) {
+        let mut batch = Vec::with_capacity(self.config.max_batch_events);
+        let mut deadline = Instant::now();
+
+        loop {
+            tokio::select! {
+                log = receiver.recv() => match log {
+                    Some(log) => {
+                        if batch.is_empty() {
+                            deadline = Instant::now() + self.config.max_batch_delay;
+                        }
+                        batch.push(log);
+                        if batch.len() >= self.config.max_batch_events {
+                            self.queue(&batches, std::mem::take(&mut batch)).await;
+                        }
+                    }
+                    None => {
+                        // The logging system is shutting down: send what we have
+                        if !batch.is_empty() && batches.send(batch).await.is_err() {
+                            error!("Could not queue the last logs for {}", self.endpoint.name);
+                        }
+                        return;
+                    }
+                },
+                _ = tokio::time::sleep_until(deadline), if !batch.is_empty() => {
+                    self.queue(&batches, std::mem::take(&mut batch)).await;
+                }
+            }
+        }
+    }
+
+    /// Queue a batch for delivery, or spill it if too many batches are already waiting
+    async fn queue(&self, batches: &Sender<Vec<String>>, batch: Vec<String>) {
+        match batches.try_send(batch) {
+            Ok(()) => (),
+            Err(TrySendError::Full(batch) | TrySendError::Closed(batch)) => {
+                self.spill(&batch.join("\n"), batch.len()).await
+            }
+        }
+    }
+
+    /// Deliver queued batches, starting with those spilled before Plaid last stopped
+    async fn deliver(self: Arc<Self>, mut batches: Receiver<Vec<String>>) {
+        self.replay_spilled().await;
+        while let Some(batch) = batches.recv().await {
+            self.flush(batch).await;
+        }
+    }
+
+    /// Deliver a batch, retrying with backoff. Batches that cannot be delivered are spilled.
+    async fn flush(&self, batch: Vec<String>) {
+        let body = batch.join("\n");
+        let mut backoff = self.config.retry_backoff;
+
+        for attempt in 0..=self.config.max_retries {
+            if attempt > 0 {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+
+            match self.post(&body).await {
+                Attempt::Delivered => {
+                    self.replay_spilled().await;
+                    return;
+                }
+                Attempt::Rejected(e) => {
+                    error!(
+                        "{} rejected {} logs, dropping them: {e}",
+                        self.endpoint.name,
+                        batch.len()
+                    );
+                    return;
+                }
+                Attempt::Retryable(e) => warn!(
+                    "Could not log to {} (attempt {} of {}): {e}",
+                    self.endpoint.name,
+                    attempt + 1,
+                    self.config.max_retries + 1
+                ),
+            }
+        }
+
+        self.spill(&body, batch.len()).await;
+    }
+
+    /// Send a single request with the given body
+    async fn post(&self, body: &str) -> Attempt {
+        let mut request = self
+            .endpoint
+            .client
+            .post(&self.endpoint.url)
+            .headers(self.endpoint.headers.clone());
+
+        request = if self.config.gzip {
+            let mut encoder = GzEncoder::new(vec![], Compression::default());
+            let compressed = encoder
+                .write_all(body.as_bytes())
+                .and_then(|_| encoder.finish());
+            match compressed {
+                Ok(compressed) => request.header("Content-Encoding", "gzip").body(compressed),
+                Err(e) => return Attempt::Rejected(format!("could not compress logs: {e}")),
+            }
+        } else {
+            request.body(body.to_string())
+        };
+
+        match request.send().await {
+            Ok(response) if response.status().is_success() => Attempt::Delivered,
+            Ok(response) => {
+                let status = response.status();
+                let error = format!(
+                    "status {status}: {}",
+                    response.text().await.unwrap_or_default()
+                );
+                if status.is_server_error()
+                    || status == StatusCode::TOO_MANY_REQUESTS
+                    || status == StatusCode::REQUEST_TIMEOUT
+                {
+                    Attempt::Retryable(error)
+                } else {
+                    Attempt::Rejected(error)
+                }
+            }
+            Err(e) => Attempt::Retryable(e.to_string()),
+        }
+    }
+
+    /// Write a batch that could not be delivered to the spill directory
+    async fn spill(&self, body: &str, count: usize) {
+        let Some(directory) = &self.spill_directory else {
+            error!(
+                "Could not log to {}, dropping {count} logs",
+                self.endpoint.name
+            );
+            return;
+        };
+        if let Err(e) = tokio::fs::create_dir_all(directory).await {
+            error!(
+                "Could not log to {} or create spill directory {}, dropping {count} logs: {e}",
+                self.endpoint.name,
+                directory.display()
+            );
+            return;
+        }
+
+        let mut used = 0;
+        for path in spilled_files(directory).await {
+            if let Ok(metadata) = tokio::fs::metadata(&path).await {
+                used += metadata.len();
+            }
+        }
+        if used + body.len() as u64 > self.config.max_spill_size {
+            error!(
+                "Could not log to {} and its spill directory is full, dropping {count} logs",
+                self.endpoint.name
+            );
+            return;
+        }
+
+        // Names sort in the order batches were spilled
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let path = directory.join(format!("{nanos:020}-{}.log", uuid::Uuid::new_v4()));
+        match tokio::fs::write(&path, body).await {
+            Ok(()) => warn!(
+                "Could not log to {}, spilled {count} logs to {}",
+                self.endpoint.name,
+                path.display()
+            ),
+            Err(e) => error!(
+                "Could not log to {} or spill {count} logs to {}: {e}",
+                self.endpoint.name,
+                path.display()
+            ),
+        }
+    }
+
+    /// Send the batches in the spill directory, oldest first, stopping at the first failure
+    async fn replay_spilled(&self) {
+        let Some(directory) = &self.spill_directory else {
+            return;
+        };
+
+        for path in spilled_files(directory).await {
+            let body = match tokio::fs::read_to_string(&path).await {
+                Ok(body) => body,
+                Err(e) => {
+                    error!("Could not read spilled logs {}: {e}", path.display());
+                    continue;
+                }
+            };
+
+            match self.post(&body).await {
+                Attempt::Delivered => info!(
+                    "Delivered spilled logs {} to {}",
+                    path.display(),
+                    self.endpoint.name
+                ),
+                Attempt::Rejected(e) => error!(
+                    "{} rejected spilled logs {}, deleting them: {e}",
+                    self.endpoint.name,
+                    path.display()
+                ),
+                Attempt::Retryable(_) => return,
+            }
+
+            if let Err(e) = tokio::fs::remove_file(&path).await {
+                error!("Could not delete spilled logs {}: {e}", path.display());
+            }
+        }
+    }
+}
+
+/// List the batches in a spill directory, oldest first. A directory that does not
+/// exist yet has no batches.
+async fn spilled_files(directory: &Path) -> Vec<PathBuf> {
+    let mut entries = match tokio::fs::read_dir(directory).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return vec![],
+        Err(e) => {
+            error!(
+                "Could not list spill directory {}: {e}",
+                directory.display()
+            );
+            return vec![];
+        }
+    };
+
+    let mut files = vec![];
+    loop {
+        match entries.next_entry().await {
+            Ok(Some(entry)) => {
+                let path = entry.path();
+                if path.extension().is_some_and(|ext| ext == "log") {
+                    files.push(path);
+                }
+            }
+            Ok(None) => break,
+            Err(e) => {
+                error!(
+                    "Could not list spill directory {}: {e}",
+                    directory.display()
+                );
+                break;
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config(spill_directory: &Path) -> DeliveryConfig {
+        toml::from_str(&format!(
+            r#"
+            max_batch_events = 2
+            max_batch_delay = 10
+            max_retries = 1
+            retry_backoff = 1
+            gzip = true
+            spill_directory = "{}"
+            "#,
+            spill_directory.display()
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn spill_undeliverable_batch() {
+        let directory = std::env::temp_dir().join(format!("plaid-spill-{}", uuid::Uuid::new_v4()));
+        let endpoint = Endpoint {
+            name: "test",
+            client: reqwest::Client::new(),
+            // Nothing listens on this port so every attempt fails
+            url: "http://127.0.0.1:1".to_string(),
+            headers: HeaderMap::new(),
+        };
+        let delivery = Delivery::new(endpoint, test_config(&directory), &Handle::current());
+
+        delivery.send("first".to_string()).unwrap();
+        delivery.send("second".to_string()).unwrap();
+
+        let spill_directory = directory.join("test");
+        let mut spilled = vec![];
+        for _ in 0..100 {
+            spilled = spilled_files(&spill_directory).await;
+            if !spilled.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        assert_eq!(spilled.len(), 1);
+        assert_eq!(
+            std::fs::read_to_string(&spilled[0]).unwrap(),
+            "first\nsecond"
+        );
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn replay_spilled_batches_on_start() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let directory = std::env::temp_dir().join(format!("plaid-spill-{}", uuid::Uuid::new_v4()));
+        let spill_directory = directory.join("test");
+        std::fs::create_dir_all(&spill_directory).unwrap();
+        std::fs::write(spill_directory.join("1-spilled.log"), "spilled").unwrap();
+
+        // Accept a single request and report what was sent
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let read = stream.read(&mut request).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&request[..read]).into_owned()
+        });
+
+        let endpoint = Endpoint {
+            name: "test",
+            client: reqwest::Client::new(),
+            url,
+            headers: HeaderMap::new(),
+        };
+        let mut config = test_config(&directory);
+        config.gzip = false;
+        let _delivery = Delivery::new(endpoint, config, &Handle::current());
+
+        let request = tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(request.ends_with("\r\n\r\nspilled"));
+        for _ in 0..100 {
+            if spilled_files(&spill_directory).await.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert!(spilled_files(&spill_directory).await.is_empty());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
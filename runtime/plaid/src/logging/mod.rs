@@ -1,12 +1,15 @@
+mod delivery;
 mod splunk;
 mod webhook;
 
 mod stdout;
 
-use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender};
+use crossbeam_channel::{bounded, unbounded, Receiver, RecvTimeoutError, Sender};
 
+use plaid_stl::plaid::log::{Level, ModuleLog};
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
     thread::{self, JoinHandle},
     time::Duration,
 };
@@ -48,6 +51,16 @@ pub enum Log {
     WebSocketConnectionDropped {
         socket_name: String,
     },
+    /// A log written by a module through the logging host API
+    ModuleLog {
+        module: String,
+        /// The ID of the message the module was running on
+        message_id: String,
+        log_type: String,
+        level: Level,
+        message: String,
+        fields: BTreeMap<String, String>,
+    },
     /// Is not used by other components of Plaid. This is created and sent
     /// by the logging system if it has not received a message from the server
     /// module for a period of time.
@@ -183,6 +196,25 @@ impl Logger {
             .map_err(|_| LoggingError::LoggingSystemDead)
     }
 
+    pub fn log_module_message(
+        &self,
+        module: String,
+        message_id: String,
+        log_type: String,
+        log: ModuleLog,
+    ) -> Result<(), LoggingError> {
+        self.sender
+            .send(Log::ModuleLog {
+                module,
+                message_id,
+                log_type,
+                level: log.level,
+                message: log.message,
+                fields: log.fields,
+            })
+            .map_err(|_| LoggingError::LoggingSystemDead)
+    }
+
     pub fn log_websocket_dropped(&self, socket_name: String) -> Result<(), LoggingError> {
         self.sender
             .send(Log::WebSocketConnectionDropped { socket_name })
@@ -280,4 +312,17 @@ impl Logger {
             _handle,
         )
     }
+
+    /// Create a logger that delivers logs to the returned receiver instead of
+    /// to logging backends. This is used when testing modules.
+    pub fn to_channel(show_log_on_error: bool) -> (Self, Receiver<Log>) {
+        let (sender, rx) = unbounded();
+        (
+            Self {
+                sender,
+                show_log_on_error,
+            },
+            rx,
+        )
+    }
 }
@@ -1,7 +1,11 @@
 //! This module provides a way for Plaid to log to Splunk.
 
-use super::{LoggingError, PlaidLogger, WrappedLog};
+use super::{
+    delivery::{Delivery, DeliveryConfig, Endpoint},
+    LoggingError, PlaidLogger, WrappedLog,
+};
 
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
@@ -14,19 +18,17 @@ pub struct Config {
     pub token: String,
     pub url: String,
     pub timeout: u8,
+    /// How logs are batched, retried and buffered. Batches use the HEC
+    /// format of concatenated events.
+    #[serde(flatten)]
+    pub delivery: DeliveryConfig,
 }
 
 /// The Splunk specific logger that is configured from the Splunk
 /// `Config` struct.
 pub struct SplunkLogger {
-    /// A tokio runtime to send logs on
-    runtime: Handle,
-    /// A reqwest client configured with the Splunk endpoint and authentication
-    client: reqwest::Client,
-    /// An API token to send with our logs for authentication
-    token: String,
-    /// The endpoint to send the logs to
-    url: String,
+    /// Batches logs and sends them to the HEC endpoint in the background
+    delivery: Delivery,
 }
 
 /// Splunk needs it in the format of the whole log within the event key
@@ -52,38 +54,42 @@ impl SplunkLogger {
             .build()
             .unwrap();
 
-        Self {
-            runtime: handle,
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        match HeaderValue::from_str(&format!("Splunk {}", config.token)) {
+            Ok(auth) => {
+                headers.insert(AUTHORIZATION, auth);
+            }
+            Err(e) => error!("Invalid token for the Splunk logger: {e}"),
+        }
+
+        let endpoint = Endpoint {
+            name: "Splunk",
             client,
-            token: config.token.clone(),
-            url: config.url.clone(),
+            url: config.url,
+            headers,
+        };
+
+        Self {
+            delivery: Delivery::new(endpoint, config.delivery, &handle),
         }
     }
 }
 
 impl PlaidLogger for SplunkLogger {
-    /// Send a log to Splunk via an HEC endpoint. This function uses a tokio
-    /// runtime within the SplunkLogger type. This means that sending a log
+    /// Queue a log for Splunk's HEC endpoint. Logs are delivered by a task on
+    /// the tokio runtime passed to the SplunkLogger. This means that sending a log
     /// will not block sending logs to other services (like stdout) but it
-    /// does mean we cannot return a proper LoggingError to the caller since
-    /// we cannot wait for it to complete.
+    /// does mean we cannot return a proper LoggingError to the caller if
+    /// delivery eventually fails.
     fn send_log(&self, log: &WrappedLog) -> Result<(), LoggingError> {
         let splunk_log = SplunkLogWrapper { event: log };
 
-        let res = self
-            .client
-            .post(&self.url)
-            .header("Authorization", format!("Splunk {}", &self.token))
-            .header("Content-Type", "application/json")
-            .json(&splunk_log);
-
-        self.runtime.spawn(async move {
-            match res.send().await {
-                Ok(_) => (),
-                Err(e) => error!("Could not log to Splunk: {}", e.to_string()),
-            };
-        });
+        let data = match serde_json::to_string(&splunk_log) {
+            Ok(json) => json,
+            Err(e) => return Err(LoggingError::SerializationError(e.to_string())),
+        };
 
-        Ok(())
+        self.delivery.send(data)
     }
 }
@@ -2,6 +2,7 @@
 
 use super::{Log, LoggingError, PlaidLogger, Severity, WrappedLog};
 
+use plaid_stl::plaid::log::Level;
 use serde::Deserialize;
 
 #[derive(Deserialize)]
@@ -40,6 +41,29 @@ impl PlaidLogger for StdoutLogger {
             Log::WebSocketConnectionDropped { socket_name } => {
                 warn!("Connection to socket: {socket_name} dropped unexpectedly");
             }
+            Log::ModuleLog {
+                module,
+                message_id,
+                log_type,
+                level,
+                message,
+                fields,
+            } => {
+                let level = match level {
+                    Level::Debug => log::Level::Debug,
+                    Level::Info => log::Level::Info,
+                    Level::Warn => log::Level::Warn,
+                    Level::Error => log::Level::Error,
+                };
+                let fields: String = fields
+                    .iter()
+                    .map(|(key, value)| format!(" {key}={value}"))
+                    .collect();
+                log!(
+                    level,
+                    "[{module}] {message}{fields} (message [{message_id}] of type [{log_type}])"
+                );
+            }
             Log::Heartbeat { .. } => (),
         }
         Ok(())
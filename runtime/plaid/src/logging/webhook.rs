@@ -1,7 +1,11 @@
 //! This module provides a way for Plaid to log to an arbitrary webhook.
 
-use super::{LoggingError, PlaidLogger, WrappedLog};
+use super::{
+    delivery::{Delivery, DeliveryConfig, Endpoint},
+    LoggingError, PlaidLogger, WrappedLog,
+};
 
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
 use serde::Deserialize;
 use std::time::Duration;
 
@@ -14,22 +18,23 @@ pub struct Config {
     pub auth_header: Option<String>,
     pub url: String,
     pub timeout: u8,
+    /// How logs are batched, retried and buffered. Each request carries a single log
+    /// unless `max_batch_events` is set, in which case batches are sent as newline
+    /// delimited JSON.
+    #[serde(flatten)]
+    pub delivery: DeliveryConfig,
 }
 
 /// The specific logger that is configured from the `Config` struct.
 pub struct WebhookLogger {
-    /// A tokio runtime to send logs on
-    runtime: Handle,
-    /// A reqwest client configured with the Splunk endpoint and authentication
-    client: reqwest::Client,
-    /// The configuration struct
-    config: Config,
+    /// Batches logs and sends them to the webhook in the background
+    delivery: Delivery,
 }
 
 impl WebhookLogger {
-    /// Implement the new function for the Splunk logger. This converts
+    /// Implement the new function for the webhook logger. This converts
     /// the configuration struct into a type that can handle sending
-    /// logs directly to a Splunk HEC endpoint.
+    /// logs to the configured endpoint.
     pub fn new(config: Config, handle: Handle) -> Self {
         // I don't think this can fail with our settings so we do an unwrap
         let client = reqwest::Client::builder()
@@ -37,45 +42,46 @@ impl WebhookLogger {
             .build()
             .unwrap();
 
-        Self {
-            runtime: handle,
+        let content_type = if config.delivery.max_batch_events > 1 {
+            "application/x-ndjson"
+        } else {
+            "application/x-www-form-urlencoded"
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+        if let Some(auth) = &config.auth_header {
+            match HeaderValue::from_str(auth) {
+                Ok(auth) => {
+                    headers.insert(AUTHORIZATION, auth);
+                }
+                Err(e) => error!("Invalid auth_header for the webhook logger: {e}"),
+            }
+        }
+
+        let endpoint = Endpoint {
+            name: "webhook",
             client,
-            config,
+            url: config.url,
+            headers,
+        };
+
+        Self {
+            delivery: Delivery::new(endpoint, config.delivery, &handle),
         }
     }
 }
 
 impl PlaidLogger for WebhookLogger {
-    /// Send a log to the webhook. Sending a log
+    /// Queue a log for the webhook. Sending a log
     /// will not block sending logs to other services (like stdout) but it
-    /// does mean we cannot return a proper LoggingError to the caller since
-    /// we cannot wait for it to complete.
+    /// does mean we cannot return a proper LoggingError to the caller if
+    /// delivery eventually fails.
     fn send_log(&self, log: &WrappedLog) -> Result<(), LoggingError> {
         let data = match serde_json::to_string(&log) {
             Ok(json) => json,
             Err(e) => return Err(LoggingError::SerializationError(e.to_string())),
         };
 
-        let res = self
-            .client
-            .post(&self.config.url)
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .header("Content-Length", data.len())
-            .body(data);
-
-        let res = if let Some(auth) = &self.config.auth_header {
-            res.header("Authorization", auth)
-        } else {
-            res
-        };
-
-        self.runtime.spawn(async move {
-            match res.send().await {
-                Ok(_) => (),
-                Err(e) => error!("Could not log to webhook: {}", e.to_string()),
-            };
-        });
-
-        Ok(())
+        self.delivery.send(data)
     }
 }
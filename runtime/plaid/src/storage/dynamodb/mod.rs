@@ -5,25 +5,31 @@ use std::collections::HashMap;
 use async_trait::async_trait;
 
 use aws_sdk_dynamodb::{
-    types::{AttributeValue, KeyType},
+    types::{AttributeValue, KeyType, ReturnValue, TimeToLiveStatus},
     Client,
 };
 use serde::Deserialize;
 
 use crate::{get_aws_sdk_config, AwsAuthentication};
 
-use super::{StorageError, StorageProvider};
+use super::{expiry::now, StorageError, StorageProvider};
 
 const NAMESPACE: &str = "namespace";
 const KEY: &str = "key";
 const VALUE: &str = "value";
+/// The Unix time, in seconds, at which a key inserted with a TTL expires.
+/// This should be set as the table's TTL attribute.
+const EXPIRES_AT: &str = "expires_at";
 
 /// Configuration for DynamoDB
 #[derive(Deserialize)]
 pub struct Config {
     /// How to authenticate to AWS
     pub authentication: AwsAuthentication,
-    /// The name of DynamoDB table used for Plaid's DB
+    /// The name of DynamoDB table used for Plaid's DB. Enable TTL on the table's `expires_at`
+    /// attribute: Plaid does not sweep DynamoDB for expired keys, and relies on DynamoDB to
+    /// delete them. Expired keys are absent as soon as they expire, and their bytes are released
+    /// from storage usage when it is next recomputed, every `expiry_sweep_interval` seconds.
     pub table_name: String,
 }
 
@@ -40,6 +46,7 @@ impl DynamoDb {
 
         // Perform schema validation
         Self::validate_schema(&client, &config.table_name).await?;
+        Self::check_ttl(&client, &config.table_name).await;
 
         Ok(Self {
             client,
@@ -75,6 +82,40 @@ impl DynamoDb {
 
         Ok(())
     }
+
+    /// Warn if DynamoDB is not deleting expired keys on its own. Without TTL enabled on the
+    /// table, expired keys are never deleted.
+    async fn check_ttl(client: &Client, table_name: &str) {
+        let enabled = client
+            .describe_time_to_live()
+            .table_name(table_name)
+            .send()
+            .await
+            .ok()
+            .and_then(|resp| resp.time_to_live_description)
+            .is_some_and(|ttl| {
+                ttl.attribute_name() == Some(EXPIRES_AT)
+                    && ttl.time_to_live_status() == Some(&TimeToLiveStatus::Enabled)
+            });
+        if !enabled {
+            warn!("TTL is not enabled on the [{EXPIRES_AT}] attribute of DynamoDB table [{table_name}]");
+        }
+    }
+}
+
+/// Whether an item expired at or before `now`
+fn has_expired(item: &HashMap<String, AttributeValue>, now: u64) -> bool {
+    item.get(EXPIRES_AT)
+        .and_then(|v| v.as_n().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .is_some_and(|e| e <= now)
+}
+
+/// Get the value of an item, unless it expired at or before `now`. DynamoDB can take a while
+/// to delete expired items, which are absent until then.
+fn live_value(item: Option<HashMap<String, AttributeValue>>, now: u64) -> Option<Vec<u8>> {
+    item.filter(|item| !has_expired(item, now))
+        .and_then(|item| Some(item.get(VALUE)?.as_b().ok()?.clone().into_inner()))
 }
 
 #[async_trait]
@@ -83,6 +124,10 @@ impl StorageProvider for DynamoDb {
         true
     }
 
+    fn deletes_expired_keys(&self) -> bool {
+        true
+    }
+
     async fn insert(
         &self,
         namespace: String,
@@ -101,10 +146,30 @@ impl StorageProvider for DynamoDb {
             .await
             .map_err(|e| StorageError::Access(format!("Could not insert to storage: {e}")))?;
         // Return the previous entry, if any
-        Ok(response
-            .attributes
-            .and_then(|attr| Some(attr.get(VALUE)?.as_b().ok()?.clone()))
-            .and_then(|v| Some(v.into_inner())))
+        Ok(live_value(response.attributes, now()))
+    }
+
+    async fn insert_with_ttl(
+        &self,
+        namespace: String,
+        key: String,
+        value: Vec<u8>,
+        expires_at: u64,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        let response = self
+            .client
+            .put_item()
+            .table_name(&self.table_name)
+            .item(NAMESPACE, AttributeValue::S(namespace))
+            .item(KEY, AttributeValue::S(key))
+            .item(VALUE, AttributeValue::B(value.into()))
+            .item(EXPIRES_AT, AttributeValue::N(expires_at.to_string()))
+            .return_values(ReturnValue::AllOld)
+            .send()
+            .await
+            .map_err(|e| StorageError::Access(format!("Could not insert to storage: {e}")))?;
+        // Return the previous entry, if any
+        Ok(live_value(response.attributes, now()))
     }
 
     async fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
@@ -118,10 +183,24 @@ impl StorageProvider for DynamoDb {
             .send()
             .await
             .map_err(|e| StorageError::Access(format!("Could not get from storage: {e}")))?;
+        Ok(live_value(response.item, now()))
+    }
+
+    async fn get_expiry(&self, namespace: &str, key: &str) -> Result<Option<u64>, StorageError> {
+        let response = self
+            .client
+            .get_item()
+            .consistent_read(true)
+            .table_name(&self.table_name)
+            .key(NAMESPACE, AttributeValue::S(namespace.to_string()))
+            .key(KEY, AttributeValue::S(key.to_string()))
+            .projection_expression(EXPIRES_AT)
+            .send()
+            .await
+            .map_err(|e| StorageError::Access(format!("Could not get from storage: {e}")))?;
         Ok(response
             .item
-            .and_then(|attr| Some(attr.get(VALUE)?.as_b().ok()?.clone()))
-            .and_then(|v| Some(v.into_inner())))
+            .and_then(|item| item.get(EXPIRES_AT)?.as_n().ok()?.parse::<u64>().ok()))
     }
 
     async fn delete(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
@@ -141,6 +220,49 @@ impl StorageProvider for DynamoDb {
             .and_then(|v| Some(v.into_inner())))
     }
 
+    async fn compare_and_swap(
+        &self,
+        namespace: &str,
+        key: &str,
+        expected: Option<Vec<u8>>,
+        value: Vec<u8>,
+    ) -> Result<bool, StorageError> {
+        let mut request = self
+            .client
+            .put_item()
+            .table_name(&self.table_name)
+            .item(NAMESPACE, AttributeValue::S(namespace.to_string()))
+            .item(KEY, AttributeValue::S(key.to_string()))
+            .item(VALUE, AttributeValue::B(value.into()));
+        // The write only goes through if the item is as expected when DynamoDB applies it.
+        // An expired item counts as absent.
+        request = request
+            .expression_attribute_names("#e", EXPIRES_AT)
+            .expression_attribute_values(":now", AttributeValue::N(now().to_string()));
+        request = match expected {
+            None => request
+                .condition_expression("attribute_not_exists(#k) OR #e <= :now")
+                .expression_attribute_names("#k", KEY),
+            Some(expected) => request
+                .condition_expression("#v = :expected AND (attribute_not_exists(#e) OR #e > :now)")
+                .expression_attribute_names("#v", VALUE)
+                .expression_attribute_values(":expected", AttributeValue::B(expected.into())),
+        };
+
+        match request.send().await {
+            Ok(_) => Ok(true),
+            Err(e)
+                if e.as_service_error()
+                    .is_some_and(|e| e.is_conditional_check_failed_exception()) =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(StorageError::Access(format!(
+                "Could not insert to storage: {e}"
+            ))),
+        }
+    }
+
     async fn list_keys(
         &self,
         namespace: &str,
@@ -153,12 +275,13 @@ impl StorageProvider for DynamoDb {
             &self.table_name,
             namespace,
             prefix,
-            vec![KEY].as_slice(),
+            vec![KEY, EXPIRES_AT].as_slice(),
         )
         .await?;
 
-        // Add retrieved items to our growing list
-        for item in &items {
+        // Add retrieved items to our growing list. Expired items are absent.
+        let now = now();
+        for item in items.iter().filter(|item| !has_expired(item, now)) {
             all_keys.push(
                 item.get(KEY)
                     .ok_or(StorageError::Access(
@@ -187,12 +310,13 @@ impl StorageProvider for DynamoDb {
             &self.table_name,
             namespace,
             prefix,
-            vec!["key", "value"].as_slice(),
+            vec![KEY, VALUE, EXPIRES_AT].as_slice(),
         )
         .await?;
 
-        // Add retrieved items to our growing list
-        for item in &items {
+        // Add retrieved items to our growing list. Expired items are absent.
+        let now = now();
+        for item in items.iter().filter(|item| !has_expired(item, now)) {
             let key = item
                 .get(KEY)
                 .ok_or(StorageError::Access(
@@ -218,6 +342,49 @@ impl StorageProvider for DynamoDb {
 
         Ok(everything)
     }
+
+    async fn expired_keys(&self, _now: u64) -> Result<Vec<(String, String)>, StorageError> {
+        // DynamoDB deletes expired items itself, so they are never swept. Finding them would
+        // take a scan of the whole table.
+        Ok(vec![])
+    }
+
+    async fn delete_expired(
+        &self,
+        namespace: &str,
+        key: &str,
+        now: u64,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        let response = self
+            .client
+            .delete_item()
+            .table_name(&self.table_name)
+            .key(NAMESPACE, AttributeValue::S(namespace.to_string()))
+            .key(KEY, AttributeValue::S(key.to_string()))
+            // The key may have been inserted again since it was listed as expired
+            .condition_expression("#e <= :now")
+            .expression_attribute_names("#e", EXPIRES_AT)
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .return_values(ReturnValue::AllOld)
+            .send()
+            .await;
+
+        match response {
+            Ok(response) => Ok(response
+                .attributes
+                .and_then(|attr| Some(attr.get(VALUE)?.as_b().ok()?.clone()))
+                .map(|v| v.into_inner())),
+            Err(e)
+                if e.as_service_error()
+                    .is_some_and(|e| e.is_conditional_check_failed_exception()) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(StorageError::Access(format!(
+                "Could not delete from storage: {e}"
+            ))),
+        }
+    }
 }
 
 /// Perform a query on DynamoDB.
@@ -330,3 +497,32 @@ async fn dynamodb_query(
 
     Ok(output)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expired_items_are_absent() {
+        let item = |expires_at: Option<u64>| {
+            let mut item = HashMap::from([
+                (KEY.to_string(), AttributeValue::S("key".to_string())),
+                (VALUE.to_string(), AttributeValue::B(vec![1].into())),
+            ]);
+            if let Some(expires_at) = expires_at {
+                item.insert(
+                    EXPIRES_AT.to_string(),
+                    AttributeValue::N(expires_at.to_string()),
+                );
+            }
+            item
+        };
+
+        assert_eq!(live_value(Some(item(None)), 100), Some(vec![1]));
+        assert_eq!(live_value(Some(item(Some(101))), 100), Some(vec![1]));
+        assert_eq!(live_value(Some(item(Some(100))), 100), None);
+        assert_eq!(live_value(None, 100), None);
+        assert!(has_expired(&item(Some(99)), 100));
+        assert!(!has_expired(&item(None), 100));
+    }
+}
@@ -0,0 +1,274 @@
+//! Keys inserted with a TTL are treated as absent once they expire, and deleted by a background
+//! sweeper. The bytes they used are released from the storage usage of the module or shared DB
+//! that owns them. Storage providers that delete expired keys on their own are not swept:
+//! instead, the storage usage of every module and shared DB is periodically recomputed from
+//! what is stored, which releases the bytes of the keys the provider deleted.
+
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::runtime::Handle;
+use tokio_util::sync::CancellationToken;
+
+use crate::loader::{LimitValue, PlaidModules};
+
+use super::{Storage, StorageError};
+
+/// The current Unix time, in seconds
+pub(super) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl Storage {
+    /// Delete every key that has expired and return how many were deleted.
+    ///
+    /// `module_usage` gives the storage limit and usage counter of the module owning a namespace,
+    /// if any. The counter is held while a key is deleted, so this blocks on `handle` and must
+    /// not be called from within the async runtime.
+    pub fn sweep_expired(
+        &self,
+        handle: &Handle,
+        module_usage: impl Fn(&str) -> Option<(LimitValue, Arc<RwLock<u64>>)>,
+    ) -> Result<usize, StorageError> {
+        let now = now();
+        let expired = handle.block_on(self.database.expired_keys(now))?;
+
+        let mut deleted = 0;
+        for (namespace, key) in expired {
+            let usage = match self.shared_dbs.as_ref().and_then(|dbs| dbs.get(&namespace)) {
+                Some(db) => Some((db.config.size_limit.clone(), db.used_storage.clone())),
+                None => module_usage(&namespace),
+            };
+
+            let result = match usage {
+                Some((LimitValue::Limited(_), counter)) => {
+                    // Hold the counter like inserts and deletes do, so that the key cannot
+                    // be written between being deleted and released
+                    let mut storage_current = match counter.write() {
+                        Ok(g) => g,
+                        Err(e) => {
+                            error!("Critical error getting a lock on used storage: {:?}", e);
+                            continue;
+                        }
+                    };
+                    let result =
+                        handle.block_on(self.database.delete_expired(&namespace, &key, now));
+                    if let Ok(Some(ref data)) = result {
+                        *storage_current =
+                            storage_current.saturating_sub(key.len() as u64 + data.len() as u64);
+                    }
+                    result
+                }
+                // Usage is not tracked for namespaces without a limit
+                _ => handle.block_on(self.database.delete_expired(&namespace, &key, now)),
+            };
+
+            match result {
+                Ok(Some(_)) => deleted += 1,
+                Ok(None) => (),
+                Err(e) => error!("Could not delete expired key [{key}] in [{namespace}]: {e}"),
+            }
+        }
+
+        Ok(deleted)
+    }
+
+    /// Recompute the storage usage of every namespace with a storage limit from what is stored
+    /// in it, and return how many bytes were released.
+    ///
+    /// `module_usage` gives the name, storage limit and usage counter of every module. Each counter
+    /// is held while its namespace is measured, so this blocks on `handle` and must not be called
+    /// from within the async runtime.
+    pub fn recompute_usage(
+        &self,
+        handle: &Handle,
+        module_usage: impl IntoIterator<Item = (String, LimitValue, Arc<RwLock<u64>>)>,
+    ) -> u64 {
+        let shared_db_usage = self.shared_dbs.iter().flatten().map(|(name, db)| {
+            (
+                name.clone(),
+                db.config.size_limit.clone(),
+                db.used_storage.clone(),
+            )
+        });
+
+        let mut released = 0;
+        for (namespace, limit, counter) in module_usage.into_iter().chain(shared_db_usage) {
+            // Usage is not tracked for namespaces without a limit
+            if !matches!(limit, LimitValue::Limited(_)) {
+                continue;
+            }
+            // Hold the counter like inserts and deletes do, so that the namespace cannot
+            // be written while it is measured
+            let mut storage_current = match counter.write() {
+                Ok(g) => g,
+                Err(e) => {
+                    error!("Critical error getting a lock on used storage: {:?}", e);
+                    continue;
+                }
+            };
+            match handle.block_on(self.database.get_namespace_byte_size(&namespace)) {
+                Ok(size) => {
+                    released += storage_current.saturating_sub(size);
+                    *storage_current = size;
+                }
+                Err(e) => error!("Could not recompute the storage usage of [{namespace}]: {e}"),
+            }
+        }
+
+        released
+    }
+}
+
+/// Sweep expired keys every `storage.expiry_sweep_interval` seconds. If the storage provider
+/// deletes expired keys itself, storage usage is recomputed at that interval instead.
+/// Returns once `cancellation_token` is cancelled.
+pub async fn sweep_expired_keys(
+    storage: Arc<Storage>,
+    modules: Arc<PlaidModules>,
+    cancellation_token: CancellationToken,
+) {
+    if !storage.sweeps_expired_keys() {
+        info!("The storage provider deletes expired keys itself, so storage usage is recomputed instead of sweeping them");
+    }
+
+    let interval = Duration::from_secs(storage.expiry_sweep_interval);
+    loop {
+        tokio::select! {
+            _ = cancellation_token.cancelled() => {
+                return;
+            }
+
+            _ = tokio::time::sleep(interval) => {}
+        }
+
+        if !storage.sweeps_expired_keys() {
+            let storage = storage.clone();
+            let modules = modules.clone();
+            let handle = Handle::current();
+            let result = tokio::task::spawn_blocking(move || {
+                let module_usage = modules.get_modules().into_values().map(|module| {
+                    (
+                        module.name.clone(),
+                        module.storage_limit.clone(),
+                        module.storage_current.clone(),
+                    )
+                });
+                storage.recompute_usage(&handle, module_usage)
+            })
+            .await;
+
+            match result {
+                Ok(0) => (),
+                Ok(released) => {
+                    debug!("Released {released} byte(s) of expired keys from storage usage")
+                }
+                Err(e) => error!("Storage usage recompute task failed: {e}"),
+            }
+            continue;
+        }
+
+        let storage = storage.clone();
+        let modules = modules.clone();
+        let handle = Handle::current();
+        let result = tokio::task::spawn_blocking(move || {
+            storage.sweep_expired(&handle, |namespace| {
+                modules
+                    .get_module(namespace)
+                    .map(|module| (module.storage_limit.clone(), module.storage_current.clone()))
+            })
+        })
+        .await;
+
+        match result {
+            Ok(Ok(0)) => (),
+            Ok(Ok(deleted)) => debug!("Deleted {deleted} expired key(s) from storage"),
+            Ok(Err(e)) => error!("Could not sweep expired keys from storage: {e}"),
+            Err(e) => error!("Expired key sweep task failed: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expired_keys_are_deleted_and_released() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let storage = Storage::new_in_memory();
+        let counter = Arc::new(RwLock::new(0));
+
+        runtime.block_on(async {
+            storage
+                .insert_with_ttl("a.wasm".to_string(), "old".to_string(), vec![0; 10], 0)
+                .await
+                .unwrap();
+            storage
+                .insert_with_ttl("a.wasm".to_string(), "new".to_string(), vec![0; 10], 3600)
+                .await
+                .unwrap();
+            // Inserting a key again without a TTL removes its expiry
+            storage
+                .insert_with_ttl("a.wasm".to_string(), "kept".to_string(), vec![0; 10], 0)
+                .await
+                .unwrap();
+            storage
+                .insert("a.wasm".to_string(), "kept".to_string(), vec![0; 10])
+                .await
+                .unwrap();
+        });
+        // Each key is counted with its value
+        *counter.write().unwrap() = 13 + 13 + 14;
+
+        let deleted = storage
+            .sweep_expired(runtime.handle(), |_| {
+                Some((LimitValue::Limited(1000), counter.clone()))
+            })
+            .unwrap();
+
+        assert_eq!(deleted, 1);
+        assert_eq!(*counter.read().unwrap(), 13 + 14);
+        let mut keys = runtime.block_on(storage.list_keys("a.wasm", None)).unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["kept", "new"]);
+    }
+
+    #[test]
+    fn usage_is_recomputed() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let storage = Storage::new_in_memory();
+        let limited = Arc::new(RwLock::new(100));
+        let unlimited = Arc::new(RwLock::new(100));
+
+        runtime.block_on(async {
+            storage
+                .insert("a.wasm".to_string(), "key".to_string(), vec![0; 10])
+                .await
+                .unwrap();
+        });
+
+        let released = storage.recompute_usage(
+            runtime.handle(),
+            [
+                (
+                    "a.wasm".to_string(),
+                    LimitValue::Limited(1000),
+                    limited.clone(),
+                ),
+                (
+                    "b.wasm".to_string(),
+                    LimitValue::Unlimited,
+                    unlimited.clone(),
+                ),
+            ],
+        );
+
+        assert_eq!(released, 100 - 13);
+        assert_eq!(*limited.read().unwrap(), 13);
+        assert_eq!(*unlimited.read().unwrap(), 100);
+    }
+}
@@ -1,12 +1,28 @@
 //! This module provides a way for Plaid to use an in-memory store as a DB. Note - This storage is not persisted across reboots.
 
-use super::{StorageError, StorageProvider};
+use super::{expiry::now, StorageError, StorageProvider};
 use async_trait::async_trait;
 use std::{collections::HashMap, sync::Arc};
 use tokio::sync::RwLock;
 
+/// A value and, if it was inserted with a TTL, the Unix time at which it expires
+struct Entry {
+    value: Vec<u8>,
+    expires_at: Option<u64>,
+}
+
+impl Entry {
+    /// The value, unless it expired at or before `now`
+    fn live_value(&self, now: u64) -> Option<&Vec<u8>> {
+        match self.expires_at {
+            Some(expires_at) if expires_at <= now => None,
+            _ => Some(&self.value),
+        }
+    }
+}
+
 pub struct InMemoryDb {
-    db: Arc<RwLock<HashMap<String, HashMap<String, Vec<u8>>>>>,
+    db: Arc<RwLock<HashMap<String, HashMap<String, Entry>>>>,
 }
 
 impl InMemoryDb {
@@ -15,6 +31,22 @@ impl InMemoryDb {
             db: Arc::new(RwLock::new(HashMap::new())),
         })
     }
+
+    async fn insert_entry(
+        &self,
+        namespace: String,
+        key: String,
+        entry: Entry,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        let mut db = self.db.write().await;
+        let ns = db.entry(namespace).or_default();
+        // An expired value is absent, so it is not returned
+        let now = now();
+        Ok(ns
+            .insert(key, entry)
+            .filter(|old| old.live_value(now).is_some())
+            .map(|old| old.value))
+    }
 }
 
 #[async_trait]
@@ -29,39 +61,88 @@ impl StorageProvider for InMemoryDb {
         key: String,
         value: Vec<u8>,
     ) -> Result<Option<Vec<u8>>, StorageError> {
-        let mut db = self.db.write().await;
-        let ns = db.entry(namespace).or_default();
-        Ok(ns.insert(key, value))
+        let entry = Entry {
+            value,
+            expires_at: None,
+        };
+        self.insert_entry(namespace, key, entry).await
+    }
+
+    async fn insert_with_ttl(
+        &self,
+        namespace: String,
+        key: String,
+        value: Vec<u8>,
+        expires_at: u64,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        let entry = Entry {
+            value,
+            expires_at: Some(expires_at),
+        };
+        self.insert_entry(namespace, key, entry).await
     }
 
     async fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
         let db = self.db.read().await;
-        Ok(db.get(namespace).and_then(|ns| ns.get(key).cloned()))
+        Ok(db
+            .get(namespace)
+            .and_then(|ns| ns.get(key)?.live_value(now()).cloned()))
+    }
+
+    async fn get_expiry(&self, namespace: &str, key: &str) -> Result<Option<u64>, StorageError> {
+        let db = self.db.read().await;
+        Ok(db.get(namespace).and_then(|ns| ns.get(key)?.expires_at))
     }
 
     async fn delete(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
         let mut db = self.db.write().await;
         if let Some(ns) = db.get_mut(namespace) {
-            Ok(ns.remove(key))
+            Ok(ns.remove(key).map(|entry| entry.value))
         } else {
             Ok(None)
         }
     }
 
+    async fn compare_and_swap(
+        &self,
+        namespace: &str,
+        key: &str,
+        expected: Option<Vec<u8>>,
+        value: Vec<u8>,
+    ) -> Result<bool, StorageError> {
+        // Holding the write lock makes the comparison and the swap atomic
+        let mut db = self.db.write().await;
+        let ns = db.entry(namespace.to_string()).or_default();
+        if ns.get(key).and_then(|entry| entry.live_value(now())) != expected.as_ref() {
+            return Ok(false);
+        }
+        ns.insert(
+            key.to_string(),
+            Entry {
+                value,
+                expires_at: None,
+            },
+        );
+        Ok(true)
+    }
+
     async fn list_keys(
         &self,
         namespace: &str,
         prefix: Option<&str>,
     ) -> Result<Vec<String>, StorageError> {
+        let now = now();
         let keys = self
             .db
             .read()
             .await
             .get(namespace)
             .map(|ns| {
-                ns.keys()
-                    .filter(|k| prefix.map_or(true, |p| k.starts_with(p)))
-                    .cloned()
+                ns.iter()
+                    .filter(|(k, entry)| {
+                        prefix.is_none_or(|p| k.starts_with(p)) && entry.live_value(now).is_some()
+                    })
+                    .map(|(k, _)| k.clone())
                     .collect()
             })
             .unwrap_or_default();
@@ -73,16 +154,162 @@ impl StorageProvider for InMemoryDb {
         namespace: &str,
         prefix: Option<&str>,
     ) -> Result<Vec<(String, Option<Vec<u8>>)>, StorageError> {
+        let now = now();
         let db = self.db.read().await;
         let values = db
             .get(namespace)
             .map(|ns| {
                 ns.iter()
                     .filter(|(k, _)| prefix.map_or(true, |p| k.starts_with(p)))
-                    .map(|(k, v)| (k.clone(), Some(v.clone())))
+                    .filter_map(|(k, entry)| {
+                        Some((k.clone(), Some(entry.live_value(now)?.clone())))
+                    })
                     .collect()
             })
             .unwrap_or_default();
         Ok(values)
     }
+
+    async fn get_namespace_byte_size(&self, namespace: &str) -> Result<u64, StorageError> {
+        // Expired keys count until they are swept
+        let db = self.db.read().await;
+        let size = db
+            .get(namespace)
+            .map(|ns| {
+                ns.iter()
+                    .map(|(k, entry)| k.len() as u64 + entry.value.len() as u64)
+                    .sum()
+            })
+            .unwrap_or_default();
+        Ok(size)
+    }
+
+    async fn expired_keys(&self, now: u64) -> Result<Vec<(String, String)>, StorageError> {
+        let db = self.db.read().await;
+        let expired = db
+            .iter()
+            .flat_map(|(namespace, ns)| {
+                ns.iter()
+                    .filter(|(_, entry)| entry.expires_at.is_some_and(|e| e <= now))
+                    .map(move |(key, _)| (namespace.clone(), key.clone()))
+            })
+            .collect();
+        Ok(expired)
+    }
+
+    async fn delete_expired(
+        &self,
+        namespace: &str,
+        key: &str,
+        now: u64,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        let mut db = self.db.write().await;
+        let Some(ns) = db.get_mut(namespace) else {
+            return Ok(None);
+        };
+        match ns.get(key) {
+            Some(entry) if entry.expires_at.is_some_and(|e| e <= now) => {
+                Ok(ns.remove(key).map(|entry| entry.value))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn conditional_writes_and_counters() {
+        let db = InMemoryDb::new().unwrap();
+
+        assert!(db.insert_if_absent("ns", "seen", vec![1]).await.unwrap());
+        assert!(!db.insert_if_absent("ns", "seen", vec![2]).await.unwrap());
+
+        assert!(!db
+            .compare_and_swap("ns", "seen", Some(vec![2]), vec![3])
+            .await
+            .unwrap());
+        assert!(db
+            .compare_and_swap("ns", "seen", Some(vec![1]), vec![3])
+            .await
+            .unwrap());
+        assert_eq!(db.get("ns", "seen").await.unwrap(), Some(vec![3]));
+
+        assert_eq!(db.increment("ns", "count", 5).await.unwrap(), (None, 5));
+        assert_eq!(
+            db.increment("ns", "count", -7).await.unwrap(),
+            (Some(b"5".to_vec()), -2)
+        );
+
+        // Expired keys are absent until they are swept
+        db.insert_with_ttl("ns".to_string(), "expired".to_string(), vec![1], 0)
+            .await
+            .unwrap();
+        assert_eq!(db.get("ns", "expired").await.unwrap(), None);
+        assert!(db.insert_if_absent("ns", "expired", vec![2]).await.unwrap());
+        assert_eq!(db.get("ns", "expired").await.unwrap(), Some(vec![2]));
+        assert!(matches!(
+            db.increment("ns", "seen", 1).await,
+            Err(StorageError::InvalidCounter(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn expired_keys_are_not_listed() {
+        let db = InMemoryDb::new().unwrap();
+        db.insert("ns".to_string(), "live".to_string(), vec![1])
+            .await
+            .unwrap();
+        db.insert_with_ttl("ns".to_string(), "expired".to_string(), vec![2], 0)
+            .await
+            .unwrap();
+
+        assert_eq!(db.list_keys("ns", None).await.unwrap(), vec!["live"]);
+        assert_eq!(
+            db.fetch_all("ns", None).await.unwrap(),
+            vec![("live".to_string(), Some(vec![1]))]
+        );
+        // The expired key still counts towards usage until it is swept
+        assert_eq!(
+            db.get_namespace_byte_size("ns").await.unwrap(),
+            4 + 1 + 7 + 1
+        );
+
+        // Overwriting an expired key does not return its value
+        assert_eq!(
+            db.insert("ns".to_string(), "expired".to_string(), vec![3])
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            db.list_keys("ns", Some("exp")).await.unwrap(),
+            vec!["expired"]
+        );
+    }
+
+    #[tokio::test]
+    async fn migrations_keep_expiries() {
+        let db = InMemoryDb::new().unwrap();
+        db.insert_with_ttl("ns".to_string(), "old".to_string(), vec![1], 4000000000)
+            .await
+            .unwrap();
+        db.insert("ns".to_string(), "kept".to_string(), vec![2])
+            .await
+            .unwrap();
+
+        db.apply_migration(
+            "ns",
+            Box::new(|key, value| (key.replace("old", "new"), value)),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(db.get_expiry("ns", "old").await.unwrap(), None);
+        assert_eq!(db.get_expiry("ns", "new").await.unwrap(), Some(4000000000));
+        assert_eq!(db.get_expiry("ns", "kept").await.unwrap(), None);
+        assert_eq!(db.get("ns", "kept").await.unwrap(), Some(vec![2]));
+    }
 }
@@ -8,14 +8,21 @@ use async_trait::async_trait;
 #[cfg(feature = "aws")]
 pub mod dynamodb;
 
+#[cfg(feature = "redis")]
+pub mod redis;
+
 #[cfg(feature = "sled")]
 pub mod sled;
 
+#[cfg(feature = "sql")]
+pub mod sql;
+
+pub mod expiry;
 pub mod in_memory;
 
 use futures_util::future::join_all;
 use in_memory::InMemoryDb;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 
 use crate::loader::LimitValue;
 
@@ -48,6 +55,10 @@ pub enum DatabaseConfig {
     Sled(sled::Config),
     #[cfg(feature = "aws")]
     DynamoDb(dynamodb::Config),
+    #[cfg(feature = "sql")]
+    Sql(sql::Config),
+    #[cfg(feature = "redis")]
+    Redis(redis::Config),
     InMemory,
 }
 
@@ -58,12 +69,39 @@ pub struct Config {
     /// Map `{ db_name --> db_config }`  
     /// Note - `db_name` must not terminate with ".wasm" to avoid confusing it with a rule-specific namespace
     pub shared_dbs: Option<HashMap<String, SharedDbConfig>>,
+    /// How often, in seconds, keys inserted with a TTL are checked for expiry. Expired keys
+    /// are treated as absent straight away, but still count towards storage limits until they
+    /// are swept. Defaults to 60.
+    #[serde(
+        default = "default_expiry_sweep_interval",
+        deserialize_with = "deserialize_expiry_sweep_interval"
+    )]
+    pub expiry_sweep_interval: u64,
+}
+
+fn default_expiry_sweep_interval() -> u64 {
+    60
+}
+
+fn deserialize_expiry_sweep_interval<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = u64::deserialize(deserializer)?;
+    if value == 0 {
+        return Err(serde::de::Error::custom(
+            "`expiry_sweep_interval` must be at least 1",
+        ));
+    }
+    Ok(value)
 }
 
 /// The storage that underpins Plaid
 pub struct Storage {
     database: Box<dyn StorageProvider + Send + Sync>,
     pub shared_dbs: Option<HashMap<String, SharedDb>>,
+    /// How often, in seconds, expired keys are swept
+    pub expiry_sweep_interval: u64,
 }
 
 /// Errors encountered while trying to use Plaid's persistent storage.
@@ -74,6 +112,7 @@ pub enum StorageError {
     CouldNotAccessStorage(String),
     Access(String),
     SharedDbError(String),
+    InvalidCounter(String),
 }
 
 impl std::fmt::Display for StorageError {
@@ -90,49 +129,141 @@ impl std::fmt::Display for StorageError {
             Self::SharedDbError(ref e) => {
                 write!(f, "Error while attempting an operation on a shared DB: {e}")
             }
+            Self::InvalidCounter(ref e) => write!(f, "Could not increment a counter: {e}"),
         }
     }
 }
 
 impl std::error::Error for StorageError {}
 
+/// How many times an increment is attempted before giving up because other writers keep
+/// changing the counter
+const MAX_INCREMENT_ATTEMPTS: usize = 100;
+
 /// Defines the basic methods that all storage providers must offer.
 #[async_trait]
 pub trait StorageProvider {
     /// Return whether this storage provider is backed by persistent storage.
     /// If not, it means the data only lives in memory and is lost in case of a reboot.
     fn is_persistent(&self) -> bool;
-    /// Insert a new key pair into the storage provider
+    /// Insert a new key pair into the storage provider. Returns the previous value, unless
+    /// there was none or it had expired.
     async fn insert(
         &self,
         namespace: String,
         key: String,
         value: Vec<u8>,
     ) -> Result<Option<Vec<u8>>, StorageError>;
-    /// Get a value by key from the storage provider. If the key doesn't exist, then it will
-    /// return Ok(None) signifying the storage provider was successfully able to identify
-    /// the key was not set.
+    /// Insert a new key pair into the storage provider that expires at `expires_at`, a Unix time
+    /// in seconds. Inserting the key again with `insert` removes the expiry.
+    async fn insert_with_ttl(
+        &self,
+        namespace: String,
+        key: String,
+        value: Vec<u8>,
+        expires_at: u64,
+    ) -> Result<Option<Vec<u8>>, StorageError>;
+    /// Whether the storage provider deletes expired keys on its own, in which case they are
+    /// not swept and storage usage is periodically recomputed instead. Defaults to false.
+    fn deletes_expired_keys(&self) -> bool {
+        false
+    }
+    /// Get a value by key from the storage provider. If the key doesn't exist or has expired,
+    /// then it will return Ok(None) signifying the storage provider was successfully able to
+    /// identify the key was not set.
     async fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError>;
+    /// Get the Unix time, in seconds, at which a key inserted with a TTL expires. Returns Ok(None)
+    /// if the key has no expiry or doesn't exist.
+    async fn get_expiry(&self, namespace: &str, key: &str) -> Result<Option<u64>, StorageError>;
     /// Delete a value by key from the storage provider. If the key exists this will return
     /// Ok(Some(previous_value)), if not, Ok(None)
     async fn delete(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError>;
-    /// List all keys in the given namespace. An optional prefix can be provided such that only
-    /// specific keys can be returned. This is helpful as it reduces the amount of compute that
-    /// needs to be taken by modules to do basic filtering. More complex filtering (i.e regex)
-    /// is not supported as computation for that has unbounded complexity.
+    /// List all keys in the given namespace, except those that have expired. An optional prefix
+    /// can be provided such that only specific keys can be returned. This is helpful as it
+    /// reduces the amount of compute that needs to be taken by modules to do basic filtering.
+    /// More complex filtering (i.e regex) is not supported as computation for that has
+    /// unbounded complexity.
     async fn list_keys(
         &self,
         namespace: &str,
         prefix: Option<&str>,
     ) -> Result<Vec<String>, StorageError>;
-    /// Same as list_keys but will return the keys and values. An optional prefix can be provided
-    /// but this only applies to the key, values have no host provided filtering.
+    /// Same as list_keys but will return the keys and values. Expired keys are not returned.
+    /// An optional prefix can be provided but this only applies to the key, values have no
+    /// host provided filtering.
     async fn fetch_all(
         &self,
         namespace: &str,
         prefix: Option<&str>,
     ) -> Result<Vec<(String, Option<Vec<u8>>)>, StorageError>;
+    /// Set `key` to `value` only if its current value is `expected`, where `None` means the key
+    /// must not exist or must have expired. Returns whether the value was set. Like `insert`,
+    /// setting the value removes any expiry.
+    async fn compare_and_swap(
+        &self,
+        namespace: &str,
+        key: &str,
+        expected: Option<Vec<u8>>,
+        value: Vec<u8>,
+    ) -> Result<bool, StorageError>;
+    /// Insert a new key pair only if the key doesn't exist. Returns whether it was inserted.
+    async fn insert_if_absent(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: Vec<u8>,
+    ) -> Result<bool, StorageError> {
+        self.compare_and_swap(namespace, key, None, value).await
+    }
+    /// Add `delta` to the counter at `key`, which starts at 0 if the key doesn't exist.
+    /// Counters are stored as integers in decimal. Returns the previous value, if any, and
+    /// the new counter.
+    async fn increment(
+        &self,
+        namespace: &str,
+        key: &str,
+        delta: i64,
+    ) -> Result<(Option<Vec<u8>>, i64), StorageError> {
+        for _ in 0..MAX_INCREMENT_ATTEMPTS {
+            let previous = self.get(namespace, key).await?;
+            let counter = match &previous {
+                None => 0,
+                Some(value) => parse_counter(value)?,
+            };
+            let counter = counter.checked_add(delta).ok_or_else(|| {
+                StorageError::InvalidCounter(format!("Adding {delta} to [{key}] overflows"))
+            })?;
+
+            if self
+                .compare_and_swap(
+                    namespace,
+                    key,
+                    previous.clone(),
+                    counter.to_string().into_bytes(),
+                )
+                .await?
+            {
+                return Ok((previous, counter));
+            }
+        }
+
+        Err(StorageError::Access(format!(
+            "Counter [{key}] in [{namespace}] kept changing while being incremented"
+        )))
+    }
+    /// List the `(namespace, key)` pairs of all keys that expired at or before `now`
+    async fn expired_keys(&self, now: u64) -> Result<Vec<(String, String)>, StorageError>;
+    /// Delete a key if it expired at or before `now`. If the key was deleted this will return
+    /// Ok(Some(previous_value)), if not (e.g., because it has been inserted again since), Ok(None)
+    async fn delete_expired(
+        &self,
+        namespace: &str,
+        key: &str,
+        now: u64,
+    ) -> Result<Option<Vec<u8>>, StorageError>;
     /// Get the number of bytes stored in a namespace. This will include keys and values.
+    /// Providers that are swept must also count the keys that have expired but have not
+    /// been swept yet, since sweeping them releases their bytes.
     async fn get_namespace_byte_size(&self, namespace: &str) -> Result<u64, StorageError> {
         let all = self.fetch_all(namespace, None).await?;
 
@@ -163,11 +294,18 @@ pub trait StorageProvider {
             if let Some(value) = value {
                 // Apply the transformation and obtain a new key and new value
                 let (new_key, new_value) = f(key.clone(), value);
+                // The new entry keeps the expiry of the old one, if any
+                let expires_at = self.get_expiry(namespace, &key).await?;
                 // Delete the old entry because we are about to insert the new one
                 self.delete(namespace, &key).await?;
                 // And insert the new pair
-                self.insert(namespace.to_string(), new_key, new_value)
-                    .await?;
+                match expires_at {
+                    Some(expires_at) => {
+                        self.insert_with_ttl(namespace.to_string(), new_key, new_value, expires_at)
+                            .await?
+                    }
+                    None => self.insert(namespace.to_string(), new_key, new_value).await?,
+                };
             }
         }
         Ok(())
@@ -179,6 +317,7 @@ impl Storage {
         Self {
             database: Box::new(InMemoryDb::new().unwrap()),
             shared_dbs: None,
+            expiry_sweep_interval: default_expiry_sweep_interval(),
         }
     }
 
@@ -193,6 +332,10 @@ impl Storage {
                     .await
                     .map_err(|e| StorageError::StorageInitError(e))?,
             ),
+            #[cfg(feature = "sql")]
+            Some(DatabaseConfig::Sql(sql)) => Box::new(sql::Sql::new(sql).await?),
+            #[cfg(feature = "redis")]
+            Some(DatabaseConfig::Redis(redis)) => Box::new(redis::Redis::new(redis).await?),
             Some(DatabaseConfig::InMemory) => Box::new(InMemoryDb::new().unwrap()), // unwrap OK: cannot fail
             _ => {
                 return Err(StorageError::NoStorageConfigured);
@@ -213,6 +356,7 @@ impl Storage {
         Ok(Storage {
             database,
             shared_dbs,
+            expiry_sweep_interval: config.expiry_sweep_interval,
         })
     }
 
@@ -225,6 +369,20 @@ impl Storage {
         self.database.insert(namespace, key, value).await
     }
 
+    /// Insert a key pair that is deleted once `ttl` seconds have passed
+    pub async fn insert_with_ttl(
+        &self,
+        namespace: String,
+        key: String,
+        value: Vec<u8>,
+        ttl: u64,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        let expires_at = expiry::now() + ttl;
+        self.database
+            .insert_with_ttl(namespace, key, value, expires_at)
+            .await
+    }
+
     pub async fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
         self.database.get(namespace, key).await
     }
@@ -237,6 +395,53 @@ impl Storage {
         self.database.delete(namespace, key).await
     }
 
+    /// Delete a key if it has expired. If the key was deleted this will return
+    /// Ok(Some(previous_value)), if not, Ok(None)
+    pub async fn delete_expired(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        self.database
+            .delete_expired(namespace, key, expiry::now())
+            .await
+    }
+
+    /// Whether expired keys need to be swept, i.e., the storage provider doesn't delete them itself
+    pub fn sweeps_expired_keys(&self) -> bool {
+        !self.database.deletes_expired_keys()
+    }
+
+    pub async fn compare_and_swap(
+        &self,
+        namespace: &str,
+        key: &str,
+        expected: Option<Vec<u8>>,
+        value: Vec<u8>,
+    ) -> Result<bool, StorageError> {
+        self.database
+            .compare_and_swap(namespace, key, expected, value)
+            .await
+    }
+
+    pub async fn insert_if_absent(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: Vec<u8>,
+    ) -> Result<bool, StorageError> {
+        self.database.insert_if_absent(namespace, key, value).await
+    }
+
+    pub async fn increment(
+        &self,
+        namespace: &str,
+        key: &str,
+        delta: i64,
+    ) -> Result<(Option<Vec<u8>>, i64), StorageError> {
+        self.database.increment(namespace, key, delta).await
+    }
+
     pub async fn list_keys(
         &self,
         namespace: &str,
@@ -258,6 +463,16 @@ impl Storage {
     }
 }
 
+/// Read a counter written by [`StorageProvider::increment`]
+pub fn parse_counter(value: &[u8]) -> Result<i64, StorageError> {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .ok_or(StorageError::InvalidCounter(
+            "The stored value is not an integer".to_string(),
+        ))
+}
+
 /// Validates a shared DB name, reads its current byte size from storage, and builds a [`SharedDb`].
 async fn init_shared_db(
     db_name: String,
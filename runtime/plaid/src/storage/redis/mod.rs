@@ -0,0 +1,391 @@
+//! This module provides a way for Plaid to use Redis for persistent storage, so that several
+//! Plaid instances can share state. Data is only as durable as the Redis server's persistence
+//! settings (RDB snapshots and/or AOF) make it.
+//!
+//! All keys live under a configurable prefix:
+//! * `{prefix}:data:{namespace}:{key}` holds the value of a key
+//! * `{prefix}:sizes` is a hash giving the number of bytes used by each namespace
+//! * `{prefix}:expiry` is a sorted set of the keys inserted with a TTL, scored by the Unix
+//!   time at which they expire
+//!
+//! Writes go through Lua scripts so that the sizes and expiries are updated atomically with
+//! the values. Since the scripts touch several keys, Redis Cluster is not supported.
+
+use async_trait::async_trait;
+use redis::{aio::ConnectionManager, AsyncCommands, Script};
+use serde::Deserialize;
+
+use super::{expiry::now, StorageError, StorageProvider};
+
+/// How many keys are requested per `SCAN` and fetched per `MGET`
+const BATCH_SIZE: usize = 1000;
+
+/// Configuration for a Redis DB
+#[derive(Deserialize)]
+pub struct Config {
+    /// The URL of the Redis server, e.g., `redis://:password@redis.internal:6379/0`
+    /// or `rediss://` for TLS
+    pub url: String,
+    /// The prefix of all the keys written by Plaid. Defaults to `plaid`.
+    #[serde(default = "default_key_prefix")]
+    pub key_prefix: String,
+}
+
+impl Config {
+    /// Configuration for the Redis server at `url`, with default settings
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            key_prefix: default_key_prefix(),
+        }
+    }
+}
+
+fn default_key_prefix() -> String {
+    "plaid".to_string()
+}
+
+/// A wrapper around a connection to a Redis server
+pub struct Redis {
+    connection_manager: ConnectionManager,
+    key_prefix: String,
+}
+
+/// Escape the characters of a namespace that would make the Redis keys ambiguous
+fn escape_namespace(namespace: &str) -> String {
+    namespace.replace('%', "%25").replace(':', "%3A")
+}
+
+/// Escape the characters that have a special meaning in `SCAN` patterns
+fn escape_pattern(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+impl Redis {
+    pub async fn new(config: Config) -> Result<Self, StorageError> {
+        let client = redis::Client::open(config.url).map_err(|e| {
+            StorageError::StorageInitError(format!("Could not create redis client: {e}"))
+        })?;
+        let connection_manager = client
+            .get_connection_manager()
+            .await
+            .map_err(|e| StorageError::CouldNotAccessStorage(e.to_string()))?;
+
+        Ok(Self {
+            connection_manager,
+            key_prefix: config.key_prefix,
+        })
+    }
+
+    /// The start of the Redis keys of all the keys in a namespace
+    fn namespace_prefix(&self, namespace: &str) -> String {
+        format!("{}:data:{}:", self.key_prefix, escape_namespace(namespace))
+    }
+
+    /// The Redis key holding the value of a key
+    fn data_key(&self, namespace: &str, key: &str) -> String {
+        format!("{}{key}", self.namespace_prefix(namespace))
+    }
+
+    /// The hash of namespace sizes
+    fn sizes_key(&self) -> String {
+        format!("{}:sizes", self.key_prefix)
+    }
+
+    /// The sorted set of expiry times
+    fn expiry_key(&self) -> String {
+        format!("{}:expiry", self.key_prefix)
+    }
+
+    /// A key's member in the sorted set of expiry times
+    fn expiry_member(namespace: &str, key: &str) -> String {
+        // unwrap OK: serializing a pair of strings cannot fail
+        serde_json::to_string(&(namespace, key)).unwrap()
+    }
+
+    /// Insert a value and set or clear its expiry, returning the previous value
+    async fn insert_with_expiry(
+        &self,
+        namespace: String,
+        key: String,
+        value: Vec<u8>,
+        expires_at: Option<u64>,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        let mut connection = self.connection_manager.clone();
+        Script::new(include_str!("./insert.redis"))
+            .key(self.data_key(&namespace, &key))
+            .key(self.sizes_key())
+            .key(self.expiry_key())
+            .arg(value)
+            .arg(&namespace)
+            .arg(key.len())
+            .arg(Self::expiry_member(&namespace, &key))
+            .arg(expires_at.map(|e| e.to_string()).unwrap_or_default())
+            .arg(now())
+            .invoke_async(&mut connection)
+            .await
+            .map_err(|e| StorageError::Access(format!("Could not insert to storage: {e}")))
+    }
+
+    /// Delete a value, only if it expired at or before `now` if given, and return it
+    async fn delete_if(
+        &self,
+        namespace: &str,
+        key: &str,
+        now: Option<u64>,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        let mut connection = self.connection_manager.clone();
+        Script::new(include_str!("./delete.redis"))
+            .key(self.data_key(namespace, key))
+            .key(self.sizes_key())
+            .key(self.expiry_key())
+            .arg(namespace)
+            .arg(key.len())
+            .arg(Self::expiry_member(namespace, key))
+            .arg(now.map(|n| n.to_string()).unwrap_or_default())
+            .invoke_async(&mut connection)
+            .await
+            .map_err(|e| StorageError::Access(format!("Could not delete from storage: {e}")))
+    }
+}
+
+#[async_trait]
+impl StorageProvider for Redis {
+    fn is_persistent(&self) -> bool {
+        true
+    }
+
+    async fn insert(
+        &self,
+        namespace: String,
+        key: String,
+        value: Vec<u8>,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        self.insert_with_expiry(namespace, key, value, None).await
+    }
+
+    async fn insert_with_ttl(
+        &self,
+        namespace: String,
+        key: String,
+        value: Vec<u8>,
+        expires_at: u64,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        self.insert_with_expiry(namespace, key, value, Some(expires_at))
+            .await
+    }
+
+    async fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        let mut connection = self.connection_manager.clone();
+        let (value, expires_at): (Option<Vec<u8>>, Option<f64>) = redis::pipe()
+            .atomic()
+            .get(self.data_key(namespace, key))
+            .zscore(self.expiry_key(), Self::expiry_member(namespace, key))
+            .query_async(&mut connection)
+            .await
+            .map_err(|e| StorageError::Access(format!("Could not get from storage: {e}")))?;
+
+        // Expired keys are absent, even before they are swept
+        match expires_at {
+            Some(expires_at) if expires_at <= now() as f64 => Ok(None),
+            _ => Ok(value),
+        }
+    }
+
+    async fn get_expiry(&self, namespace: &str, key: &str) -> Result<Option<u64>, StorageError> {
+        let mut connection = self.connection_manager.clone();
+        let expires_at: Option<f64> = connection
+            .zscore(self.expiry_key(), Self::expiry_member(namespace, key))
+            .await
+            .map_err(|e| StorageError::Access(format!("Could not get from storage: {e}")))?;
+        Ok(expires_at.map(|e| e as u64))
+    }
+
+    async fn delete(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        self.delete_if(namespace, key, None).await
+    }
+
+    async fn compare_and_swap(
+        &self,
+        namespace: &str,
+        key: &str,
+        expected: Option<Vec<u8>>,
+        value: Vec<u8>,
+    ) -> Result<bool, StorageError> {
+        let mut connection = self.connection_manager.clone();
+        let script = Script::new(include_str!("./compare_and_swap.redis"));
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(self.data_key(namespace, key))
+            .key(self.sizes_key())
+            .key(self.expiry_key())
+            .arg(value)
+            .arg(namespace)
+            .arg(key.len())
+            .arg(Self::expiry_member(namespace, key))
+            .arg(now());
+        match expected {
+            Some(expected) => invocation.arg("1").arg(expected),
+            None => invocation.arg("0"),
+        };
+        invocation
+            .invoke_async(&mut connection)
+            .await
+            .map_err(|e| StorageError::Access(format!("Could not insert to storage: {e}")))
+    }
+
+    async fn list_keys(
+        &self,
+        namespace: &str,
+        prefix: Option<&str>,
+    ) -> Result<Vec<String>, StorageError> {
+        let mut connection = self.connection_manager.clone();
+        let namespace_prefix = self.namespace_prefix(namespace);
+        let pattern = format!(
+            "{}{}*",
+            escape_pattern(&namespace_prefix),
+            escape_pattern(prefix.unwrap_or_default())
+        );
+
+        // SCAN can return a key more than once, so we deduplicate at the end
+        let mut keys = vec![];
+        let mut cursor = 0u64;
+        loop {
+            let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(BATCH_SIZE)
+                .query_async(&mut connection)
+                .await
+                .map_err(|e| {
+                    StorageError::Access(format!("Could not list storage contents: {e}"))
+                })?;
+            keys.extend(
+                batch
+                    .into_iter()
+                    .filter_map(|k| k.strip_prefix(&namespace_prefix).map(str::to_string)),
+            );
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+
+        keys.sort();
+        keys.dedup();
+
+        // Expired keys are absent, even before they are swept
+        let now = now() as f64;
+        let mut live = Vec::with_capacity(keys.len());
+        for batch in keys.chunks(BATCH_SIZE) {
+            let mut pipe = redis::pipe();
+            for key in batch {
+                pipe.zscore(self.expiry_key(), Self::expiry_member(namespace, key));
+            }
+            let expiries: Vec<Option<f64>> =
+                pipe.query_async(&mut connection).await.map_err(|e| {
+                    StorageError::Access(format!("Could not list storage contents: {e}"))
+                })?;
+            live.extend(
+                batch
+                    .iter()
+                    .zip(expiries)
+                    .filter(|(_, expires_at)| expires_at.is_none_or(|e| e > now))
+                    .map(|(key, _)| key.clone()),
+            );
+        }
+        Ok(live)
+    }
+
+    async fn fetch_all(
+        &self,
+        namespace: &str,
+        prefix: Option<&str>,
+    ) -> Result<Vec<(String, Option<Vec<u8>>)>, StorageError> {
+        let keys = self.list_keys(namespace, prefix).await?;
+        let mut connection = self.connection_manager.clone();
+
+        let mut all = Vec::with_capacity(keys.len());
+        for batch in keys.chunks(BATCH_SIZE) {
+            let data_keys: Vec<String> = batch
+                .iter()
+                .map(|key| self.data_key(namespace, key))
+                .collect();
+            // Keys deleted since they were listed come back as None
+            let values: Vec<Option<Vec<u8>>> = redis::cmd("MGET")
+                .arg(data_keys)
+                .query_async(&mut connection)
+                .await
+                .map_err(|e| {
+                    StorageError::Access(format!("Could not list storage contents: {e}"))
+                })?;
+            all.extend(batch.iter().cloned().zip(values));
+        }
+        Ok(all)
+    }
+
+    async fn get_namespace_byte_size(&self, namespace: &str) -> Result<u64, StorageError> {
+        // The scripts keep a running total, so there is no need to fetch every value
+        let mut connection = self.connection_manager.clone();
+        let size: Option<i64> =
+            connection
+                .hget(self.sizes_key(), namespace)
+                .await
+                .map_err(|e| {
+                    StorageError::Access(format!("Could not get the size of namespace: {e}"))
+                })?;
+        Ok(size.unwrap_or_default().max(0) as u64)
+    }
+
+    async fn expired_keys(&self, now: u64) -> Result<Vec<(String, String)>, StorageError> {
+        let mut connection = self.connection_manager.clone();
+        let members: Vec<String> = connection
+            .zrangebyscore(self.expiry_key(), "-inf", now)
+            .await
+            .map_err(|e| StorageError::Access(format!("Could not list expired keys: {e}")))?;
+
+        members
+            .iter()
+            .map(|member| {
+                serde_json::from_str(member).map_err(|e| {
+                    StorageError::Access(format!("Invalid entry [{member}] in expiry set: {e}"))
+                })
+            })
+            .collect()
+    }
+
+    async fn delete_expired(
+        &self,
+        namespace: &str,
+        key: &str,
+        now: u64,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        // The key may have been inserted again since it was listed as expired
+        self.delete_if(namespace, key, Some(now)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_unambiguous() {
+        // A key in "a" must not be listed as a key in "a:b", and vice versa
+        assert_eq!(escape_namespace("a:b"), "a%3Ab");
+        assert_eq!(escape_namespace("a%3Ab"), "a%253Ab");
+        assert_eq!(
+            escape_pattern("plaid:data:rule.wasm:[*]?\\"),
+            "plaid:data:rule.wasm:\\[\\*\\]\\?\\\\"
+        );
+    }
+}
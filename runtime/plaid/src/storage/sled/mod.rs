@@ -4,9 +4,59 @@ use async_trait::async_trait;
 
 use serde::Deserialize;
 
-use sled::Db;
+use sled::{
+    transaction::{
+        ConflictableTransactionError, TransactionError, TransactionalTree,
+        UnabortableTransactionError,
+    },
+    Db, Transactional, Tree,
+};
 
-use super::{StorageError, StorageProvider};
+use super::{expiry::now, StorageError, StorageProvider};
+
+/// The tree holding the Unix time at which keys inserted with a TTL expire.
+/// Its keys are built with [`expiry_key`].
+pub const EXPIRIES_TREE: &str = "__plaid_expiries";
+
+/// The key under which the expiry of `key` in `namespace` is stored in [`EXPIRIES_TREE`]:
+/// the namespace's length as a big-endian u32, the namespace, then the key.
+pub fn expiry_key(namespace: &str, key: &str) -> Vec<u8> {
+    let mut expiry_key = (namespace.len() as u32).to_be_bytes().to_vec();
+    expiry_key.extend_from_slice(namespace.as_bytes());
+    expiry_key.extend_from_slice(key.as_bytes());
+    expiry_key
+}
+
+/// Split a key of [`EXPIRIES_TREE`] back into a namespace and a key
+fn parse_expiry_key(expiry_key: &[u8]) -> Option<(String, String)> {
+    let (len, rest) = expiry_key.split_first_chunk::<4>()?;
+    let len = u32::from_be_bytes(*len) as usize;
+    if rest.len() < len {
+        return None;
+    }
+    let (namespace, key) = rest.split_at(len);
+    Some((
+        String::from_utf8(namespace.to_vec()).ok()?,
+        String::from_utf8(key.to_vec()).ok()?,
+    ))
+}
+
+/// Read an expiry stored in [`EXPIRIES_TREE`]
+pub fn parse_expires_at(value: &[u8]) -> Option<u64> {
+    Some(u64::from_be_bytes(value.try_into().ok()?))
+}
+
+/// Whether the key whose expiry is stored under `expiry_key` expired at or before `now`
+fn has_expired(
+    expiries: &TransactionalTree,
+    expiry_key: &[u8],
+    now: u64,
+) -> Result<bool, UnabortableTransactionError> {
+    Ok(expiries
+        .get(expiry_key)?
+        .and_then(|v| parse_expires_at(&v))
+        .is_some_and(|e| e <= now))
+}
 
 /// Configuration for a Sled DB
 #[derive(Deserialize)]
@@ -17,13 +67,65 @@ pub struct Config {
 /// A wrapper around a Sled DB object
 pub struct Sled {
     db: Db,
+    expiries: Tree,
 }
 
 impl Sled {
     pub fn new(config: Config) -> Result<Self, StorageError> {
         let db: sled::Db = sled::open(&config.sled_path)
             .map_err(|e| StorageError::CouldNotAccessStorage(e.to_string()))?;
-        Ok(Self { db })
+        let expiries = db
+            .open_tree(EXPIRIES_TREE)
+            .map_err(|e| StorageError::CouldNotAccessStorage(e.to_string()))?;
+        Ok(Self { db, expiries })
+    }
+
+    /// Whether `key` in `namespace` expired at or before `now`. Expiries that cannot be read
+    /// are treated as not expired.
+    fn is_expired(&self, namespace: &str, key: &str, now: u64) -> bool {
+        self.expiries
+            .get(expiry_key(namespace, key))
+            .ok()
+            .flatten()
+            .and_then(|v| parse_expires_at(&v))
+            .is_some_and(|e| e <= now)
+    }
+
+    /// Insert a value and set or clear its expiry in a single transaction
+    fn insert_with_expiry(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: Vec<u8>,
+        expires_at: Option<u64>,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        let tree = self
+            .db
+            .open_tree(namespace.as_bytes())
+            .map_err(|_| StorageError::Access(format!("Could not open Sled tree {namespace}")))?;
+        let expiry_key = expiry_key(namespace, key);
+        let now = now();
+
+        let result = (&tree, &self.expiries)
+            .transaction(|(tree, expiries)| {
+                // An expired value is absent, so it is not returned
+                let expired = has_expired(expiries, &expiry_key, now)?;
+                match expires_at {
+                    Some(expires_at) => {
+                        expiries.insert(expiry_key.as_slice(), &expires_at.to_be_bytes())?
+                    }
+                    None => expiries.remove(expiry_key.as_slice())?,
+                };
+                let previous = tree.insert(key.as_bytes(), value.as_slice())?;
+                Ok(previous.filter(|_| !expired))
+            })
+            .map_err(|_: TransactionError| {
+                StorageError::Access(format!(
+                    "Could not access Sled value at {key} in {namespace}"
+                ))
+            })?;
+
+        Ok(result.map(|v| v.to_vec()))
     }
 }
 
@@ -39,48 +141,107 @@ impl StorageProvider for Sled {
         key: String,
         value: Vec<u8>,
     ) -> Result<Option<Vec<u8>>, StorageError> {
+        self.insert_with_expiry(&namespace, &key, value, None)
+    }
+
+    async fn insert_with_ttl(
+        &self,
+        namespace: String,
+        key: String,
+        value: Vec<u8>,
+        expires_at: u64,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        self.insert_with_expiry(&namespace, &key, value, Some(expires_at))
+    }
+
+    async fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
         let tree = self
             .db
             .open_tree(namespace.as_bytes())
             .map_err(|_| StorageError::Access(format!("Could not open Sled tree {namespace}")))?;
+        let expiry_key = expiry_key(namespace, key);
+        let now = now();
+
+        let result = (&tree, &self.expiries)
+            .transaction(|(tree, expiries)| {
+                // Expired keys are absent, even before they are swept
+                if has_expired(expiries, &expiry_key, now)? {
+                    return Ok(None);
+                }
+                Ok::<_, ConflictableTransactionError>(tree.get(key.as_bytes())?)
+            })
+            .map_err(|_: TransactionError| {
+                StorageError::Access(format!(
+                    "Could not access Sled value at {key} in {namespace}"
+                ))
+            })?;
+
+        Ok(result.map(|v| v.to_vec()))
+    }
 
-        let result = tree.insert(key.as_bytes(), value).map_err(|_| {
+    async fn get_expiry(&self, namespace: &str, key: &str) -> Result<Option<u64>, StorageError> {
+        let expires_at = self.expiries.get(expiry_key(namespace, key)).map_err(|_| {
             StorageError::Access(format!(
-                "Could not access Sled value at {key} in {namespace}"
+                "Could not access Sled expiry of {key} in {namespace}"
             ))
         })?;
-
-        Ok(result.map(|v| v.to_vec()))
+        Ok(expires_at.and_then(|v| parse_expires_at(&v)))
     }
 
-    async fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+    async fn delete(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
         let tree = self
             .db
             .open_tree(namespace.as_bytes())
             .map_err(|_| StorageError::Access(format!("Could not open Sled tree {namespace}")))?;
+        let expiry_key = expiry_key(namespace, key);
 
-        let result = tree.get(key.as_bytes()).map_err(|_| {
-            StorageError::Access(format!(
-                "Could not access Sled value at {key} in {namespace}"
-            ))
-        })?;
+        let result = (&tree, &self.expiries)
+            .transaction(|(tree, expiries)| {
+                expiries.remove(expiry_key.as_slice())?;
+                Ok(tree.remove(key.as_bytes())?)
+            })
+            .map_err(|_: TransactionError| {
+                StorageError::Access(format!(
+                    "Could not access Sled value at {key} in {namespace}"
+                ))
+            })?;
 
         Ok(result.map(|v| v.to_vec()))
     }
 
-    async fn delete(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+    async fn compare_and_swap(
+        &self,
+        namespace: &str,
+        key: &str,
+        expected: Option<Vec<u8>>,
+        value: Vec<u8>,
+    ) -> Result<bool, StorageError> {
         let tree = self
             .db
             .open_tree(namespace.as_bytes())
             .map_err(|_| StorageError::Access(format!("Could not open Sled tree {namespace}")))?;
+        let expiry_key = expiry_key(namespace, key);
+        let now = now();
 
-        let result = tree.remove(key.as_bytes()).map_err(|_| {
-            StorageError::Access(format!(
-                "Could not access Sled value at {key} in {namespace}"
-            ))
-        })?;
-
-        Ok(result.map(|v| v.to_vec()))
+        (&tree, &self.expiries)
+            .transaction(|(tree, expiries)| {
+                let current = if has_expired(expiries, &expiry_key, now)? {
+                    None
+                } else {
+                    tree.get(key.as_bytes())?
+                };
+                if current.as_deref() != expected.as_deref() {
+                    return Ok(false);
+                }
+                expiries.remove(expiry_key.as_slice())?;
+                tree.insert(key.as_bytes(), value.as_slice())?;
+                Ok(true)
+            })
+            .map_err(|_: TransactionError| {
+                StorageError::Access(format!(
+                    "Could not access Sled value at {key} in {namespace}"
+                ))
+            })
     }
 
     async fn list_keys(
@@ -97,12 +258,15 @@ impl StorageProvider for Sled {
             Some(p) => tree.scan_prefix(p),
             None => tree.iter(),
         };
+        let now = now();
         // The use of a filter_map here means keys that fail to be pulled will be thrown away.
         // I don't know if this is possible? Maybe if the database is moved out from under us?
         let keys: Vec<String> = key_iter
             .keys()
             .filter_map(|x| match x {
-                Ok(v) => String::from_utf8(v.to_vec()).ok(),
+                Ok(v) => String::from_utf8(v.to_vec())
+                    .ok()
+                    .filter(|key| !self.is_expired(namespace, key, now)),
                 Err(e) => {
                     error!("Storage Error Listing Keys: {e}");
                     None
@@ -127,12 +291,14 @@ impl StorageProvider for Sled {
             Some(p) => tree.scan_prefix(p),
             None => tree.iter(),
         };
+        let now = now();
         // The use of a filter_map here means keys that fail to be pulled will be thrown away.
         // I don't know if this is possible? Maybe if the database is moved out from under us?
         let data = key_iter
             .filter_map(|x| match x {
                 Ok((k, v)) => String::from_utf8(k.to_vec())
                     .ok()
+                    .filter(|key| !self.is_expired(namespace, key, now))
                     .map(|key| (key, Some(v.to_vec()))),
                 Err(e) => {
                     error!("Storage Error Listing Keys: {e}");
@@ -143,4 +309,118 @@ impl StorageProvider for Sled {
 
         Ok(data)
     }
+
+    async fn get_namespace_byte_size(&self, namespace: &str) -> Result<u64, StorageError> {
+        let tree = self
+            .db
+            .open_tree(namespace.as_bytes())
+            .map_err(|_| StorageError::Access(format!("Could not open Sled tree {namespace}")))?;
+
+        // Expired keys count until they are swept
+        let mut size = 0;
+        for entry in tree.iter() {
+            let (k, v) = entry.map_err(|e| {
+                StorageError::Access(format!("Could not read Sled tree {namespace}: {e}"))
+            })?;
+            size += k.len() as u64 + v.len() as u64;
+        }
+        Ok(size)
+    }
+
+    async fn expired_keys(&self, now: u64) -> Result<Vec<(String, String)>, StorageError> {
+        // The use of a filter_map here means entries that fail to be pulled will be thrown away.
+        let expired = self
+            .expiries
+            .iter()
+            .filter_map(|x| match x {
+                Ok((k, v)) if parse_expires_at(&v).is_some_and(|e| e <= now) => {
+                    parse_expiry_key(&k)
+                }
+                Ok(_) => None,
+                Err(e) => {
+                    error!("Storage Error Listing Expired Keys: {e}");
+                    None
+                }
+            })
+            .collect();
+
+        Ok(expired)
+    }
+
+    async fn delete_expired(
+        &self,
+        namespace: &str,
+        key: &str,
+        now: u64,
+    ) -> Result<Option<Vec<u8>>, StorageError> {
+        let tree = self
+            .db
+            .open_tree(namespace.as_bytes())
+            .map_err(|_| StorageError::Access(format!("Could not open Sled tree {namespace}")))?;
+        let expiry_key = expiry_key(namespace, key);
+
+        let result = (&tree, &self.expiries)
+            .transaction(|(tree, expiries)| {
+                // The key may have been inserted again since it was listed as expired
+                if !has_expired(expiries, &expiry_key, now)? {
+                    return Ok(None);
+                }
+                expiries.remove(expiry_key.as_slice())?;
+                Ok::<_, ConflictableTransactionError>(tree.remove(key.as_bytes())?)
+            })
+            .map_err(|_: TransactionError| {
+                StorageError::Access(format!(
+                    "Could not access Sled value at {key} in {namespace}"
+                ))
+            })?;
+
+        Ok(result.map(|v| v.to_vec()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn expired_keys_are_not_listed() {
+        let path = std::env::temp_dir().join(format!("plaid-sled-{}", uuid::Uuid::new_v4()));
+        let db = Sled::new(Config {
+            sled_path: path.display().to_string(),
+        })
+        .unwrap();
+
+        db.insert("ns".to_string(), "live".to_string(), vec![1])
+            .await
+            .unwrap();
+        db.insert_with_ttl("ns".to_string(), "expired".to_string(), vec![2], 0)
+            .await
+            .unwrap();
+
+        assert_eq!(db.list_keys("ns", None).await.unwrap(), vec!["live"]);
+        assert_eq!(
+            db.fetch_all("ns", None).await.unwrap(),
+            vec![("live".to_string(), Some(vec![1]))]
+        );
+        // The expired key still counts towards usage until it is swept
+        assert_eq!(
+            db.get_namespace_byte_size("ns").await.unwrap(),
+            4 + 1 + 7 + 1
+        );
+
+        // Overwriting an expired key does not return its value
+        assert_eq!(
+            db.insert("ns".to_string(), "expired".to_string(), vec![3])
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            db.list_keys("ns", Some("exp")).await.unwrap(),
+            vec!["expired"]
+        );
+
+        drop(db);
+        std::fs::remove_dir_all(path).unwrap();
+    }
 }
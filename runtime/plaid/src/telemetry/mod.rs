@@ -0,0 +1,209 @@
+//! Distributed tracing of messages through Plaid.
+//!
+//! Every message carries the W3C `traceparent` of the span that produced it: the webhook
+//! request it arrived on, or the module execution that logged it back. Each module run on
+//! the message is a child of that span, and each host function the module calls is a
+//! child of the run. Spans are exported over OTLP/HTTP when tracing is configured.
+//! Otherwise no spans are recorded, but inbound trace context is still propagated.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::{SpanKind, Status, TraceContextExt, Tracer};
+use opentelemetry::{global, Context, KeyValue};
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter, WithExportConfig, WithHttpConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use serde::Deserialize;
+
+use crate::executor::Message;
+
+/// The name of the instrumentation scope all of Plaid's spans are created in
+const TRACER_NAME: &str = "plaid";
+/// The header that carries trace context, see https://www.w3.org/TR/trace-context/
+const TRACEPARENT: &str = "traceparent";
+
+#[derive(Deserialize)]
+pub struct TracingConfiguration {
+    /// The OTLP/HTTP endpoint of the collector spans are exported to,
+    /// e.g., `http://localhost:4318/v1/traces`
+    pub endpoint: String,
+    /// The service name spans are reported under
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+    /// Headers sent with every export, e.g., for authentication
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// The fraction of new traces that are recorded, between 0 and 1. Traces continued
+    /// from an inbound `traceparent` follow the sampling decision of the caller.
+    #[serde(default = "default_sample_ratio")]
+    pub sample_ratio: f64,
+    /// How long to wait for the collector to accept a batch of spans, in seconds
+    #[serde(default = "default_export_timeout")]
+    pub export_timeout: u64,
+}
+
+fn default_service_name() -> String {
+    "plaid".to_string()
+}
+
+fn default_sample_ratio() -> f64 {
+    1.0
+}
+
+fn default_export_timeout() -> u64 {
+    10
+}
+
+/// Exports spans to the configured collector until it is shut down
+pub struct Tracing {
+    provider: SdkTracerProvider,
+}
+
+impl Tracing {
+    /// Start exporting spans. This installs the global tracer used by the rest of Plaid.
+    pub fn start(config: TracingConfiguration) -> Result<Self, ExporterBuildError> {
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(config.endpoint)
+            .with_headers(config.headers)
+            .with_timeout(Duration::from_secs(config.export_timeout))
+            .build()?;
+
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                config.sample_ratio,
+            ))))
+            .with_resource(
+                Resource::builder()
+                    .with_service_name(config.service_name)
+                    .build(),
+            )
+            .build();
+
+        global::set_tracer_provider(provider.clone());
+        Ok(Self { provider })
+    }
+
+    /// Export any spans that are still buffered and stop the exporter
+    pub fn shutdown(self) {
+        if let Err(e) = self.provider.shutdown() {
+            error!("Failed to shut down the tracing system: {e}");
+        }
+    }
+}
+
+/// Read trace context from a `traceparent` value. An empty context is returned if
+/// there is no value or it is not valid.
+pub fn extract_context(traceparent: Option<&str>) -> Context {
+    let Some(traceparent) = traceparent else {
+        return Context::new();
+    };
+    let carrier = HashMap::from([(TRACEPARENT.to_string(), traceparent.to_string())]);
+    TraceContextPropagator::new().extract(&carrier)
+}
+
+/// Get the `traceparent` of the span in a context, so it can be carried by a message.
+/// There is none if the context has no valid span.
+pub fn traceparent(cx: &Context) -> Option<String> {
+    let mut carrier = HashMap::new();
+    TraceContextPropagator::new().inject_context(cx, &mut carrier);
+    carrier.remove(TRACEPARENT)
+}
+
+/// Attributes that identify a message on every span about it
+fn message_attributes(message: &Message) -> Vec<KeyValue> {
+    vec![
+        KeyValue::new("plaid.message.id", message.id.clone()),
+        KeyValue::new("plaid.log_type", message.type_.clone()),
+        KeyValue::new("plaid.source", message.source.to_string()),
+    ]
+}
+
+/// Start the span for a webhook request that produced `message`, continuing the caller's
+/// trace if the request had a `traceparent` header. The span's context is recorded on
+/// the message so that the modules it runs on are part of the same trace.
+pub fn start_ingestion(webhook: &str, inbound: Option<&str>, message: &mut Message) -> Context {
+    let parent = extract_context(inbound);
+    let mut attributes = message_attributes(message);
+    attributes.push(KeyValue::new("plaid.webhook", webhook.to_string()));
+
+    let tracer = global::tracer(TRACER_NAME);
+    let span = tracer
+        .span_builder(format!("webhook {webhook}"))
+        .with_kind(SpanKind::Server)
+        .with_attributes(attributes)
+        .start_with_context(&tracer, &parent);
+    let cx = parent.with_span(span);
+    message.trace_parent = traceparent(&cx);
+    cx
+}
+
+/// Start the span for running `module` on `message`. It is a child of the span that
+/// produced the message, if there was one.
+pub fn start_execution(module: &str, message: &Message) -> Context {
+    let parent = extract_context(message.trace_parent.as_deref());
+    let mut attributes = message_attributes(message);
+    attributes.push(KeyValue::new("plaid.module", module.to_string()));
+    attributes.push(KeyValue::new("plaid.attempt", i64::from(message.attempt)));
+
+    let tracer = global::tracer(TRACER_NAME);
+    let span = tracer
+        .span_builder(format!("execute {module}"))
+        .with_kind(SpanKind::Consumer)
+        .with_attributes(attributes)
+        .start_with_context(&tracer, &parent);
+    parent.with_span(span)
+}
+
+/// Mark the span in a context as failed
+pub fn record_error(cx: &Context, error: impl ToString) {
+    cx.span().set_status(Status::error(error.to_string()));
+}
+
+/// Start the span for a host function called by a module
+pub fn start_host_call(cx: &Context, api: &'static str, function: &'static str) -> Context {
+    let tracer = global::tracer(TRACER_NAME);
+    let span = tracer
+        .span_builder(function)
+        .with_kind(SpanKind::Client)
+        .with_attributes([
+            KeyValue::new("plaid.api", api),
+            KeyValue::new("plaid.function", function),
+        ])
+        .start_with_context(&tracer, cx);
+    cx.with_span(span)
+}
+
+/// End the span for a host function with the code it returned to the module.
+/// Negative codes are errors.
+pub fn end_host_call(cx: Context, code: i32) {
+    let span = cx.span();
+    span.set_attribute(KeyValue::new("plaid.result_code", i64::from(code)));
+    if code < 0 {
+        span.set_status(Status::error(format!("returned error code {code}")));
+    }
+    span.end();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn traceparent_round_trip() {
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let cx = extract_context(Some(traceparent));
+        assert!(cx.span().span_context().is_remote());
+        assert_eq!(super::traceparent(&cx).as_deref(), Some(traceparent));
+
+        assert!(!extract_context(Some("not a traceparent"))
+            .span()
+            .span_context()
+            .is_valid());
+        assert_eq!(super::traceparent(&extract_context(None)), None);
+    }
+}
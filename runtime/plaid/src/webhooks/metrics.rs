@@ -0,0 +1,35 @@
+use prometheus::{IntCounterVec, Opts};
+
+use crate::metrics::MetricsHandle;
+
+/// Counters for requests received by the webhook servers.
+pub struct WebhookMetrics {
+    verification_failures: IntCounterVec,
+}
+
+impl WebhookMetrics {
+    pub fn register(handle: &MetricsHandle) -> Self {
+        let verification_failures = IntCounterVec::new(
+            Opts::new(
+                "plaid_webhook_verification_failures_total",
+                "Number of webhook requests rejected because their signature could not be verified",
+            ),
+            &["webhook"],
+        )
+        .expect("valid metric definition");
+
+        handle
+            .register(Box::new(verification_failures.clone()))
+            .expect("expected unique collector");
+
+        Self {
+            verification_failures,
+        }
+    }
+
+    pub fn record_verification_failure(&self, webhook: &str) {
+        self.verification_failures
+            .with_label_values(&[webhook])
+            .inc();
+    }
+}
@@ -0,0 +1,307 @@
+use ring::hmac;
+use serde::{de, Deserialize, Deserializer};
+use warp::http::HeaderMap;
+
+/// How the sender of a webhook signs its requests.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VerificationScheme {
+    /// GitHub: `X-Hub-Signature-256: sha256=<hex HMAC-SHA256 of the body>`
+    Github,
+    /// Slack: `X-Slack-Signature: v0=<hex HMAC-SHA256 of "v0:{timestamp}:{body}">`, with the
+    /// timestamp sent in `X-Slack-Request-Timestamp`
+    Slack,
+    /// Stripe: `Stripe-Signature: t=<timestamp>,v1=<hex HMAC-SHA256 of "{timestamp}.{body}">`
+    Stripe,
+    /// A hex HMAC-SHA256 of the body in a configured header, optionally after a fixed prefix
+    Hmac,
+}
+
+impl VerificationScheme {
+    /// The header the scheme's signature is sent in, if the scheme defines one
+    fn default_header(&self) -> Option<&'static str> {
+        match self {
+            Self::Github => Some("x-hub-signature-256"),
+            Self::Slack => Some("x-slack-signature"),
+            Self::Stripe => Some("stripe-signature"),
+            Self::Hmac => None,
+        }
+    }
+}
+
+/// Configuration for verifying the signature on requests sent to a webhook.
+/// Requests that fail verification are rejected before a message is created.
+///
+/// E.g.,
+/// ```toml
+/// [webhooks."external".webhooks."github".verification]
+/// scheme = "github"
+/// secret = "{plaid-secret{github-webhook-secret}}"
+/// ```
+#[derive(Deserialize, Clone)]
+pub struct WebhookVerification {
+    /// The signature scheme used by the sender
+    pub scheme: VerificationScheme,
+    /// The secret shared with the sender. This should be a `{plaid-secret{...}}` reference
+    /// so that the value lives in the secrets file.
+    pub secret: String,
+    /// The header the signature is read from. Defaults to the scheme's standard header
+    /// and must be set for the `hmac` scheme.
+    pub header: Option<String>,
+    /// For the `hmac` scheme, a prefix that comes before the hex signature in the header
+    /// (e.g., `sha256=`)
+    pub prefix: Option<String>,
+    /// For schemes that sign a timestamp, how many seconds old (or in the future) the
+    /// timestamp may be before the request is treated as a replay. Defaults to 300.
+    #[serde(default = "default_replay_window")]
+    pub replay_window: u64,
+}
+
+fn default_replay_window() -> u64 {
+    300
+}
+
+/// Reasons a request can fail verification
+#[derive(Debug, PartialEq, Eq)]
+pub enum VerificationError {
+    MissingHeader(String),
+    MalformedHeader(String),
+    StaleTimestamp(u64),
+    InvalidSignature,
+}
+
+impl std::fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingHeader(header) => write!(f, "Missing header [{header}]"),
+            Self::MalformedHeader(header) => write!(f, "Malformed header [{header}]"),
+            Self::StaleTimestamp(timestamp) => {
+                write!(f, "Timestamp [{timestamp}] is outside the replay window")
+            }
+            Self::InvalidSignature => write!(f, "Signature does not match"),
+        }
+    }
+}
+
+/// Deserializer for a webhook's verification block, which checks that a signature header is
+/// known for the configured scheme.
+pub fn deserialize_verification<'de, D>(
+    deserializer: D,
+) -> Result<Option<WebhookVerification>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let verification = Option::<WebhookVerification>::deserialize(deserializer)?;
+    if let Some(ref v) = verification {
+        if v.header.is_none() && v.scheme.default_header().is_none() {
+            return Err(de::Error::custom(
+                "`header` must be set when using the `hmac` verification scheme",
+            ));
+        }
+    }
+    Ok(verification)
+}
+
+impl WebhookVerification {
+    /// Verify a request's signature. `now` is the current Unix time in seconds, which is
+    /// compared against signed timestamps to reject replays.
+    pub fn verify(
+        &self,
+        headers: &HeaderMap,
+        body: &[u8],
+        now: u64,
+    ) -> Result<(), VerificationError> {
+        let header = self
+            .header
+            .as_deref()
+            .or(self.scheme.default_header())
+            .unwrap_or_default();
+        let value = get_header(headers, header)?;
+
+        match self.scheme {
+            VerificationScheme::Github => {
+                let signature = value
+                    .strip_prefix("sha256=")
+                    .ok_or(VerificationError::MalformedHeader(header.to_string()))?;
+                self.check_signature(body, signature, header)
+            }
+            VerificationScheme::Slack => {
+                let timestamp_header = "x-slack-request-timestamp";
+                let timestamp =
+                    parse_timestamp(get_header(headers, timestamp_header)?, timestamp_header)?;
+                self.check_timestamp(timestamp, now)?;
+
+                let signature = value
+                    .strip_prefix("v0=")
+                    .ok_or(VerificationError::MalformedHeader(header.to_string()))?;
+                let signed = [format!("v0:{timestamp}:").as_bytes(), body].concat();
+                self.check_signature(&signed, signature, header)
+            }
+            VerificationScheme::Stripe => {
+                let mut timestamp = None;
+                let mut signatures = vec![];
+                for part in value.split(',') {
+                    match part.trim().split_once('=') {
+                        Some(("t", t)) => timestamp = Some(parse_timestamp(t, header)?),
+                        Some(("v1", signature)) => signatures.push(signature),
+                        _ => {}
+                    }
+                }
+                let timestamp =
+                    timestamp.ok_or(VerificationError::MalformedHeader(header.to_string()))?;
+                self.check_timestamp(timestamp, now)?;
+
+                let signed = [format!("{timestamp}.").as_bytes(), body].concat();
+                // Stripe sends one signature per active secret while a secret is being rolled
+                if signatures
+                    .into_iter()
+                    .any(|signature| self.check_signature(&signed, signature, header).is_ok())
+                {
+                    Ok(())
+                } else {
+                    Err(VerificationError::InvalidSignature)
+                }
+            }
+            VerificationScheme::Hmac => {
+                let signature = match self.prefix {
+                    Some(ref prefix) => value
+                        .strip_prefix(prefix.as_str())
+                        .ok_or(VerificationError::MalformedHeader(header.to_string()))?,
+                    None => value,
+                };
+                self.check_signature(body, signature, header)
+            }
+        }
+    }
+
+    /// Check a hex encoded HMAC-SHA256 over `signed` in constant time
+    fn check_signature(
+        &self,
+        signed: &[u8],
+        signature: &str,
+        header: &str,
+    ) -> Result<(), VerificationError> {
+        let signature = hex::decode(signature.trim())
+            .map_err(|_| VerificationError::MalformedHeader(header.to_string()))?;
+        let key = hmac::Key::new(hmac::HMAC_SHA256, self.secret.as_bytes());
+        hmac::verify(&key, signed, &signature).map_err(|_| VerificationError::InvalidSignature)
+    }
+
+    fn check_timestamp(&self, timestamp: u64, now: u64) -> Result<(), VerificationError> {
+        if now.abs_diff(timestamp) > self.replay_window {
+            return Err(VerificationError::StaleTimestamp(timestamp));
+        }
+        Ok(())
+    }
+}
+
+fn get_header<'a>(headers: &'a HeaderMap, header: &str) -> Result<&'a str, VerificationError> {
+    headers
+        .get(header)
+        .ok_or(VerificationError::MissingHeader(header.to_string()))?
+        .to_str()
+        .map_err(|_| VerificationError::MalformedHeader(header.to_string()))
+}
+
+fn parse_timestamp(value: &str, header: &str) -> Result<u64, VerificationError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| VerificationError::MalformedHeader(header.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "It's a Secret to Everybody";
+    const BODY: &[u8] = b"Hello, World!";
+    const NOW: u64 = 1_700_000_000;
+
+    fn verification(scheme: VerificationScheme) -> WebhookVerification {
+        WebhookVerification {
+            scheme,
+            secret: SECRET.to_string(),
+            header: None,
+            prefix: None,
+            replay_window: default_replay_window(),
+        }
+    }
+
+    fn sign(data: &[u8]) -> String {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, SECRET.as_bytes());
+        hex::encode(hmac::sign(&key, data))
+    }
+
+    fn headers(pairs: &[(&'static str, String)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn github_signature() {
+        // Test vector from GitHub's webhook validation documentation
+        let expected = "757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+        assert_eq!(sign(BODY), expected);
+
+        let v = verification(VerificationScheme::Github);
+        let good = headers(&[("x-hub-signature-256", format!("sha256={expected}"))]);
+        assert_eq!(v.verify(&good, BODY, NOW), Ok(()));
+        assert_eq!(
+            v.verify(&good, b"Goodbye, World!", NOW),
+            Err(VerificationError::InvalidSignature)
+        );
+        assert_eq!(
+            v.verify(&HeaderMap::new(), BODY, NOW),
+            Err(VerificationError::MissingHeader(
+                "x-hub-signature-256".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn slack_signature_and_replay_window() {
+        let v = verification(VerificationScheme::Slack);
+        let signed = [format!("v0:{NOW}:").as_bytes(), BODY].concat();
+        let good = headers(&[
+            ("x-slack-signature", format!("v0={}", sign(&signed))),
+            ("x-slack-request-timestamp", NOW.to_string()),
+        ]);
+        assert_eq!(v.verify(&good, BODY, NOW + 10), Ok(()));
+        assert_eq!(
+            v.verify(&good, BODY, NOW + 301),
+            Err(VerificationError::StaleTimestamp(NOW))
+        );
+    }
+
+    #[test]
+    fn stripe_accepts_any_matching_signature() {
+        let v = verification(VerificationScheme::Stripe);
+        let signed = [format!("{NOW}.").as_bytes(), BODY].concat();
+        let good = headers(&[(
+            "stripe-signature",
+            format!("t={NOW},v1={},v1={}", "00".repeat(32), sign(&signed)),
+        )]);
+        assert_eq!(v.verify(&good, BODY, NOW), Ok(()));
+    }
+
+    #[test]
+    fn generic_hmac_with_prefix() {
+        let mut v = verification(VerificationScheme::Hmac);
+        v.header = Some("x-signature".to_string());
+        v.prefix = Some("sha256=".to_string());
+        let good = headers(&[("x-signature", format!("sha256={}", sign(BODY)))]);
+        assert_eq!(v.verify(&good, BODY, NOW), Ok(()));
+
+        let unprefixed = headers(&[("x-signature", sign(BODY))]);
+        assert_eq!(
+            v.verify(&unprefixed, BODY, NOW),
+            Err(VerificationError::MalformedHeader(
+                "x-signature".to_string()
+            ))
+        );
+    }
+}